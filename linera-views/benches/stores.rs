@@ -1,13 +1,22 @@
 // Copyright (c) Zefchain Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
 #[cfg(with_dynamodb)]
-use linera_views::dynamo_db::DynamoDbStore;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
 #[cfg(with_rocksdb)]
 use linera_views::rocks_db::RocksDbStore;
 #[cfg(with_scylladb)]
 use linera_views::scylla_db::ScyllaDbStore;
+#[cfg(with_dynamodb)]
+use linera_views::{
+    batch::{SimpleUnorderedBatch, SimplifiedBatch as _},
+    dynamo_db::{DynamoDbStore, DynamoDbStoreInternal, UncachedDynamoDbStore},
+    journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+    random::generate_test_namespace,
+    store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, TestKeyValueStore as _},
+};
 use linera_views::{memory::MemoryStore, test_utils::performance};
 use tokio::runtime::Runtime;
 
@@ -190,6 +199,16 @@ fn bench_read_value_bytes(criterion: &mut Criterion) {
             })
     });
 
+    #[cfg(with_dynamodb)]
+    criterion.bench_function("store_dynamodb_uncached_read_value_bytes", |bencher| {
+        bencher
+            .to_async(Runtime::new().expect("Failed to create Tokio runtime"))
+            .iter_custom(|iterations| async move {
+                performance::read_value_bytes::<UncachedDynamoDbStore, _>(iterations, black_box)
+                    .await
+            })
+    });
+
     #[cfg(with_scylladb)]
     criterion.bench_function("store_scylladb_read_value_bytes", |bencher| {
         bencher
@@ -228,6 +247,21 @@ fn bench_read_multi_values_bytes(criterion: &mut Criterion) {
             })
     });
 
+    #[cfg(with_dynamodb)]
+    criterion.bench_function(
+        "store_dynamodb_uncached_read_multi_values_bytes",
+        |bencher| {
+            bencher
+                .to_async(Runtime::new().expect("Failed to create Tokio runtime"))
+                .iter_custom(|iterations| async move {
+                    performance::read_multi_values_bytes::<UncachedDynamoDbStore, _>(
+                        iterations, black_box,
+                    )
+                    .await
+                })
+        },
+    );
+
     #[cfg(with_scylladb)]
     criterion.bench_function("store_scylladb_read_multi_values_bytes", |bencher| {
         bencher
@@ -276,6 +310,121 @@ fn bench_write_batch(criterion: &mut Criterion) {
     });
 }
 
+/// Reads large values from DynamoDB and hands each of them to several consumers, either
+/// as `Vec<u8>`, which are copied, or as `Bytes`, which share the buffer read.
+#[cfg(with_dynamodb)]
+async fn read_large_values(iterations: u64, shared: bool) -> Duration {
+    const NUM_VALUES: u8 = 20;
+    const LEN_VALUE: usize = 300_000;
+    const NUM_CONSUMERS: usize = 8;
+
+    let config = JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+        .await
+        .unwrap();
+    let store = DynamoDbStoreInternal::recreate_and_connect(&config, &generate_test_namespace())
+        .await
+        .unwrap();
+    for index in 0..NUM_VALUES {
+        let mut batch = SimpleUnorderedBatch::default();
+        batch.add_insert(vec![1, index], vec![index; LEN_VALUE]);
+        store.write_batch(batch).await.unwrap();
+    }
+
+    let measurement = Instant::now();
+    for _ in 0..iterations {
+        for index in 0..NUM_VALUES {
+            let key = [1, index];
+            if shared {
+                let value = store.read_value_shared(&key).await.unwrap().unwrap();
+                for _ in 0..NUM_CONSUMERS {
+                    black_box(value.clone());
+                }
+            } else {
+                let value = store.read_value_bytes(&key).await.unwrap().unwrap();
+                for _ in 0..NUM_CONSUMERS {
+                    black_box(value.clone());
+                }
+            }
+        }
+    }
+    measurement.elapsed()
+}
+
+#[cfg(with_dynamodb)]
+fn bench_dynamodb_shared_values(criterion: &mut Criterion) {
+    criterion.bench_function("store_dynamodb_read_large_values_copied", |bencher| {
+        bencher
+            .to_async(Runtime::new().expect("Failed to create Tokio runtime"))
+            .iter_custom(|iterations| async move { read_large_values(iterations, false).await })
+    });
+
+    criterion.bench_function("store_dynamodb_read_large_values_shared", |bencher| {
+        bencher
+            .to_async(Runtime::new().expect("Failed to create Tokio runtime"))
+            .iter_custom(|iterations| async move { read_large_values(iterations, true).await })
+    });
+}
+
+/// Copies a table of `NUM_ITEMS` key-values to a new table with `copy_to`.
+#[cfg(with_dynamodb)]
+async fn copy_table(iterations: u64, concurrency: usize) -> Duration {
+    const NUM_ITEMS: usize = 100_000;
+    const LEN_VALUE: usize = 100;
+
+    let config = JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+        .await
+        .unwrap();
+    let store = DynamoDbStoreInternal::recreate_and_connect(&config, &generate_test_namespace())
+        .await
+        .unwrap();
+    let mut batch = SimpleUnorderedBatch::default();
+    for index in 0..NUM_ITEMS {
+        // The keys are spread over the view tags, which are copied in parallel.
+        let mut key = vec![1 + (index % 255) as u8];
+        key.extend((index as u32).to_be_bytes());
+        batch.add_insert(key, vec![0; LEN_VALUE]);
+        if batch.len() == DynamoDbStoreInternal::MAX_BATCH_SIZE {
+            store.write_batch(std::mem::take(&mut batch)).await.unwrap();
+        }
+    }
+    if !batch.is_empty() {
+        store.write_batch(batch).await.unwrap();
+    }
+
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let target =
+            DynamoDbStoreInternal::recreate_and_connect(&config, &generate_test_namespace())
+                .await
+                .unwrap();
+        let measurement = Instant::now();
+        black_box(store.copy_to(&target, concurrency).await.unwrap());
+        total += measurement.elapsed();
+    }
+    total
+}
+
+#[cfg(with_dynamodb)]
+fn bench_dynamodb_copy_to(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("store_dynamodb_copy_to");
+    group.sample_size(10);
+    for concurrency in [1, 8] {
+        group.bench_function(format!("concurrency_{concurrency}"), |bencher| {
+            bencher
+                .to_async(Runtime::new().expect("Failed to create Tokio runtime"))
+                .iter_custom(|iterations| async move { copy_table(iterations, concurrency).await })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(with_dynamodb)]
+criterion_group!(
+    dynamodb_benches,
+    bench_dynamodb_shared_values,
+    bench_dynamodb_copy_to
+);
+
 criterion_group!(
     benches,
     bench_contains_key,
@@ -286,4 +435,7 @@ criterion_group!(
     bench_read_multi_values_bytes,
     bench_write_batch
 );
+#[cfg(with_dynamodb)]
+criterion_main!(benches, dynamodb_benches);
+#[cfg(not(with_dynamodb))]
 criterion_main!(benches);
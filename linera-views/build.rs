@@ -10,5 +10,7 @@ fn main() {
         with_indexeddb: { all(web, feature = "indexeddb") },
         with_rocksdb: { all(not(target_arch = "wasm32"), feature = "rocksdb") },
         with_scylladb: { all(not(target_arch = "wasm32"), feature = "scylladb") },
+        with_mark_and_sweep: { feature = "mark-and-sweep" },
+        with_packing: { feature = "packing" },
     };
 }
@@ -2,41 +2,89 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Implements [`crate::store::KeyValueStore`] for the DynamoDB database.
+//!
+//! A namespace is a DynamoDB table. Inside a table, the value of the partition key of an
+//! item is the root key of the store that wrote it, prefixed by `[0]`. Stores sharing a
+//! table can thus be isolated from each other by giving them distinct root keys with
+//! `clone_with_root_key`: each of them then lives in its own partition, which can be
+//! listed with `list_root_keys` and cleared independently of the others.
 
+#[cfg(with_metrics)]
+use std::sync::LazyLock;
 use std::{
-    collections::HashMap,
-    env,
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    env, fmt,
+    hash::{BuildHasher as _, RandomState},
+    ops::{Bound, Range, RangeBounds as _},
+    pin::pin,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
 };
 
-use async_lock::{Semaphore, SemaphoreGuard};
+use async_lock::{OnceCell, Semaphore, SemaphoreGuard};
 use async_trait::async_trait;
+#[cfg(with_testing)]
+use aws_sdk_dynamodb::config::{
+    http::HttpResponse,
+    interceptors::{
+        BeforeDeserializationInterceptorContextMut, BeforeTransmitInterceptorContextMut,
+    },
+    ConfigBag, Intercept, RuntimeComponents,
+};
 use aws_sdk_dynamodb::{
-    error::SdkError,
+    config::Region,
+    error::{ProvideErrorMetadata, SdkError},
     operation::{
         batch_write_item::BatchWriteItemError,
         create_table::CreateTableError,
+        delete_item::DeleteItemError,
         delete_table::DeleteTableError,
         describe_table::DescribeTableError,
-        get_item::GetItemError,
+        describe_time_to_live::DescribeTimeToLiveError,
+        execute_statement::ExecuteStatementError,
+        get_item::{GetItemError, GetItemOutput},
         list_tables::ListTablesError,
+        put_item::PutItemError,
         query::{QueryError, QueryOutput},
-        transact_write_items::TransactWriteItemsError,
+        scan::ScanError,
+        transact_get_items::TransactGetItemsError,
+        transact_write_items::{TransactWriteItemsError, TransactWriteItemsOutput},
+        update_item::UpdateItemError,
     },
     primitives::Blob,
     types::{
-        AttributeDefinition, AttributeValue, Delete, KeySchemaElement, KeyType,
-        ProvisionedThroughput, Put, ScalarAttributeType, TransactWriteItem,
+        AttributeDefinition, AttributeValue, ConsumedCapacity, Delete, DeleteRequest, Get,
+        KeySchemaElement, KeyType, LocalSecondaryIndex, Projection, ProjectionType,
+        ProvisionedThroughput, Put, PutRequest, ReturnConsumedCapacity,
+        ReturnItemCollectionMetrics, ReturnValue, ScalarAttributeType, Select, TableStatus,
+        TransactGetItem, TransactWriteItem, WriteRequest,
     },
     Client,
 };
 use aws_smithy_types::error::operation::BuildError;
-use futures::future::{join_all, FutureExt as _};
-use linera_base::ensure;
-use serde::{Deserialize, Serialize};
+#[cfg(with_testing)]
+use aws_smithy_types::{
+    body::SdkBody,
+    config_bag::{Storable, StoreReplace},
+};
+use bytes::Bytes;
+use futures::{
+    future::{try_join_all, FutureExt as _},
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    stream, Stream, StreamExt as _, TryStreamExt as _,
+};
+#[cfg(with_metrics)]
+use linera_base::prometheus_util::{linear_bucket_interval, register_histogram_vec};
+use linera_base::{
+    ensure,
+    time::{timer, Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+#[cfg(with_metrics)]
+use prometheus::HistogramVec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 
 #[cfg(with_metrics)]
@@ -44,15 +92,21 @@ use crate::metering::MeteredStore;
 #[cfg(with_testing)]
 use crate::store::TestKeyValueStore;
 use crate::{
-    batch::SimpleUnorderedBatch,
-    common::get_uleb128_size,
-    journaling::{DirectWritableKeyValueStore, JournalConsistencyError, JournalingKeyValueStore},
-    lru_caching::{LruCachingConfig, LruCachingStore},
+    batch::{Batch, SimpleUnorderedBatch, SimplifiedBatch, UnorderedBatch, WriteOperation},
+    common::{
+        get_internal_key, get_interval, get_uleb128_size, get_upper_bound_option, InternalKeyKind,
+        INTERNAL_TAG,
+    },
+    journaling::{
+        DirectWritableKeyValueStore, JournalConsistencyError, JournalingKeyValueStore, KeyPages,
+    },
+    lru_caching::{LruCachingConfig, LruCachingStore, StorageCacheConfig},
     store::{
         AdminKeyValueStore, CommonStoreInternalConfig, KeyIterable, KeyValueIterable,
         KeyValueStoreError, ReadableKeyValueStore, WithError,
     },
     value_splitting::{ValueSplittingError, ValueSplittingStore},
+    views::MIN_VIEW_TAG,
 };
 
 /// Name of the environment variable with the address to a LocalStack instance.
@@ -70,24 +124,125 @@ fn get_endpoint_address() -> Option<String> {
     env::var(LOCALSTACK_ENDPOINT).ok()
 }
 
-/// Gets the LocalStack config
-async fn get_localstack_config() -> Result<aws_sdk_dynamodb::Config, DynamoDbStoreInternalError> {
+/// Gets the LocalStack config, using the given endpoint or, if none, the one from the
+/// environment.
+async fn get_localstack_config(
+    endpoint: Option<&str>,
+) -> Result<aws_sdk_dynamodb::Config, DynamoDbStoreInternalError> {
     let base_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest())
         .boxed()
         .await;
-    let endpoint_address = get_endpoint_address().unwrap();
+    let endpoint_address = match endpoint {
+        Some(endpoint) => endpoint.to_string(),
+        None => get_endpoint_address().unwrap(),
+    };
     let config = aws_sdk_dynamodb::config::Builder::from(&base_config)
         .endpoint_url(endpoint_address)
         .build();
     Ok(config)
 }
 
+/// Builds the key of the entry of sort key `sort` for the entity `entity`.
+///
+/// The entity is terminated by `[0, 0]`, and its zero bytes are escaped as `[0, 255]`, so
+/// that no encoded entity is a prefix of another one. This way, the lexicographic order of
+/// the keys is the order of the pairs `(entity, sort)`, whatever the lengths of the parts.
+pub fn composite_key(entity: &[u8], sort: &[u8]) -> Vec<u8> {
+    let mut key = composite_entity_prefix(entity);
+    key.extend(sort);
+    key
+}
+
+/// The common prefix of the keys built by [`composite_key`] for the entity `entity`.
+fn composite_entity_prefix(entity: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(entity.len() + 2);
+    for byte in entity {
+        prefix.push(*byte);
+        if *byte == 0 {
+            prefix.push(u8::MAX);
+        }
+    }
+    prefix.extend([0, 0]);
+    prefix
+}
+
+/// Builds the key of the entry of index `index` under `prefix`.
+///
+/// The index is encoded in big-endian, so that the keys sort as their indices. This is
+/// not the case of the BCS encoding of integers (e.g. in `get_journaling_key`), which is
+/// little-endian: `1u32` is encoded as `[1, 0, 0, 0]` and `256u32` as `[0, 1, 0, 0]`.
+pub fn index_key(prefix: &[u8], index: u64) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend(index.to_be_bytes());
+    key
+}
+
+/// Tests whether an error indicates that the backend does not implement the operation, as
+/// is the case of `TransactWriteItems` for some DynamoDB-compatible databases.
+///
+/// Only the `UnknownOperationException` code counts, and not the messages of the other
+/// errors, since a validation error may well mention a feature that is not supported.
+fn is_unsupported_operation<E: ProvideErrorMetadata>(error: &SdkError<E>) -> bool {
+    let SdkError::ServiceError(error) = error else {
+        return false;
+    };
+    error.err().code() == Some("UnknownOperationException")
+}
+
+/// Tests whether an error indicates that the region is unavailable, in which case a
+/// read can be retried in another region.
+fn is_region_failure<E: ProvideErrorMetadata>(error: &SdkError<E>) -> bool {
+    matches!(
+        sdk_error_category(error),
+        ErrorCategory::Throttling | ErrorCategory::ServiceUnavailable
+    )
+}
+
+/// Classifies an error returned by the DynamoDB SDK.
+fn sdk_error_category<E: ProvideErrorMetadata>(error: &SdkError<E>) -> ErrorCategory {
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => {
+            ErrorCategory::ServiceUnavailable
+        }
+        SdkError::ConstructionFailure(_) => ErrorCategory::Validation,
+        _ => error_code_category(error.code()).unwrap_or_else(|| match error.raw_response() {
+            Some(response) if response.status().is_server_error() => {
+                ErrorCategory::ServiceUnavailable
+            }
+            _ => ErrorCategory::Other,
+        }),
+    }
+}
+
+/// Classifies the error codes returned by DynamoDB, if known.
+fn error_code_category(code: Option<&str>) -> Option<ErrorCategory> {
+    match code? {
+        "ThrottlingException"
+        | "ProvisionedThroughputExceededException"
+        | "RequestLimitExceeded"
+        | "LimitExceededException" => Some(ErrorCategory::Throttling),
+        "InternalServerError" | "ServiceUnavailable" => Some(ErrorCategory::ServiceUnavailable),
+        "AccessDeniedException"
+        | "UnrecognizedClientException"
+        | "MissingAuthenticationToken"
+        | "InvalidSignatureException"
+        | "ExpiredTokenException" => Some(ErrorCategory::AccessDenied),
+        "ValidationException" | "SerializationException" => Some(ErrorCategory::Validation),
+        _ => None,
+    }
+}
+
 /// DynamoDB forbids the iteration over the partition keys.
 /// Therefore we use a special partition key named `[1]` for storing
 /// the root keys. For normal root keys, we simply put a `[0]` in
 /// front therefore no intersection is possible.
 const PARTITION_KEY_ROOT_KEY: &[u8] = &[1];
 
+/// The tag in front of the root key in the partition keys of the internal items, when
+/// they are stored apart from the data. See
+/// [`DynamoDbStoreInternalConfig::with_separate_internal_partition`].
+const INTERNAL_PARTITION_TAG: u8 = 2;
+
 /// The attribute name of the partition key.
 const PARTITION_ATTRIBUTE: &str = "item_partition";
 
@@ -97,12 +252,36 @@ const EMPTY_ROOT_KEY: &[u8] = &[0];
 /// A key being used for testing existence of tables
 const DB_KEY: &[u8] = &[0];
 
+/// The key of the schema version of the table, in the partition `PARTITION_KEY_ROOT_KEY`.
+/// The root keys are stored in this partition with a `[0]` in front, so there is no
+/// intersection.
+const SCHEMA_VERSION_KEY: &[u8] = &[1];
+
+/// The version of the layout of the data written by this code. It is increased when the
+/// layout changes in a way that older code cannot read.
+const SCHEMA_VERSION: u32 = 1;
+
 /// The attribute name of the primary key (used as a sort key).
 const KEY_ATTRIBUTE: &str = "item_key";
 
 /// The attribute name of the table value blob.
 const VALUE_ATTRIBUTE: &str = "item_value";
 
+/// The attribute name of the expiry of a lease, in milliseconds since the Unix epoch.
+const LEASE_EXPIRY_ATTRIBUTE: &str = "lease_expiry";
+
+/// The attribute of the expiry of an item set by `touch`, in seconds since the Unix epoch,
+/// which is the format of the time to live of DynamoDB.
+const EXPIRY_ATTRIBUTE: &str = "expiry";
+
+/// The attribute of the time of the last write of an item, in milliseconds since the Unix
+/// epoch, if the store records it.
+const LAST_MODIFIED_ATTRIBUTE: &str = "last_modified";
+
+/// The local secondary index of the tables created with item collection metrics, which
+/// DynamoDB only returns for the tables having such an index.
+const ITEM_COLLECTION_INDEX: &str = "item_collection";
+
 /// The attribute for obtaining the primary key (used as a sort key) with the stored value.
 const KEY_VALUE_ATTRIBUTE: &str = "item_key, item_value";
 
@@ -110,6 +289,20 @@ const KEY_VALUE_ATTRIBUTE: &str = "item_key, item_value";
 /// This is the maximum size of a raw value in DynamoDB.
 const RAW_MAX_VALUE_SIZE: usize = 409600;
 
+/// The version byte starting the values stored with a checksum.
+const VALUE_CHECKSUM_VERSION: u8 = 1;
+
+/// The version byte starting the values stored as they are, below the minimal size of
+/// the transformed values, when checksums are enabled.
+const VALUE_RAW_VERSION: u8 = 0;
+
+/// The default size below which the values are stored as they are.
+const DEFAULT_MIN_TRANSFORM_SIZE: usize = 256;
+
+/// The size of the header of the values stored with a checksum: the version byte
+/// followed by the CRC32C of the value.
+const VALUE_CHECKSUM_HEADER_SIZE: usize = 1 + 4;
+
 /// Fundamental constants in DynamoDB: The maximum size of a value is 400 KB
 /// See https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/ServiceQuotas.html
 /// However, the value being written can also be the serialization of a `SimpleUnorderedBatch`
@@ -121,14 +314,32 @@ const RAW_MAX_VALUE_SIZE: usize = 409600;
 /// Note on the following formula:
 /// * We write 3 because `get_uleb128_size(400*1024) == 3`
 /// * We write `1 + 1` because the `SimpleUnorderedBatch` has two entries
+/// * We also leave room for the checksum header of the value.
 ///
-/// This gets us a maximal value of 408569;
+/// This gets us a maximal value of 408564;
+///
+/// The limit is a constant of the store, so the checksum header is reserved even when the
+/// checksums are disabled. This is a breaking change of the limit: `MAX_VALUE_SIZE` is 5
+/// bytes lower than before the checksums were introduced. The values written through a
+/// `ValueSplittingStore` are split at the new limit transparently, but a value of the
+/// previous maximal size written directly is now rejected.
 const VISIBLE_MAX_VALUE_SIZE: usize = RAW_MAX_VALUE_SIZE
     - MAX_KEY_SIZE
     - get_uleb128_size(RAW_MAX_VALUE_SIZE)
     - get_uleb128_size(MAX_KEY_SIZE)
     - 1
-    - 1;
+    - 1
+    - VALUE_CHECKSUM_HEADER_SIZE;
+
+/// Fundamental constant in DynamoDB: The maximum size of an item is 400 KB, counting the
+/// names of its attributes as well as their values.
+/// See https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/ServiceQuotas.html
+const MAX_ITEM_SIZE: usize = 409600;
+
+/// Fundamental constant in DynamoDB: A number counts for at most 21 bytes in the size of
+/// an item.
+/// See https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/CapacityUnitCalculations.html
+const MAX_NUMBER_SIZE: usize = 21;
 
 /// Fundamental constant in DynamoDB: The maximum size of a key is 1024 bytes
 /// See https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/HowItWorks.NamingRulesDataTypes.html
@@ -149,6 +360,63 @@ const TEST_DYNAMO_DB_MAX_CONCURRENT_QUERIES: usize = 10;
 #[cfg(with_testing)]
 const TEST_DYNAMO_DB_MAX_STREAM_QUERIES: usize = 10;
 
+/// Fundamental constants in DynamoDB: The maximum size of an item collection is 10 GB when
+/// the table has a local secondary index. Above this size, writes to the partition fail.
+/// See <https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/LSI.html#LSI.ItemCollections.SizeLimit>
+const MAX_ITEM_COLLECTION_SIZE_GB: f64 = 10.0;
+
+/// The fraction of `MAX_ITEM_COLLECTION_SIZE_GB` above which a warning is logged.
+const ITEM_COLLECTION_SIZE_WARNING_RATIO: f64 = 0.8;
+
+#[cfg(with_metrics)]
+/// The estimated size of the item collection of the store, in GB.
+static DYNAMO_DB_ITEM_COLLECTION_SIZE: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec(
+        "dynamo_db_item_collection_size",
+        "DynamoDB item collection size estimate (GB)",
+        &[],
+        linear_bucket_interval(0.5, 0.5, MAX_ITEM_COLLECTION_SIZE_GB),
+    )
+});
+
+/// Fundamental constants in DynamoDB: The maximum number of requests in a `BatchWriteItem` is 25.
+/// See <https://docs.aws.amazon.com/amazondynamodb/latest/APIReference/API_BatchWriteItem.html>
+const MAX_BATCH_WRITE_ITEM_SIZE: usize = 25;
+
+/// The initial delay of [`ExponentialJitter`] by default.
+const BATCH_WRITE_MIN_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// The number of rows of the count-min sketch of an [`AccessTracker`].
+const ACCESS_SKETCH_DEPTH: usize = 4;
+
+/// The number of counters in each row of the count-min sketch of an [`AccessTracker`].
+const ACCESS_SKETCH_WIDTH: usize = 1024;
+
+/// The number of most accessed prefixes reported by an [`AccessTracker`].
+const MAX_TRACKED_PREFIXES: usize = 64;
+
+/// The period over which a [`RequestRateMonitor`] counts the requests.
+const REQUEST_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// The minimal delay between two warnings of a [`RequestRateMonitor`].
+const REQUEST_RATE_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The period over which `capacity_headroom` averages the consumed capacity.
+const CONSUMED_CAPACITY_WINDOW: Duration = Duration::from_secs(60);
+
+/// The maximal delay of [`ExponentialJitter`] by default.
+const BATCH_WRITE_MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The delay between two checks of the status of a table being created.
+const TABLE_STATUS_POLL_DELAY: Duration = Duration::from_millis(100);
+
+/// The time after which a table being created is considered to have failed to become
+/// active.
+const TABLE_CREATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The number of attempts of [`DynamoDbStoreInternal::update`] by default.
+const DEFAULT_MAX_UPDATE_ATTEMPTS: usize = 10;
+
 /// Fundamental constants in DynamoDB: The maximum size of a [`TransactWriteItem`] is 100.
 /// See <https://docs.aws.amazon.com/amazondynamodb/latest/APIReference/API_TransactWriteItems.html>
 const MAX_TRANSACT_WRITE_ITEM_SIZE: usize = 100;
@@ -160,6 +428,14 @@ fn extend_root_key(root_key: &[u8]) -> Vec<u8> {
     start_key
 }
 
+/// Returns the partition key of the internal items of `root_key`, when they are stored
+/// apart from the data.
+fn internal_partition_key(root_key: &[u8]) -> Vec<u8> {
+    let mut start_key = vec![INTERNAL_PARTITION_TAG];
+    start_key.extend(root_key);
+    start_key
+}
+
 /// Builds the key attributes for a table item.
 ///
 /// The key is composed of two attributes that are both binary blobs. The first attribute is a
@@ -209,6 +485,50 @@ fn check_key_size(key: &[u8]) -> Result<(), DynamoDbStoreInternalError> {
     Ok(())
 }
 
+/// Returns the size of an item as counted by DynamoDB for its size limit: the lengths of
+/// the attribute names plus the sizes of the attribute values.
+///
+/// Numbers are counted at their maximal size, and the attribute types that the store
+/// does not write (lists and maps) are not supported.
+fn item_size(item: &HashMap<String, AttributeValue>) -> usize {
+    item.iter()
+        .map(|(name, value)| {
+            let value_size = match value {
+                AttributeValue::B(blob) => blob.as_ref().len(),
+                AttributeValue::Bs(blobs) => blobs.iter().map(|blob| blob.as_ref().len()).sum(),
+                AttributeValue::S(string) => string.len(),
+                AttributeValue::N(_) => MAX_NUMBER_SIZE,
+                _ => 1,
+            };
+            name.len() + value_size
+        })
+        .sum()
+}
+
+/// Returns the size, as counted by [`item_size`], of the item built by `build_key_value`
+/// with a partition key of `start_key_len` bytes, a key of `key_len` bytes and a value of
+/// `value_len` bytes.
+fn key_value_item_size(start_key_len: usize, key_len: usize, value_len: usize) -> usize {
+    PARTITION_ATTRIBUTE.len()
+        + start_key_len
+        + KEY_ATTRIBUTE.len()
+        + key_len
+        + VALUE_ATTRIBUTE.len()
+        + value_len
+}
+
+/// Checks that an item, with its key and attribute names, fits in a DynamoDB item.
+fn check_item_size(
+    item: &HashMap<String, AttributeValue>,
+) -> Result<(), DynamoDbStoreInternalError> {
+    let size = item_size(item);
+    ensure!(
+        size <= MAX_ITEM_SIZE,
+        DynamoDbStoreInternalError::ItemTooLarge { size }
+    );
+    Ok(())
+}
+
 /// Extracts the key attribute from an item.
 fn extract_key(
     prefix_len: usize,
@@ -223,9 +543,70 @@ fn extract_key(
     }
 }
 
-/// Extracts the value attribute from an item.
+/// Extracts the key attribute from an item (returned by value).
+fn extract_key_owned(
+    prefix_len: usize,
+    attributes: &mut HashMap<String, AttributeValue>,
+) -> Result<Vec<u8>, DynamoDbStoreInternalError> {
+    let key = attributes
+        .remove(KEY_ATTRIBUTE)
+        .ok_or(DynamoDbStoreInternalError::MissingKey)?;
+    match key {
+        AttributeValue::B(blob) => {
+            let mut key = blob.into_inner();
+            key.drain(..prefix_len);
+            Ok(key)
+        }
+        key => Err(DynamoDbStoreInternalError::wrong_key_type(&key)),
+    }
+}
+
+/// Prepends the checksum header to a value.
+fn add_value_checksum(value: Vec<u8>) -> Vec<u8> {
+    let mut stored_value = Vec::with_capacity(VALUE_CHECKSUM_HEADER_SIZE + value.len());
+    stored_value.push(VALUE_CHECKSUM_VERSION);
+    stored_value.extend(crc32c::crc32c(&value).to_be_bytes());
+    stored_value.extend(value);
+    stored_value
+}
+
+/// Prepends the header of its format to a value: the raw values below
+/// `min_transform_size` only get their version byte, the others a checksum.
+fn add_value_header(value: Vec<u8>, min_transform_size: usize) -> Vec<u8> {
+    if value.len() >= min_transform_size {
+        return add_value_checksum(value);
+    }
+    let mut stored_value = Vec::with_capacity(1 + value.len());
+    stored_value.push(VALUE_RAW_VERSION);
+    stored_value.extend(value);
+    stored_value
+}
+
+/// Checks the header of a stored value, and its checksum if any, and returns the value
+/// without the header.
+fn verify_value_checksum<'a>(
+    attributes: &HashMap<String, AttributeValue>,
+    stored_value: &'a [u8],
+) -> Result<&'a [u8], DynamoDbStoreInternalError> {
+    if let Some((&VALUE_RAW_VERSION, value)) = stored_value.split_first() {
+        return Ok(value);
+    }
+    if stored_value.len() >= VALUE_CHECKSUM_HEADER_SIZE && stored_value[0] == VALUE_CHECKSUM_VERSION
+    {
+        let (header, value) = stored_value.split_at(VALUE_CHECKSUM_HEADER_SIZE);
+        if header[1..] == crc32c::crc32c(value).to_be_bytes() {
+            return Ok(value);
+        }
+    }
+    let key = extract_key(0, attributes).unwrap_or_default().to_vec();
+    Err(DynamoDbStoreInternalError::ChecksumMismatch { key })
+}
+
+/// Extracts the value attribute from an item, checking its checksum if `value_checksums`
+/// is set.
 fn extract_value(
     attributes: &HashMap<String, AttributeValue>,
+    value_checksums: bool,
 ) -> Result<&[u8], DynamoDbStoreInternalError> {
     // According to the official AWS DynamoDB documentation:
     // "Binary must have a length greater than zero if the attribute is used as a key attribute for a table or index"
@@ -233,31 +614,68 @@ fn extract_value(
         .get(VALUE_ATTRIBUTE)
         .ok_or(DynamoDbStoreInternalError::MissingValue)?;
     match value {
+        AttributeValue::B(blob) if value_checksums => {
+            verify_value_checksum(attributes, blob.as_ref())
+        }
         AttributeValue::B(blob) => Ok(blob.as_ref()),
         value => Err(DynamoDbStoreInternalError::wrong_value_type(value)),
     }
 }
 
-/// Extracts the value attribute from an item (returned by value).
+/// Extracts the value attribute from an item (returned by value), checking its checksum
+/// if `value_checksums` is set.
 fn extract_value_owned(
     attributes: &mut HashMap<String, AttributeValue>,
+    value_checksums: bool,
 ) -> Result<Vec<u8>, DynamoDbStoreInternalError> {
     let value = attributes
         .remove(VALUE_ATTRIBUTE)
         .ok_or(DynamoDbStoreInternalError::MissingValue)?;
     match value {
-        AttributeValue::B(blob) => Ok(blob.into_inner()),
+        AttributeValue::B(blob) => {
+            let mut value = blob.into_inner();
+            if value_checksums {
+                let header_len = value.len() - verify_value_checksum(attributes, &value)?.len();
+                value.drain(..header_len);
+            }
+            Ok(value)
+        }
         value => Err(DynamoDbStoreInternalError::wrong_value_type(&value)),
     }
 }
 
+/// Extracts the time of the last write of an item, if it was recorded.
+fn extract_modification_time(
+    attributes: &HashMap<String, AttributeValue>,
+) -> Result<Option<SystemTime>, DynamoDbStoreInternalError> {
+    let Some(value) = attributes.get(LAST_MODIFIED_ATTRIBUTE) else {
+        return Ok(None);
+    };
+    let millis = value
+        .as_n()
+        .ok()
+        .and_then(|millis| millis.parse().ok())
+        .ok_or(DynamoDbStoreInternalError::InvalidModificationTime)?;
+    Ok(Some(UNIX_EPOCH + Duration::from_millis(millis)))
+}
+
+/// Converts a value stored with the wrong attribute type to bytes, if this can be done
+/// without guessing. Only strings are converted, using their UTF-8 encoding.
+fn coerce_value(value: &AttributeValue) -> Option<Vec<u8>> {
+    match value {
+        AttributeValue::S(string) => Some(string.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
 /// Extracts the key and value attributes from an item.
 fn extract_key_value(
     prefix_len: usize,
     attributes: &HashMap<String, AttributeValue>,
+    value_checksums: bool,
 ) -> Result<(&[u8], &[u8]), DynamoDbStoreInternalError> {
     let key = extract_key(prefix_len, attributes)?;
-    let value = extract_value(attributes)?;
+    let value = extract_value(attributes, value_checksums)?;
     Ok((key, value))
 }
 
@@ -265,31 +683,27 @@ fn extract_key_value(
 fn extract_key_value_owned(
     prefix_len: usize,
     attributes: &mut HashMap<String, AttributeValue>,
+    value_checksums: bool,
 ) -> Result<(Vec<u8>, Vec<u8>), DynamoDbStoreInternalError> {
-    let key = extract_key(prefix_len, attributes)?.to_vec();
-    let value = extract_value_owned(attributes)?;
+    // The value is extracted first, so that the key is still there to report a mismatch.
+    let value = extract_value_owned(attributes, value_checksums)?;
+    let key = extract_key_owned(prefix_len, attributes)?;
     Ok((key, value))
 }
 
+#[derive(Default)]
 struct TransactionBuilder {
-    start_key: Vec<u8>,
     transactions: Vec<TransactWriteItem>,
 }
 
 impl TransactionBuilder {
-    fn new(start_key: &[u8]) -> Self {
-        Self {
-            start_key: start_key.to_vec(),
-            transactions: Vec::new(),
-        }
-    }
-
     fn insert_delete_request(
         &mut self,
         key: Vec<u8>,
         store: &DynamoDbStoreInternal,
     ) -> Result<(), DynamoDbStoreInternalError> {
-        let transaction = store.build_delete_transaction(&self.start_key, key)?;
+        store.validate_key(&key)?;
+        let transaction = store.build_delete_transaction(store.partition_of(&key), key)?;
         self.transactions.push(transaction);
         Ok(())
     }
@@ -300,401 +714,4611 @@ impl TransactionBuilder {
         value: Vec<u8>,
         store: &DynamoDbStoreInternal,
     ) -> Result<(), DynamoDbStoreInternalError> {
-        let transaction = store.build_put_transaction(&self.start_key, key, value)?;
+        store.validate_key(&key)?;
+        let start_key = store.partition_of(&key);
+        let size = store.stored_item_size(start_key.len(), key.len(), value.len());
+        ensure!(
+            size <= MAX_ITEM_SIZE,
+            DynamoDbStoreInternalError::ItemTooLarge { size }
+        );
+        let transaction = store.build_put_transaction(start_key, key, value)?;
         self.transactions.push(transaction);
         Ok(())
     }
 }
 
-/// A DynamoDB client.
-#[derive(Clone, Debug)]
-pub struct DynamoDbStoreInternal {
-    client: Client,
-    namespace: String,
-    semaphore: Option<Arc<Semaphore>>,
-    max_stream_queries: usize,
-    start_key: Vec<u8>,
-    root_key_written: Arc<AtomicBool>,
+/// The kind of a key seen by a [`KeyValidator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    /// A key of the user of the store.
+    Key,
+    /// The prefix of a scan of the keys of the user of the store.
+    Prefix,
+    /// A key or the prefix of a scan of the internal data of the store, such as the
+    /// journal, starting with `INTERNAL_TAG`.
+    Internal,
 }
 
-/// The initial configuration of the system.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DynamoDbStoreInternalConfig {
-    /// Whether to use local stack or not.
-    use_localstack: bool,
-    /// The common configuration of the key value store
-    common_config: CommonStoreInternalConfig,
-}
+/// A callback validating the keys before they are sent to DynamoDB.
+///
+/// The validator sees every key read or written through the store, including the keys
+/// of the journal, as well as the prefixes of the scans, with their [`KeyKind`], and
+/// rejects a key by returning a description of the problem.
+#[derive(Clone)]
+pub struct KeyValidator(Arc<KeyValidatorFn>);
 
-impl DynamoDbStoreInternalConfig {
-    async fn client(&self) -> Result<Client, DynamoDbStoreInternalError> {
-        let config = if self.use_localstack {
-            get_localstack_config().await?
-        } else {
-            get_base_config().await?
-        };
-        Ok(Client::from_conf(config))
+type KeyValidatorFn = dyn Fn(&[u8], KeyKind) -> Result<(), String> + Send + Sync;
+
+impl KeyValidator {
+    /// Creates a `KeyValidator` from a closure.
+    pub fn new(
+        validator: impl Fn(&[u8], KeyKind) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(validator))
     }
 }
 
-impl AdminKeyValueStore for DynamoDbStoreInternal {
-    type Config = DynamoDbStoreInternalConfig;
-
-    fn get_name() -> String {
-        "dynamodb internal".to_string()
+impl fmt::Debug for KeyValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyValidator").finish_non_exhaustive()
     }
+}
 
-    async fn connect(
-        config: &Self::Config,
-        namespace: &str,
-    ) -> Result<Self, DynamoDbStoreInternalError> {
-        Self::check_namespace(namespace)?;
-        let client = config.client().await?;
-        let semaphore = config
-            .common_config
-            .max_concurrent_queries
-            .map(|n| Arc::new(Semaphore::new(n)));
-        let max_stream_queries = config.common_config.max_stream_queries;
-        let namespace = namespace.to_string();
-        let start_key = extend_root_key(&[]);
-        let store = Self {
-            client,
-            namespace,
-            semaphore,
-            max_stream_queries,
-            start_key,
-            root_key_written: Arc::new(AtomicBool::new(false)),
-        };
-        Ok(store)
+/// A callback notified of the writes committed by the store, for instance to maintain
+/// a derived index or cache.
+///
+/// The callback is invoked after each successful `write_batch`, once the batch has been
+/// written durably, with the deleted keys and the inserted key-values. It is never
+/// invoked for a failed write. The keys with a tag lower than `MIN_VIEW_TAG` are not
+/// reported: when the store is journaled, the writes of the journal are hidden and the
+/// batch is reported when the journal is resolved.
+///
+/// This is an in-process change feed: the notifications are not persisted and are lost
+/// when the process stops. DynamoDB Streams should be used for a durable change feed.
+#[derive(Clone)]
+pub struct WriteHook(Arc<WriteHookFn>);
+
+type WriteHookFn = dyn Fn(&[Vec<u8>], &[(Vec<u8>, Vec<u8>)]) + Send + Sync;
+
+impl WriteHook {
+    /// Creates a `WriteHook` from a closure taking the deleted keys and the inserted
+    /// key-values.
+    pub fn new(hook: impl Fn(&[Vec<u8>], &[(Vec<u8>, Vec<u8>)]) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
     }
+}
 
-    fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, DynamoDbStoreInternalError> {
-        let client = self.client.clone();
-        let namespace = self.namespace.clone();
-        let semaphore = self.semaphore.clone();
-        let max_stream_queries = self.max_stream_queries;
-        let start_key = extend_root_key(root_key);
-        Ok(Self {
-            client,
-            namespace,
-            semaphore,
-            max_stream_queries,
-            start_key,
-            root_key_written: Arc::new(AtomicBool::new(false)),
-        })
+impl fmt::Debug for WriteHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHook").finish_non_exhaustive()
     }
+}
 
-    async fn list_all(config: &Self::Config) -> Result<Vec<String>, DynamoDbStoreInternalError> {
-        let client = config.client().await?;
-        let mut namespaces = Vec::new();
-        let mut start_table = None;
-        loop {
-            let response = client
-                .list_tables()
-                .set_exclusive_start_table_name(start_table)
-                .send()
-                .boxed()
-                .await?;
-            if let Some(namespaces_blk) = response.table_names {
-                namespaces.extend(namespaces_blk);
-            }
-            if response.last_evaluated_table_name.is_none() {
-                break;
-            } else {
-                start_table = response.last_evaluated_table_name;
-            }
+/// The delays between the retries of the requests that DynamoDB did not fully process,
+/// e.g. the unprocessed items of a throttled `BatchWriteItem`.
+pub trait BackoffStrategy: fmt::Debug + Send + Sync {
+    /// Returns the delay before the retry number `attempt`, counting from 0, or `None`
+    /// to give up.
+    fn next_delay(&self, attempt: usize) -> Option<Duration>;
+}
+
+/// Retries with a delay doubling at every attempt, up to `max_delay`, of which a random
+/// part of up to one half is removed so that the clients throttled together do not
+/// retry together. This is the default strategy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExponentialJitter {
+    /// The delay before the first retry, before the jitter.
+    pub min_delay: Duration,
+    /// The maximal delay before a retry, before the jitter.
+    pub max_delay: Duration,
+    /// The number of retries before giving up, or `None` to retry forever.
+    pub max_attempts: Option<usize>,
+}
+
+impl Default for ExponentialJitter {
+    fn default() -> Self {
+        Self {
+            min_delay: BATCH_WRITE_MIN_RETRY_DELAY,
+            max_delay: BATCH_WRITE_MAX_RETRY_DELAY,
+            max_attempts: None,
         }
-        Ok(namespaces)
     }
+}
 
-    async fn list_root_keys(
-        config: &Self::Config,
-        namespace: &str,
-    ) -> Result<Vec<Vec<u8>>, DynamoDbStoreInternalError> {
-        let mut store = Self::connect(config, namespace).await?;
-        store.start_key = PARTITION_KEY_ROOT_KEY.to_vec();
+impl BackoffStrategy for ExponentialJitter {
+    fn next_delay(&self, attempt: usize) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| attempt >= max_attempts)
+        {
+            return None;
+        }
+        let factor = u32::try_from(attempt)
+            .ok()
+            .and_then(|shift| 1u32.checked_shl(shift))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .min_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        Some(delay.mul_f64(1.0 - rand::random::<f64>() / 2.0))
+    }
+}
 
-        let keys = store.find_keys_by_prefix(EMPTY_ROOT_KEY).await?;
+/// Retries after the same delay every time, which is deterministic, e.g. for tests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedDelay {
+    /// The delay before every retry.
+    pub delay: Duration,
+    /// The number of retries before giving up, or `None` to retry forever.
+    pub max_attempts: Option<usize>,
+}
 
-        let mut root_keys = Vec::new();
-        for key in keys.iterator() {
-            let key = key?;
-            root_keys.push(key.to_vec());
+impl BackoffStrategy for FixedDelay {
+    fn next_delay(&self, attempt: usize) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| attempt >= max_attempts)
+        {
+            return None;
         }
-        Ok(root_keys)
+        Some(self.delay)
     }
+}
 
-    async fn delete_all(config: &Self::Config) -> Result<(), DynamoDbStoreInternalError> {
-        let client = config.client().await?;
-        let tables = Self::list_all(config).await?;
-        for table in tables {
-            client
-                .delete_table()
-                .table_name(&table)
-                .send()
-                .boxed()
-                .await?;
-        }
-        Ok(())
+/// Never retries: the requests that are not fully processed fail with
+/// [`DynamoDbStoreInternalError::RetriesExhausted`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoRetry;
+
+impl BackoffStrategy for NoRetry {
+    fn next_delay(&self, _attempt: usize) -> Option<Duration> {
+        None
     }
+}
 
-    async fn exists(
-        config: &Self::Config,
-        namespace: &str,
-    ) -> Result<bool, DynamoDbStoreInternalError> {
-        Self::check_namespace(namespace)?;
-        let client = config.client().await?;
-        let key_db = build_key(EMPTY_ROOT_KEY, DB_KEY.to_vec());
+/// A failure injected by a [`FaultInjector`] into a request to DynamoDB.
+#[cfg(with_testing)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The request is never sent, as if the process stopped before sending it. The
+    /// request fails with a dispatch failure that the SDK does not retry.
+    Crash,
+    /// The request is not executed and DynamoDB answers with a `ThrottlingException`,
+    /// which the SDK retries.
+    Throttle,
+    /// The request is not executed and DynamoDB answers with an `InternalServerError`,
+    /// which the SDK retries.
+    InternalError,
+}
+
+/// A rule of a [`FaultInjector`]: after `skip` matching requests, the next `count`
+/// matching requests fail with `fault`.
+#[cfg(with_testing)]
+#[derive(Debug)]
+struct FaultRule {
+    operation: Option<String>,
+    skip: usize,
+    count: usize,
+    fault: Fault,
+}
+
+#[cfg(with_testing)]
+#[derive(Debug, Default)]
+struct FaultInjectorState {
+    rules: Vec<FaultRule>,
+    num_injected: usize,
+}
+
+/// Injects failures into the requests of a DynamoDB client, to test how the store
+/// behaves when DynamoDB fails, e.g. when the process crashes in the middle of the
+/// resolution of a journal.
+///
+/// The injector is installed with [`DynamoDbStoreInternal::with_fault_injector`] and is
+/// shared by its clones, so that the rules can be added after the store is connected.
+/// The rules count every attempt, including the retries made by the SDK, from the moment
+/// they are added.
+#[cfg(with_testing)]
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector(Arc<Mutex<FaultInjectorState>>);
+
+#[cfg(with_testing)]
+impl FaultInjector {
+    /// Creates a `FaultInjector` without any rule.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the `n`-th next request of the operation fail with `fault`, counting from 1.
+    /// The operation is named as in the DynamoDB API, e.g. `"TransactWriteItems"`.
+    pub fn fail_nth(&self, operation: &str, n: usize, fault: Fault) {
+        assert!(n > 0, "the requests are counted from 1");
+        self.add_rule(FaultRule {
+            operation: Some(operation.to_string()),
+            skip: n - 1,
+            count: 1,
+            fault,
+        });
+    }
+
+    /// Makes the next `count` requests of any operation fail with `fault`.
+    pub fn fail_first(&self, count: usize, fault: Fault) {
+        self.add_rule(FaultRule {
+            operation: None,
+            skip: 0,
+            count,
+            fault,
+        });
+    }
+
+    /// Returns the number of failures injected so far.
+    pub fn num_injected(&self) -> usize {
+        self.0.lock().unwrap().num_injected
+    }
+
+    fn add_rule(&self, rule: FaultRule) {
+        self.0.lock().unwrap().rules.push(rule);
+    }
+
+    /// Returns the fault to inject into the next request of the operation, if any. The
+    /// first matching rule applies, but the request counts for all the matching rules.
+    fn next_fault(&self, operation: &str) -> Option<Fault> {
+        let mut state = self.0.lock().unwrap();
+        let mut fault = None;
+        for rule in &mut state.rules {
+            if rule
+                .operation
+                .as_deref()
+                .is_some_and(|name| name != operation)
+            {
+                continue;
+            }
+            if rule.skip > 0 {
+                rule.skip -= 1;
+            } else if rule.count > 0 {
+                rule.count -= 1;
+                fault = fault.or(Some(rule.fault));
+            }
+        }
+        state.rules.retain(|rule| rule.count > 0);
+        if fault.is_some() {
+            state.num_injected += 1;
+        }
+        fault
+    }
+}
+
+/// The fault injected into the current attempt, passed from `modify_before_signing` to
+/// `modify_before_deserialization`.
+#[cfg(with_testing)]
+#[derive(Debug)]
+struct InjectedFault(Option<Fault>);
+
+#[cfg(with_testing)]
+impl Storable for InjectedFault {
+    type Storer = StoreReplace<Self>;
+}
+
+#[cfg(with_testing)]
+impl Intercept for FaultInjector {
+    fn name(&self) -> &'static str {
+        "FaultInjector"
+    }
+
+    fn modify_before_signing(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let request = context.request_mut();
+        // The target is of the form `DynamoDB_20120810.TransactWriteItems`.
+        let operation = request
+            .headers()
+            .get("x-amz-target")
+            .and_then(|target| target.rsplit('.').next())
+            .unwrap_or_default()
+            .to_string();
+        let fault = self.next_fault(&operation);
+        if fault == Some(Fault::Crash) {
+            return Err(format!("injected crash before sending {operation}").into());
+        }
+        if fault.is_some() {
+            // The request is replaced by one without any effect, whose response is then
+            // replaced by the error.
+            *request.body_mut() = SdkBody::from("{}");
+            request.headers_mut().insert("content-length", "2");
+        }
+        cfg.interceptor_state().store_put(InjectedFault(fault));
+        Ok(())
+    }
+
+    fn modify_before_deserialization(
+        &self,
+        context: &mut BeforeDeserializationInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (status, error_type) = match cfg.load::<InjectedFault>() {
+            Some(InjectedFault(Some(Fault::Throttle))) => (400, "ThrottlingException"),
+            Some(InjectedFault(Some(Fault::InternalError))) => (500, "InternalServerError"),
+            _ => return Ok(()),
+        };
+        let body = format!(
+            "{{\"__type\":\"com.amazonaws.dynamodb.v20120810#{error_type}\",\
+             \"message\":\"injected {error_type}\"}}"
+        );
+        let mut response = HttpResponse::new(
+            status.try_into().expect("valid status code"),
+            SdkBody::from(body),
+        );
+        response
+            .headers_mut()
+            .insert("content-type", "application/x-amz-json-1.0");
+        *context.response_mut() = response;
+        Ok(())
+    }
+}
+
+/// A DynamoDB client.
+#[derive(Clone, Debug)]
+pub struct DynamoDbStoreInternal {
+    client: Client,
+    namespace: String,
+    semaphore: Option<Arc<Semaphore>>,
+    max_stream_queries: usize,
+    start_key: Vec<u8>,
+    /// The partition key of the internal items, if they are stored apart from the data.
+    internal_start_key: Option<Vec<u8>>,
+    allow_empty_key_prefix: bool,
+    root_key_written: Arc<AtomicBool>,
+    key_validator: Option<KeyValidator>,
+    write_hook: Option<WriteHook>,
+    backoff: Arc<dyn BackoffStrategy>,
+    read_repair: bool,
+    return_item_collection_metrics: bool,
+    fallback_clients: Vec<Client>,
+    transactions_supported: Arc<AtomicBool>,
+    query_page_size: Option<i32>,
+    inflight_bytes: Option<Arc<InflightBytesBudget>>,
+    value_checksums: bool,
+    consumed_capacity: Arc<ConsumedCapacityTracker>,
+    access_tracker: Option<Arc<AccessTracker>>,
+    request_rate_monitor: Option<Arc<RequestRateMonitor>>,
+    max_recovery_jitter: Duration,
+    reject_conflicting_operations: bool,
+    record_modification_times: bool,
+    malformed_items: MalformedItems,
+    max_update_attempts: usize,
+    separate_internal_partition: bool,
+    batch_dump: BatchDump,
+    min_transform_size: usize,
+    /// The features of the backend, configured or probed once.
+    capabilities: Arc<OnceCell<Capabilities>>,
+}
+
+/// The capacity units consumed by the recent requests of the stores sharing a client.
+#[derive(Debug, Default)]
+struct ConsumedCapacityTracker {
+    reads: Mutex<VecDeque<(Instant, f64)>>,
+    writes: Mutex<VecDeque<(Instant, f64)>>,
+}
+
+impl ConsumedCapacityTracker {
+    /// Records the read capacity consumed by a request.
+    fn record_read(&self, capacity: Option<&ConsumedCapacity>) {
+        let units = capacity.and_then(ConsumedCapacity::capacity_units);
+        Self::record(&self.reads, units.unwrap_or(0.0));
+    }
+
+    /// Records the write capacity consumed by a request, over all the tables it wrote to.
+    fn record_write(&self, capacities: &[ConsumedCapacity]) {
+        let units = capacities
+            .iter()
+            .filter_map(ConsumedCapacity::capacity_units);
+        Self::record(&self.writes, units.sum());
+    }
+
+    fn record(samples: &Mutex<VecDeque<(Instant, f64)>>, units: f64) {
+        if units > 0.0 {
+            let mut samples = samples.lock().unwrap();
+            Self::prune(&mut samples);
+            samples.push_back((Instant::now(), units));
+        }
+    }
+
+    /// Removes the samples older than `CONSUMED_CAPACITY_WINDOW`.
+    fn prune(samples: &mut VecDeque<(Instant, f64)>) {
+        while samples
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() > CONSUMED_CAPACITY_WINDOW)
+        {
+            samples.pop_front();
+        }
+    }
+
+    /// Returns the average number of capacity units consumed per second over the window.
+    fn units_per_second(samples: &Mutex<VecDeque<(Instant, f64)>>) -> f64 {
+        let mut samples = samples.lock().unwrap();
+        Self::prune(&mut samples);
+        let units = samples.iter().map(|(_, units)| units).sum::<f64>();
+        units / CONSUMED_CAPACITY_WINDOW.as_secs_f64()
+    }
+}
+
+/// Counts the reads of the stores sharing a client, per key prefix, in a fixed amount of
+/// memory.
+///
+/// The counts are estimated with a count-min sketch, which may overestimate them but
+/// never underestimates them, and only the most accessed prefixes are kept for the
+/// report. The counts are halved at the end of every window, so that they reflect the
+/// recent accesses.
+#[derive(Debug)]
+struct AccessTracker {
+    prefix_len: usize,
+    window: Duration,
+    hashers: [RandomState; ACCESS_SKETCH_DEPTH],
+    state: Mutex<AccessTrackerState>,
+}
+
+#[derive(Debug)]
+struct AccessTrackerState {
+    sketch: Vec<[u64; ACCESS_SKETCH_WIDTH]>,
+    /// The estimated counts of the most accessed prefixes.
+    top_prefixes: HashMap<Vec<u8>, u64>,
+    window_start: Instant,
+}
+
+impl AccessTracker {
+    fn new(prefix_len: usize, window: Duration) -> Self {
+        let state = AccessTrackerState {
+            sketch: vec![[0; ACCESS_SKETCH_WIDTH]; ACCESS_SKETCH_DEPTH],
+            top_prefixes: HashMap::new(),
+            window_start: Instant::now(),
+        };
+        Self {
+            prefix_len,
+            window,
+            hashers: Default::default(),
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Records a read of `key`.
+    fn record(&self, key: &[u8]) {
+        let prefix = &key[..key.len().min(self.prefix_len)];
+        let mut state = self.state.lock().unwrap();
+        if state.window_start.elapsed() >= self.window {
+            state.window_start = Instant::now();
+            for counter in state.sketch.iter_mut().flatten() {
+                *counter /= 2;
+            }
+            state.top_prefixes.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+        }
+        let mut count = u64::MAX;
+        for (row, hasher) in state.sketch.iter_mut().zip(&self.hashers) {
+            let counter = &mut row[hasher.hash_one(prefix) as usize % ACCESS_SKETCH_WIDTH];
+            *counter += 1;
+            count = count.min(*counter);
+        }
+        if let Some(top_count) = state.top_prefixes.get_mut(prefix) {
+            *top_count = count;
+        } else if state.top_prefixes.len() < MAX_TRACKED_PREFIXES {
+            state.top_prefixes.insert(prefix.to_vec(), count);
+        } else {
+            let (least_prefix, least_count) = state
+                .top_prefixes
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(prefix, count)| (prefix.clone(), *count))
+                .expect("the top prefixes are not empty");
+            if count > least_count {
+                state.top_prefixes.remove(&least_prefix);
+                state.top_prefixes.insert(prefix.to_vec(), count);
+            }
+        }
+    }
+
+    /// Returns the most accessed prefixes with their estimated counts, the most accessed
+    /// first.
+    fn stats(&self) -> Vec<(Vec<u8>, u64)> {
+        let state = self.state.lock().unwrap();
+        let mut stats = state
+            .top_prefixes
+            .iter()
+            .map(|(prefix, count)| (prefix.clone(), *count))
+            .collect::<Vec<_>>();
+        stats.sort_by(|(prefix1, count1), (prefix2, count2)| {
+            count2.cmp(count1).then_with(|| prefix1.cmp(prefix2))
+        });
+        stats
+    }
+}
+
+/// Counts the requests of a store to its partition over windows of one second, to warn
+/// about a rate that DynamoDB may throttle.
+#[derive(Debug)]
+struct RequestRateMonitor {
+    max_reads_per_second: u64,
+    max_writes_per_second: u64,
+    state: Mutex<RequestRateState>,
+}
+
+#[derive(Debug)]
+struct RequestRateState {
+    window_start: Instant,
+    reads: u64,
+    writes: u64,
+    last_warning: Option<Instant>,
+}
+
+impl RequestRateMonitor {
+    fn new(max_reads_per_second: u64, max_writes_per_second: u64) -> Self {
+        let state = RequestRateState {
+            window_start: Instant::now(),
+            reads: 0,
+            writes: 0,
+            last_warning: None,
+        };
+        Self {
+            max_reads_per_second,
+            max_writes_per_second,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Records `reads` read requests and `writes` written items. Returns the counts of
+    /// the current window if they exceed a limit and no warning was due recently.
+    fn record(&self, reads: u64, writes: u64) -> Option<(u64, u64)> {
+        let mut state = self.state.lock().unwrap();
+        if state.window_start.elapsed() >= REQUEST_RATE_WINDOW {
+            state.window_start = Instant::now();
+            state.reads = 0;
+            state.writes = 0;
+        }
+        state.reads += reads;
+        state.writes += writes;
+        let exceeded =
+            state.reads > self.max_reads_per_second || state.writes > self.max_writes_per_second;
+        let warning_due = state
+            .last_warning
+            .is_none_or(|time| time.elapsed() >= REQUEST_RATE_WARNING_INTERVAL);
+        if !exceeded || !warning_due {
+            return None;
+        }
+        state.last_warning = Some(Instant::now());
+        Some((state.reads, state.writes))
+    }
+}
+
+/// An estimate of how close a table is to its provisioned capacity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapacityHeadroom {
+    /// The provisioned read capacity units per second, if the table is provisioned.
+    pub provisioned_read_units: Option<i64>,
+    /// The provisioned write capacity units per second, if the table is provisioned.
+    pub provisioned_write_units: Option<i64>,
+    /// The read capacity units consumed per second by this process over the last minute.
+    pub consumed_read_units: f64,
+    /// The write capacity units consumed per second by this process over the last minute.
+    pub consumed_write_units: f64,
+}
+
+impl CapacityHeadroom {
+    /// Returns the percentage of the provisioned read capacity in use, if provisioned.
+    pub fn read_utilization_percent(&self) -> Option<f64> {
+        utilization_percent(self.consumed_read_units, self.provisioned_read_units)
+    }
+
+    /// Returns the percentage of the provisioned write capacity in use, if provisioned.
+    pub fn write_utilization_percent(&self) -> Option<f64> {
+        utilization_percent(self.consumed_write_units, self.provisioned_write_units)
+    }
+}
+
+fn utilization_percent(consumed_units: f64, provisioned_units: Option<i64>) -> Option<f64> {
+    let provisioned_units = provisioned_units.filter(|units| *units > 0)?;
+    Some(100.0 * consumed_units / provisioned_units as f64)
+}
+
+/// A DynamoDB constraint violated by an operation of a batch, as found by
+/// [`DynamoDbStoreInternal::validate_batch`]. The `index` is the position of the
+/// operation in the batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchViolation {
+    /// The key is empty.
+    EmptyKey {
+        /// The position of the operation.
+        index: usize,
+    },
+    /// The key is longer than the 1024 bytes allowed by DynamoDB.
+    KeyTooLong {
+        /// The position of the operation.
+        index: usize,
+        /// The length of the key.
+        length: usize,
+    },
+    /// The value, once encoded, is larger than an attribute value can be.
+    ValueTooLarge {
+        /// The position of the operation.
+        index: usize,
+        /// The length of the encoded value.
+        length: usize,
+    },
+    /// The item written, with its key and attributes, is larger than a DynamoDB item can
+    /// be.
+    ItemTooLarge {
+        /// The position of the operation.
+        index: usize,
+        /// The size of the item.
+        size: usize,
+    },
+    /// The key, or the deleted key prefix, overlaps the keys reserved for the internal
+    /// use of the store, whose tag is lower than `MIN_VIEW_TAG`.
+    ReservedKey {
+        /// The position of the operation.
+        index: usize,
+    },
+    /// The key is rejected by the key validator of the store.
+    RejectedKey {
+        /// The position of the operation.
+        index: usize,
+        /// The reason given by the key validator.
+        reason: String,
+    },
+}
+
+/// The report of [`DynamoDbStoreInternal::validate_batch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchValidation {
+    /// The violations found, in the order of the operations.
+    pub violations: Vec<BatchViolation>,
+    /// Whether the batch can be written in a single transaction, without the journal.
+    pub fits_fastpath: bool,
+    /// The number of deletions of key prefixes, which are not expanded by the validation.
+    pub prefix_deletions: usize,
+}
+
+impl BatchValidation {
+    /// Returns whether no violation was found.
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The unit in which the budget of bytes in flight is counted, so that a request only
+/// acquires a few permits.
+const INFLIGHT_BYTES_UNIT: usize = 1024;
+
+/// A budget of bytes shared by the requests in flight, in units of
+/// [`INFLIGHT_BYTES_UNIT`] bytes.
+#[derive(Debug)]
+struct InflightBytesBudget {
+    semaphore: Semaphore,
+    /// Held while a request acquires its units one by one, so that two requests never
+    /// wait for the units held by each other.
+    acquiring: async_lock::Mutex<()>,
+    max_units: usize,
+}
+
+impl InflightBytesBudget {
+    fn new(max_bytes: usize) -> Self {
+        let max_units = max_bytes.div_ceil(INFLIGHT_BYTES_UNIT).max(1);
+        Self {
+            semaphore: Semaphore::new(max_units),
+            acquiring: async_lock::Mutex::new(()),
+            max_units,
+        }
+    }
+
+    /// Reserves `num_bytes`, or the whole budget if it is smaller, until the returned
+    /// permit is dropped.
+    async fn reserve(&self, num_bytes: usize) -> InflightBytesPermit<'_> {
+        let units = num_bytes.div_ceil(INFLIGHT_BYTES_UNIT).min(self.max_units);
+        // The units acquired so far are released if the reservation is canceled.
+        let mut permit = InflightBytesPermit {
+            semaphore: &self.semaphore,
+            units: 0,
+        };
+        let _acquiring = self.acquiring.lock().await;
+        while permit.units < units {
+            self.semaphore.acquire().await.forget();
+            permit.units += 1;
+        }
+        permit
+    }
+}
+
+/// The units of an [`InflightBytesBudget`] reserved by a request, released when dropped.
+struct InflightBytesPermit<'a> {
+    semaphore: &'a Semaphore,
+    units: usize,
+}
+
+impl Drop for InflightBytesPermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.units);
+    }
+}
+
+/// The initial configuration of the system.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamoDbStoreInternalConfig {
+    /// Whether to use local stack or not.
+    use_localstack: bool,
+    /// The address of the LocalStack instance. If none, it is read from the
+    /// `LOCALSTACK_ENDPOINT` environment variable.
+    #[serde(default)]
+    localstack_endpoint: Option<String>,
+    /// The common configuration of the key value store
+    common_config: CommonStoreInternalConfig,
+    /// Whether a missing table is created, which requires the `CreateTable` permission.
+    #[serde(default = "default_create_if_missing")]
+    create_if_missing: bool,
+    /// The regions of the replicas of a global table used for reads when the primary
+    /// region is unavailable.
+    #[serde(default)]
+    fallback_regions: Vec<String>,
+    /// Whether the backend supports `TransactWriteItems`.
+    #[serde(default = "default_transactions_supported")]
+    transactions_supported: bool,
+    /// Whether to check the schema version of the table when connecting.
+    #[serde(default)]
+    check_schema_version: bool,
+    /// The maximum number of items returned by each page of a query, if any.
+    #[serde(default)]
+    query_page_size: Option<i32>,
+    /// The maximum number of bytes of the multi-key reads and bulk writes in flight, if any.
+    #[serde(default)]
+    max_inflight_bytes: Option<usize>,
+    /// Whether the values are stored with a checksum, verified when they are read.
+    #[serde(default)]
+    value_checksums: bool,
+    /// The regions that the clients may connect to, if restricted.
+    #[serde(default)]
+    allowed_regions: Option<Vec<String>>,
+    /// The maximal random delay before resolving a journal left over by a crash.
+    #[serde(default)]
+    max_recovery_jitter: Duration,
+    /// Whether the batches deleting and then inserting the same key are rejected.
+    #[serde(default)]
+    reject_conflicting_operations: bool,
+    /// Whether the time of the last write is stored with every item.
+    #[serde(default)]
+    record_modification_times: bool,
+    /// What the prefix scans do with the items that cannot be read.
+    #[serde(default)]
+    malformed_items: MalformedItems,
+    /// The maximal number of read-modify-write cycles of an `update`.
+    #[serde(default = "default_max_update_attempts")]
+    max_update_attempts: usize,
+    /// Whether the internal items are stored in a partition apart from the data.
+    #[serde(default)]
+    separate_internal_partition: bool,
+    /// Whether the prefix scans accept an empty prefix, reading all the key-values.
+    #[serde(default)]
+    allow_empty_key_prefix: bool,
+    /// When the operations of the write requests are logged, for debugging.
+    #[serde(default)]
+    batch_dump: BatchDump,
+    /// The size below which the values are stored without a checksum.
+    #[serde(default = "default_min_transform_size")]
+    min_transform_size: usize,
+    /// The features of the backend, or `None` to probe them when first needed.
+    #[serde(default)]
+    capabilities: Option<Capabilities>,
+    /// Whether the writes request the size estimate of the item collection.
+    #[serde(default)]
+    item_collection_metrics: bool,
+}
+
+/// What the prefix scans of a [`DynamoDbStoreInternal`] do with the items whose key, or
+/// value, cannot be read, e.g. because an attribute is missing or of the wrong type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MalformedItems {
+    /// The iterator of the scan fails on the malformed item.
+    #[default]
+    Strict,
+    /// The malformed items are logged and skipped, and the iterator of the scan only
+    /// yields the well-formed items. The number of items skipped is reported by the
+    /// result of the scan, and the items can be fixed with `repair_values`.
+    Skip,
+}
+
+/// When a [`DynamoDbStoreInternal`] logs the operations of its write requests, for
+/// debugging. Only the kinds of the operations and the sizes of their keys and values are
+/// logged, never the bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchDump {
+    /// Nothing is logged.
+    #[default]
+    Off,
+    /// The operations of the failed requests are logged as warnings.
+    OnFailure,
+    /// The operations of all the requests are logged, the successful ones at the debug
+    /// level.
+    Always,
+}
+
+/// The shape of an operation of a write request, as logged by [`BatchDump`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct WriteItemSummary {
+    /// The kind of the operation: `Put`, `Delete`, `Update` or `ConditionCheck`.
+    operation: &'static str,
+    /// The length of the key, partition excluded.
+    key_length: usize,
+    /// The length of the written value, if any.
+    value_length: Option<usize>,
+}
+
+/// Returns the length of the binary attribute `name` of `attributes`, if present.
+fn binary_attribute_length(
+    attributes: &HashMap<String, AttributeValue>,
+    name: &str,
+) -> Option<usize> {
+    match attributes.get(name) {
+        Some(AttributeValue::B(blob)) => Some(blob.as_ref().len()),
+        _ => None,
+    }
+}
+
+/// Describes the operations of a write request without their keys and values.
+fn summarize_write_items(items: &[TransactWriteItem]) -> Vec<WriteItemSummary> {
+    items
+        .iter()
+        .map(|item| {
+            let (operation, key, value) = if let Some(put) = &item.put {
+                ("Put", Some(&put.item), Some(&put.item))
+            } else if let Some(delete) = &item.delete {
+                ("Delete", Some(&delete.key), None)
+            } else if let Some(update) = &item.update {
+                ("Update", Some(&update.key), None)
+            } else if let Some(check) = &item.condition_check {
+                ("ConditionCheck", Some(&check.key), None)
+            } else {
+                ("Unknown", None, None)
+            };
+            WriteItemSummary {
+                operation,
+                key_length: key
+                    .and_then(|key| binary_attribute_length(key, KEY_ATTRIBUTE))
+                    .unwrap_or_default(),
+                value_length: value
+                    .and_then(|value| binary_attribute_length(value, VALUE_ATTRIBUTE)),
+            }
+        })
+        .collect()
+}
+
+fn default_transactions_supported() -> bool {
+    true
+}
+
+fn default_create_if_missing() -> bool {
+    true
+}
+
+fn default_max_update_attempts() -> usize {
+    DEFAULT_MAX_UPDATE_ATTEMPTS
+}
+
+fn default_min_transform_size() -> usize {
+    DEFAULT_MIN_TRANSFORM_SIZE
+}
+
+impl DynamoDbStoreInternalConfig {
+    async fn client(&self) -> Result<Client, DynamoDbStoreInternalError> {
+        let config = if self.use_localstack {
+            get_localstack_config(self.localstack_endpoint.as_deref()).await?
+        } else {
+            get_base_config().await?
+        };
+        self.check_region(config.region())?;
+        Ok(Client::from_conf(config))
+    }
+
+    /// Checks that `region` is one of the allowed regions, if they are restricted.
+    fn check_region(&self, region: Option<&Region>) -> Result<(), DynamoDbStoreInternalError> {
+        let Some(allowed_regions) = &self.allowed_regions else {
+            return Ok(());
+        };
+        let region = region.map(|region| region.to_string());
+        ensure!(
+            region
+                .as_ref()
+                .is_some_and(|region| allowed_regions.contains(region)),
+            DynamoDbStoreInternalError::RegionNotAllowed { region }
+        );
+        Ok(())
+    }
+
+    async fn fallback_clients(&self) -> Result<Vec<Client>, DynamoDbStoreInternalError> {
+        let mut clients = Vec::new();
+        for region in &self.fallback_regions {
+            let config = if self.use_localstack {
+                get_localstack_config(self.localstack_endpoint.as_deref()).await?
+            } else {
+                get_base_config().await?
+            };
+            let config = config
+                .to_builder()
+                .region(Region::new(region.clone()))
+                .build();
+            self.check_region(config.region())?;
+            clients.push(Client::from_conf(config));
+        }
+        Ok(clients)
+    }
+
+    /// Connects to the LocalStack instance at `endpoint`, instead of the one given by the
+    /// `LOCALSTACK_ENDPOINT` environment variable.
+    pub fn with_localstack_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.use_localstack = true;
+        self.localstack_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets whether connecting to a table fails with `SchemaVersionMismatch` if the table
+    /// was written with a newer layout than the one of this code. Tables written before
+    /// the schema version was introduced are accepted.
+    pub fn with_check_schema_version(mut self, check_schema_version: bool) -> Self {
+        self.check_schema_version = check_schema_version;
+        self
+    }
+
+    /// Sets the maximum number of items read by each page of the prefix queries.
+    ///
+    /// By default, DynamoDB returns pages of up to 1 MB. Smaller pages reduce the memory
+    /// used and the capacity consumed by queries that are not read entirely, at the cost
+    /// of more requests for the queries that are.
+    pub fn with_query_page_size(mut self, query_page_size: Option<i32>) -> Self {
+        self.query_page_size = query_page_size;
+        self
+    }
+
+    /// Sets the maximum number of bytes of the multi-key reads and bulk writes in flight.
+    ///
+    /// This bounds the memory used by the responses of large requests, in addition to the
+    /// bound on the number of concurrent queries. Since the size of a value is not known
+    /// before it is read, each read of `read_multi_values_bytes` counts as the maximal
+    /// size of a value. A single request larger than the budget is still sent, alone. The
+    /// bytes are counted in KiB, rounded up.
+    pub fn with_max_inflight_bytes(mut self, max_inflight_bytes: Option<usize>) -> Self {
+        self.max_inflight_bytes = max_inflight_bytes;
+        self
+    }
+
+    /// Sets whether the values are stored with a checksum, verified when they are read.
+    ///
+    /// Each value, unless it is smaller than [`Self::with_min_transform_size`], is then
+    /// prefixed by a version byte and its CRC32C, and reading a value whose checksum does
+    /// not match fails with
+    /// [`DynamoDbStoreInternalError::ChecksumMismatch`]. This also covers the blocks of
+    /// the journal. Since values written without a checksum have no header, this must not
+    /// be enabled on a table that already contains data written without it.
+    pub fn with_value_checksums(mut self, value_checksums: bool) -> Self {
+        self.value_checksums = value_checksums;
+        self
+    }
+
+    /// Sets whether the backend supports `TransactWriteItems`.
+    ///
+    /// If not, the batches are written with non-atomic `BatchWriteItem` requests, which
+    /// gives up the atomicity of `write_batch` and thus the crash-safety of the journal.
+    /// This is also done after a `TransactWriteItems` request fails because the backend
+    /// does not implement it, as is the case of some DynamoDB-compatible databases.
+    /// Conditional writes such as `write_batch_with_version` always require transactions.
+    pub fn with_transactions_supported(mut self, transactions_supported: bool) -> Self {
+        self.transactions_supported = transactions_supported;
+        self
+    }
+
+    /// Sets the regions of the replicas used for reads when the primary region is
+    /// unavailable.
+    ///
+    /// The table must be a global table replicated in these regions. Since the
+    /// replication is asynchronous, a read served by a replica may not reflect the latest
+    /// writes to the primary region, so this is only suitable for disaster recovery.
+    /// Writes are always sent to the primary region.
+    pub fn with_fallback_regions(mut self, fallback_regions: Vec<String>) -> Self {
+        self.fallback_regions = fallback_regions;
+        self
+    }
+
+    /// Sets whether a missing table is created. If not, the table is assumed to be
+    /// managed separately: connecting to a missing table fails with `TableNotFound`, and
+    /// deleting a table, e.g. in `recreate_and_connect`, fails with
+    /// `TableDeletionDisabled`.
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Restricts the regions that the clients may connect to, or lifts the restriction
+    /// with `None`.
+    ///
+    /// The region resolved from the environment and the fallback regions are checked
+    /// whenever a client is created, before any request is sent, and a region that is
+    /// not allowed fails with [`DynamoDbStoreInternalError::RegionNotAllowed`]. This
+    /// catches a misconfiguration, such as a missing region setting, before any data is
+    /// written to the wrong region.
+    pub fn with_allowed_regions(mut self, allowed_regions: Option<Vec<String>>) -> Self {
+        self.allowed_regions = allowed_regions;
+        self
+    }
+
+    /// Sets the maximal random delay before `clear_journal` resolves a journal left over
+    /// by a crash. Zero, the default, resolves it immediately.
+    ///
+    /// This is meant for fleets of processes sharing a table: after they restart
+    /// together, the jitter spreads the recovery of their journals over time instead of
+    /// throttling the table. Only the stores with a journal to resolve are delayed. When
+    /// the writers also hold the lease of their root key, the lease should be acquired
+    /// with a time-to-live exceeding the jitter, since it is held while waiting.
+    pub fn with_max_recovery_jitter(mut self, max_recovery_jitter: Duration) -> Self {
+        self.max_recovery_jitter = max_recovery_jitter;
+        self
+    }
+
+    /// Sets whether the batches deleting a key and then inserting it again are rejected
+    /// with [`DynamoDbStoreInternalError::ConflictingSameKeyOps`].
+    ///
+    /// By default, such batches are simplified into the final insertion, since a
+    /// transaction cannot operate twice on the same key. This is the intended result for
+    /// most batches, but a caller relying on the deletion taking effect first, e.g. to
+    /// clear the other attributes of the item, should write the deletion and the
+    /// insertion in two batches, which the strict mode enforces.
+    pub fn with_reject_conflicting_operations(mut self, reject: bool) -> Self {
+        self.reject_conflicting_operations = reject;
+        self
+    }
+
+    /// Sets whether the time of the last write is stored with every item written, as a
+    /// numeric attribute, and read by [`DynamoDbStoreInternal::read_key_with_metadata`].
+    ///
+    /// The attribute adds about 20 bytes to every item, which is not counted in the
+    /// maximal size of the values. The items written while it is disabled have no time.
+    pub fn with_record_modification_times(mut self, record: bool) -> Self {
+        self.record_modification_times = record;
+        self
+    }
+
+    /// Sets what the prefix scans do with the items that cannot be read. The default,
+    /// [`MalformedItems::Strict`], fails the scan.
+    pub fn with_malformed_items(mut self, malformed_items: MalformedItems) -> Self {
+        self.malformed_items = malformed_items;
+        self
+    }
+
+    /// Sets the maximal number of read-modify-write cycles of
+    /// [`DynamoDbStoreInternal::update`] before it fails with
+    /// [`DynamoDbStoreInternalError::UpdateConflict`]. The default is 10.
+    pub fn with_max_update_attempts(mut self, max_update_attempts: usize) -> Self {
+        self.max_update_attempts = max_update_attempts;
+        self
+    }
+
+    /// Sets whether the internal items of each root key, such as the journal and the
+    /// lease, are stored in a DynamoDB partition of their own rather than next to the
+    /// data, so that a scan of the whole data of a root key never reads them.
+    ///
+    /// This changes where the internal items are looked up, and the items written with
+    /// the other setting are neither moved nor detected, so the setting must be the same
+    /// for all the processes using a table, for its whole life. Toggling it strands the
+    /// internal items already written: a pending journal is never resolved, leaving its
+    /// batch half applied, the tombstones of `mark-and-sweep` no longer hide the keys
+    /// under the deleted prefixes, and a held lease is no longer seen, so that another
+    /// writer can acquire it.
+    pub fn with_separate_internal_partition(mut self, separate: bool) -> Self {
+        self.separate_internal_partition = separate;
+        self
+    }
+
+    /// Sets whether the prefix scans accept an empty prefix, which reads all the
+    /// key-values of the root key. By default, an empty prefix is rejected with
+    /// [`DynamoDbStoreInternalError::ZeroLengthKeyPrefix`], so that a full scan is never
+    /// made by accident.
+    ///
+    /// The internal items, such as the journal, are part of the result unless they are
+    /// stored apart, see [`Self::with_separate_internal_partition`].
+    pub fn with_allow_empty_key_prefix(mut self, allow: bool) -> Self {
+        self.allow_empty_key_prefix = allow;
+        self
+    }
+
+    /// Sets when the operations of the write requests are logged, which helps diagnose
+    /// the requests rejected by DynamoDB, e.g. for exceeding a limit. The default,
+    /// [`BatchDump::Off`], logs nothing.
+    pub fn with_batch_dump(mut self, batch_dump: BatchDump) -> Self {
+        self.batch_dump = batch_dump;
+        self
+    }
+
+    /// Sets the size below which the values are stored as they are, with a single
+    /// version byte, when [`Self::with_value_checksums`] is enabled. The checksum header
+    /// is larger than many small values, while their corruption is unlikely. The default
+    /// is 256 bytes, and 0 stores every value with a checksum.
+    ///
+    /// Values of both formats are read whatever the setting. However, a conditional write
+    /// compares the stored form of the values, so the writers of a table must agree on
+    /// the setting.
+    pub fn with_min_transform_size(mut self, min_transform_size: usize) -> Self {
+        self.min_transform_size = min_transform_size;
+        self
+    }
+
+    /// Sets the features supported by the backend, as returned by
+    /// [`DynamoDbStoreInternal::capabilities`], instead of probing them. Configuring
+    /// backends without transactions also disables them, as
+    /// [`Self::with_transactions_supported`] does.
+    pub fn with_capabilities(mut self, capabilities: Option<Capabilities>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Sets whether the writes request the size estimate of the item collection, which is
+    /// then reported in the metrics and logged when approaching the DynamoDB size limit.
+    ///
+    /// DynamoDB only returns the estimate for the tables with a local secondary index, so
+    /// the tables created with this setting get one, on the time of the last write. The
+    /// tables created without it report nothing.
+    pub fn with_item_collection_metrics(mut self, item_collection_metrics: bool) -> Self {
+        self.item_collection_metrics = item_collection_metrics;
+        self
+    }
+}
+
+impl AdminKeyValueStore for DynamoDbStoreInternal {
+    type Config = DynamoDbStoreInternalConfig;
+
+    fn get_name() -> String {
+        "dynamodb internal".to_string()
+    }
+
+    async fn connect(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<Self, DynamoDbStoreInternalError> {
+        Self::check_namespace(namespace)?;
+        let client = config.client().await?;
+        let fallback_clients = config.fallback_clients().await?;
+        let semaphore = config
+            .common_config
+            .max_concurrent_queries
+            .map(|n| Arc::new(Semaphore::new(n)));
+        let inflight_bytes = config
+            .max_inflight_bytes
+            .map(|max_bytes| Arc::new(InflightBytesBudget::new(max_bytes)));
+        let max_stream_queries = config.common_config.max_stream_queries;
+        let namespace = namespace.to_string();
+        let start_key = extend_root_key(&[]);
+        let internal_start_key = config
+            .separate_internal_partition
+            .then(|| internal_partition_key(&[]));
+        let store = Self {
+            client,
+            namespace,
+            semaphore,
+            max_stream_queries,
+            start_key,
+            internal_start_key,
+            allow_empty_key_prefix: config.allow_empty_key_prefix,
+            root_key_written: Arc::new(AtomicBool::new(false)),
+            key_validator: None,
+            write_hook: None,
+            backoff: Arc::new(ExponentialJitter::default()),
+            read_repair: false,
+            return_item_collection_metrics: config.item_collection_metrics,
+            fallback_clients,
+            transactions_supported: Arc::new(AtomicBool::new(
+                config.transactions_supported
+                    && config
+                        .capabilities
+                        .is_none_or(|capabilities| capabilities.transactions),
+            )),
+            query_page_size: config.query_page_size,
+            inflight_bytes,
+            // The schema version is stored without a checksum.
+            value_checksums: false,
+            consumed_capacity: Arc::default(),
+            access_tracker: None,
+            request_rate_monitor: None,
+            max_recovery_jitter: config.max_recovery_jitter,
+            reject_conflicting_operations: config.reject_conflicting_operations,
+            record_modification_times: config.record_modification_times,
+            malformed_items: config.malformed_items,
+            max_update_attempts: config.max_update_attempts,
+            separate_internal_partition: config.separate_internal_partition,
+            batch_dump: config.batch_dump,
+            min_transform_size: config.min_transform_size,
+            capabilities: Arc::new(
+                config
+                    .capabilities
+                    .map_or_else(OnceCell::new, OnceCell::from),
+            ),
+        };
+        if config.check_schema_version {
+            store.check_schema_version().await?;
+        }
+        Ok(Self {
+            value_checksums: config.value_checksums,
+            ..store
+        })
+    }
+
+    fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, DynamoDbStoreInternalError> {
+        let client = self.client.clone();
+        let namespace = self.namespace.clone();
+        let semaphore = self.semaphore.clone();
+        let max_stream_queries = self.max_stream_queries;
+        let start_key = extend_root_key(root_key);
+        let internal_start_key = self
+            .separate_internal_partition
+            .then(|| internal_partition_key(root_key));
+        let key_validator = self.key_validator.clone();
+        Ok(Self {
+            client,
+            namespace,
+            semaphore,
+            max_stream_queries,
+            start_key,
+            internal_start_key,
+            allow_empty_key_prefix: self.allow_empty_key_prefix,
+            root_key_written: Arc::new(AtomicBool::new(false)),
+            key_validator,
+            write_hook: self.write_hook.clone(),
+            backoff: self.backoff.clone(),
+            read_repair: self.read_repair,
+            return_item_collection_metrics: self.return_item_collection_metrics,
+            fallback_clients: self.fallback_clients.clone(),
+            transactions_supported: self.transactions_supported.clone(),
+            query_page_size: self.query_page_size,
+            inflight_bytes: self.inflight_bytes.clone(),
+            value_checksums: self.value_checksums,
+            consumed_capacity: self.consumed_capacity.clone(),
+            access_tracker: self.access_tracker.clone(),
+            // The other root keys live in other partitions, with their own rates.
+            request_rate_monitor: self.request_rate_monitor.as_ref().map(|monitor| {
+                Arc::new(RequestRateMonitor::new(
+                    monitor.max_reads_per_second,
+                    monitor.max_writes_per_second,
+                ))
+            }),
+            max_recovery_jitter: self.max_recovery_jitter,
+            reject_conflicting_operations: self.reject_conflicting_operations,
+            record_modification_times: self.record_modification_times,
+            malformed_items: self.malformed_items,
+            max_update_attempts: self.max_update_attempts,
+            separate_internal_partition: self.separate_internal_partition,
+            batch_dump: self.batch_dump,
+            min_transform_size: self.min_transform_size,
+            capabilities: self.capabilities.clone(),
+        })
+    }
+
+    async fn list_all(config: &Self::Config) -> Result<Vec<String>, DynamoDbStoreInternalError> {
+        let client = config.client().await?;
+        let mut namespaces = Vec::new();
+        let mut start_table = None;
+        loop {
+            let response = client
+                .list_tables()
+                .set_exclusive_start_table_name(start_table)
+                .send()
+                .boxed()
+                .await?;
+            if let Some(namespaces_blk) = response.table_names {
+                namespaces.extend(namespaces_blk);
+            }
+            if response.last_evaluated_table_name.is_none() {
+                break;
+            } else {
+                start_table = response.last_evaluated_table_name;
+            }
+        }
+        Ok(namespaces)
+    }
+
+    async fn list_root_keys(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<Vec<Vec<u8>>, DynamoDbStoreInternalError> {
+        let mut store = Self::connect(config, namespace).await?;
+        store.start_key = PARTITION_KEY_ROOT_KEY.to_vec();
+        // The keys of the root keys start with `EMPTY_ROOT_KEY`, like the internal keys.
+        store.internal_start_key = None;
+
+        let keys = store.find_keys_by_prefix(EMPTY_ROOT_KEY).await?;
+
+        let mut root_keys = Vec::new();
+        for key in keys.iterator() {
+            let key = key?;
+            root_keys.push(key.to_vec());
+        }
+        Ok(root_keys)
+    }
+
+    async fn delete_all(config: &Self::Config) -> Result<(), DynamoDbStoreInternalError> {
+        let client = config.client().await?;
+        let tables = Self::list_all(config).await?;
+        for table in tables {
+            client
+                .delete_table()
+                .table_name(&table)
+                .send()
+                .boxed()
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn exists(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<bool, DynamoDbStoreInternalError> {
+        Self::check_namespace(namespace)?;
+        let client = config.client().await?;
+        let key_db = build_key(EMPTY_ROOT_KEY, DB_KEY.to_vec());
+        let response = client
+            .get_item()
+            .table_name(namespace)
+            .set_key(Some(key_db))
+            .send()
+            .boxed()
+            .await;
+        let Err(error) = response else {
+            return Ok(true);
+        };
+        let test = match &error {
+            SdkError::ServiceError(error) => match error.err() {
+                GetItemError::ResourceNotFoundException(error) => {
+                    error.message
+                        == Some("Cannot do operations on a non-existent table".to_string())
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        if test {
+            Ok(false)
+        } else {
+            Err(error.into())
+        }
+    }
+
+    async fn create(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        Self::check_namespace(namespace)?;
+        ensure!(
+            config.create_if_missing,
+            DynamoDbStoreInternalError::TableNotFound(namespace.to_string())
+        );
+        let client = config.client().await?;
+        let mut request = client
+            .create_table()
+            .table_name(namespace)
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(PARTITION_ATTRIBUTE)
+                    .attribute_type(ScalarAttributeType::B)
+                    .build()?,
+            )
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(KEY_ATTRIBUTE)
+                    .attribute_type(ScalarAttributeType::B)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(PARTITION_ATTRIBUTE)
+                    .key_type(KeyType::Hash)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(KEY_ATTRIBUTE)
+                    .key_type(KeyType::Range)
+                    .build()?,
+            );
+        if config.item_collection_metrics {
+            request = request
+                .attribute_definitions(
+                    AttributeDefinition::builder()
+                        .attribute_name(LAST_MODIFIED_ATTRIBUTE)
+                        .attribute_type(ScalarAttributeType::N)
+                        .build()?,
+                )
+                .local_secondary_indexes(
+                    LocalSecondaryIndex::builder()
+                        .index_name(ITEM_COLLECTION_INDEX)
+                        .key_schema(
+                            KeySchemaElement::builder()
+                                .attribute_name(PARTITION_ATTRIBUTE)
+                                .key_type(KeyType::Hash)
+                                .build()?,
+                        )
+                        .key_schema(
+                            KeySchemaElement::builder()
+                                .attribute_name(LAST_MODIFIED_ATTRIBUTE)
+                                .key_type(KeyType::Range)
+                                .build()?,
+                        )
+                        .projection(
+                            Projection::builder()
+                                .projection_type(ProjectionType::KeysOnly)
+                                .build(),
+                        )
+                        .build()?,
+                );
+        }
+        request
+            .provisioned_throughput(
+                ProvisionedThroughput::builder()
+                    .read_capacity_units(10)
+                    .write_capacity_units(10)
+                    .build()?,
+            )
+            .send()
+            .boxed()
+            .await?;
+        Self::wait_for_active_table(&client, namespace).await?;
+        Self::write_schema_version(&client, namespace).await
+    }
+
+    /// Deletes the table, unless creating tables is disabled: the table could then not be
+    /// created again, so that `recreate_and_connect` fails before deleting it.
+    async fn delete(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        Self::check_namespace(namespace)?;
+        ensure!(
+            config.create_if_missing,
+            DynamoDbStoreInternalError::TableDeletionDisabled(namespace.to_string())
+        );
+        let client = config.client().await?;
+        client
+            .delete_table()
+            .table_name(namespace)
+            .send()
+            .boxed()
+            .await?;
+        Ok(())
+    }
+}
+
+impl DynamoDbStoreInternal {
+    /// Waits until the table `namespace`, just created, is active and can be written.
+    async fn wait_for_active_table(
+        client: &Client,
+        namespace: &str,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let start = Instant::now();
+        loop {
+            let response = client
+                .describe_table()
+                .table_name(namespace)
+                .send()
+                .boxed()
+                .await?;
+            let status = response.table().and_then(|table| table.table_status());
+            if status == Some(&TableStatus::Active) {
+                return Ok(());
+            }
+            ensure!(
+                start.elapsed() < TABLE_CREATION_TIMEOUT,
+                DynamoDbStoreInternalError::TableNotActive(namespace.to_string())
+            );
+            timer::sleep(TABLE_STATUS_POLL_DELAY).await;
+        }
+    }
+
+    /// Writes the schema version of a new table, unless a concurrent creator already
+    /// wrote it, in which case the version written must be the one of this code.
+    async fn write_schema_version(
+        client: &Client,
+        namespace: &str,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let item = build_key_value(
+            PARTITION_KEY_ROOT_KEY,
+            SCHEMA_VERSION_KEY.to_vec(),
+            bcs::to_bytes(&SCHEMA_VERSION)?,
+        );
         let response = client
-            .get_item()
+            .put_item()
             .table_name(namespace)
+            .set_item(Some(item))
+            .condition_expression(format!("attribute_not_exists({KEY_ATTRIBUTE})"))
+            .send()
+            .boxed()
+            .await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError(error))
+                if matches!(
+                    error.err(),
+                    PutItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                let response = client
+                    .get_item()
+                    .table_name(namespace)
+                    .set_key(Some(build_key(
+                        PARTITION_KEY_ROOT_KEY,
+                        SCHEMA_VERSION_KEY.to_vec(),
+                    )))
+                    .consistent_read(true)
+                    .send()
+                    .boxed()
+                    .await?;
+                let value = response
+                    .item()
+                    .ok_or(DynamoDbStoreInternalError::MissingValue)
+                    .and_then(|item| extract_value(item, false))?;
+                let found = bcs::from_bytes::<u32>(value)?;
+                ensure!(
+                    found == SCHEMA_VERSION,
+                    DynamoDbStoreInternalError::SchemaVersionMismatch {
+                        found,
+                        expected: SCHEMA_VERSION,
+                    }
+                );
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Namespaces are named table names in DynamoDB [naming
+    /// rules](https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/HowItWorks.NamingRulesDataTypes.html#HowItWorks.NamingRules),
+    /// so we need to check correctness of the namespace
+    fn check_namespace(namespace: &str) -> Result<(), InvalidNamespace> {
+        if namespace.len() < 3 {
+            return Err(InvalidNamespace::TooShort);
+        }
+        if namespace.len() > 255 {
+            return Err(InvalidNamespace::TooLong);
+        }
+        if !namespace.chars().all(|character| {
+            character.is_ascii_alphanumeric()
+                || character == '.'
+                || character == '-'
+                || character == '_'
+        }) {
+            return Err(InvalidNamespace::InvalidCharacter);
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the DynamoDB table used by the store.
+    pub fn table_name(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns the root key under which the store writes its key-values.
+    pub fn root_key(&self) -> &[u8] {
+        &self.start_key[EMPTY_ROOT_KEY.len()..]
+    }
+
+    /// Returns the partition key of the item of `key`, or of the items under the prefix
+    /// `key`: the internal keys may be stored apart from the data.
+    fn partition_of(&self, key: &[u8]) -> &[u8] {
+        match &self.internal_start_key {
+            Some(internal_start_key) if key.first() == Some(&INTERNAL_TAG) => internal_start_key,
+            _ => &self.start_key,
+        }
+    }
+
+    /// Checks the prefix of a scan as a key, which may only be empty if the configuration
+    /// allows it.
+    fn check_key_prefix(&self, key_prefix: &[u8]) -> Result<(), DynamoDbStoreInternalError> {
+        if key_prefix.is_empty() {
+            ensure!(
+                self.allow_empty_key_prefix,
+                DynamoDbStoreInternalError::ZeroLengthKeyPrefix
+            );
+            return Ok(());
+        }
+        ensure!(
+            key_prefix.len() <= MAX_KEY_SIZE,
+            DynamoDbStoreInternalError::KeyPrefixTooLong
+        );
+        self.validate_key_as(key_prefix, KeyKind::Prefix)
+    }
+
+    /// Sets the callback validating every key before it is sent to DynamoDB.
+    pub fn with_key_validator(mut self, key_validator: KeyValidator) -> Self {
+        self.key_validator = Some(key_validator);
+        self
+    }
+
+    /// Sets the callback notified of the writes committed by the store.
+    pub fn with_write_hook(mut self, write_hook: WriteHook) -> Self {
+        self.write_hook = Some(write_hook);
+        self
+    }
+
+    /// Enables counting the reads of `read_value_bytes` and `read_multi_values_bytes` per
+    /// prefix of `prefix_len` bytes of the keys, over a window of about `window`.
+    ///
+    /// The counts are shared with the stores cloned from this one, for all their root
+    /// keys, and are reported by [`DynamoDbStoreInternal::access_stats`].
+    pub fn with_access_tracking(mut self, prefix_len: usize, window: Duration) -> Self {
+        self.access_tracker = Some(Arc::new(AccessTracker::new(prefix_len, window)));
+        self
+    }
+
+    /// Returns the most read key prefixes with their estimated number of reads, the most
+    /// read first, if access tracking is enabled.
+    ///
+    /// At most 64 prefixes are reported. The counts are estimates that may be too high
+    /// when many prefixes are read, and are halved at the end of every window.
+    pub fn access_stats(&self) -> Vec<(Vec<u8>, u64)> {
+        self.access_tracker
+            .as_ref()
+            .map_or_else(Vec::new, |tracker| tracker.stats())
+    }
+
+    /// Enables a warning when the store sends more than `max_reads_per_second` read
+    /// requests or writes more than `max_writes_per_second` items in a second.
+    ///
+    /// All the items of a root key live in a single partition, which DynamoDB throttles
+    /// beyond about 3000 reads and 1000 writes per second. The warning is emitted at most
+    /// once a minute, before the requests start failing with
+    /// `ProvisionedThroughputExceededException`. The rates are counted separately for
+    /// the stores created by `clone_with_root_key`.
+    pub fn with_request_rate_warning(
+        mut self,
+        max_reads_per_second: u64,
+        max_writes_per_second: u64,
+    ) -> Self {
+        self.request_rate_monitor = Some(Arc::new(RequestRateMonitor::new(
+            max_reads_per_second,
+            max_writes_per_second,
+        )));
+        self
+    }
+
+    /// Counts the requests for the warning of [`Self::with_request_rate_warning`].
+    fn monitor_request_rate(&self, reads: u64, writes: u64) {
+        let Some(monitor) = &self.request_rate_monitor else {
+            return;
+        };
+        if let Some((reads, writes)) = monitor.record(reads, writes) {
+            tracing::warn!(
+                "The store of table {} made {reads} reads and {writes} writes in the last \
+                 second, above the rate that a single DynamoDB partition sustains. Consider \
+                 spreading the load over several root keys with `clone_with_root_key`.",
+                self.namespace
+            );
+        }
+    }
+
+    /// Sets the strategy for the delays between the retries of the requests that were not
+    /// fully processed. The default is [`ExponentialJitter`], retrying forever.
+    pub fn with_backoff_strategy(mut self, backoff: impl BackoffStrategy + 'static) -> Self {
+        self.backoff = Arc::new(backoff);
+        self
+    }
+
+    /// Sets whether values stored with the wrong attribute type are repaired when read.
+    ///
+    /// When enabled, a string value found by `read_value_bytes` is re-written as a blob
+    /// holding its UTF-8 encoding instead of failing with `WrongValueType`.
+    pub fn with_read_repair(mut self, read_repair: bool) -> Self {
+        self.read_repair = read_repair;
+        self
+    }
+
+    /// Installs a fault injector on the client of the store, to test the behavior of the
+    /// store when the requests to DynamoDB fail.
+    #[cfg(with_testing)]
+    pub fn with_fault_injector(mut self, injector: FaultInjector) -> Self {
+        let config = self
+            .client
+            .config()
+            .to_builder()
+            .interceptor(injector)
+            .build();
+        self.client = Client::from_conf(config);
+        self
+    }
+
+    /// Runs the key validator, if any, on the key.
+    fn validate_key(&self, key: &[u8]) -> Result<(), DynamoDbStoreInternalError> {
+        self.validate_key_as(key, KeyKind::Key)
+    }
+
+    /// Runs the key validator, if any, on a key or a prefix of the given kind, the
+    /// internal keys and prefixes being always of kind [`KeyKind::Internal`].
+    fn validate_key_as(&self, key: &[u8], kind: KeyKind) -> Result<(), DynamoDbStoreInternalError> {
+        if let Some(KeyValidator(validator)) = &self.key_validator {
+            let kind = if key.first() == Some(&INTERNAL_TAG) {
+                KeyKind::Internal
+            } else {
+                kind
+            };
+            validator(key, kind).map_err(DynamoDbStoreInternalError::InvalidKey)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a key is of the correct size and is accepted by the key validator.
+    fn check_key(&self, key: &[u8]) -> Result<(), DynamoDbStoreInternalError> {
+        check_key_size(key)?;
+        self.validate_key(key)
+    }
+
+    fn build_delete_transaction(
+        &self,
+        start_key: &[u8],
+        key: Vec<u8>,
+    ) -> Result<TransactWriteItem, DynamoDbStoreInternalError> {
+        check_key_size(&key)?;
+        let request = Delete::builder()
+            .table_name(&self.namespace)
+            .set_key(Some(build_key(start_key, key)))
+            .build()?;
+        Ok(TransactWriteItem::builder().delete(request).build())
+    }
+
+    fn build_put_transaction(
+        &self,
+        start_key: &[u8],
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<TransactWriteItem, DynamoDbStoreInternalError> {
+        check_key_size(&key)?;
+        let value = self.encode_value(value);
+        ensure!(
+            value.len() <= RAW_MAX_VALUE_SIZE,
+            DynamoDbStoreInternalError::ValueLengthTooLarge
+        );
+        let item = self.build_item(start_key, key, value);
+        check_item_size(&item)?;
+        let request = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(item))
+            .build()?;
+        Ok(TransactWriteItem::builder().put(request).build())
+    }
+
+    /// Builds the `Put` that bumps the version stored at `version_key`, conditioned on the
+    /// stored version being `expected_version`.
+    fn build_version_guard_transaction(
+        &self,
+        version_key: &[u8],
+        expected_version: Option<u64>,
+        new_version: u64,
+    ) -> Result<TransactWriteItem, DynamoDbStoreInternalError> {
+        check_key_size(version_key)?;
+        let value = self.encode_value(bcs::to_bytes(&new_version)?);
+        let builder = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(build_key_value(
+                self.partition_of(version_key),
+                version_key.to_vec(),
+                value,
+            )));
+        let builder = match expected_version {
+            None => builder.condition_expression(format!("attribute_not_exists({KEY_ATTRIBUTE})")),
+            Some(expected_version) => {
+                let expected_value = self.encode_value(bcs::to_bytes(&expected_version)?);
+                builder
+                    .condition_expression(format!("{VALUE_ATTRIBUTE} = :version"))
+                    .expression_attribute_values(
+                        ":version",
+                        AttributeValue::B(Blob::new(expected_value)),
+                    )
+            }
+        };
+        Ok(TransactWriteItem::builder().put(builder.build()?).build())
+    }
+
+    /// Builds the item of `key` and the encoded `value`, with the time of the write if
+    /// the store records it.
+    fn build_item(
+        &self,
+        start_key: &[u8],
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> HashMap<String, AttributeValue> {
+        let mut item = build_key_value(start_key, key, value);
+        if self.record_modification_times {
+            item.insert(
+                LAST_MODIFIED_ATTRIBUTE.to_owned(),
+                AttributeValue::N(unix_millis(SystemTime::now()).to_string()),
+            );
+        }
+        item
+    }
+
+    /// Adds the checksum header to `value` if the values are stored with a checksum.
+    fn encode_value(&self, value: Vec<u8>) -> Vec<u8> {
+        if self.value_checksums {
+            add_value_header(value, self.min_transform_size)
+        } else {
+            value
+        }
+    }
+
+    /// Reserves `num_bytes` of the budget of bytes in flight, if any, until the returned
+    /// permit is dropped.
+    async fn reserve_inflight_bytes(&self, num_bytes: usize) -> Option<InflightBytesPermit<'_>> {
+        let budget = self.inflight_bytes.as_ref()?;
+        Some(budget.reserve(num_bytes).await)
+    }
+
+    /// Obtains the semaphore lock on the database if needed, for requests making `reads`
+    /// reads and writing `writes` items, which are counted for the warning of
+    /// [`Self::with_request_rate_warning`].
+    async fn acquire(&self, reads: u64, writes: u64) -> Option<SemaphoreGuard<'_>> {
+        self.monitor_request_rate(reads, writes);
+        match &self.semaphore {
+            None => None,
+            Some(count) => Some(count.acquire().await),
+        }
+    }
+
+    async fn get_query_output(
+        &self,
+        attribute_str: &str,
+        start_key: &[u8],
+        key_prefix: &[u8],
+        start_key_map: Option<HashMap<String, AttributeValue>>,
+        limit: Option<i32>,
+        consistent_read: bool,
+    ) -> Result<QueryOutput, DynamoDbStoreInternalError> {
+        let _guard = self.acquire(1, 0).await;
+        // The replicas of other regions cannot serve strongly consistent reads.
+        let num_clients = if consistent_read { 1 } else { usize::MAX };
+        let mut clients = self.read_clients().take(num_clients).peekable();
+        loop {
+            let client = clients.next().expect("there is always a primary client");
+            let builder = client
+                .query()
+                .table_name(&self.namespace)
+                .projection_expression(attribute_str)
+                .expression_attribute_values(
+                    ":partition",
+                    AttributeValue::B(Blob::new(start_key.to_vec())),
+                );
+            // DynamoDB rejects an empty prefix: the whole partition is read instead.
+            let builder = if key_prefix.is_empty() {
+                builder.key_condition_expression(format!("{PARTITION_ATTRIBUTE} = :partition"))
+            } else {
+                builder
+                    .key_condition_expression(format!(
+                        "{PARTITION_ATTRIBUTE} = :partition and begins_with({KEY_ATTRIBUTE}, :prefix)"
+                    ))
+                    .expression_attribute_values(
+                        ":prefix",
+                        AttributeValue::B(Blob::new(key_prefix)),
+                    )
+            };
+            let response = builder
+                .set_exclusive_start_key(start_key_map.clone())
+                .set_limit(limit)
+                .consistent_read(consistent_read)
+                .return_consumed_capacity(ReturnConsumedCapacity::Total)
+                .send()
+                .boxed()
+                .await;
+            match response {
+                Err(error) if is_region_failure(&error) && clients.peek().is_some() => {}
+                response => {
+                    let response = response?;
+                    self.consumed_capacity
+                        .record_read(response.consumed_capacity());
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    /// Writes `value` at the composite key made of `entity` and `sort`.
+    /// See [`composite_key`] for the encoding of the key.
+    pub async fn write_composite(
+        &self,
+        entity: &[u8],
+        sort: &[u8],
+        value: Vec<u8>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let mut batch = SimpleUnorderedBatch::default();
+        batch.add_insert(composite_key(entity, sort), value);
+        DirectWritableKeyValueStore::write_batch(self, batch).await
+    }
+
+    /// Returns the pairs `(sort, value)` written by `write_composite` for the entity
+    /// `entity`, with `sort` in `sort_range`, in the order of `sort`.
+    ///
+    /// The range is queried natively by DynamoDB, so only the matching entries are read.
+    pub async fn query_composite(
+        &self,
+        entity: &[u8],
+        sort_range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DynamoDbStoreInternalError> {
+        let prefix = composite_entity_prefix(entity);
+        let with_prefix = |sort: &[u8]| {
+            let mut key = prefix.clone();
+            key.extend(sort);
+            key
+        };
+        let lower_key = match &sort_range.0 {
+            Bound::Included(sort) | Bound::Excluded(sort) => with_prefix(sort),
+            Bound::Unbounded => prefix.clone(),
+        };
+        let upper_key = match &sort_range.1 {
+            Bound::Included(sort) | Bound::Excluded(sort) => with_prefix(sort),
+            Bound::Unbounded => get_upper_bound_option(&prefix).expect("the prefix ends with 0"),
+        };
+        let key_values = self.query_key_range(lower_key, upper_key).await?;
+        let mut entries = Vec::new();
+        for (key, value) in key_values {
+            let Some(sort) = key.strip_prefix(prefix.as_slice()) else {
+                continue;
+            };
+            if sort_range.contains(&sort.to_vec()) {
+                entries.push((sort.to_vec(), value));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns the pairs `(index, value)` of the entries written at the keys
+    /// `index_key(prefix, index)` for `index` in `range`, in the order of the indices.
+    ///
+    /// The range is read with a single bounded query.
+    pub async fn read_index_range(
+        &self,
+        prefix: &[u8],
+        range: Range<u64>,
+    ) -> Result<Vec<(u64, Vec<u8>)>, DynamoDbStoreInternalError> {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let lower_key = index_key(prefix, range.start);
+        let upper_key = index_key(prefix, range.end - 1);
+        let key_values = self.query_key_range(lower_key, upper_key).await?;
+        let mut entries = Vec::new();
+        for (key, value) in key_values {
+            let Some(index) = key
+                .strip_prefix(prefix)
+                .and_then(|index| <[u8; 8]>::try_from(index).ok())
+            else {
+                continue;
+            };
+            entries.push((u64::from_be_bytes(index), value));
+        }
+        Ok(entries)
+    }
+
+    /// Returns the key-values with a key between `lower_key` and `upper_key`, both
+    /// included, in the order of the keys.
+    async fn query_key_range(
+        &self,
+        lower_key: Vec<u8>,
+        upper_key: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DynamoDbStoreInternalError> {
+        check_key_size(&lower_key)?;
+        check_key_size(&upper_key)?;
+        if lower_key > upper_key {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        let mut start_key_map = None;
+        loop {
+            let response = {
+                let _guard = self.acquire(1, 0).await;
+                self.client
+                    .query()
+                    .table_name(&self.namespace)
+                    .projection_expression(KEY_VALUE_ATTRIBUTE)
+                    .key_condition_expression(format!(
+                        "{PARTITION_ATTRIBUTE} = :partition and {KEY_ATTRIBUTE} between :lower and :upper"
+                    ))
+                    .expression_attribute_values(
+                        ":partition",
+                        AttributeValue::B(Blob::new(self.partition_of(&lower_key).to_vec())),
+                    )
+                    .expression_attribute_values(
+                        ":lower",
+                        AttributeValue::B(Blob::new(lower_key.clone())),
+                    )
+                    .expression_attribute_values(
+                        ":upper",
+                        AttributeValue::B(Blob::new(upper_key.clone())),
+                    )
+                    .set_exclusive_start_key(start_key_map)
+                    .set_limit(self.query_page_size)
+                    .send()
+                    .boxed()
+                    .await?
+            };
+            for mut item in response.items.unwrap_or_default() {
+                entries.push(extract_key_value_owned(0, &mut item, self.value_checksums)?);
+            }
+            start_key_map = response.last_evaluated_key;
+            if start_key_map.is_none() {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Reads the key-values under each of the `prefixes` and returns them grouped by
+    /// prefix. The keys are returned without their prefix, as in
+    /// `find_key_values_by_prefix`.
+    ///
+    /// At most `max_stream_queries` prefixes are queried concurrently. As for
+    /// `find_key_values_by_prefix`, the prefixes must not be empty.
+    pub async fn find_key_values_grouped(
+        &self,
+        prefixes: Vec<Vec<u8>>,
+    ) -> Result<HashMap<Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>>, DynamoDbStoreInternalError> {
+        for prefix in &prefixes {
+            check_key_size(prefix)?;
+        }
+        futures::stream::iter(prefixes)
+            .map(|prefix| async move {
+                let key_values = self
+                    .find_key_values_by_prefix(&prefix)
+                    .await?
+                    .into_iterator_owned()
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((prefix, key_values))
+            })
+            .buffer_unordered(self.max_stream_queries)
+            .try_collect()
+            .await
+    }
+
+    /// Checks that the table was not written with a newer layout than the one of this code.
+    async fn check_schema_version(&self) -> Result<(), DynamoDbStoreInternalError> {
+        let key_db = build_key(PARTITION_KEY_ROOT_KEY, SCHEMA_VERSION_KEY.to_vec());
+        let Some(value) = self.read_value_bytes_general(key_db, false).await? else {
+            return Ok(());
+        };
+        let found = bcs::from_bytes::<u32>(&value)?;
+        ensure!(
+            found <= SCHEMA_VERSION,
+            DynamoDbStoreInternalError::SchemaVersionMismatch {
+                found,
+                expected: SCHEMA_VERSION,
+            }
+        );
+        Ok(())
+    }
+
+    /// Returns the clients to use for reads, the primary one first.
+    fn read_clients(&self) -> impl Iterator<Item = &Client> {
+        std::iter::once(&self.client).chain(&self.fallback_clients)
+    }
+
+    async fn read_value_bytes_general(
+        &self,
+        key_db: HashMap<String, AttributeValue>,
+        consistent_read: bool,
+    ) -> Result<Option<Vec<u8>>, DynamoDbStoreInternalError> {
+        let value = self
+            .read_value_with_metadata_general(key_db, consistent_read)
+            .await?;
+        Ok(value.map(|(value, _)| value))
+    }
+
+    /// Reads the value at `key` with the time of its last write, if the item has one.
+    ///
+    /// The time is only stored when the store was configured with
+    /// [`DynamoDbStoreInternalConfig::with_record_modification_times`] at the time of
+    /// the write.
+    pub async fn read_key_with_metadata(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, Option<SystemTime>)>, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        self.read_value_with_metadata_general(key_db, false).await
+    }
+
+    /// Reads the value at `key` and passes it to `f` as a slice borrowed from the
+    /// response, without copying it, or `None` if there is no value at `key`.
+    ///
+    /// This saves an allocation for the large values that are processed in place. The
+    /// values stored with the wrong attribute type are not repaired by this method.
+    pub async fn read_key_with<R>(
+        &self,
+        key: &[u8],
+        f: impl FnOnce(Option<&[u8]>) -> R,
+    ) -> Result<R, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        if let Some(tracker) = &self.access_tracker {
+            tracker.record(key);
+        }
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        let response = self.get_item_output(key_db, false).await?;
+        let value = response
+            .item
+            .as_ref()
+            .map(|item| extract_value(item, self.value_checksums))
+            .transpose()?;
+        Ok(f(value))
+    }
+
+    /// Reads the value at `key` with a strongly consistent read, which reflects all the
+    /// writes that succeeded before it, whatever the other reads of the store use.
+    ///
+    /// Such a read consumes twice the capacity of an eventually consistent one, and is
+    /// only served by the primary region.
+    pub async fn read_key_bytes_consistent(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        if let Some(tracker) = &self.access_tracker {
+            tracker.record(key);
+        }
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        self.read_value_bytes_general(key_db, true).await
+    }
+
+    /// Reads the values at `keys` with strongly consistent reads.
+    /// See [`DynamoDbStoreInternal::read_key_bytes_consistent`].
+    pub async fn read_multi_key_bytes_consistent(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, DynamoDbStoreInternalError> {
+        self.read_multi_key_bytes_general(keys, true).await
+    }
+
+    async fn read_multi_key_bytes_general(
+        &self,
+        keys: Vec<Vec<u8>>,
+        consistent_read: bool,
+    ) -> Result<Vec<Option<Vec<u8>>>, DynamoDbStoreInternalError> {
+        let mut handles = Vec::new();
+        for key in keys {
+            self.check_key(&key)?;
+            if let Some(tracker) = &self.access_tracker {
+                tracker.record(&key);
+            }
+            let num_bytes = key.len() + RAW_MAX_VALUE_SIZE;
+            let key_db = build_key(self.partition_of(&key), key);
+            let handle = async move {
+                let _permit = self.reserve_inflight_bytes(num_bytes).await;
+                self.read_value_bytes_general(key_db, consistent_read).await
+            };
+            handles.push(handle);
+        }
+        // Throttled requests are retried by the SDK, so an error is final: the other
+        // requests are dropped.
+        try_join_all(handles).await
+    }
+
+    async fn read_value_with_metadata_general(
+        &self,
+        key_db: HashMap<String, AttributeValue>,
+        consistent_read: bool,
+    ) -> Result<Option<(Vec<u8>, Option<SystemTime>)>, DynamoDbStoreInternalError> {
+        let response = self.get_item_output(key_db, consistent_read).await?;
+        match response.item {
+            Some(mut item) => {
+                let modified = extract_modification_time(&item)?;
+                if self.read_repair {
+                    if let Some(new_value) = item.get(VALUE_ATTRIBUTE).and_then(coerce_value) {
+                        let value = item.remove(VALUE_ATTRIBUTE).expect("value is present");
+                        let key = extract_key_owned(0, &mut item)?;
+                        self.repair_value(key, value, new_value.clone()).await?;
+                        return Ok(Some((new_value, modified)));
+                    }
+                }
+                let value = extract_value_owned(&mut item, self.value_checksums)?;
+                Ok(Some((value, modified)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the item of `key_db`, falling back to the other regions if the primary one
+    /// fails and the read is not strongly consistent.
+    async fn get_item_output(
+        &self,
+        key_db: HashMap<String, AttributeValue>,
+        consistent_read: bool,
+    ) -> Result<GetItemOutput, DynamoDbStoreInternalError> {
+        let response = {
+            let _guard = self.acquire(1, 0).await;
+            // The replicas of other regions cannot serve strongly consistent reads.
+            let num_clients = if consistent_read { 1 } else { usize::MAX };
+            let mut clients = self.read_clients().take(num_clients).peekable();
+            loop {
+                let client = clients.next().expect("there is always a primary client");
+                let response = client
+                    .get_item()
+                    .table_name(&self.namespace)
+                    .set_key(Some(key_db.clone()))
+                    .consistent_read(consistent_read)
+                    .return_consumed_capacity(ReturnConsumedCapacity::Total)
+                    .send()
+                    .boxed()
+                    .await;
+                match response {
+                    Err(error) if is_region_failure(&error) && clients.peek().is_some() => {}
+                    response => break response?,
+                }
+            }
+        };
+        self.consumed_capacity
+            .record_read(response.consumed_capacity());
+        Ok(response)
+    }
+
+    /// Re-writes as the blob `new_value` a value stored with the wrong attribute type,
+    /// provided that it was not changed in the meantime.
+    async fn repair_value(
+        &self,
+        key: Vec<u8>,
+        value: AttributeValue,
+        new_value: Vec<u8>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let request = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(self.build_item(
+                self.partition_of(&key),
+                key,
+                self.encode_value(new_value),
+            )))
+            .condition_expression(format!("{VALUE_ATTRIBUTE} = :value"))
+            .expression_attribute_values(":value", value)
+            .build()?;
+        let _guard = self.acquire(0, 1).await;
+        self.client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().put(request).build())
+            .send()
+            .boxed()
+            .await?;
+        Ok(())
+    }
+
+    /// Scans the keys starting with `key_prefix` and re-writes as blobs the values stored
+    /// with the wrong attribute type. Returns the keys of the values that could not be
+    /// converted safely, which are left untouched for an operator to review.
+    pub async fn repair_values(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Vec<Vec<u8>>, DynamoDbStoreInternalError> {
+        let result_queries = self
+            .get_list_responses(
+                KEY_VALUE_ATTRIBUTE,
+                self.partition_of(key_prefix),
+                key_prefix,
+            )
+            .await?;
+        let mut unrepairable_keys = Vec::new();
+        for response in result_queries.responses {
+            for mut item in response.items.unwrap_or_default() {
+                let key = extract_key_owned(0, &mut item)?;
+                let value = item
+                    .remove(VALUE_ATTRIBUTE)
+                    .ok_or(DynamoDbStoreInternalError::MissingValue)?;
+                if matches!(value, AttributeValue::B(_)) {
+                    continue;
+                }
+                match coerce_value(&value) {
+                    Some(new_value) => self.repair_value(key, value, new_value).await?,
+                    None => unrepairable_keys.push(key),
+                }
+            }
+        }
+        Ok(unrepairable_keys)
+    }
+
+    /// Scans the keys of the store and returns the ones that are not under exactly one
+    /// of the `known_prefixes`, each with the known prefixes that it is under. An empty
+    /// list of prefixes means that the key is under none of them.
+    ///
+    /// The keys with a tag lower than `MIN_VIEW_TAG`, such as the keys of the journal,
+    /// are not reported. The store is only read, so that this can be used to check the
+    /// consistency of a live database.
+    pub async fn detect_prefix_overlaps(
+        &self,
+        known_prefixes: Vec<Vec<u8>>,
+    ) -> Result<Vec<(Vec<u8>, Vec<Vec<u8>>)>, DynamoDbStoreInternalError> {
+        let mut misrouted_keys = Vec::new();
+        for key in self.find_all_keys().await? {
+            if key.first().is_some_and(|tag| *tag < MIN_VIEW_TAG) {
+                continue;
+            }
+            let prefixes = known_prefixes
+                .iter()
+                .filter(|prefix| key.starts_with(prefix))
+                .cloned()
+                .collect::<Vec<_>>();
+            if prefixes.len() != 1 {
+                misrouted_keys.push((key, prefixes));
+            }
+        }
+        Ok(misrouted_keys)
+    }
+
+    /// Compares the key-values of this store with the ones of `other` and reports the
+    /// differences, for instance to check the result of a migration.
+    ///
+    /// Both stores are read page by page in the order of the keys, so that only a page
+    /// of each is held in memory. The keys with a tag lower than `MIN_VIEW_TAG`, such as
+    /// the keys of the journal, are not compared. At most `max_differences` differences
+    /// are reported, after which the comparison stops and the diff is marked as
+    /// truncated.
+    ///
+    /// With a `concurrency` above 1, the keys are split by view tag and up to
+    /// `concurrency` tags are compared in parallel, at the cost of a query per tag even
+    /// when it has no keys. The differences reported are the same as with a sequential
+    /// comparison, and the requests in flight remain bounded by the
+    /// `max_concurrent_queries` of the stores.
+    pub async fn diff(
+        &self,
+        other: &DynamoDbStoreInternal,
+        max_differences: usize,
+        concurrency: usize,
+    ) -> Result<StoreDiff, DynamoDbStoreInternalError> {
+        let mut range_diffs = stream::iter(view_key_ranges(concurrency))
+            .map(|key_prefix| self.diff_prefix(other, key_prefix, max_differences))
+            .buffered(concurrency.max(1));
+        let mut diff = StoreDiff::default();
+        while let Some(range_diff) = range_diffs.try_next().await? {
+            diff.append(range_diff, max_differences);
+            if diff.truncated {
+                break;
+            }
+        }
+        Ok(diff)
+    }
+
+    /// Compares the key-values under `key_prefix` of this store and of `other`, for
+    /// [`Self::diff`].
+    async fn diff_prefix(
+        &self,
+        other: &DynamoDbStoreInternal,
+        key_prefix: Vec<u8>,
+        max_differences: usize,
+    ) -> Result<StoreDiff, DynamoDbStoreInternalError> {
+        let mut diff = StoreDiff::default();
+        let mut cursor = KeyValueCursor::new(self, key_prefix.clone());
+        let mut other_cursor = KeyValueCursor::new(other, key_prefix);
+        let mut entry = cursor.next().await?;
+        let mut other_entry = other_cursor.next().await?;
+        loop {
+            let ordering = match (&entry, &other_entry) {
+                (None, None) => return Ok(diff),
+                (Some(_), None) => cmp::Ordering::Less,
+                (None, Some(_)) => cmp::Ordering::Greater,
+                (Some((key, _)), Some((other_key, _))) => key.cmp(other_key),
+            };
+            let is_difference = ordering != cmp::Ordering::Equal
+                || entry.as_ref().map(|(_, value)| value)
+                    != other_entry.as_ref().map(|(_, value)| value);
+            if is_difference && diff.len() == max_differences {
+                diff.truncated = true;
+                return Ok(diff);
+            }
+            match ordering {
+                cmp::Ordering::Less => {
+                    let (key, _) = entry.take().expect("the entry is present");
+                    diff.only_in_self.push(key);
+                    entry = cursor.next().await?;
+                }
+                cmp::Ordering::Greater => {
+                    let (key, _) = other_entry.take().expect("the entry is present");
+                    diff.only_in_other.push(key);
+                    other_entry = other_cursor.next().await?;
+                }
+                cmp::Ordering::Equal => {
+                    let (key, _) = entry.take().expect("the entry is present");
+                    if is_difference {
+                        diff.different_values.push(key);
+                    }
+                    entry = cursor.next().await?;
+                    other_entry = other_cursor.next().await?;
+                }
+            }
+        }
+    }
+
+    /// Copies the key-values of this store to `target`, e.g. to migrate them to another
+    /// table, and returns the number of key-values copied.
+    ///
+    /// The keys with a tag lower than `MIN_VIEW_TAG`, such as the keys of the journal,
+    /// are not copied. With a `concurrency` above 1, the keys are split by view tag and up
+    /// to `concurrency` tags are copied in parallel, as in [`Self::diff`].
+    ///
+    /// The key-values are written with `BatchWriteItem` requests, whose unprocessed items
+    /// are retried by each request on its own, so that the copy is not atomic: an
+    /// interrupted copy can be made again, overwriting the key-values already copied. The
+    /// write hook of `target` is not notified.
+    pub async fn copy_to(
+        &self,
+        target: &DynamoDbStoreInternal,
+        concurrency: usize,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        target.write_root_key_if_needed().await?;
+        let counts = stream::iter(view_key_ranges(concurrency))
+            .map(|key_prefix| self.copy_prefix_to(target, key_prefix))
+            .buffer_unordered(concurrency.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(counts.into_iter().sum())
+    }
+
+    /// Copies the key-values under `key_prefix` to `target`, for [`Self::copy_to`].
+    async fn copy_prefix_to(
+        &self,
+        target: &DynamoDbStoreInternal,
+        key_prefix: Vec<u8>,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        let mut cursor = KeyValueCursor::new(self, key_prefix);
+        let mut count = 0;
+        let mut items = Vec::new();
+        while let Some((key, value)) = cursor.next().await? {
+            target.validate_key(&key)?;
+            items.push(target.build_put_transaction(target.partition_of(&key), key, value)?);
+            if items.len() == MAX_BATCH_WRITE_ITEM_SIZE {
+                count += target
+                    .write_copied_items(std::mem::take(&mut items))
+                    .await?;
+            }
+        }
+        count += target.write_copied_items(items).await?;
+        Ok(count)
+    }
+
+    /// Writes the `items` copied by [`Self::copy_to`] and returns their number.
+    async fn write_copied_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        let count = items.len();
+        if count > 0 {
+            let _guard = self.acquire(0, count as u64).await;
+            self.write_items_without_transaction(items).await?;
+        }
+        Ok(count)
+    }
+
+    /// Reads all the key-values under `key_prefix` into a [`Snapshot`], from which they
+    /// can then be read without further requests.
+    ///
+    /// Every page of the query is read with strong consistency, so that each page
+    /// reflects all the writes completed before it was read. DynamoDB does not provide
+    /// atomicity across the pages, however: when the key-values span several pages, a
+    /// batch committed while the snapshot is taken may be seen by some of the pages only.
+    /// The snapshot is exactly consistent when no batch is written under the prefix
+    /// concurrently, e.g. while holding the lease of the root key.
+    pub async fn snapshot_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Snapshot, DynamoDbStoreInternalError> {
+        check_key_size(key_prefix)?;
+        let mut key_values = BTreeMap::new();
+        let mut start_key_map = None;
+        loop {
+            let response = self
+                .get_query_output(
+                    KEY_VALUE_ATTRIBUTE,
+                    self.partition_of(key_prefix),
+                    key_prefix,
+                    start_key_map,
+                    self.query_page_size,
+                    true,
+                )
+                .await?;
+            start_key_map = response.last_evaluated_key.clone();
+            for mut item in response.items.unwrap_or_default() {
+                let (key, value) = extract_key_value_owned(0, &mut item, self.value_checksums)?;
+                key_values.insert(key, value);
+            }
+            if start_key_map.is_none() {
+                return Ok(Snapshot {
+                    key_prefix: key_prefix.to_vec(),
+                    key_values,
+                });
+            }
+        }
+    }
+
+    /// Runs the PartiQL `statement` and returns the raw items that it selects, reading
+    /// all the pages of the result.
+    ///
+    /// This is a low-level tool for debugging and ad-hoc analytics, which bypasses the
+    /// key encoding of the store and is not meant for the hot path. The statement is not
+    /// restricted to the table or the root key of the store, but only `SELECT`
+    /// statements are accepted.
+    pub async fn partiql_query(
+        &self,
+        statement: &str,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbStoreInternalError> {
+        ensure!(
+            statement
+                .trim_start()
+                .get(..6)
+                .is_some_and(|verb| verb.eq_ignore_ascii_case("select")),
+            DynamoDbStoreInternalError::NonSelectStatement
+        );
+        let mut items = Vec::new();
+        let mut next_token = None;
+        loop {
+            let response = {
+                let _guard = self.acquire(1, 0).await;
+                self.client
+                    .execute_statement()
+                    .statement(statement)
+                    .set_next_token(next_token)
+                    .send()
+                    .boxed()
+                    .await?
+            };
+            items.extend(response.items.unwrap_or_default());
+            next_token = response.next_token;
+            if next_token.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// Reads the value at `key` as [`Bytes`], which can then be cloned and passed around
+    /// without copying the value. The buffer received from DynamoDB is reused.
+    pub async fn read_value_shared(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<Bytes>, DynamoDbStoreInternalError> {
+        let value = ReadableKeyValueStore::read_value_bytes(self, key).await?;
+        Ok(value.map(Bytes::from))
+    }
+
+    /// Reads the key-values under `key_prefix` as [`Bytes`], like
+    /// [`Self::read_value_shared`]. The keys are returned without the prefix.
+    pub async fn find_key_values_by_prefix_shared(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Vec<(Bytes, Bytes)>, DynamoDbStoreInternalError> {
+        ReadableKeyValueStore::find_key_values_by_prefix(self, key_prefix)
+            .await?
+            .into_iterator_owned()
+            .map(|entry| entry.map(|(key, value)| (Bytes::from(key), Bytes::from(value))))
+            .collect()
+    }
+
+    /// Writes the named sub-values of `map` together under `key`, as a single DynamoDB
+    /// map attribute, replacing the value at `key`.
+    ///
+    /// The map is read back with [`Self::read_map`]: the other reads of the store fail
+    /// with `WrongValueType` on this key. The names and sub-values must fit together in
+    /// the size of a single value.
+    pub async fn write_map(
+        &self,
+        key: &[u8],
+        map: HashMap<String, Vec<u8>>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let map = map
+            .into_iter()
+            .map(|(name, value)| (name, self.encode_value(value)))
+            .collect::<HashMap<_, _>>();
+        let size = map
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .sum::<usize>();
+        ensure!(
+            size <= RAW_MAX_VALUE_SIZE,
+            DynamoDbStoreInternalError::ValueLengthTooLarge
+        );
+        let map = map
+            .into_iter()
+            .map(|(name, value)| (name, AttributeValue::B(Blob::new(value))))
+            .collect();
+        let mut item = build_key(self.partition_of(key), key.to_vec());
+        item.insert(VALUE_ATTRIBUTE.to_owned(), AttributeValue::M(map));
+        let request = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(item))
+            .build()?;
+        self.write_root_key_if_needed().await?;
+        self.write_items(vec![TransactWriteItem::builder().put(request).build()])
+            .await
+    }
+
+    /// Reads the named sub-values written under `key` by [`Self::write_map`], if any.
+    pub async fn read_map(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<HashMap<String, Vec<u8>>>, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        let response = {
+            let _guard = self.acquire(1, 0).await;
+            let mut clients = self.read_clients().peekable();
+            loop {
+                let client = clients.next().expect("there is always a primary client");
+                let response = client
+                    .get_item()
+                    .table_name(&self.namespace)
+                    .set_key(Some(key_db.clone()))
+                    .send()
+                    .boxed()
+                    .await;
+                match response {
+                    Err(error) if is_region_failure(&error) && clients.peek().is_some() => {}
+                    response => break response?,
+                }
+            }
+        };
+        let Some(mut item) = response.item else {
+            return Ok(None);
+        };
+        let map = match item.remove(VALUE_ATTRIBUTE) {
+            Some(AttributeValue::M(map)) => map,
+            Some(AttributeValue::B(_)) => {
+                return Err(DynamoDbStoreInternalError::WrongValueType(
+                    "a binary blob".to_owned(),
+                ))
+            }
+            Some(value) => {
+                return Err(DynamoDbStoreInternalError::WrongValueType(
+                    DynamoDbStoreInternalError::type_description_of(&value),
+                ))
+            }
+            None => return Err(DynamoDbStoreInternalError::MissingValue),
+        };
+        let mut values = HashMap::new();
+        for (name, value) in map {
+            let AttributeValue::B(blob) = value else {
+                return Err(DynamoDbStoreInternalError::wrong_value_type(&value));
+            };
+            let mut value = blob.into_inner();
+            if self.value_checksums {
+                let header_len = value.len() - verify_value_checksum(&item, &value)?.len();
+                value.drain(..header_len);
+            }
+            values.insert(name, value);
+        }
+        Ok(Some(values))
+    }
+
+    /// Adds the `members` to the set stored under `key`, as a single DynamoDB binary set
+    /// attribute, creating the set if needed. The update is atomic.
+    ///
+    /// The set is read back with [`Self::set_read`]: the other reads of the store fail
+    /// with `WrongValueType` on this key. The members must fit together in a single item
+    /// of 400 KB, which is only checked by DynamoDB.
+    pub async fn set_add(
+        &self,
+        key: &[u8],
+        members: Vec<Vec<u8>>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let members = self.set_members(key, members)?;
+        if members.is_empty() {
+            return Ok(());
+        }
+        self.write_root_key_if_needed().await?;
+        let _guard = self.acquire(0, 1).await;
+        self.client
+            .update_item()
+            .table_name(&self.namespace)
+            .set_key(Some(build_key(self.partition_of(key), key.to_vec())))
+            .update_expression(format!("ADD {VALUE_ATTRIBUTE} :members"))
+            .expression_attribute_values(":members", AttributeValue::Bs(members))
+            .send()
+            .boxed()
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the `members` from the set stored under `key`, if present. The update is
+    /// atomic.
+    ///
+    /// Nothing is written if there is no set under `key`. Removing the last members
+    /// deletes the item, unless members were added again concurrently, so that no item
+    /// is left without value for the scans of the store.
+    pub async fn set_remove(
+        &self,
+        key: &[u8],
+        members: Vec<Vec<u8>>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let members = self.set_members(key, members)?;
+        if members.is_empty() {
+            return Ok(());
+        }
+        let _guard = self.acquire(0, 1).await;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        let response = self
+            .client
+            .update_item()
+            .table_name(&self.namespace)
+            .set_key(Some(key_db.clone()))
+            .update_expression(format!("DELETE {VALUE_ATTRIBUTE} :members"))
+            .condition_expression(format!("attribute_exists({VALUE_ATTRIBUTE})"))
+            .expression_attribute_values(":members", AttributeValue::Bs(members))
+            .return_values(ReturnValue::UpdatedNew)
+            .send()
+            .boxed()
+            .await;
+        let attributes = match response {
+            Ok(response) => response.attributes,
+            Err(SdkError::ServiceError(error))
+                if matches!(
+                    error.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                return Ok(());
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if attributes.is_some_and(|attributes| attributes.contains_key(VALUE_ATTRIBUTE)) {
+            return Ok(());
+        }
+        self.monitor_request_rate(0, 1);
+        let response = self
+            .client
+            .delete_item()
+            .table_name(&self.namespace)
             .set_key(Some(key_db))
+            .condition_expression(format!("attribute_not_exists({VALUE_ATTRIBUTE})"))
             .send()
             .boxed()
             .await;
-        let Err(error) = response else {
-            return Ok(true);
+        match response {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError(error))
+                if matches!(
+                    error.err(),
+                    DeleteItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Checks the `key` and the `members` of a set update, and returns the members
+    /// without duplicates, which DynamoDB rejects, as well as empty sets.
+    fn set_members(
+        &self,
+        key: &[u8],
+        members: Vec<Vec<u8>>,
+    ) -> Result<Vec<Blob>, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let members = members.into_iter().collect::<BTreeSet<_>>();
+        let size = members.iter().map(Vec::len).sum::<usize>();
+        ensure!(
+            size <= RAW_MAX_VALUE_SIZE,
+            DynamoDbStoreInternalError::ValueLengthTooLarge
+        );
+        Ok(members.into_iter().map(Blob::new).collect())
+    }
+
+    /// Reads the set written under `key` by [`Self::set_add`], which is empty if no set
+    /// was written.
+    pub async fn set_read(
+        &self,
+        key: &[u8],
+    ) -> Result<BTreeSet<Vec<u8>>, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        let response = {
+            let _guard = self.acquire(1, 0).await;
+            let mut clients = self.read_clients().peekable();
+            loop {
+                let client = clients.next().expect("there is always a primary client");
+                let response = client
+                    .get_item()
+                    .table_name(&self.namespace)
+                    .set_key(Some(key_db.clone()))
+                    .send()
+                    .boxed()
+                    .await;
+                match response {
+                    Err(error) if is_region_failure(&error) && clients.peek().is_some() => {}
+                    response => break response?,
+                }
+            }
         };
-        let test = match &error {
-            SdkError::ServiceError(error) => match error.err() {
-                GetItemError::ResourceNotFoundException(error) => {
-                    error.message
-                        == Some("Cannot do operations on a non-existent table".to_string())
+        match response
+            .item
+            .and_then(|mut item| item.remove(VALUE_ATTRIBUTE))
+        {
+            Some(AttributeValue::Bs(members)) => {
+                Ok(members.into_iter().map(Blob::into_inner).collect())
+            }
+            Some(AttributeValue::B(_)) => Err(DynamoDbStoreInternalError::WrongValueType(
+                "a binary blob".to_owned(),
+            )),
+            Some(value) => Err(DynamoDbStoreInternalError::WrongValueType(
+                DynamoDbStoreInternalError::type_description_of(&value),
+            )),
+            None => Ok(BTreeSet::new()),
+        }
+    }
+
+    /// Increments the counter stored under `key` and returns its new value, starting
+    /// from `1` if no counter was written. The increment is atomic, so that concurrent
+    /// callers never get the same value.
+    ///
+    /// The counter is stored as a DynamoDB number attribute: it is only read by this
+    /// method, and the other reads of the store fail with `WrongValueType` on this key.
+    pub async fn next_sequence(&self, key: &[u8]) -> Result<u64, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        self.write_root_key_if_needed().await?;
+        let response = {
+            let _guard = self.acquire(0, 1).await;
+            self.client
+                .update_item()
+                .table_name(&self.namespace)
+                .set_key(Some(build_key(self.partition_of(key), key.to_vec())))
+                .update_expression(format!("ADD {VALUE_ATTRIBUTE} :one"))
+                .expression_attribute_values(":one", AttributeValue::N("1".to_owned()))
+                .return_values(ReturnValue::UpdatedNew)
+                .send()
+                .boxed()
+                .await?
+        };
+        match response
+            .attributes
+            .and_then(|mut attributes| attributes.remove(VALUE_ATTRIBUTE))
+        {
+            Some(AttributeValue::N(number)) => number.parse().map_err(|_| {
+                DynamoDbStoreInternalError::WrongValueType(format!("the number {number}"))
+            }),
+            Some(AttributeValue::B(_)) => Err(DynamoDbStoreInternalError::WrongValueType(
+                "a binary blob".to_owned(),
+            )),
+            Some(value) => Err(DynamoDbStoreInternalError::wrong_value_type(&value)),
+            None => Err(DynamoDbStoreInternalError::MissingValue),
+        }
+    }
+
+    /// Sets the expiry of the item at `key` to `new_expiry` without rewriting its value,
+    /// e.g. to extend the life of a cached entry when it is read, and returns whether the
+    /// item exists. Nothing is written if it does not.
+    ///
+    /// The expiry is stored in seconds since the Unix epoch, in the `expiry` attribute, as
+    /// the time to live of DynamoDB expects: the item is deleted by DynamoDB some time
+    /// after it expires, provided that the time to live of the table is enabled on this
+    /// attribute, see [`Capabilities::time_to_live`]. The store does not enable it, and
+    /// reads the expired items until they are deleted. Writing the key replaces the item,
+    /// which then has no expiry.
+    pub async fn touch(
+        &self,
+        key: &[u8],
+        new_expiry: SystemTime,
+    ) -> Result<bool, DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let expiry = new_expiry
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let response = {
+            let _guard = self.acquire(0, 1).await;
+            self.client
+                .update_item()
+                .table_name(&self.namespace)
+                .set_key(Some(build_key(self.partition_of(key), key.to_vec())))
+                .update_expression(format!("SET {EXPIRY_ATTRIBUTE} = :expiry"))
+                .condition_expression(format!("attribute_exists({KEY_ATTRIBUTE})"))
+                .expression_attribute_values(":expiry", AttributeValue::N(expiry.to_string()))
+                .send()
+                .boxed()
+                .await
+        };
+        match response {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(error))
+                if matches!(
+                    error.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Counts the items of the whole table, over all the root keys, with a `Scan`.
+    ///
+    /// Unlike the item count of `DescribeTable`, which DynamoDB updates about every six
+    /// hours, the count is exact at the time of the scan, but it reads every item of the
+    /// table: this is expensive and slow on a large table, and meant for occasional
+    /// checks, e.g. after a migration. The table is scanned in `segments` parallel
+    /// segments, at least one. If `exclude_internal_items` is set, only the items of the
+    /// key-values of the views are counted, and not those of the journal, of the leases
+    /// or of the list of root keys.
+    pub async fn exact_item_count(
+        &self,
+        exclude_internal_items: bool,
+        segments: u32,
+    ) -> Result<u64, DynamoDbStoreInternalError> {
+        let segments = segments.max(1);
+        let counts =
+            try_join_all((0..segments).map(|segment| {
+                self.count_segment_items(exclude_internal_items, segment, segments)
+            }))
+            .await?;
+        Ok(counts.into_iter().sum())
+    }
+
+    /// Counts the items of the `segment` of a scan in `segments` segments.
+    async fn count_segment_items(
+        &self,
+        exclude_internal_items: bool,
+        segment: u32,
+        segments: u32,
+    ) -> Result<u64, DynamoDbStoreInternalError> {
+        let mut count = 0;
+        let mut start_key_map = None;
+        loop {
+            let response = {
+                let _guard = self.acquire(1, 0).await;
+                let mut builder = self
+                    .client
+                    .scan()
+                    .table_name(&self.namespace)
+                    .select(Select::Count)
+                    .set_exclusive_start_key(start_key_map)
+                    .set_limit(self.query_page_size);
+                if segments > 1 {
+                    builder = builder
+                        .segment(segment as i32)
+                        .total_segments(segments as i32);
                 }
-                _ => false,
-            },
-            _ => false,
+                if exclude_internal_items {
+                    builder = builder
+                        .filter_expression(format!(
+                            "{PARTITION_ATTRIBUTE} <> :root_keys and not begins_with({KEY_ATTRIBUTE}, :internal)"
+                        ))
+                        .expression_attribute_values(
+                            ":root_keys",
+                            AttributeValue::B(Blob::new(PARTITION_KEY_ROOT_KEY)),
+                        )
+                        .expression_attribute_values(
+                            ":internal",
+                            AttributeValue::B(Blob::new([INTERNAL_TAG])),
+                        );
+                }
+                builder.send().boxed().await?
+            };
+            count += response.count() as u64;
+            if response.last_evaluated_key.is_none() {
+                return Ok(count);
+            }
+            start_key_map = response.last_evaluated_key;
+        }
+    }
+
+    /// Estimates how close the table is to its provisioned capacity.
+    ///
+    /// The provisioned capacity is read with `DescribeTable`, and the consumed capacity
+    /// is the one reported by DynamoDB for the reads, queries and transactions of this
+    /// process over the last minute. Other processes using the table are not accounted
+    /// for. Tables billed on demand have no provisioned capacity.
+    pub async fn capacity_headroom(&self) -> Result<CapacityHeadroom, DynamoDbStoreInternalError> {
+        let response = {
+            let _guard = self.acquire(0, 0).await;
+            self.client
+                .describe_table()
+                .table_name(&self.namespace)
+                .send()
+                .boxed()
+                .await?
         };
-        if test {
-            Ok(false)
-        } else {
-            Err(error.into())
+        let throughput = response
+            .table()
+            .and_then(|table| table.provisioned_throughput());
+        Ok(CapacityHeadroom {
+            provisioned_read_units: throughput
+                .and_then(|throughput| throughput.read_capacity_units())
+                .filter(|units| *units > 0),
+            provisioned_write_units: throughput
+                .and_then(|throughput| throughput.write_capacity_units())
+                .filter(|units| *units > 0),
+            consumed_read_units: ConsumedCapacityTracker::units_per_second(
+                &self.consumed_capacity.reads,
+            ),
+            consumed_write_units: ConsumedCapacityTracker::units_per_second(
+                &self.consumed_capacity.writes,
+            ),
+        })
+    }
+
+    /// Reads all the keys of the store, in order.
+    async fn find_all_keys(&self) -> Result<Vec<Vec<u8>>, DynamoDbStoreInternalError> {
+        let mut keys = Vec::new();
+        let mut start_key_map = None;
+        loop {
+            let response = {
+                let _guard = self.acquire(1, 0).await;
+                self.client
+                    .query()
+                    .table_name(&self.namespace)
+                    .projection_expression(KEY_ATTRIBUTE)
+                    .key_condition_expression(format!("{PARTITION_ATTRIBUTE} = :partition"))
+                    .expression_attribute_values(
+                        ":partition",
+                        AttributeValue::B(Blob::new(self.start_key.clone())),
+                    )
+                    .set_exclusive_start_key(start_key_map)
+                    .set_limit(self.query_page_size)
+                    .send()
+                    .boxed()
+                    .await?
+            };
+            for mut item in response.items.unwrap_or_default() {
+                keys.push(extract_key_owned(0, &mut item)?);
+            }
+            start_key_map = response.last_evaluated_key;
+            if start_key_map.is_none() {
+                return Ok(keys);
+            }
         }
     }
 
-    async fn create(
-        config: &Self::Config,
-        namespace: &str,
-    ) -> Result<(), DynamoDbStoreInternalError> {
-        Self::check_namespace(namespace)?;
-        let client = config.client().await?;
-        client
-            .create_table()
-            .table_name(namespace)
-            .attribute_definitions(
-                AttributeDefinition::builder()
-                    .attribute_name(PARTITION_ATTRIBUTE)
-                    .attribute_type(ScalarAttributeType::B)
-                    .build()?,
-            )
-            .attribute_definitions(
-                AttributeDefinition::builder()
-                    .attribute_name(KEY_ATTRIBUTE)
-                    .attribute_type(ScalarAttributeType::B)
-                    .build()?,
-            )
-            .key_schema(
-                KeySchemaElement::builder()
-                    .attribute_name(PARTITION_ATTRIBUTE)
-                    .key_type(KeyType::Hash)
-                    .build()?,
-            )
-            .key_schema(
-                KeySchemaElement::builder()
-                    .attribute_name(KEY_ATTRIBUTE)
-                    .key_type(KeyType::Range)
-                    .build()?,
+    async fn contains_key_general(
+        &self,
+        key_db: HashMap<String, AttributeValue>,
+    ) -> Result<bool, DynamoDbStoreInternalError> {
+        let _guard = self.acquire(1, 0).await;
+        let mut clients = self.read_clients().peekable();
+        loop {
+            let client = clients.next().expect("there is always a primary client");
+            let response = client
+                .get_item()
+                .table_name(&self.namespace)
+                .set_key(Some(key_db.clone()))
+                .projection_expression(PARTITION_ATTRIBUTE)
+                .return_consumed_capacity(ReturnConsumedCapacity::Total)
+                .send()
+                .boxed()
+                .await;
+            match response {
+                Err(error) if is_region_failure(&error) && clients.peek().is_some() => {}
+                response => {
+                    let response = response?;
+                    self.consumed_capacity
+                        .record_read(response.consumed_capacity());
+                    return Ok(response.item.is_some());
+                }
+            }
+        }
+    }
+
+    async fn get_list_responses(
+        &self,
+        attribute: &str,
+        start_key: &[u8],
+        key_prefix: &[u8],
+    ) -> Result<QueryResponses, DynamoDbStoreInternalError> {
+        self.check_key_prefix(key_prefix)?;
+        let mut responses = Vec::new();
+        let mut start_key_map = None;
+        loop {
+            let response = self
+                .get_query_output(
+                    attribute,
+                    start_key,
+                    key_prefix,
+                    start_key_map,
+                    self.query_page_size,
+                    false,
+                )
+                .await?;
+            let last_evaluated = response.last_evaluated_key.clone();
+            responses.push(response);
+            match last_evaluated {
+                None => {
+                    break;
+                }
+                Some(value) => {
+                    start_key_map = Some(value);
+                }
+            }
+        }
+        Ok(QueryResponses {
+            prefix_len: key_prefix.len(),
+            value_checksums: self.value_checksums,
+            responses,
+            skipped_items: 0,
+        })
+    }
+
+    /// Reads a single page of the query for `key_prefix`, resuming after the key given by
+    /// `from_continuation`, if any. The prefix is not checked, and may be empty.
+    async fn get_list_page(
+        &self,
+        attribute: &str,
+        key_prefix: &[u8],
+        from_continuation: Option<Vec<u8>>,
+    ) -> Result<QueryResponses, DynamoDbStoreInternalError> {
+        let start_key = self.partition_of(key_prefix);
+        let start_key_map = from_continuation.map(|key| build_key(start_key, key));
+        let response = self
+            .get_query_output(
+                attribute,
+                start_key,
+                key_prefix,
+                start_key_map,
+                self.query_page_size,
+                false,
             )
-            .provisioned_throughput(
-                ProvisionedThroughput::builder()
-                    .read_capacity_units(10)
-                    .write_capacity_units(10)
-                    .build()?,
+            .await?;
+        Ok(QueryResponses {
+            prefix_len: key_prefix.len(),
+            value_checksums: self.value_checksums,
+            responses: vec![response],
+            skipped_items: 0,
+        })
+    }
+
+    /// Reads a single page of the keys under `key_prefix`, starting after the
+    /// `from_continuation` token returned with the previous page, if any.
+    ///
+    /// The size of the page is decided by DynamoDB and by `query_page_size`. The token
+    /// of the next page is given by [`DynamoDbKeys::continuation_token`].
+    pub async fn find_keys_by_prefix_from(
+        &self,
+        key_prefix: &[u8],
+        from_continuation: Option<Vec<u8>>,
+    ) -> Result<DynamoDbKeys, DynamoDbStoreInternalError> {
+        self.check_key_prefix(key_prefix)?;
+        let mut result_queries = self
+            .get_list_page(KEY_ATTRIBUTE, key_prefix, from_continuation)
+            .await?;
+        if self.malformed_items == MalformedItems::Skip {
+            result_queries.skip_malformed_items(false);
+        }
+        Ok(DynamoDbKeys { result_queries })
+    }
+
+    /// Reads the first key-value under `key_prefix` whose key is greater than `after`, if
+    /// any, and tells whether another key-value follows it under the prefix.
+    ///
+    /// This reads at most two items in a single request, which makes it suitable for
+    /// iterating over a prefix one entry at a time: the returned key is the full key, to
+    /// be passed as `after` to read the next entry. The key `after` must be under
+    /// `key_prefix`.
+    pub async fn read_with_next(
+        &self,
+        key_prefix: &[u8],
+        after: Option<&[u8]>,
+    ) -> Result<Option<((Vec<u8>, Vec<u8>), bool)>, DynamoDbStoreInternalError> {
+        check_key_size(key_prefix)?;
+        if let Some(after) = after {
+            check_key_size(after)?;
+        }
+        let start_key = self.partition_of(key_prefix);
+        let start_key_map = after.map(|key| build_key(start_key, key.to_vec()));
+        let response = self
+            .get_query_output(
+                KEY_VALUE_ATTRIBUTE,
+                start_key,
+                key_prefix,
+                start_key_map,
+                Some(2),
+                false,
             )
+            .await?;
+        let mut items = response.items.unwrap_or_default().into_iter();
+        let Some(mut item) = items.next() else {
+            return Ok(None);
+        };
+        let entry = extract_key_value_owned(0, &mut item, self.value_checksums)?;
+        Ok(Some((entry, items.next().is_some())))
+    }
+
+    /// Reads up to `total_cap` key-values under the given prefixes, taking one entry from
+    /// each prefix in turn until the cap is reached or all the prefixes are exhausted.
+    ///
+    /// The entries are returned with their full key, in round-robin order. Each query is
+    /// limited to the share of the cap that is left, so that the prefixes are not read
+    /// beyond what is returned by more than a few entries.
+    pub async fn find_key_values_capped(
+        &self,
+        key_prefixes: Vec<Vec<u8>>,
+        total_cap: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DynamoDbStoreInternalError> {
+        let mut queries = Vec::new();
+        for key_prefix in key_prefixes {
+            check_key_size(&key_prefix)?;
+            queries.push(CappedPrefixQuery {
+                key_prefix,
+                entries: VecDeque::new(),
+                next_page: Some(None),
+            });
+        }
+        let mut key_values = Vec::new();
+        while key_values.len() < total_cap && !queries.is_empty() {
+            let mut index = 0;
+            while index < queries.len() && key_values.len() < total_cap {
+                let query = &mut queries[index];
+                if query.entries.is_empty() {
+                    if let Some(start_key_map) = query.next_page.take() {
+                        let share = (total_cap - key_values.len()).div_ceil(queries.len());
+                        let limit = i32::try_from(share).unwrap_or(i32::MAX);
+                        let query = &mut queries[index];
+                        let response = self
+                            .get_query_output(
+                                KEY_VALUE_ATTRIBUTE,
+                                self.partition_of(&query.key_prefix),
+                                &query.key_prefix,
+                                start_key_map,
+                                Some(limit),
+                                false,
+                            )
+                            .await?;
+                        query.next_page = response.last_evaluated_key.map(Some);
+                        for mut item in response.items.unwrap_or_default() {
+                            let entry =
+                                extract_key_value_owned(0, &mut item, self.value_checksums)?;
+                            query.entries.push_back(entry);
+                        }
+                    }
+                }
+                let query = &mut queries[index];
+                match query.entries.pop_front() {
+                    Some(entry) => {
+                        key_values.push(entry);
+                        index += 1;
+                    }
+                    None if query.next_page.is_none() => {
+                        queries.remove(index);
+                    }
+                    // The page was empty but the query is not over: the next turn
+                    // reads the following page.
+                    None => index += 1,
+                }
+            }
+        }
+        Ok(key_values)
+    }
+
+    /// Reads a single page of the key-values under `key_prefix`, starting after the
+    /// `from_continuation` token returned with the previous page, if any.
+    ///
+    /// The token of the next page is given by [`DynamoDbKeyValues::continuation_token`].
+    pub async fn find_key_values_by_prefix_from(
+        &self,
+        key_prefix: &[u8],
+        from_continuation: Option<Vec<u8>>,
+    ) -> Result<DynamoDbKeyValues, DynamoDbStoreInternalError> {
+        self.check_key_prefix(key_prefix)?;
+        self.key_values_page(key_prefix, from_continuation).await
+    }
+
+    /// Reads a page of the key-values under `key_prefix`, which may be empty whatever the
+    /// configuration.
+    async fn key_values_page(
+        &self,
+        key_prefix: &[u8],
+        from_continuation: Option<Vec<u8>>,
+    ) -> Result<DynamoDbKeyValues, DynamoDbStoreInternalError> {
+        let mut result_queries = self
+            .get_list_page(KEY_VALUE_ATTRIBUTE, key_prefix, from_continuation)
+            .await?;
+        if self.malformed_items == MalformedItems::Skip {
+            result_queries.skip_malformed_items(true);
+        }
+        Ok(DynamoDbKeyValues { result_queries })
+    }
+
+    /// Streams the key-values under `key_prefix`, with the values deserialized from BCS.
+    ///
+    /// The keys are returned without the prefix, as in `find_key_values_by_prefix`. The
+    /// pages of the query are read as the stream is consumed, so that only one page is
+    /// held in memory at a time. A value that cannot be deserialized is reported with
+    /// its key by [`DynamoDbStoreInternalError::BcsErrorAtKey`].
+    pub fn find_typed_by_prefix<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Stream<Item = Result<(Vec<u8>, T), DynamoDbStoreInternalError>> + 'a {
+        // The state is the continuation token of the next page, if there is one.
+        futures::stream::try_unfold(Some(None), move |continuation| async move {
+            let Some(continuation) = continuation else {
+                return Ok::<_, DynamoDbStoreInternalError>(None);
+            };
+            let page = self
+                .find_key_values_by_prefix_from(key_prefix, continuation)
+                .await?;
+            let next_continuation = page.continuation_token().map(Some);
+            let entries = page.into_iterator_owned().map(|result| {
+                let (key, value) = result?;
+                match bcs::from_bytes(&value) {
+                    Ok(value) => Ok((key, value)),
+                    Err(error) => Err(DynamoDbStoreInternalError::BcsErrorAtKey { key, error }),
+                }
+            });
+            let entries = futures::stream::iter(entries.collect::<Vec<_>>());
+            Ok(Some((entries, next_continuation)))
+        })
+        .try_flatten()
+    }
+
+    /// Writes the key-values under `key_prefix` to `writer`, and returns the number of
+    /// records written.
+    ///
+    /// Each record is the length of the key as a little-endian `u32`, the key without
+    /// the prefix, then the length of the value as a little-endian `u32` and the value.
+    /// The pages of the query are written as they are read, so that only one page is
+    /// held in memory at a time. The records are read back by
+    /// [`DynamoDbStoreInternal::import_prefix`].
+    pub async fn export_prefix<W: AsyncWrite + Unpin>(
+        &self,
+        key_prefix: &[u8],
+        writer: &mut W,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        let mut num_records = 0;
+        let mut continuation = None;
+        loop {
+            let page = self
+                .find_key_values_by_prefix_from(key_prefix, continuation)
+                .await?;
+            continuation = page.continuation_token();
+            for result in page.into_iterator_owned() {
+                let (key, value) = result?;
+                writer.write_all(&(key.len() as u32).to_le_bytes()).await?;
+                writer.write_all(&key).await?;
+                writer
+                    .write_all(&(value.len() as u32).to_le_bytes())
+                    .await?;
+                writer.write_all(&value).await?;
+                num_records += 1;
+            }
+            if continuation.is_none() {
+                break;
+            }
+        }
+        writer.flush().await?;
+        Ok(num_records)
+    }
+
+    /// Reads the records written by [`DynamoDbStoreInternal::export_prefix`] from
+    /// `reader` and writes them under `key_prefix`, which need not be the prefix of the
+    /// export. Returns the number of records written.
+    ///
+    /// The import is not atomic and does not go through the journal: the records are
+    /// written as they are read, in transactions sized like those of the journal, so that
+    /// a failed import leaves some of the records written. The keys that are already
+    /// present are skipped, so that an interrupted import can be resumed by running it
+    /// again.
+    pub async fn import_prefix<R: AsyncRead + Unpin>(
+        &self,
+        key_prefix: &[u8],
+        reader: &mut R,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        let mut num_records = 0;
+        let mut insertions = Vec::new();
+        let mut batch_size = 0;
+        loop {
+            let record = match read_record_part(reader, MAX_KEY_SIZE).await? {
+                Some(suffix) => {
+                    let value = read_record_part(reader, VISIBLE_MAX_VALUE_SIZE)
+                        .await?
+                        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+                    let mut key = key_prefix.to_vec();
+                    key.extend(suffix);
+                    Some((key, value))
+                }
+                None => None,
+            };
+            let record_size = record.as_ref().map_or(0, |(key, value)| {
+                DirectWritableKeyValueStore::size_of_item(self, key.len(), value.len())
+            });
+            let flush = record.is_none()
+                || insertions.len() == <Self as DirectWritableKeyValueStore>::MAX_BATCH_SIZE
+                || batch_size + record_size
+                    > <Self as DirectWritableKeyValueStore>::MAX_BATCH_TOTAL_SIZE;
+            if flush && !insertions.is_empty() {
+                let keys = insertions
+                    .iter()
+                    .map(|(key, _)| key)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let present = self.contains_keys(keys).await?;
+                let insertions = std::mem::take(&mut insertions)
+                    .into_iter()
+                    .zip(present)
+                    .filter_map(|(insertion, present)| (!present).then_some(insertion))
+                    .collect::<Vec<_>>();
+                num_records += insertions.len();
+                let batch = SimpleUnorderedBatch {
+                    deletions: Vec::new(),
+                    insertions,
+                };
+                DirectWritableKeyValueStore::write_batch(self, batch).await?;
+                batch_size = 0;
+            }
+            let Some(record) = record else {
+                return Ok(num_records);
+            };
+            batch_size += record_size;
+            insertions.push(record);
+        }
+    }
+
+    /// Registers the root key of this store in the table of root keys, if not already done.
+    async fn write_root_key_if_needed(&self) -> Result<(), DynamoDbStoreInternalError> {
+        if !self.root_key_written.fetch_or(true, Ordering::SeqCst) {
+            let transaction =
+                self.build_put_transaction(PARTITION_KEY_ROOT_KEY, self.start_key.clone(), vec![])?;
+            self.write_items(vec![transaction]).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the items in a single transaction or, if the backend does not support
+    /// transactions, with `BatchWriteItem` requests. The operations are logged as set by
+    /// [`DynamoDbStoreInternalConfig::with_batch_dump`].
+    async fn write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        if self.batch_dump == BatchDump::Off {
+            return self.send_write_items(items).await;
+        }
+        let summaries = summarize_write_items(&items);
+        let result = self.send_write_items(items).await;
+        match &result {
+            Err(error) => tracing::warn!(
+                namespace = %self.namespace,
+                operations = ?summaries,
+                "Failed to write {} items: {error}",
+                summaries.len()
+            ),
+            Ok(()) if self.batch_dump == BatchDump::Always => tracing::debug!(
+                namespace = %self.namespace,
+                operations = ?summaries,
+                "Wrote {} items",
+                summaries.len()
+            ),
+            Ok(()) => {}
+        }
+        result
+    }
+
+    /// Sends the items of [`Self::write_items`].
+    async fn send_write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        self.monitor_request_rate(0, items.len() as u64);
+        if self.transactions_supported.load(Ordering::Relaxed) {
+            let response = self
+                .client
+                .transact_write_items()
+                .set_transact_items(Some(items.clone()))
+                .set_return_item_collection_metrics(
+                    self.return_item_collection_metrics
+                        .then_some(ReturnItemCollectionMetrics::Size),
+                )
+                .return_consumed_capacity(ReturnConsumedCapacity::Total)
+                .send()
+                .boxed()
+                .await;
+            match response {
+                Err(error) if is_unsupported_operation(&error) => {
+                    tracing::error!(
+                        "TransactWriteItems is not supported by the backend, \
+                         falling back to non-atomic writes: {error}"
+                    );
+                    self.transactions_supported.store(false, Ordering::Relaxed);
+                }
+                response => {
+                    let response = response?;
+                    report_item_collection_metrics(&response);
+                    self.consumed_capacity
+                        .record_write(response.consumed_capacity());
+                    return Ok(());
+                }
+            }
+        }
+        self.write_items_without_transaction(items).await
+    }
+
+    /// Writes the items with non-atomic `BatchWriteItem` requests, retrying the
+    /// unprocessed items.
+    async fn write_items_without_transaction(
+        &self,
+        items: Vec<TransactWriteItem>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let mut requests = Vec::new();
+        for item in items {
+            if let Some(put) = item.put {
+                let request = PutRequest::builder().set_item(Some(put.item)).build()?;
+                requests.push(WriteRequest::builder().put_request(request).build());
+            } else if let Some(delete) = item.delete {
+                let request = DeleteRequest::builder().set_key(Some(delete.key)).build()?;
+                requests.push(WriteRequest::builder().delete_request(request).build());
+            }
+        }
+        for chunk in requests.chunks(MAX_BATCH_WRITE_ITEM_SIZE) {
+            let mut pending = chunk.to_vec();
+            let mut attempt = 0;
+            while !pending.is_empty() {
+                let output = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.namespace, pending)
+                    .send()
+                    .boxed()
+                    .await?;
+                pending = output
+                    .unprocessed_items
+                    .and_then(|mut items| items.remove(&self.namespace))
+                    .unwrap_or_default();
+                if !pending.is_empty() {
+                    let delay = self
+                        .backoff
+                        .next_delay(attempt)
+                        .ok_or(DynamoDbStoreInternalError::RetriesExhausted)?;
+                    timer::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the `batch` against the constraints of DynamoDB and of the store, without
+    /// writing it or making any request, and reports all the violations found.
+    ///
+    /// The deletions of key prefixes are not expanded into the deletions of their keys,
+    /// which requires reading them. A batch deleting key prefixes may thus need the
+    /// journal even though [`BatchValidation::fits_fastpath`] is true.
+    pub fn validate_batch(
+        &self,
+        batch: &Batch,
+    ) -> Result<BatchValidation, DynamoDbStoreInternalError> {
+        let mut violations = Vec::new();
+        let is_reserved = |key: &[u8]| key.first().is_none_or(|tag| *tag < MIN_VIEW_TAG);
+        for (index, operation) in batch.operations.iter().enumerate() {
+            let (key, value) = match operation {
+                WriteOperation::DeletePrefix { key_prefix } => {
+                    if is_reserved(key_prefix) {
+                        violations.push(BatchViolation::ReservedKey { index });
+                    }
+                    continue;
+                }
+                WriteOperation::Delete { key } => (key, None),
+                WriteOperation::Put { key, value } => (key, Some(value)),
+            };
+            if key.is_empty() {
+                violations.push(BatchViolation::EmptyKey { index });
+                continue;
+            }
+            if key.len() > MAX_KEY_SIZE {
+                violations.push(BatchViolation::KeyTooLong {
+                    index,
+                    length: key.len(),
+                });
+            }
+            if is_reserved(key) {
+                violations.push(BatchViolation::ReservedKey { index });
+            }
+            if let Err(DynamoDbStoreInternalError::InvalidKey(reason)) = self.validate_key(key) {
+                violations.push(BatchViolation::RejectedKey { index, reason });
+            }
+            let Some(value) = value else {
+                continue;
+            };
+            let length = self.stored_value_len(value.len());
+            if length > RAW_MAX_VALUE_SIZE {
+                violations.push(BatchViolation::ValueTooLarge { index, length });
+            }
+            let size = self.stored_item_size(self.partition_of(key).len(), key.len(), value.len());
+            if size > MAX_ITEM_SIZE {
+                violations.push(BatchViolation::ItemTooLarge { index, size });
+            }
+        }
+        let UnorderedBatch {
+            key_prefix_deletions,
+            simple_unordered_batch,
+        } = batch.clone().simplify();
+        let fits_fastpath = simple_unordered_batch.len()
+            <= <Self as DirectWritableKeyValueStore>::MAX_BATCH_SIZE
+            && simple_unordered_batch.items_size(|key_len, value_len| {
+                DirectWritableKeyValueStore::size_of_item(self, key_len, value_len)
+            }) <= <Self as DirectWritableKeyValueStore>::MAX_BATCH_TOTAL_SIZE;
+        Ok(BatchValidation {
+            violations,
+            fits_fastpath,
+            prefix_deletions: key_prefix_deletions.len(),
+        })
+    }
+
+    /// Returns the length of a value of `value_len` bytes once encoded for DynamoDB.
+    fn stored_value_len(&self, value_len: usize) -> usize {
+        if !self.value_checksums {
+            value_len
+        } else if value_len < self.min_transform_size {
+            1 + value_len
+        } else {
+            VALUE_CHECKSUM_HEADER_SIZE + value_len
+        }
+    }
+
+    /// Returns the size of the item written for a key of `key_len` bytes and a value of
+    /// `value_len` bytes, with a partition key of `start_key_len` bytes.
+    fn stored_item_size(&self, start_key_len: usize, key_len: usize, value_len: usize) -> usize {
+        let size = key_value_item_size(start_key_len, key_len, self.stored_value_len(value_len));
+        if self.record_modification_times {
+            size + LAST_MODIFIED_ATTRIBUTE.len() + MAX_NUMBER_SIZE
+        } else {
+            size
+        }
+    }
+
+    /// Writes the `batch` with one transaction per partition key, instead of the single
+    /// transaction of `write_batch`, and returns the number of transactions.
+    ///
+    /// The atomicity boundary is the partition: the operations on the keys of a partition
+    /// are written together or not at all, but nothing is guaranteed across partitions.
+    /// The partitions are written in increasing order of their partition keys, so the data
+    /// is written before the internal items stored apart, see
+    /// [`DynamoDbStoreInternalConfig::with_separate_internal_partition`]. If a
+    /// transaction fails, the partitions written before it are left written and the
+    /// following ones are not written. A write that must be atomic across partitions has
+    /// to go through the journal of a [`JournalingKeyValueStore`] instead.
+    ///
+    /// The operations of every partition must fit in a transaction, otherwise
+    /// [`DynamoDbStoreInternalError::TransactUpperLimitSize`] is returned and nothing is
+    /// written.
+    pub async fn write_batch_per_partition(
+        &self,
+        batch: SimpleUnorderedBatch,
+    ) -> Result<usize, DynamoDbStoreInternalError> {
+        let mut partitions = BTreeMap::<Vec<u8>, SimpleUnorderedBatch>::new();
+        for key in batch.deletions {
+            let partition = self.partition_of(&key).to_vec();
+            partitions.entry(partition).or_default().deletions.push(key);
+        }
+        for (key, value) in batch.insertions {
+            let partition = self.partition_of(&key).to_vec();
+            partitions
+                .entry(partition)
+                .or_default()
+                .insertions
+                .push((key, value));
+        }
+        ensure!(
+            partitions
+                .values()
+                .all(|batch| batch.len() <= MAX_TRANSACT_WRITE_ITEM_SIZE),
+            DynamoDbStoreInternalError::TransactUpperLimitSize
+        );
+        let count = partitions.len();
+        for batch in partitions.into_values() {
+            DirectWritableKeyValueStore::write_batch(self, batch).await?;
+        }
+        Ok(count)
+    }
+
+    /// Writes the `batch` atomically, conditioned on the version stored at `version_key`
+    /// being `expected_version` (`None` meaning that no version was ever written).
+    ///
+    /// The version is incremented in the same transaction and the new version is
+    /// returned. If a concurrent writer changed the version in the meantime, nothing is
+    /// written and [`DynamoDbStoreInternalError::VersionConflict`] is returned, so that
+    /// the caller can retry the whole computation. The stored version is a BCS-serialized
+    /// `u64` and can be read back with `read_value::<u64>`.
+    pub async fn write_batch_with_version(
+        &self,
+        batch: SimpleUnorderedBatch,
+        version_key: &[u8],
+        expected_version: Option<u64>,
+    ) -> Result<u64, DynamoDbStoreInternalError> {
+        ensure!(
+            batch.len() < MAX_TRANSACT_WRITE_ITEM_SIZE,
+            DynamoDbStoreInternalError::TransactUpperLimitSize
+        );
+        ensure!(
+            !batch.deletions.iter().any(|key| key == version_key)
+                && !batch.insertions.iter().any(|(key, _)| key == version_key),
+            DynamoDbStoreInternalError::VersionKeyInBatch
+        );
+        self.validate_key(version_key)?;
+        self.write_root_key_if_needed().await?;
+        let new_version = expected_version.map_or(0, |version| version + 1);
+        let mut builder = TransactionBuilder::default();
+        for key in batch.deletions {
+            builder.insert_delete_request(key, self)?;
+        }
+        for (key, value) in batch.insertions {
+            builder.insert_put_request(key, value, self)?;
+        }
+        let transaction =
+            self.build_version_guard_transaction(version_key, expected_version, new_version)?;
+        builder.transactions.push(transaction);
+        let _guard = self.acquire(0, builder.transactions.len() as u64).await;
+        let response = self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(builder.transactions))
             .send()
             .boxed()
-            .await?;
-        Ok(())
+            .await;
+        match response {
+            Ok(_) => Ok(new_version),
+            Err(error) if is_conditional_check_failure(&error) => {
+                Err(DynamoDbStoreInternalError::VersionConflict)
+            }
+            Err(error) => Err(error.into()),
+        }
     }
 
-    async fn delete(
-        config: &Self::Config,
-        namespace: &str,
+    /// Atomically writes `new_value` at `index_key(prefix, new_index)` and deletes the
+    /// entry at `index_key(prefix, evict_index)`, so that a log bounded by the caller
+    /// never exceeds its bound, even transiently.
+    ///
+    /// The two indices must differ, otherwise
+    /// [`DynamoDbStoreInternalError::AppendEvictsItself`] is returned. If the transaction
+    /// is canceled by a concurrent write, nothing is written and
+    /// [`DynamoDbStoreInternalError::TransactionConflict`] is returned, so that the caller
+    /// can retry.
+    pub async fn append_bounded(
+        &self,
+        prefix: &[u8],
+        new_index: u64,
+        new_value: Vec<u8>,
+        evict_index: u64,
     ) -> Result<(), DynamoDbStoreInternalError> {
-        Self::check_namespace(namespace)?;
-        let client = config.client().await?;
-        client
-            .delete_table()
-            .table_name(namespace)
+        ensure!(
+            new_index != evict_index,
+            DynamoDbStoreInternalError::AppendEvictsItself { index: new_index }
+        );
+        self.write_root_key_if_needed().await?;
+        let mut builder = TransactionBuilder::default();
+        builder.insert_put_request(index_key(prefix, new_index), new_value, self)?;
+        builder.insert_delete_request(index_key(prefix, evict_index), self)?;
+        let _guard = self.acquire(0, 2).await;
+        let response = self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(builder.transactions))
             .send()
             .boxed()
-            .await?;
-        Ok(())
+            .await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(error) if is_transaction_canceled(&error) => {
+                Err(DynamoDbStoreInternalError::TransactionConflict)
+            }
+            Err(error) => Err(error.into()),
+        }
     }
-}
 
-impl DynamoDbStoreInternal {
-    /// Namespaces are named table names in DynamoDB [naming
-    /// rules](https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/HowItWorks.NamingRulesDataTypes.html#HowItWorks.NamingRules),
-    /// so we need to check correctness of the namespace
-    fn check_namespace(namespace: &str) -> Result<(), InvalidNamespace> {
-        if namespace.len() < 3 {
-            return Err(InvalidNamespace::TooShort);
-        }
-        if namespace.len() > 255 {
-            return Err(InvalidNamespace::TooLong);
-        }
-        if !namespace.chars().all(|character| {
-            character.is_ascii_alphanumeric()
-                || character == '.'
-                || character == '-'
-                || character == '_'
-        }) {
-            return Err(InvalidNamespace::InvalidCharacter);
-        }
-        Ok(())
+    /// Writes `value` at `key` if the value currently stored at `key` satisfies
+    /// `condition`. Otherwise, nothing is written and
+    /// [`DynamoDbStoreInternalError::ConditionFailed`] is returned.
+    pub async fn write_conditional(
+        &self,
+        key: &[u8],
+        value: Vec<u8>,
+        condition: &ConditionExpr,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let value = self.encode_value(value);
+        ensure!(
+            value.len() <= RAW_MAX_VALUE_SIZE,
+            DynamoDbStoreInternalError::ValueLengthTooLarge
+        );
+        self.write_root_key_if_needed().await?;
+        let item = build_key_value(self.partition_of(key), key.to_vec(), value);
+        check_item_size(&item)?;
+        let mut values = HashMap::new();
+        let expression = condition.compile(&mut values, &|value| self.encode_value(value));
+        let request = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(item))
+            .condition_expression(expression)
+            .set_expression_attribute_values((!values.is_empty()).then_some(values))
+            .build()?;
+        self.transact_conditional(TransactWriteItem::builder().put(request).build())
+            .await
     }
 
-    fn build_delete_transaction(
+    /// Deletes `key` if the value currently stored at `key` satisfies `condition`.
+    /// Otherwise, nothing is deleted and [`DynamoDbStoreInternalError::ConditionFailed`]
+    /// is returned.
+    async fn delete_conditional(
         &self,
-        start_key: &[u8],
-        key: Vec<u8>,
-    ) -> Result<TransactWriteItem, DynamoDbStoreInternalError> {
-        check_key_size(&key)?;
+        key: &[u8],
+        condition: &ConditionExpr,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let mut values = HashMap::new();
+        let expression = condition.compile(&mut values, &|value| self.encode_value(value));
         let request = Delete::builder()
             .table_name(&self.namespace)
-            .set_key(Some(build_key(start_key, key)))
+            .set_key(Some(build_key(self.partition_of(key), key.to_vec())))
+            .condition_expression(expression)
+            .set_expression_attribute_values((!values.is_empty()).then_some(values))
             .build()?;
-        Ok(TransactWriteItem::builder().delete(request).build())
+        self.transact_conditional(TransactWriteItem::builder().delete(request).build())
+            .await
     }
 
-    fn build_put_transaction(
+    /// Writes a single conditional item, mapping the failure of its condition to
+    /// [`DynamoDbStoreInternalError::ConditionFailed`].
+    async fn transact_conditional(
         &self,
-        start_key: &[u8],
-        key: Vec<u8>,
-        value: Vec<u8>,
-    ) -> Result<TransactWriteItem, DynamoDbStoreInternalError> {
-        check_key_size(&key)?;
-        ensure!(
-            value.len() <= RAW_MAX_VALUE_SIZE,
-            DynamoDbStoreInternalError::ValueLengthTooLarge
+        item: TransactWriteItem,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        let _guard = self.acquire(0, 1).await;
+        let response = self
+            .client
+            .transact_write_items()
+            .transact_items(item)
+            .send()
+            .boxed()
+            .await;
+        match response {
+            Ok(_) => Ok(()),
+            Err(error) if is_conditional_check_failure(&error) => {
+                Err(DynamoDbStoreInternalError::ConditionFailed)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Replaces the value at `key` by the result of `f` on the current value, or deletes
+    /// `key` if `f` returns `None`.
+    ///
+    /// The value is read with a strongly consistent read, and the result is only written
+    /// if the value at `key` did not change since it was read. Otherwise, the whole
+    /// read-modify-write cycle is retried, so `f` may be called several times. After the number of attempts set by
+    /// [`DynamoDbStoreInternalConfig::with_max_update_attempts`],
+    /// [`DynamoDbStoreInternalError::UpdateConflict`] is returned.
+    pub async fn update(
+        &self,
+        key: &[u8],
+        f: impl Fn(Option<Vec<u8>>) -> Option<Vec<u8>>,
+    ) -> Result<(), DynamoDbStoreInternalError> {
+        self.check_key(key)?;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        for _ in 0..self.max_update_attempts {
+            let value = self.read_value_bytes_general(key_db.clone(), true).await?;
+            let condition = match &value {
+                None => ConditionExpr::NotExists,
+                Some(value) => ConditionExpr::ValueEquals(value.clone()),
+            };
+            let result = match (value.is_some(), f(value)) {
+                (false, None) => return Ok(()),
+                (_, Some(new_value)) => self.write_conditional(key, new_value, &condition).await,
+                (true, None) => self.delete_conditional(key, &condition).await,
+            };
+            match result {
+                Err(DynamoDbStoreInternalError::ConditionFailed) => {}
+                result => return result,
+            }
+        }
+        Err(DynamoDbStoreInternalError::UpdateConflict)
+    }
+
+    /// Acquires the advisory lease of the root key of this store for `ttl`, failing with
+    /// [`DynamoDbStoreInternalError::LeaseHeld`] if another holder has a lease that has
+    /// not expired.
+    ///
+    /// The journal assumes a single writer per root key: processes sharing a root key
+    /// should hold the lease around their writes. The lease is only advisory, so writes
+    /// are not blocked for the processes that do not take it. Expiries are computed with
+    /// the local clock, so the `ttl` should leave a margin for the clock skew between
+    /// processes. Long operations should call [`Lease::renew`] before the lease expires.
+    pub async fn acquire_lease(&self, ttl: Duration) -> Result<Lease, DynamoDbStoreInternalError> {
+        let holder = self.encode_value(rand::random::<u64>().to_le_bytes().to_vec());
+        let now = AttributeValue::N(unix_millis(SystemTime::now()).to_string());
+        let condition =
+            format!("attribute_not_exists({KEY_ATTRIBUTE}) OR {LEASE_EXPIRY_ATTRIBUTE} < :now");
+        let expiry = self
+            .put_lease_item(
+                &holder,
+                ttl,
+                condition,
+                HashMap::from([(":now".into(), now)]),
+            )
+            .await
+            .map_err(|error| match error {
+                DynamoDbStoreInternalError::ConditionFailed => {
+                    DynamoDbStoreInternalError::LeaseHeld
+                }
+                error => error,
+            })?;
+        Ok(Lease {
+            store: self.clone(),
+            holder,
+            expiry,
+            released: false,
+        })
+    }
+
+    /// Writes the lease item held by `holder`, expiring after `ttl`, if `condition`
+    /// holds. Returns the expiry of the lease.
+    async fn put_lease_item(
+        &self,
+        holder: &[u8],
+        ttl: Duration,
+        condition: String,
+        values: HashMap<String, AttributeValue>,
+    ) -> Result<SystemTime, DynamoDbStoreInternalError> {
+        self.write_root_key_if_needed().await?;
+        let expiry = SystemTime::now() + ttl;
+        let key = get_internal_key(InternalKeyKind::Lease, &[]);
+        let mut item = build_key_value(self.partition_of(&key), key, holder.to_vec());
+        item.insert(
+            LEASE_EXPIRY_ATTRIBUTE.to_owned(),
+            AttributeValue::N(unix_millis(expiry).to_string()),
         );
         let request = Put::builder()
             .table_name(&self.namespace)
-            .set_item(Some(build_key_value(start_key, key, value)))
+            .set_item(Some(item))
+            .condition_expression(condition)
+            .set_expression_attribute_values(Some(values))
             .build()?;
-        Ok(TransactWriteItem::builder().put(request).build())
+        let _guard = self.acquire(0, 1).await;
+        let response = self
+            .client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().put(request).build())
+            .send()
+            .boxed()
+            .await;
+        match response {
+            Ok(_) => Ok(expiry),
+            Err(error) if is_conditional_check_failure(&error) => {
+                Err(DynamoDbStoreInternalError::ConditionFailed)
+            }
+            Err(error) => Err(error.into()),
+        }
     }
 
-    /// Obtains the semaphore lock on the database if needed.
-    async fn acquire(&self) -> Option<SemaphoreGuard<'_>> {
-        match &self.semaphore {
-            None => None,
-            Some(count) => Some(count.acquire().await),
+    /// Deletes the lease item if it is still held by `holder`.
+    async fn delete_lease_item(&self, holder: &[u8]) -> Result<(), DynamoDbStoreInternalError> {
+        let key = get_internal_key(InternalKeyKind::Lease, &[]);
+        let request = Delete::builder()
+            .table_name(&self.namespace)
+            .set_key(Some(build_key(self.partition_of(&key), key)))
+            .condition_expression(format!("{VALUE_ATTRIBUTE} = :holder"))
+            .expression_attribute_values(":holder", AttributeValue::B(Blob::new(holder)))
+            .build()?;
+        let _guard = self.acquire(0, 1).await;
+        let response = self
+            .client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().delete(request).build())
+            .send()
+            .boxed()
+            .await;
+        match response {
+            // The lease expired and was taken by another holder: there is nothing to do.
+            Ok(_) => Ok(()),
+            Err(error) if is_conditional_check_failure(&error) => Ok(()),
+            Err(error) => Err(error.into()),
         }
     }
 
-    async fn get_query_output(
-        &self,
-        attribute_str: &str,
-        start_key: &[u8],
-        key_prefix: &[u8],
-        start_key_map: Option<HashMap<String, AttributeValue>>,
-    ) -> Result<QueryOutput, DynamoDbStoreInternalError> {
-        let _guard = self.acquire().await;
-        let start_key = start_key.to_vec();
+    /// Probes the limits that the backend actually enforces, by writing throwaway values
+    /// of increasing sizes to a sentinel key of this root key, which is deleted afterwards.
+    ///
+    /// The size of the values is found by a binary search, so that about 22 writes of up
+    /// to 4 MB are done: the probe is meant to run once, for instance when deploying on a
+    /// DynamoDB-compatible backend, and its result to be cached.
+    pub async fn probe_limits(&self) -> Result<BackendLimits, DynamoDbStoreInternalError> {
+        self.write_root_key_if_needed().await?;
+        let key = get_internal_key(InternalKeyKind::LimitProbe, &[]);
+        let max_value_size = self.probe_max_value_size(&key).await;
+        let partition_len = self.partition_of(&key).len();
+        let key_len = key.len();
+        let transaction = self.build_delete_transaction(self.partition_of(&key), key)?;
+        let deletion = self.write_items(vec![transaction]).await;
+        let max_value_size = max_value_size?;
+        deletion?;
+        let max_item_size = key_value_item_size(partition_len, key_len, max_value_size);
+        Ok(BackendLimits {
+            max_value_size,
+            max_item_size,
+        })
+    }
+
+    /// Returns the optional features of DynamoDB supported by the backend, so that the
+    /// callers can choose their code paths, e.g. on DynamoDB-compatible databases.
+    ///
+    /// The capabilities are the ones of the configuration, see
+    /// [`DynamoDbStoreInternalConfig::with_capabilities`], or else are probed with a
+    /// request per feature, which only reads, the first time they are needed. The result
+    /// is shared by the stores created by `clone_with_root_key`. The transactions are
+    /// reported as unsupported once the store fell back to non-atomic writes.
+    pub async fn capabilities(&self) -> Result<Capabilities, DynamoDbStoreInternalError> {
+        let capabilities = *self
+            .capabilities
+            .get_or_try_init(|| self.probe_capabilities())
+            .await?;
+        Ok(Capabilities {
+            transactions: capabilities.transactions
+                && self.transactions_supported.load(Ordering::Relaxed),
+            ..capabilities
+        })
+    }
+
+    /// Probes the features of [`Self::capabilities`]. The operations that the backend
+    /// does not implement are the ones rejected as unsupported.
+    async fn probe_capabilities(&self) -> Result<Capabilities, DynamoDbStoreInternalError> {
+        let key = get_internal_key(InternalKeyKind::LimitProbe, &[]);
+        let partition = self.partition_of(&key).to_vec();
+        let _guard = self.acquire(1, 0).await;
+        let transactions = if self.transactions_supported.load(Ordering::Relaxed) {
+            let get = Get::builder()
+                .table_name(&self.namespace)
+                .set_key(Some(build_key(&partition, key.clone())))
+                .build()?;
+            let response = self
+                .client
+                .transact_get_items()
+                .transact_items(TransactGetItem::builder().get(get).build())
+                .send()
+                .boxed()
+                .await;
+            match response {
+                Err(error) if is_unsupported_operation(&error) => false,
+                response => response.map(|_| true)?,
+            }
+        } else {
+            false
+        };
         let response = self
             .client
-            .query()
+            .describe_time_to_live()
             .table_name(&self.namespace)
-            .projection_expression(attribute_str)
-            .key_condition_expression(format!(
-                "{PARTITION_ATTRIBUTE} = :partition and begins_with({KEY_ATTRIBUTE}, :prefix)"
+            .send()
+            .boxed()
+            .await;
+        let time_to_live = match response {
+            Err(error) if is_unsupported_operation(&error) => false,
+            response => response.map(|_| true)?,
+        };
+        let response = self
+            .client
+            .execute_statement()
+            .statement(format!(
+                "SELECT {KEY_ATTRIBUTE} FROM \"{}\" WHERE {PARTITION_ATTRIBUTE} = ? AND {KEY_ATTRIBUTE} = ?",
+                self.namespace
             ))
-            .expression_attribute_values(":partition", AttributeValue::B(Blob::new(start_key)))
-            .expression_attribute_values(":prefix", AttributeValue::B(Blob::new(key_prefix)))
-            .set_exclusive_start_key(start_key_map)
+            .parameters(AttributeValue::B(Blob::new(partition)))
+            .parameters(AttributeValue::B(Blob::new(key)))
             .send()
             .boxed()
-            .await?;
-        Ok(response)
+            .await;
+        let partiql = match response {
+            Err(error) if is_unsupported_operation(&error) => false,
+            response => response.map(|_| true)?,
+        };
+        Ok(Capabilities {
+            transactions,
+            time_to_live,
+            partiql,
+        })
     }
 
-    async fn read_value_bytes_general(
+    /// Returns the size of the largest value accepted at `key`, up to
+    /// `MAX_TRANSACT_WRITE_ITEM_TOTAL_SIZE`.
+    async fn probe_max_value_size(&self, key: &[u8]) -> Result<usize, DynamoDbStoreInternalError> {
+        // A value of size `accepted` is accepted and one of size `rejected` is not.
+        let mut accepted = 0;
+        let mut rejected = MAX_TRANSACT_WRITE_ITEM_TOTAL_SIZE + 1;
+        while rejected - accepted > 1 {
+            let size = accepted + (rejected - accepted) / 2;
+            if self.try_put_probe_value(key, size).await? {
+                accepted = size;
+            } else {
+                rejected = size;
+            }
+        }
+        Ok(accepted)
+    }
+
+    /// Writes a value of `size` bytes at `key`, bypassing the checks of the store.
+    /// Returns whether the backend accepted it.
+    async fn try_put_probe_value(
         &self,
-        key_db: HashMap<String, AttributeValue>,
-    ) -> Result<Option<Vec<u8>>, DynamoDbStoreInternalError> {
-        let _guard = self.acquire().await;
+        key: &[u8],
+        size: usize,
+    ) -> Result<bool, DynamoDbStoreInternalError> {
+        let request = Put::builder()
+            .table_name(&self.namespace)
+            .set_item(Some(build_key_value(
+                self.partition_of(key),
+                key.to_vec(),
+                vec![0; size],
+            )))
+            .build()?;
+        let _guard = self.acquire(0, 1).await;
         let response = self
             .client
-            .get_item()
-            .table_name(&self.namespace)
-            .set_key(Some(key_db))
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().put(request).build())
             .send()
             .boxed()
-            .await?;
+            .await;
+        match response {
+            Ok(_) => Ok(true),
+            Err(error) => {
+                let error = DynamoDbStoreInternalError::from(error);
+                if error.category() == ErrorCategory::Validation {
+                    Ok(false)
+                } else {
+                    Err(error)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the number of milliseconds between the Unix epoch and `time`.
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// The limits enforced by the backend, as found by [`DynamoDbStoreInternal::probe_limits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendLimits {
+    /// The size of the largest raw value accepted for an item.
+    pub max_value_size: usize,
+    /// The size of the largest item accepted, as counted by `check_item_size`: the value
+    /// together with the key and the names of the attributes.
+    pub max_item_size: usize,
+}
+
+impl BackendLimits {
+    /// Returns whether the backend accepts the largest values written by the store.
+    ///
+    /// The values of the store are split in chunks of a fixed size, so a backend with
+    /// lower limits than DynamoDB cannot be used, while higher limits are not used. The
+    /// limit of DynamoDB is on the whole item, so it is the size of the items that is
+    /// compared, with the overhead of the key of the probe included.
+    pub fn supports_store_values(&self) -> bool {
+        self.max_item_size >= MAX_ITEM_SIZE
+    }
+}
+
+/// The optional features of DynamoDB supported by a backend, as returned by
+/// [`DynamoDbStoreInternal::capabilities`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Whether `TransactWriteItems` and `TransactGetItems` are supported.
+    pub transactions: bool,
+    /// Whether the time to live of the items can be configured on the table.
+    pub time_to_live: bool,
+    /// Whether PartiQL statements can be run, e.g. by
+    /// [`DynamoDbStoreInternal::partiql_query`].
+    pub partiql: bool,
+}
+
+impl Capabilities {
+    /// The capabilities of DynamoDB itself, which supports all the features.
+    pub const DYNAMO_DB: Self = Self {
+        transactions: true,
+        time_to_live: true,
+        partiql: true,
+    };
+}
+
+/// An advisory lease on the root key of a store, acquired with
+/// [`DynamoDbStoreInternal::acquire_lease`].
+///
+/// The lease is released when dropped, in a task spawned on the current Tokio runtime,
+/// or explicitly with [`Lease::release`].
+#[derive(Debug)]
+pub struct Lease {
+    store: DynamoDbStoreInternal,
+    holder: Vec<u8>,
+    expiry: SystemTime,
+    released: bool,
+}
+
+impl Lease {
+    /// Returns the time at which the lease expires, unless renewed.
+    pub fn expiry(&self) -> SystemTime {
+        self.expiry
+    }
+
+    /// Extends the lease to expire `ttl` from now. Fails with
+    /// [`DynamoDbStoreInternalError::LeaseLost`] if the lease expired and was acquired by
+    /// another holder in the meantime.
+    pub async fn renew(&mut self, ttl: Duration) -> Result<(), DynamoDbStoreInternalError> {
+        let holder = AttributeValue::B(Blob::new(self.holder.clone()));
+        let condition = format!("{VALUE_ATTRIBUTE} = :holder");
+        self.expiry = self
+            .store
+            .put_lease_item(
+                &self.holder,
+                ttl,
+                condition,
+                HashMap::from([(":holder".into(), holder)]),
+            )
+            .await
+            .map_err(|error| match error {
+                DynamoDbStoreInternalError::ConditionFailed => {
+                    DynamoDbStoreInternalError::LeaseLost
+                }
+                error => error,
+            })?;
+        Ok(())
+    }
+
+    /// Releases the lease, so that it can be acquired immediately by another holder.
+    pub async fn release(mut self) -> Result<(), DynamoDbStoreInternalError> {
+        self.released = true;
+        self.store.delete_lease_item(&self.holder).await
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("No runtime to release the lease, which is left to expire");
+            return;
+        };
+        let store = self.store.clone();
+        let holder = std::mem::take(&mut self.holder);
+        runtime.spawn(async move {
+            if let Err(error) = store.delete_lease_item(&holder).await {
+                tracing::warn!("Failed to release the lease, which is left to expire: {error}");
+            }
+        });
+    }
+}
+
+/// A condition on the value stored at a key, checked atomically by
+/// [`DynamoDbStoreInternal::write_conditional`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConditionExpr {
+    /// The key has a value.
+    Exists,
+    /// The key has no value.
+    NotExists,
+    /// The key has the given value.
+    ValueEquals(Vec<u8>),
+    /// The key has a value, different from the given one.
+    ValueNotEquals(Vec<u8>),
+    /// Both conditions hold.
+    And(Box<ConditionExpr>, Box<ConditionExpr>),
+    /// At least one of the conditions holds.
+    Or(Box<ConditionExpr>, Box<ConditionExpr>),
+    /// The condition does not hold.
+    Not(Box<ConditionExpr>),
+}
+
+impl ConditionExpr {
+    /// Returns the condition that both `self` and `other` hold.
+    pub fn and(self, other: ConditionExpr) -> Self {
+        Self::And(Box::new(self), Box::new(other))
+    }
+
+    /// Returns the condition that `self` or `other` holds.
+    pub fn or(self, other: ConditionExpr) -> Self {
+        Self::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Compiles the condition into a DynamoDB condition expression. The values compared
+    /// are converted to their stored form by `encode_value` and added to `values`, under
+    /// placeholders of the form `:condN`.
+    fn compile(
+        &self,
+        values: &mut HashMap<String, AttributeValue>,
+        encode_value: &impl Fn(Vec<u8>) -> Vec<u8>,
+    ) -> String {
+        let mut add_value = |value: &[u8]| {
+            let placeholder = format!(":cond{}", values.len());
+            let value = AttributeValue::B(Blob::new(encode_value(value.to_vec())));
+            values.insert(placeholder.clone(), value);
+            placeholder
+        };
+        match self {
+            Self::Exists => format!("attribute_exists({KEY_ATTRIBUTE})"),
+            Self::NotExists => format!("attribute_not_exists({KEY_ATTRIBUTE})"),
+            Self::ValueEquals(value) => format!("{VALUE_ATTRIBUTE} = {}", add_value(value)),
+            Self::ValueNotEquals(value) => format!("{VALUE_ATTRIBUTE} <> {}", add_value(value)),
+            Self::And(left, right) => format!(
+                "({}) AND ({})",
+                left.compile(values, encode_value),
+                right.compile(values, encode_value)
+            ),
+            Self::Or(left, right) => format!(
+                "({}) OR ({})",
+                left.compile(values, encode_value),
+                right.compile(values, encode_value)
+            ),
+            Self::Not(condition) => format!("NOT ({})", condition.compile(values, encode_value)),
+        }
+    }
+}
+
+/// Tests whether a transaction was canceled, e.g. because of a concurrent transaction on
+/// one of its items.
+fn is_transaction_canceled(error: &SdkError<TransactWriteItemsError>) -> bool {
+    let SdkError::ServiceError(error) = error else {
+        return false;
+    };
+    matches!(
+        error.err(),
+        TransactWriteItemsError::TransactionCanceledException(_)
+    )
+}
+
+/// Tests whether a transaction was canceled because one of its conditions failed.
+fn is_conditional_check_failure(error: &SdkError<TransactWriteItemsError>) -> bool {
+    let SdkError::ServiceError(error) = error else {
+        return false;
+    };
+    let TransactWriteItemsError::TransactionCanceledException(error) = error.err() else {
+        return false;
+    };
+    error
+        .cancellation_reasons()
+        .iter()
+        .any(|reason| reason.code() == Some("ConditionalCheckFailed"))
+}
+
+/// Reports the size estimates of the item collections returned by a write, if any.
+fn report_item_collection_metrics(output: &TransactWriteItemsOutput) {
+    let Some(metrics) = output.item_collection_metrics() else {
+        return;
+    };
+    for metrics in metrics.values().flatten() {
+        // The estimate is a range of sizes: we use its upper bound.
+        let Some(size) = metrics.size_estimate_range_gb().last().copied() else {
+            continue;
+        };
+        #[cfg(with_metrics)]
+        DYNAMO_DB_ITEM_COLLECTION_SIZE
+            .with_label_values(&[])
+            .observe(size);
+        if size >= ITEM_COLLECTION_SIZE_WARNING_RATIO * MAX_ITEM_COLLECTION_SIZE_GB {
+            tracing::warn!(
+                "The DynamoDB item collection is estimated at {size} GB, close to the limit of \
+                 {MAX_ITEM_COLLECTION_SIZE_GB} GB"
+            );
+        }
+    }
+}
+
+/// The key-values under a prefix, as read by [`DynamoDbStoreInternal::snapshot_prefix`].
+///
+/// The keys passed to the methods are full keys, as for the store. The keys outside of
+/// the prefix of the snapshot are not known and are reported as missing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    key_prefix: Vec<u8>,
+    key_values: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// The prefix of the keys captured by the snapshot.
+    pub fn key_prefix(&self) -> &[u8] {
+        &self.key_prefix
+    }
+
+    /// The number of key-values in the snapshot.
+    pub fn len(&self) -> usize {
+        self.key_values.len()
+    }
+
+    /// Whether the snapshot has no key-values.
+    pub fn is_empty(&self) -> bool {
+        self.key_values.is_empty()
+    }
+
+    /// Returns the value at `key` when the snapshot was taken.
+    pub fn read_value_bytes(&self, key: &[u8]) -> Option<&[u8]> {
+        self.key_values.get(key).map(Vec::as_slice)
+    }
 
-        match response.item {
-            Some(mut item) => {
-                let value = extract_value_owned(&mut item)?;
-                Ok(Some(value))
+    /// Tests whether `key` had a value when the snapshot was taken.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.key_values.contains_key(key)
+    }
+
+    /// Returns the keys under `key_prefix` in increasing order, without the prefix.
+    pub fn find_keys_by_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.find_key_values_by_prefix(key_prefix)
+            .map(|(key, _)| key)
+    }
+
+    /// Returns the key-values under `key_prefix` in increasing order of the keys, without
+    /// the prefix.
+    pub fn find_key_values_by_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        self.key_values
+            .range(get_interval(key_prefix.to_vec()))
+            .map(move |(key, value)| (&key[key_prefix.len()..], value.as_slice()))
+    }
+}
+
+/// The differences between two stores, as computed by [`DynamoDbStoreInternal::diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreDiff {
+    /// The keys that are only in the store being compared.
+    pub only_in_self: Vec<Vec<u8>>,
+    /// The keys that are only in the other store.
+    pub only_in_other: Vec<Vec<u8>>,
+    /// The keys that are in both stores, with different values.
+    pub different_values: Vec<Vec<u8>>,
+    /// Whether the comparison stopped after reaching the maximum number of differences.
+    pub truncated: bool,
+}
+
+impl StoreDiff {
+    /// Appends the differences of a range of keys following the ones of `self`, keeping
+    /// the first of them in the order of the keys so that at most `max_differences` are
+    /// reported.
+    fn append(&mut self, other: StoreDiff, max_differences: usize) {
+        let remaining = max_differences - self.len();
+        if other.len() <= remaining {
+            self.only_in_self.extend(other.only_in_self);
+            self.only_in_other.extend(other.only_in_other);
+            self.different_values.extend(other.different_values);
+            self.truncated = other.truncated;
+            return;
+        }
+        let mut differences = other
+            .only_in_self
+            .into_iter()
+            .map(|key| (key, 0))
+            .chain(other.only_in_other.into_iter().map(|key| (key, 1)))
+            .chain(other.different_values.into_iter().map(|key| (key, 2)))
+            .collect::<Vec<_>>();
+        differences.sort_unstable();
+        for (key, kind) in differences.into_iter().take(remaining) {
+            match kind {
+                0 => self.only_in_self.push(key),
+                1 => self.only_in_other.push(key),
+                _ => self.different_values.push(key),
             }
-            None => Ok(None),
         }
+        self.truncated = true;
     }
 
-    async fn contains_key_general(
-        &self,
-        key_db: HashMap<String, AttributeValue>,
-    ) -> Result<bool, DynamoDbStoreInternalError> {
-        let _guard = self.acquire().await;
-        let response = self
-            .client
-            .get_item()
-            .table_name(&self.namespace)
-            .set_key(Some(key_db))
-            .projection_expression(PARTITION_ATTRIBUTE)
-            .send()
-            .boxed()
-            .await?;
+    /// The number of differences reported.
+    pub fn len(&self) -> usize {
+        self.only_in_self.len() + self.only_in_other.len() + self.different_values.len()
+    }
 
-        Ok(response.item.is_some())
+    /// Whether no difference was found.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0 && !self.truncated
     }
+}
 
-    async fn get_list_responses(
-        &self,
-        attribute: &str,
-        start_key: &[u8],
-        key_prefix: &[u8],
-    ) -> Result<QueryResponses, DynamoDbStoreInternalError> {
-        check_key_size(key_prefix)?;
-        let mut responses = Vec::new();
-        let mut start_key_map = None;
+/// The state of the query of one prefix in [`DynamoDbStoreInternal::find_key_values_capped`].
+struct CappedPrefixQuery {
+    key_prefix: Vec<u8>,
+    /// The entries read but not yet returned.
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// The start of the next page, or `None` once the last page has been read.
+    next_page: Option<Option<HashMap<String, AttributeValue>>>,
+}
+
+/// Returns the prefixes of the keys of the views read by a scan of `concurrency`
+/// parallel queries: the empty prefix for a sequential scan, else a prefix per view tag.
+fn view_key_ranges(concurrency: usize) -> Vec<Vec<u8>> {
+    if concurrency <= 1 {
+        vec![Vec::new()]
+    } else {
+        (MIN_VIEW_TAG..=u8::MAX).map(|tag| vec![tag]).collect()
+    }
+}
+
+/// Reads the key-values of a store one page at a time, in the order of the keys,
+/// skipping the keys reserved for internal use.
+struct KeyValueCursor<'a> {
+    store: &'a DynamoDbStoreInternal,
+    key_prefix: Vec<u8>,
+    page: DynamoDbKeyValueIteratorOwned,
+    /// The token of the next page, or `None` once the last page has been read.
+    next_page: Option<Option<Vec<u8>>>,
+}
+
+impl<'a> KeyValueCursor<'a> {
+    fn new(store: &'a DynamoDbStoreInternal, key_prefix: Vec<u8>) -> Self {
+        let page = DynamoDbKeyValueIteratorOwned {
+            prefix_len: 0,
+            value_checksums: store.value_checksums,
+            pages: VecDeque::new(),
+        };
+        Self {
+            store,
+            key_prefix,
+            page,
+            next_page: Some(None),
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>, DynamoDbStoreInternalError> {
         loop {
-            let response = self
-                .get_query_output(attribute, start_key, key_prefix, start_key_map)
-                .await?;
-            let last_evaluated = response.last_evaluated_key.clone();
-            responses.push(response);
-            match last_evaluated {
-                None => {
-                    break;
-                }
-                Some(value) => {
-                    start_key_map = Some(value);
+            if let Some(entry) = self.page.next() {
+                let (suffix, value) = entry?;
+                let key = if self.key_prefix.is_empty() {
+                    suffix
+                } else {
+                    [self.key_prefix.as_slice(), &suffix].concat()
+                };
+                if key.first().is_some_and(|tag| *tag < MIN_VIEW_TAG) {
+                    continue;
                 }
+                return Ok(Some((key, value)));
             }
+            let Some(from_continuation) = self.next_page.take() else {
+                return Ok(None);
+            };
+            let key_values = self
+                .store
+                .key_values_page(&self.key_prefix, from_continuation)
+                .await?;
+            self.next_page = key_values.continuation_token().map(Some);
+            self.page = key_values.into_iterator_owned();
         }
-        Ok(QueryResponses {
-            prefix_len: key_prefix.len(),
-            responses,
-        })
     }
 }
 
 struct QueryResponses {
     prefix_len: usize,
+    value_checksums: bool,
     responses: Vec<QueryOutput>,
+    /// The number of malformed items removed from the responses.
+    skipped_items: usize,
+}
+
+impl QueryResponses {
+    /// Removes the items whose key, and value if `with_values`, cannot be extracted.
+    fn skip_malformed_items(&mut self, with_values: bool) {
+        for response in &mut self.responses {
+            let Some(items) = &mut response.items else {
+                continue;
+            };
+            items.retain(|item| {
+                let result = if with_values {
+                    extract_key_value(self.prefix_len, item, self.value_checksums).map(|_| ())
+                } else {
+                    extract_key(self.prefix_len, item).map(|_| ())
+                };
+                match result {
+                    Ok(()) => true,
+                    Err(error) => {
+                        tracing::warn!("Skipping a malformed item: {error}");
+                        self.skipped_items += 1;
+                        false
+                    }
+                }
+            });
+        }
+    }
+
+    /// Returns the full key of the last item evaluated by the last query, if the query
+    /// stopped before the end of the results.
+    fn continuation_token(&self) -> Option<Vec<u8>> {
+        let last_evaluated_key = self.responses.last()?.last_evaluated_key.as_ref()?;
+        extract_key(0, last_evaluated_key).ok().map(<[u8]>::to_vec)
+    }
 }
 
 // Inspired by https://depth-first.com/articles/2020/06/22/returning-rust-iterators/
@@ -735,6 +5359,20 @@ pub struct DynamoDbKeys {
     result_queries: QueryResponses,
 }
 
+impl DynamoDbKeys {
+    /// Returns the token to pass to `find_keys_by_prefix_from` to read the next page, or
+    /// `None` if there are no more keys. The token is the full key of the last key read.
+    pub fn continuation_token(&self) -> Option<Vec<u8>> {
+        self.result_queries.continuation_token()
+    }
+
+    /// Returns the number of malformed items skipped by the scan, with
+    /// [`MalformedItems::Skip`].
+    pub fn num_skipped_items(&self) -> usize {
+        self.result_queries.skipped_items
+    }
+}
+
 impl KeyIterable<DynamoDbStoreInternalError> for DynamoDbKeys {
     type Iterator<'a>
         = DynamoDbKeyBlockIterator<'a>
@@ -761,10 +5399,26 @@ pub struct DynamoDbKeyValues {
     result_queries: QueryResponses,
 }
 
+impl DynamoDbKeyValues {
+    /// Returns the token to pass to `find_key_values_by_prefix_from` to read the next
+    /// page, or `None` if there are no more key-values. The token is the full key of the
+    /// last key read.
+    pub fn continuation_token(&self) -> Option<Vec<u8>> {
+        self.result_queries.continuation_token()
+    }
+
+    /// Returns the number of malformed items skipped by the scan, with
+    /// [`MalformedItems::Skip`].
+    pub fn num_skipped_items(&self) -> usize {
+        self.result_queries.skipped_items
+    }
+}
+
 #[doc(hidden)]
 #[expect(clippy::type_complexity)]
 pub struct DynamoDbKeyValueIterator<'a> {
     prefix_len: usize,
+    value_checksums: bool,
     pos: usize,
     iters: Vec<
         std::iter::Flatten<
@@ -786,42 +5440,88 @@ impl<'a> Iterator for DynamoDbKeyValueIterator<'a> {
                 self.pos += 1;
                 self.iters[self.pos]
                     .next()
-                    .map(|x| extract_key_value(self.prefix_len, x))
+                    .map(|x| extract_key_value(self.prefix_len, x, self.value_checksums))
             }
-            Some(result) => Some(extract_key_value(self.prefix_len, result)),
+            Some(result) => Some(extract_key_value(
+                self.prefix_len,
+                result,
+                self.value_checksums,
+            )),
         }
     }
 }
 
+/// Iterates over the `(key, value)` pairs by value.
+///
+/// Items are moved out of the query pages as they are yielded, and every page is
+/// released as soon as it is exhausted, so that the memory held by the iterator
+/// decreases while it is being consumed.
 #[doc(hidden)]
-#[expect(clippy::type_complexity)]
 pub struct DynamoDbKeyValueIteratorOwned {
     prefix_len: usize,
-    pos: usize,
-    iters: Vec<
-        std::iter::Flatten<
-            std::option::IntoIter<Vec<HashMap<std::string::String, AttributeValue>>>,
-        >,
-    >,
+    value_checksums: bool,
+    pages: VecDeque<std::vec::IntoIter<HashMap<String, AttributeValue>>>,
 }
 
 impl Iterator for DynamoDbKeyValueIteratorOwned {
     type Item = Result<(Vec<u8>, Vec<u8>), DynamoDbStoreInternalError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = self.iters[self.pos].next();
-        match result {
-            None => {
-                if self.pos == self.iters.len() - 1 {
-                    return None;
+        loop {
+            let page = self.pages.front_mut()?;
+            match page.next() {
+                Some(mut item) => {
+                    return Some(extract_key_value_owned(
+                        self.prefix_len,
+                        &mut item,
+                        self.value_checksums,
+                    ))
                 }
-                self.pos += 1;
-                self.iters[self.pos]
-                    .next()
-                    .map(|mut x| extract_key_value_owned(self.prefix_len, &mut x))
+                None => {
+                    // Dropping the exhausted page frees its buffer.
+                    self.pages.pop_front();
+                }
+            }
+        }
+    }
+}
+
+impl DynamoDbKeyValueIteratorOwned {
+    /// Groups the `(key, value)` pairs in chunks of `size` pairs, the last chunk being
+    /// possibly shorter, e.g. to process every chunk in a task of its own.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn chunks(self, size: usize) -> DynamoDbKeyValueChunks {
+        assert!(size > 0, "The size of the chunks must be positive");
+        DynamoDbKeyValueChunks { inner: self, size }
+    }
+}
+
+/// Iterates over the `(key, value)` pairs in owned chunks, see
+/// [`DynamoDbKeyValueIteratorOwned::chunks`].
+///
+/// A chunk is an error as soon as one of its pairs cannot be read, and the following
+/// pairs then form the next chunks.
+pub struct DynamoDbKeyValueChunks {
+    inner: DynamoDbKeyValueIteratorOwned,
+    size: usize,
+}
+
+impl Iterator for DynamoDbKeyValueChunks {
+    type Item = Result<Vec<(Vec<u8>, Vec<u8>)>, DynamoDbStoreInternalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.size);
+        while chunk.len() < self.size {
+            match self.inner.next() {
+                Some(Ok(key_value)) => chunk.push(key_value),
+                Some(Err(error)) => return Some(Err(error)),
+                None => break,
             }
-            Some(mut result) => Some(extract_key_value_owned(self.prefix_len, &mut result)),
         }
+        (!chunk.is_empty()).then_some(Ok(chunk))
     }
 }
 
@@ -841,22 +5541,23 @@ impl KeyValueIterable<DynamoDbStoreInternalError> for DynamoDbKeyValues {
         }
         DynamoDbKeyValueIterator {
             prefix_len: self.result_queries.prefix_len,
+            value_checksums: self.result_queries.value_checksums,
             pos,
             iters,
         }
     }
 
     fn into_iterator_owned(self) -> Self::IteratorOwned {
-        let pos = 0;
-        let mut iters = Vec::new();
-        for response in self.result_queries.responses.into_iter() {
-            let iter = response.items.into_iter().flatten();
-            iters.push(iter);
-        }
+        let pages = self
+            .result_queries
+            .responses
+            .into_iter()
+            .map(|response| response.items.unwrap_or_default().into_iter())
+            .collect();
         DynamoDbKeyValueIteratorOwned {
             prefix_len: self.result_queries.prefix_len,
-            pos,
-            iters,
+            value_checksums: self.result_queries.value_checksums,
+            pages,
         }
     }
 }
@@ -878,14 +5579,17 @@ impl ReadableKeyValueStore for DynamoDbStoreInternal {
         &self,
         key: &[u8],
     ) -> Result<Option<Vec<u8>>, DynamoDbStoreInternalError> {
-        check_key_size(key)?;
-        let key_db = build_key(&self.start_key, key.to_vec());
-        self.read_value_bytes_general(key_db).await
+        self.check_key(key)?;
+        if let Some(tracker) = &self.access_tracker {
+            tracker.record(key);
+        }
+        let key_db = build_key(self.partition_of(key), key.to_vec());
+        self.read_value_bytes_general(key_db, false).await
     }
 
     async fn contains_key(&self, key: &[u8]) -> Result<bool, DynamoDbStoreInternalError> {
-        check_key_size(key)?;
-        let key_db = build_key(&self.start_key, key.to_vec());
+        self.check_key(key)?;
+        let key_db = build_key(self.partition_of(key), key.to_vec());
         self.contains_key_general(key_db).await
     }
 
@@ -895,41 +5599,33 @@ impl ReadableKeyValueStore for DynamoDbStoreInternal {
     ) -> Result<Vec<bool>, DynamoDbStoreInternalError> {
         let mut handles = Vec::new();
         for key in keys {
-            check_key_size(&key)?;
-            let key_db = build_key(&self.start_key, key);
+            self.check_key(&key)?;
+            let key_db = build_key(self.partition_of(&key), key);
             let handle = self.contains_key_general(key_db);
             handles.push(handle);
         }
-        join_all(handles)
-            .await
-            .into_iter()
-            .collect::<Result<_, _>>()
+        // Throttled requests are retried by the SDK, so an error is final: the other
+        // requests are dropped.
+        try_join_all(handles).await
     }
 
     async fn read_multi_values_bytes(
         &self,
         keys: Vec<Vec<u8>>,
     ) -> Result<Vec<Option<Vec<u8>>>, DynamoDbStoreInternalError> {
-        let mut handles = Vec::new();
-        for key in keys {
-            check_key_size(&key)?;
-            let key_db = build_key(&self.start_key, key);
-            let handle = self.read_value_bytes_general(key_db);
-            handles.push(handle);
-        }
-        join_all(handles)
-            .await
-            .into_iter()
-            .collect::<Result<_, _>>()
+        self.read_multi_key_bytes_general(keys, false).await
     }
 
     async fn find_keys_by_prefix(
         &self,
         key_prefix: &[u8],
     ) -> Result<DynamoDbKeys, DynamoDbStoreInternalError> {
-        let result_queries = self
-            .get_list_responses(KEY_ATTRIBUTE, &self.start_key, key_prefix)
+        let mut result_queries = self
+            .get_list_responses(KEY_ATTRIBUTE, self.partition_of(key_prefix), key_prefix)
             .await?;
+        if self.malformed_items == MalformedItems::Skip {
+            result_queries.skip_malformed_items(false);
+        }
         Ok(DynamoDbKeys { result_queries })
     }
 
@@ -937,13 +5633,42 @@ impl ReadableKeyValueStore for DynamoDbStoreInternal {
         &self,
         key_prefix: &[u8],
     ) -> Result<DynamoDbKeyValues, DynamoDbStoreInternalError> {
-        let result_queries = self
-            .get_list_responses(KEY_VALUE_ATTRIBUTE, &self.start_key, key_prefix)
+        let mut result_queries = self
+            .get_list_responses(
+                KEY_VALUE_ATTRIBUTE,
+                self.partition_of(key_prefix),
+                key_prefix,
+            )
             .await?;
+        if self.malformed_items == MalformedItems::Skip {
+            result_queries.skip_malformed_items(true);
+        }
         Ok(DynamoDbKeyValues { result_queries })
     }
 }
 
+/// Reads a part of a record of [`DynamoDbStoreInternal::export_prefix`], that is, its
+/// length then its bytes. Returns `None` if the reader is at its end.
+async fn read_record_part<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_length: usize,
+) -> Result<Option<Vec<u8>>, DynamoDbStoreInternalError> {
+    let mut length = [0u8; 4];
+    let num_bytes = reader.read(&mut length).await?;
+    if num_bytes == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut length[num_bytes..]).await?;
+    let length = u32::from_le_bytes(length) as usize;
+    ensure!(
+        length <= max_length,
+        DynamoDbStoreInternalError::RecordTooLarge { length }
+    );
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes).await?;
+    Ok(Some(bytes))
+}
+
 #[async_trait]
 impl DirectWritableKeyValueStore for DynamoDbStoreInternal {
     const MAX_BATCH_SIZE: usize = MAX_TRANSACT_WRITE_ITEM_SIZE;
@@ -954,17 +5679,24 @@ impl DirectWritableKeyValueStore for DynamoDbStoreInternal {
     type Batch = SimpleUnorderedBatch;
 
     async fn write_batch(&self, batch: Self::Batch) -> Result<(), DynamoDbStoreInternalError> {
-        if !self.root_key_written.fetch_or(true, Ordering::SeqCst) {
-            let mut builder = TransactionBuilder::new(PARTITION_KEY_ROOT_KEY);
-            builder.insert_put_request(self.start_key.clone(), vec![], self)?;
-            self.client
-                .transact_write_items()
-                .set_transact_items(Some(builder.transactions))
-                .send()
-                .boxed()
-                .await?;
-        }
-        let mut builder = TransactionBuilder::new(&self.start_key);
+        self.write_root_key_if_needed().await?;
+        let is_user_key = |key: &[u8]| key.first().is_some_and(|tag| *tag >= MIN_VIEW_TAG);
+        let notification = self.write_hook.as_ref().map(|_| {
+            let deletions = batch
+                .deletions
+                .iter()
+                .filter(|key| is_user_key(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            let insertions = batch
+                .insertions
+                .iter()
+                .filter(|(key, _)| is_user_key(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            (deletions, insertions)
+        });
+        let mut builder = TransactionBuilder::default();
         for key in batch.deletions {
             builder.insert_delete_request(key, self)?;
         }
@@ -972,18 +5704,260 @@ impl DirectWritableKeyValueStore for DynamoDbStoreInternal {
             builder.insert_put_request(key, value, self)?;
         }
         if !builder.transactions.is_empty() {
-            let _guard = self.acquire().await;
-            self.client
-                .transact_write_items()
-                .set_transact_items(Some(builder.transactions))
-                .send()
-                .boxed()
+            // The items are counted when `write_items` sends them.
+            let _guard = self.acquire(0, 0).await;
+            self.write_items(builder.transactions).await?;
+        }
+        if let (Some(WriteHook(hook)), Some((deletions, insertions))) =
+            (&self.write_hook, notification)
+        {
+            if !deletions.is_empty() || !insertions.is_empty() {
+                hook(&deletions, &insertions);
+            }
+        }
+        Ok(())
+    }
+
+    fn max_recovery_jitter(&self) -> Duration {
+        self.max_recovery_jitter
+    }
+
+    fn find_key_pages_by_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> Option<KeyPages<'a, DynamoDbStoreInternalError>> {
+        // The state is the continuation of the next page, or `None` after the last page.
+        let pages = stream::try_unfold(Some(None), move |continuation| async move {
+            let Some(continuation) = continuation else {
+                return Ok(None);
+            };
+            let mut result_queries = self
+                .get_list_page(KEY_ATTRIBUTE, key_prefix, continuation)
                 .await?;
+            if self.malformed_items == MalformedItems::Skip {
+                result_queries.skip_malformed_items(false);
+            }
+            let keys = DynamoDbKeys { result_queries };
+            let continuation = keys.continuation_token().map(Some);
+            let keys = keys
+                .iterator()
+                .map(|key| key.map(<[u8]>::to_vec))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Some((keys, continuation)))
+        });
+        Some(pages.boxed())
+    }
+
+    fn size_of_item(&self, key_len: usize, value_len: usize) -> usize {
+        self.stored_item_size(self.start_key.len(), key_len, value_len)
+    }
+
+    fn check_batch(&self, batch: &Batch) -> Result<(), DynamoDbStoreInternalError> {
+        if !self.reject_conflicting_operations {
+            return Ok(());
+        }
+        let mut deleted_keys = HashSet::new();
+        for operation in &batch.operations {
+            match operation {
+                WriteOperation::Delete { key } => {
+                    deleted_keys.insert(key);
+                }
+                WriteOperation::Put { key, .. } if deleted_keys.contains(key) => {
+                    return Err(DynamoDbStoreInternalError::ConflictingSameKeyOps {
+                        key: key.clone(),
+                    });
+                }
+                _ => {}
+            }
         }
         Ok(())
     }
 }
 
+/// The progress of a [`BulkLoader`].
+#[derive(Clone, Debug, Default)]
+pub struct BulkLoadProgress {
+    /// The number of key-values written so far.
+    pub items_written: usize,
+    /// The number of `BatchWriteItem` requests that were throttled, partially or fully.
+    pub throttled_requests: usize,
+    /// The write capacity units consumed so far, as reported by DynamoDB.
+    pub consumed_capacity_units: f64,
+}
+
+/// Loads a large number of key-values into DynamoDB with `BatchWriteItem` requests.
+///
+/// Unlike `write_batch`, the writes are not atomic: the loader is optimized for
+/// sustained throughput during initial data loads. The number of items per request is
+/// adapted to the observed throttling: it grows by one item after every fully processed
+/// request and is halved whenever DynamoDB throttles the request or returns unprocessed
+/// items, which are then retried after the delays of the [`BackoffStrategy`] of the store.
+pub struct BulkLoader<'a> {
+    store: &'a DynamoDbStoreInternal,
+    chunk_size: usize,
+    attempt: usize,
+    progress: BulkLoadProgress,
+}
+
+/// A write of a [`BulkLoader`] that was not processed yet.
+struct PendingWrite {
+    key: Vec<u8>,
+    request: WriteRequest,
+    /// The number of key-values of the stream written by the request: more than one if
+    /// the later values of the key replaced the earlier ones before they were sent.
+    count: usize,
+}
+
+impl<'a> BulkLoader<'a> {
+    /// Creates a loader writing the key-values under the root key of `store`.
+    pub fn new(store: &'a DynamoDbStoreInternal) -> Self {
+        Self {
+            store,
+            chunk_size: MAX_BATCH_WRITE_ITEM_SIZE,
+            attempt: 0,
+            progress: BulkLoadProgress::default(),
+        }
+    }
+
+    /// Writes all the key-values of the stream, calling `on_progress` after every
+    /// request, and returns the final progress. A key present several times in the stream
+    /// ends up with its last value.
+    pub async fn load(
+        mut self,
+        key_values: impl Stream<Item = (Vec<u8>, Vec<u8>)>,
+        mut on_progress: impl FnMut(&BulkLoadProgress),
+    ) -> Result<BulkLoadProgress, DynamoDbStoreInternalError> {
+        self.store.write_root_key_if_needed().await?;
+        let mut key_values = pin!(key_values);
+        let mut pending = VecDeque::<PendingWrite>::new();
+        loop {
+            while pending.len() < self.chunk_size {
+                let Some((key, value)) = key_values.next().await else {
+                    break;
+                };
+                let request = self.build_write_request(key.clone(), value)?;
+                // `BatchWriteItem` rejects the requests writing the same key twice, so the
+                // new value replaces the pending one.
+                match pending.iter_mut().find(|write| write.key == key) {
+                    Some(write) => {
+                        write.request = request;
+                        write.count += 1;
+                    }
+                    None => pending.push_back(PendingWrite {
+                        key,
+                        request,
+                        count: 1,
+                    }),
+                }
+            }
+            if pending.is_empty() {
+                return Ok(self.progress);
+            }
+            let count = pending.len().min(self.chunk_size);
+            let writes = pending.drain(..count).collect::<Vec<_>>();
+            let requests = writes.iter().map(|write| write.request.clone()).collect();
+            let unprocessed = self.send(requests).await?;
+            let (unprocessed, processed): (Vec<_>, Vec<_>) = writes
+                .into_iter()
+                .partition(|write| unprocessed.contains(&write.request));
+            self.progress.items_written += processed.iter().map(|write| write.count).sum::<usize>();
+            if unprocessed.is_empty() {
+                self.chunk_size = (self.chunk_size + 1).min(MAX_BATCH_WRITE_ITEM_SIZE);
+                self.attempt = 0;
+            } else {
+                self.progress.throttled_requests += 1;
+                self.chunk_size = (self.chunk_size / 2).max(1);
+                for write in unprocessed.into_iter().rev() {
+                    pending.push_front(write);
+                }
+                let delay = self
+                    .store
+                    .backoff
+                    .next_delay(self.attempt)
+                    .ok_or(DynamoDbStoreInternalError::RetriesExhausted)?;
+                timer::sleep(delay).await;
+                self.attempt += 1;
+            }
+            on_progress(&self.progress);
+        }
+    }
+
+    fn build_write_request(
+        &self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> Result<WriteRequest, DynamoDbStoreInternalError> {
+        self.store.check_key(&key)?;
+        let value = self.store.encode_value(value);
+        ensure!(
+            value.len() <= RAW_MAX_VALUE_SIZE,
+            DynamoDbStoreInternalError::ValueLengthTooLarge
+        );
+        let item = self
+            .store
+            .build_item(self.store.partition_of(&key), key, value);
+        check_item_size(&item)?;
+        let request = PutRequest::builder().set_item(Some(item)).build()?;
+        Ok(WriteRequest::builder().put_request(request).build())
+    }
+
+    /// Sends one `BatchWriteItem` request and returns the requests that were not
+    /// processed because of throttling.
+    async fn send(
+        &mut self,
+        requests: Vec<WriteRequest>,
+    ) -> Result<Vec<WriteRequest>, DynamoDbStoreInternalError> {
+        let num_bytes = requests.iter().map(write_request_size).sum();
+        let _permit = self.store.reserve_inflight_bytes(num_bytes).await;
+        let _guard = self.store.acquire(0, requests.len() as u64).await;
+        let response = self
+            .store
+            .client
+            .batch_write_item()
+            .request_items(&self.store.namespace, requests.clone())
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .boxed()
+            .await;
+        let mut output = match response {
+            Ok(output) => output,
+            Err(SdkError::ServiceError(error))
+                if matches!(
+                    error.err(),
+                    BatchWriteItemError::ProvisionedThroughputExceededException(_)
+                        | BatchWriteItemError::RequestLimitExceeded(_)
+                ) =>
+            {
+                return Ok(requests);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        for capacity in output.consumed_capacity() {
+            self.progress.consumed_capacity_units += capacity.capacity_units().unwrap_or(0.0);
+        }
+        Ok(output
+            .unprocessed_items
+            .take()
+            .and_then(|mut items| items.remove(&self.store.namespace))
+            .unwrap_or_default())
+    }
+}
+
+/// Returns the size of the binary attributes written by a request.
+fn write_request_size(request: &WriteRequest) -> usize {
+    let Some(put_request) = request.put_request() else {
+        return 0;
+    };
+    put_request
+        .item()
+        .values()
+        .map(|value| match value {
+            AttributeValue::B(blob) => blob.as_ref().len(),
+            _ => 0,
+        })
+        .sum()
+}
+
 /// Error when validating a namespace
 #[derive(Debug, Error)]
 pub enum InvalidNamespace {
@@ -1015,21 +5989,142 @@ pub enum DynamoDbStoreInternalError {
     #[error(transparent)]
     TransactWriteItem(#[from] Box<SdkError<TransactWriteItemsError>>),
 
+    /// An error occurred while updating an item.
+    #[error(transparent)]
+    UpdateItem(#[from] Box<SdkError<UpdateItemError>>),
+
+    /// An error occurred while deleting an item.
+    #[error(transparent)]
+    DeleteItem(#[from] Box<SdkError<DeleteItemError>>),
+
     /// An error occurred while doing a Query.
     #[error(transparent)]
     Query(#[from] Box<SdkError<QueryError>>),
 
-    /// An error occurred while deleting a table
-    #[error(transparent)]
-    DeleteTable(#[from] Box<SdkError<DeleteTableError>>),
+    /// An error occurred while doing a Scan.
+    #[error(transparent)]
+    Scan(#[from] Box<SdkError<ScanError>>),
+
+    /// An error occurred while deleting a table
+    #[error(transparent)]
+    DeleteTable(#[from] Box<SdkError<DeleteTableError>>),
+
+    /// An error occurred while listing tables
+    #[error(transparent)]
+    ListTables(#[from] Box<SdkError<ListTablesError>>),
+
+    /// An error occurred while running a PartiQL statement.
+    #[error(transparent)]
+    ExecuteStatement(#[from] Box<SdkError<ExecuteStatementError>>),
+
+    /// An error occurred while reading a transaction of items.
+    #[error(transparent)]
+    TransactGetItems(#[from] Box<SdkError<TransactGetItemsError>>),
+
+    /// An error occurred while describing the time to live of the table.
+    #[error(transparent)]
+    DescribeTimeToLive(#[from] Box<SdkError<DescribeTimeToLiveError>>),
+
+    /// Only `SELECT` statements can be run with `partiql_query`.
+    #[error("Only SELECT statements can be run with partiql_query")]
+    NonSelectStatement,
+
+    /// An error occurred while describing tables
+    #[error(transparent)]
+    DescribeTables(#[from] Box<SdkError<DescribeTableError>>),
+
+    /// The table was written with a newer layout than the one of this code.
+    #[error(
+        "The table has schema version {found}, but only versions up to {expected} are supported"
+    )]
+    SchemaVersionMismatch {
+        /// The schema version found in the table.
+        found: u32,
+        /// The latest schema version supported.
+        expected: u32,
+    },
+
+    /// The table did not become active in time after its creation.
+    #[error("The table {0} did not become active after its creation")]
+    TableNotActive(String),
+
+    /// An error occurred while putting an item.
+    #[error(transparent)]
+    PutItem(#[from] Box<SdkError<PutItemError>>),
+
+    /// The table does not exist and creating it is disabled in the configuration.
+    #[error("The table {0} does not exist and creating it is disabled")]
+    TableNotFound(String),
+
+    /// The table cannot be deleted since creating it again is disabled in the
+    /// configuration.
+    #[error("The table {0} cannot be deleted since creating it is disabled")]
+    TableDeletionDisabled(String),
+
+    /// The version guarding a conditional write was changed by a concurrent writer.
+    #[error("The version at the guard key does not match the expected version")]
+    VersionConflict,
+
+    /// The transaction was canceled by a concurrent write and can be retried.
+    #[error("The transaction was canceled by a concurrent write")]
+    TransactionConflict,
+
+    /// The entry appended to a bounded log is the one it evicts.
+    #[error("The entry {index} of the log cannot be both appended and evicted")]
+    AppendEvictsItself {
+        /// The index of the entry.
+        index: u64,
+    },
+
+    /// The condition of a conditional write does not hold.
+    #[error("The condition of the write does not hold")]
+    ConditionFailed,
+
+    /// The value kept changing between the reads and the writes of an update.
+    #[error("The value was changed concurrently during every attempt of the update")]
+    UpdateConflict,
+
+    /// The client would connect to a region that is not allowed.
+    #[error("The region {region:?} is not one of the allowed regions")]
+    RegionNotAllowed {
+        /// The region of the client, if any.
+        region: Option<String>,
+    },
+
+    /// The lease is held by another holder and has not expired.
+    #[error("The lease is held by another holder")]
+    LeaseHeld,
+
+    /// The lease expired and was acquired by another holder.
+    #[error("The lease was lost to another holder")]
+    LeaseLost,
+
+    /// The time of the last write of an item is not a number of milliseconds.
+    #[error("The time of the last write of the item is invalid")]
+    InvalidModificationTime,
 
-    /// An error occurred while listing tables
-    #[error(transparent)]
-    ListTables(#[from] Box<SdkError<ListTablesError>>),
+    /// The batch deletes a key and then inserts it again, which is rejected in the strict
+    /// mode set by `with_reject_conflicting_operations`.
+    #[error("The batch deletes the key {key:?} and then inserts it again")]
+    ConflictingSameKeyOps {
+        /// The key deleted and then inserted.
+        key: Vec<u8>,
+    },
 
-    /// An error occurred while describing tables
-    #[error(transparent)]
-    DescribeTables(#[from] Box<SdkError<DescribeTableError>>),
+    /// The backoff strategy gave up retrying the items that DynamoDB did not process.
+    #[error("The retries of the unprocessed items were exhausted")]
+    RetriesExhausted,
+
+    /// The checksum of a stored value does not match its content.
+    #[error("The checksum of the value at key {key:?} does not match")]
+    ChecksumMismatch {
+        /// The key of the corrupted value.
+        key: Vec<u8>,
+    },
+
+    /// The key holding the version of a conditional write cannot be part of the batch.
+    #[error("The version key cannot be written or deleted by the guarded batch")]
+    VersionKeyInBatch,
 
     /// The transact maximum size is `MAX_TRANSACT_WRITE_ITEM_SIZE`.
     #[error("The transact must have length at most MAX_TRANSACT_WRITE_ITEM_SIZE")]
@@ -1043,6 +6138,10 @@ pub enum DynamoDbStoreInternalError {
     #[error("The key must have at most 1024 bytes")]
     KeyTooLong,
 
+    /// The key was rejected by the key validator.
+    #[error("The key was rejected by the key validator: {0}")]
+    InvalidKey(String),
+
     /// The key prefix must have at most 1024 bytes
     #[error("The key prefix must have at most 1024 bytes")]
     KeyPrefixTooLong,
@@ -1063,6 +6162,13 @@ pub enum DynamoDbStoreInternalError {
     #[error("The DynamoDB value should be less than 400 KB")]
     ValueLengthTooLarge,
 
+    /// The item, counting its key and attribute names, is larger than 400 KB.
+    #[error("The DynamoDB item has {size} bytes, above the limit of 400 KB")]
+    ItemTooLarge {
+        /// The size of the item, as counted by DynamoDB.
+        size: usize,
+    },
+
     /// The stored key is missing.
     #[error("The stored key attribute is missing")]
     MissingKey,
@@ -1083,10 +6189,30 @@ pub enum DynamoDbStoreInternalError {
     #[error(transparent)]
     BcsError(#[from] bcs::Error),
 
+    /// The value at a key could not be deserialized from BCS.
+    #[error("Failed to deserialize the value at key {key:?}: {error}")]
+    BcsErrorAtKey {
+        /// The key of the value, without the prefix of the scan.
+        key: Vec<u8>,
+        /// The error of the deserialization.
+        error: bcs::Error,
+    },
+
     /// A wrong namespace error occurred
     #[error(transparent)]
     InvalidNamespace(#[from] InvalidNamespace),
 
+    /// An I/O error occurred while exporting or importing records.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// A record to import has a key or value longer than DynamoDB allows.
+    #[error("The record has a part of {length} bytes, above the limits of DynamoDB")]
+    RecordTooLarge {
+        /// The length of the part of the record.
+        length: usize,
+    },
+
     /// An error occurred while creating the table.
     #[error(transparent)]
     CreateTable(#[from] Box<SdkError<CreateTableError>>),
@@ -1111,7 +6237,48 @@ impl From<BuildError> for DynamoDbStoreInternalError {
     }
 }
 
+/// The category of a [`DynamoDbStoreInternalError`], telling whether retrying the
+/// operation may succeed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The request was throttled and can be retried after backing off.
+    Throttling,
+    /// DynamoDB or the network failed, and the request can be retried.
+    ServiceUnavailable,
+    /// The credentials are missing, invalid or not allowed to run the request.
+    AccessDenied,
+    /// The request is invalid and retrying it fails again.
+    Validation,
+    /// Any other error, including the errors that do not come from DynamoDB.
+    Other,
+}
+
 impl DynamoDbStoreInternalError {
+    /// Returns the category of the error. The errors returned by DynamoDB are classified
+    /// from their error code and HTTP status, the other errors are `Other`.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Get(error) => sdk_error_category(error),
+            Self::BatchWriteItem(error) => sdk_error_category(error),
+            Self::TransactWriteItem(error) => sdk_error_category(error),
+            Self::UpdateItem(error) => sdk_error_category(error),
+            Self::DeleteItem(error) => sdk_error_category(error),
+            Self::PutItem(error) => sdk_error_category(error),
+            Self::Query(error) => sdk_error_category(error),
+            Self::Scan(error) => sdk_error_category(error),
+            Self::DeleteTable(error) => sdk_error_category(error),
+            Self::ListTables(error) => sdk_error_category(error),
+            Self::ExecuteStatement(error) => sdk_error_category(error),
+            Self::TransactGetItems(error) => sdk_error_category(error),
+            Self::DescribeTimeToLive(error) => sdk_error_category(error),
+            Self::DescribeTables(error) => sdk_error_category(error),
+            Self::CreateTable(error) => sdk_error_category(error),
+            Self::Build(_) => ErrorCategory::Validation,
+            Self::RetriesExhausted => ErrorCategory::Throttling,
+            _ => ErrorCategory::Other,
+        }
+    }
+
     /// Creates a [`DynamoDbStoreInternalError::WrongKeyType`] instance based on the returned value type.
     ///
     /// # Panics
@@ -1161,11 +6328,291 @@ impl TestKeyValueStore for JournalingKeyValueStore<DynamoDbStoreInternal> {
         };
         Ok(DynamoDbStoreInternalConfig {
             use_localstack: true,
+            localstack_endpoint: None,
             common_config,
+            create_if_missing: true,
+            fallback_regions: Vec::new(),
+            transactions_supported: true,
+            check_schema_version: false,
+            query_page_size: None,
+            max_inflight_bytes: None,
+            value_checksums: false,
+            allowed_regions: None,
+            max_recovery_jitter: Duration::ZERO,
+            reject_conflicting_operations: false,
+            record_modification_times: false,
+            malformed_items: MalformedItems::Strict,
+            max_update_attempts: DEFAULT_MAX_UPDATE_ATTEMPTS,
+            separate_internal_partition: false,
+            allow_empty_key_prefix: false,
+            batch_dump: BatchDump::Off,
+            min_transform_size: DEFAULT_MIN_TRANSFORM_SIZE,
+            capabilities: None,
+            item_collection_metrics: false,
         })
     }
 }
 
+/// A builder for a [`DynamoDbStoreConfig`], setting its parameters by name.
+#[derive(Debug, Clone)]
+pub struct DynamoDbStoreConfigBuilder {
+    use_localstack: bool,
+    localstack_endpoint: Option<String>,
+    common_config: crate::store::CommonStoreConfig,
+    create_if_missing: bool,
+    fallback_regions: Vec<String>,
+    transactions_supported: bool,
+    check_schema_version: bool,
+    query_page_size: Option<i32>,
+    max_inflight_bytes: Option<usize>,
+    value_checksums: bool,
+    allowed_regions: Option<Vec<String>>,
+    max_recovery_jitter: Duration,
+    reject_conflicting_operations: bool,
+    record_modification_times: bool,
+    malformed_items: MalformedItems,
+    max_update_attempts: usize,
+    separate_internal_partition: bool,
+    allow_empty_key_prefix: bool,
+    batch_dump: BatchDump,
+    min_transform_size: usize,
+    capabilities: Option<Capabilities>,
+    item_collection_metrics: bool,
+}
+
+impl Default for DynamoDbStoreConfigBuilder {
+    fn default() -> Self {
+        Self {
+            use_localstack: false,
+            localstack_endpoint: None,
+            common_config: crate::store::CommonStoreConfig::default(),
+            create_if_missing: true,
+            fallback_regions: Vec::new(),
+            transactions_supported: true,
+            check_schema_version: false,
+            query_page_size: None,
+            max_inflight_bytes: None,
+            value_checksums: false,
+            allowed_regions: None,
+            max_recovery_jitter: Duration::ZERO,
+            reject_conflicting_operations: false,
+            record_modification_times: false,
+            malformed_items: MalformedItems::Strict,
+            max_update_attempts: DEFAULT_MAX_UPDATE_ATTEMPTS,
+            separate_internal_partition: false,
+            allow_empty_key_prefix: false,
+            batch_dump: BatchDump::Off,
+            min_transform_size: DEFAULT_MIN_TRANSFORM_SIZE,
+            capabilities: None,
+            item_collection_metrics: false,
+        }
+    }
+}
+
+impl DynamoDbStoreConfigBuilder {
+    /// Sets whether to connect to LocalStack instead of AWS.
+    pub fn use_localstack(mut self, use_localstack: bool) -> Self {
+        self.use_localstack = use_localstack;
+        self
+    }
+
+    /// Connects to the LocalStack instance at `endpoint`.
+    /// See [`DynamoDbStoreInternalConfig::with_localstack_endpoint`].
+    pub fn localstack_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.use_localstack = true;
+        self.localstack_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets all the parameters common to the key-value stores at once.
+    pub fn common_config(mut self, common_config: crate::store::CommonStoreConfig) -> Self {
+        self.common_config = common_config;
+        self
+    }
+
+    /// Sets the maximum number of concurrent queries, or `None` for no limit.
+    pub fn max_concurrent_queries(mut self, max_concurrent_queries: Option<usize>) -> Self {
+        self.common_config.max_concurrent_queries = max_concurrent_queries;
+        self
+    }
+
+    /// Sets the number of streams used for the async streams.
+    pub fn max_stream_queries(mut self, max_stream_queries: usize) -> Self {
+        self.common_config.max_stream_queries = max_stream_queries;
+        self
+    }
+
+    /// Sets the configuration of the LRU cache.
+    pub fn storage_cache_config(mut self, storage_cache_config: StorageCacheConfig) -> Self {
+        self.common_config.storage_cache_config = storage_cache_config;
+        self
+    }
+
+    /// Sets whether a missing table is created when connecting.
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Sets the regions of the replicas used for reads when the primary region is
+    /// unavailable. See [`DynamoDbStoreInternalConfig::with_fallback_regions`].
+    pub fn fallback_regions(mut self, fallback_regions: Vec<String>) -> Self {
+        self.fallback_regions = fallback_regions;
+        self
+    }
+
+    /// Sets whether the backend supports `TransactWriteItems`. See
+    /// [`DynamoDbStoreInternalConfig::with_transactions_supported`].
+    pub fn transactions_supported(mut self, transactions_supported: bool) -> Self {
+        self.transactions_supported = transactions_supported;
+        self
+    }
+
+    /// Sets whether to check the schema version of the table when connecting. See
+    /// [`DynamoDbStoreInternalConfig::with_check_schema_version`].
+    pub fn check_schema_version(mut self, check_schema_version: bool) -> Self {
+        self.check_schema_version = check_schema_version;
+        self
+    }
+
+    /// Sets the maximum number of items read by each page of the prefix queries. See
+    /// [`DynamoDbStoreInternalConfig::with_query_page_size`].
+    pub fn query_page_size(mut self, query_page_size: Option<i32>) -> Self {
+        self.query_page_size = query_page_size;
+        self
+    }
+
+    /// Sets the maximum number of bytes of the multi-key reads and bulk writes in flight.
+    /// See [`DynamoDbStoreInternalConfig::with_max_inflight_bytes`].
+    pub fn max_inflight_bytes(mut self, max_inflight_bytes: Option<usize>) -> Self {
+        self.max_inflight_bytes = max_inflight_bytes;
+        self
+    }
+
+    /// Sets whether the values are stored with a checksum, verified when they are read.
+    /// See [`DynamoDbStoreInternalConfig::with_value_checksums`].
+    pub fn value_checksums(mut self, value_checksums: bool) -> Self {
+        self.value_checksums = value_checksums;
+        self
+    }
+
+    /// Restricts the regions that the clients may connect to. See
+    /// [`DynamoDbStoreInternalConfig::with_allowed_regions`].
+    pub fn allowed_regions(mut self, allowed_regions: Option<Vec<String>>) -> Self {
+        self.allowed_regions = allowed_regions;
+        self
+    }
+
+    /// Sets the maximal random delay before resolving a journal left over by a crash.
+    /// See [`DynamoDbStoreInternalConfig::with_max_recovery_jitter`].
+    pub fn max_recovery_jitter(mut self, max_recovery_jitter: Duration) -> Self {
+        self.max_recovery_jitter = max_recovery_jitter;
+        self
+    }
+
+    /// Sets whether the batches deleting and then inserting the same key are rejected.
+    /// See [`DynamoDbStoreInternalConfig::with_reject_conflicting_operations`].
+    pub fn reject_conflicting_operations(mut self, reject: bool) -> Self {
+        self.reject_conflicting_operations = reject;
+        self
+    }
+
+    /// Sets whether the time of the last write is stored with every item.
+    /// See [`DynamoDbStoreInternalConfig::with_record_modification_times`].
+    pub fn record_modification_times(mut self, record: bool) -> Self {
+        self.record_modification_times = record;
+        self
+    }
+
+    /// Sets what the prefix scans do with the items that cannot be read.
+    /// See [`DynamoDbStoreInternalConfig::with_malformed_items`].
+    pub fn malformed_items(mut self, malformed_items: MalformedItems) -> Self {
+        self.malformed_items = malformed_items;
+        self
+    }
+
+    /// Sets the maximal number of read-modify-write cycles of an update.
+    /// See [`DynamoDbStoreInternalConfig::with_max_update_attempts`].
+    pub fn max_update_attempts(mut self, max_update_attempts: usize) -> Self {
+        self.max_update_attempts = max_update_attempts;
+        self
+    }
+
+    /// Sets whether the internal items are stored in a partition apart from the data.
+    /// See [`DynamoDbStoreInternalConfig::with_separate_internal_partition`].
+    pub fn separate_internal_partition(mut self, separate: bool) -> Self {
+        self.separate_internal_partition = separate;
+        self
+    }
+
+    /// Sets whether the prefix scans accept an empty prefix.
+    /// See [`DynamoDbStoreInternalConfig::with_allow_empty_key_prefix`].
+    pub fn allow_empty_key_prefix(mut self, allow: bool) -> Self {
+        self.allow_empty_key_prefix = allow;
+        self
+    }
+
+    /// Sets when the operations of the write requests are logged.
+    /// See [`DynamoDbStoreInternalConfig::with_batch_dump`].
+    pub fn batch_dump(mut self, batch_dump: BatchDump) -> Self {
+        self.batch_dump = batch_dump;
+        self
+    }
+
+    /// Sets the size below which the values are stored without a checksum.
+    /// See [`DynamoDbStoreInternalConfig::with_min_transform_size`].
+    pub fn min_transform_size(mut self, min_transform_size: usize) -> Self {
+        self.min_transform_size = min_transform_size;
+        self
+    }
+
+    /// Sets the features supported by the backend.
+    /// See [`DynamoDbStoreInternalConfig::with_capabilities`].
+    pub fn capabilities(mut self, capabilities: Option<Capabilities>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Sets whether the writes request the size estimate of the item collection.
+    /// See [`DynamoDbStoreInternalConfig::with_item_collection_metrics`].
+    pub fn item_collection_metrics(mut self, item_collection_metrics: bool) -> Self {
+        self.item_collection_metrics = item_collection_metrics;
+        self
+    }
+
+    /// Builds the `DynamoDbStoreConfig`.
+    pub fn build(self) -> DynamoDbStoreConfig {
+        let inner_config = DynamoDbStoreInternalConfig {
+            use_localstack: self.use_localstack,
+            localstack_endpoint: self.localstack_endpoint,
+            common_config: self.common_config.reduced(),
+            create_if_missing: self.create_if_missing,
+            fallback_regions: self.fallback_regions,
+            transactions_supported: self.transactions_supported,
+            check_schema_version: self.check_schema_version,
+            query_page_size: self.query_page_size,
+            max_inflight_bytes: self.max_inflight_bytes,
+            value_checksums: self.value_checksums,
+            allowed_regions: self.allowed_regions,
+            max_recovery_jitter: self.max_recovery_jitter,
+            reject_conflicting_operations: self.reject_conflicting_operations,
+            record_modification_times: self.record_modification_times,
+            malformed_items: self.malformed_items,
+            max_update_attempts: self.max_update_attempts,
+            separate_internal_partition: self.separate_internal_partition,
+            allow_empty_key_prefix: self.allow_empty_key_prefix,
+            batch_dump: self.batch_dump,
+            min_transform_size: self.min_transform_size,
+            capabilities: self.capabilities,
+            item_collection_metrics: self.item_collection_metrics,
+        };
+        DynamoDbStoreConfig {
+            inner_config,
+            storage_cache_config: self.common_config.storage_cache_config,
+        }
+    }
+}
+
 /// A shared DB client for DynamoDB implementing LRU caching and metrics
 #[cfg(with_metrics)]
 pub type DynamoDbStore = MeteredStore<
@@ -1181,6 +6628,24 @@ pub type DynamoDbStore = MeteredStore<
 pub type DynamoDbStore =
     LruCachingStore<ValueSplittingStore<JournalingKeyValueStore<DynamoDbStoreInternal>>>;
 
+/// A shared DB client for DynamoDB without LRU caching, implementing metrics.
+///
+/// It avoids the locking and bookkeeping of the cache on every call, which is preferable
+/// for access patterns without locality such as large scans. It is connected with the
+/// `inner_config` of a [`DynamoDbStoreConfig`].
+#[cfg(with_metrics)]
+pub type UncachedDynamoDbStore =
+    MeteredStore<ValueSplittingStore<MeteredStore<JournalingKeyValueStore<DynamoDbStoreInternal>>>>;
+
+/// A shared DB client for DynamoDB without LRU caching.
+///
+/// It avoids the locking and bookkeeping of the cache on every call, which is preferable
+/// for access patterns without locality such as large scans. It is connected with the
+/// `inner_config` of a [`DynamoDbStoreConfig`].
+#[cfg(not(with_metrics))]
+pub type UncachedDynamoDbStore =
+    ValueSplittingStore<JournalingKeyValueStore<DynamoDbStoreInternal>>;
+
 /// The combined error type for [`DynamoDbStore`].
 pub type DynamoDbStoreError = ValueSplittingError<DynamoDbStoreInternalError>;
 
@@ -1193,22 +6658,67 @@ impl DynamoDbStoreConfig {
         use_localstack: bool,
         common_config: crate::store::CommonStoreConfig,
     ) -> DynamoDbStoreConfig {
-        let inner_config = DynamoDbStoreInternalConfig {
-            use_localstack,
-            common_config: common_config.reduced(),
-        };
-        DynamoDbStoreConfig {
-            inner_config,
-            storage_cache_config: common_config.storage_cache_config,
-        }
+        Self::builder()
+            .use_localstack(use_localstack)
+            .common_config(common_config)
+            .build()
+    }
+
+    /// Creates a `DynamoDbStoreConfig` connecting to the LocalStack instance at `endpoint`,
+    /// instead of the one given by the `LOCALSTACK_ENDPOINT` environment variable.
+    pub fn with_localstack_endpoint(
+        endpoint: impl Into<String>,
+        common_config: crate::store::CommonStoreConfig,
+    ) -> DynamoDbStoreConfig {
+        Self::builder()
+            .localstack_endpoint(endpoint)
+            .common_config(common_config)
+            .build()
+    }
+
+    /// Returns a builder for a `DynamoDbStoreConfig`, starting from the default values.
+    pub fn builder() -> DynamoDbStoreConfigBuilder {
+        DynamoDbStoreConfigBuilder::default()
+    }
+
+    /// Sets whether a missing table is created when connecting with
+    /// `maybe_create_and_connect`. If not, tables cannot be deleted either, so
+    /// `recreate_and_connect` fails and leaves the table in place.
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.inner_config = self.inner_config.with_create_if_missing(create_if_missing);
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::{
+        config::Region,
+        error::SdkError,
+        operation::{get_item::GetItemError, query::QueryOutput},
+        primitives::Blob,
+        types::{AttributeValue, ConsumedCapacity, Delete, Put, TransactWriteItem},
+    };
     use bcs::serialized_size;
-
-    use crate::common::get_uleb128_size;
+    use linera_base::time::Duration;
+
+    use super::{
+        add_value_checksum, add_value_header, build_key, build_key_value, check_item_size,
+        coerce_value, composite_key, error_code_category, extract_value, extract_value_owned,
+        index_key, is_unsupported_operation, item_size, key_value_item_size, read_record_part,
+        summarize_write_items, AccessTracker, BackendLimits, BackoffStrategy as _,
+        CapacityHeadroom, ConditionExpr, ConsumedCapacityTracker, DynamoDbKeyValues, DynamoDbKeys,
+        DynamoDbStoreConfigBuilder, DynamoDbStoreInternalError, ErrorCategory, ExponentialJitter,
+        Fault, FaultInjector, FixedDelay, InflightBytesBudget, NoRetry, QueryResponses,
+        RequestRateMonitor, Snapshot, WriteItemSummary, BATCH_WRITE_MAX_RETRY_DELAY, KEY_ATTRIBUTE,
+        MAX_ITEM_SIZE,
+    };
+    use crate::{
+        common::get_uleb128_size,
+        store::{KeyIterable as _, KeyValueIterable as _},
+    };
 
     #[test]
     fn test_serialization_len() {
@@ -1219,4 +6729,698 @@ mod tests {
             assert_eq!(est_size, serial_size);
         }
     }
+
+    /// Checks that `encode` preserves the order of `inputs`, which are sorted.
+    fn assert_order_preserved<T>(inputs: &[T], encode: impl Fn(&T) -> Vec<u8>) {
+        let keys = inputs.iter().map(encode).collect::<Vec<_>>();
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        assert_eq!(keys, sorted_keys);
+    }
+
+    #[test]
+    fn test_composite_key_ordering() {
+        let pairs: [(&[u8], &[u8]); 7] = [
+            (&[], &[5]),
+            (&[0], &[]),
+            (&[0], &[0, 1]),
+            (&[0, 0], &[]),
+            (&[1], &[]),
+            (&[1], &[255]),
+            (&[1, 0], &[0]),
+        ];
+        assert_order_preserved(&pairs, |(entity, sort)| composite_key(entity, sort));
+    }
+
+    #[test]
+    fn test_index_key_ordering() {
+        let indices = [0, 1, 255, 256, 65536, u64::MAX];
+        assert_order_preserved(&indices, |index| index_key(&[7], *index));
+    }
+
+    #[test]
+    fn test_value_checksum() {
+        let value = b"value".to_vec();
+        let mut item = build_key_value(&[0], b"key".to_vec(), add_value_checksum(value.clone()));
+        assert_eq!(extract_value(&item, true).unwrap(), value.as_slice());
+        assert_eq!(extract_value_owned(&mut item.clone(), true).unwrap(), value);
+
+        let mut corrupted_value = add_value_checksum(value);
+        *corrupted_value.last_mut().unwrap() ^= 1;
+        item = build_key_value(&[0], b"key".to_vec(), corrupted_value);
+        assert!(matches!(
+            extract_value(&item, true),
+            Err(DynamoDbStoreInternalError::ChecksumMismatch { key }) if key == b"key"
+        ));
+        assert!(matches!(
+            extract_value_owned(&mut item, true),
+            Err(DynamoDbStoreInternalError::ChecksumMismatch { key }) if key == b"key"
+        ));
+
+        // Values written without a checksum are read as they are.
+        let mut item = build_key_value(&[0], b"key".to_vec(), vec![1, 2]);
+        assert_eq!(extract_value_owned(&mut item, false).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_value_header() {
+        // The small values only get a version byte.
+        let value = vec![7; 10];
+        let stored_value = add_value_header(value.clone(), 256);
+        assert_eq!(stored_value.len(), 1 + value.len());
+        let mut item = build_key_value(&[0], b"key".to_vec(), stored_value);
+        assert_eq!(extract_value(&item, true).unwrap(), value.as_slice());
+        assert_eq!(extract_value_owned(&mut item, true).unwrap(), value);
+
+        // The others get a checksum.
+        let value = vec![7; 256];
+        let stored_value = add_value_header(value.clone(), 256);
+        assert_eq!(stored_value, add_value_checksum(value.clone()));
+        let mut item = build_key_value(&[0], b"key".to_vec(), stored_value);
+        assert_eq!(extract_value_owned(&mut item, true).unwrap(), value);
+
+        // The empty values are stored raw as well.
+        let mut item = build_key_value(&[0], b"key".to_vec(), add_value_header(Vec::new(), 1));
+        assert_eq!(
+            extract_value_owned(&mut item, true).unwrap(),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn test_capacity_headroom() {
+        let tracker = ConsumedCapacityTracker::default();
+        tracker.record_read(Some(
+            &ConsumedCapacity::builder().capacity_units(90.0).build(),
+        ));
+        tracker.record_read(None);
+        let headroom = CapacityHeadroom {
+            provisioned_read_units: Some(10),
+            provisioned_write_units: None,
+            consumed_read_units: ConsumedCapacityTracker::units_per_second(&tracker.reads),
+            consumed_write_units: ConsumedCapacityTracker::units_per_second(&tracker.writes),
+        };
+        assert_eq!(headroom.consumed_read_units, 1.5);
+        assert_eq!(headroom.consumed_write_units, 0.0);
+        assert_eq!(headroom.read_utilization_percent(), Some(15.0));
+        assert_eq!(headroom.write_utilization_percent(), None);
+    }
+
+    #[test]
+    fn test_condition_expr_compile() {
+        let condition = ConditionExpr::NotExists.or(ConditionExpr::ValueEquals(vec![1]).and(
+            ConditionExpr::Not(Box::new(ConditionExpr::ValueNotEquals(vec![2]))),
+        ));
+        let mut values = HashMap::new();
+        let expression = condition.compile(&mut values, &|value| value);
+        assert_eq!(
+            expression,
+            "(attribute_not_exists(item_key)) OR \
+             ((item_value = :cond0) AND (NOT (item_value <> :cond1)))"
+        );
+        assert_eq!(values[":cond0"], AttributeValue::B(Blob::new(vec![1])));
+        assert_eq!(values[":cond1"], AttributeValue::B(Blob::new(vec![2])));
+    }
+
+    #[test]
+    fn test_owned_iterator_drains_pages() {
+        let page = |keys: &[&[u8]]| {
+            let items = keys
+                .iter()
+                .map(|key| build_key_value(&[0], key.to_vec(), key.to_vec()))
+                .collect::<Vec<_>>();
+            QueryOutput::builder().set_items(Some(items)).build()
+        };
+        let key_values = DynamoDbKeyValues {
+            result_queries: QueryResponses {
+                prefix_len: 1,
+                value_checksums: false,
+                skipped_items: 0,
+                responses: vec![page(&[b"a1", b"a2"]), page(&[]), page(&[b"a3"])],
+            },
+        };
+        let entries = key_values
+            .into_iterator_owned()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = [(b"1", b"a1"), (b"2", b"a2"), (b"3", b"a3")]
+            .map(|(key, value)| (key.to_vec(), value.to_vec()));
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_continuation_token() {
+        let item = build_key_value(&[0], b"a1".to_vec(), b"v".to_vec());
+        let last_page = QueryOutput::builder().set_items(Some(vec![item])).build();
+        let keys = DynamoDbKeys {
+            result_queries: QueryResponses {
+                prefix_len: 1,
+                value_checksums: false,
+                skipped_items: 0,
+                responses: vec![last_page],
+            },
+        };
+        assert_eq!(keys.continuation_token(), None);
+
+        let item = build_key_value(&[0], b"a2".to_vec(), b"v".to_vec());
+        let partial_page = QueryOutput::builder()
+            .set_items(Some(vec![item]))
+            .set_last_evaluated_key(Some(build_key(&[0], b"a2".to_vec())))
+            .build();
+        let keys = DynamoDbKeys {
+            result_queries: QueryResponses {
+                prefix_len: 1,
+                value_checksums: false,
+                skipped_items: 0,
+                responses: vec![partial_page],
+            },
+        };
+        assert_eq!(keys.continuation_token(), Some(b"a2".to_vec()));
+    }
+
+    #[test]
+    fn test_snapshot_reads() {
+        let snapshot = Snapshot {
+            key_prefix: vec![1],
+            key_values: [
+                (vec![1, 1], vec![10]),
+                (vec![1, 2, 1], vec![21]),
+                (vec![1, 2, 2], vec![22]),
+                (vec![1, 3], vec![30]),
+            ]
+            .into(),
+        };
+        assert_eq!(snapshot.len(), 4);
+        assert_eq!(snapshot.read_value_bytes(&[1, 3]), Some(&[30][..]));
+        assert!(!snapshot.contains_key(&[1, 2]));
+        assert_eq!(
+            snapshot.find_keys_by_prefix(&[1, 2]).collect::<Vec<_>>(),
+            vec![&[1][..], &[2][..]]
+        );
+        assert_eq!(
+            snapshot.find_key_values_by_prefix(&[1]).last(),
+            Some((&[3][..], &[30][..]))
+        );
+    }
+
+    #[test]
+    fn test_allowed_regions() {
+        let config = DynamoDbStoreConfigBuilder::default().build().inner_config;
+        assert!(config.check_region(None).is_ok());
+        let config = DynamoDbStoreConfigBuilder::default()
+            .allowed_regions(Some(vec!["eu-west-1".to_string()]))
+            .build()
+            .inner_config;
+        assert!(config.check_region(Some(&Region::new("eu-west-1"))).is_ok());
+        assert!(matches!(
+            config.check_region(Some(&Region::new("us-east-1"))),
+            Err(DynamoDbStoreInternalError::RegionNotAllowed { region: Some(region) })
+                if region == "us-east-1"
+        ));
+        assert!(matches!(
+            config.check_region(None),
+            Err(DynamoDbStoreInternalError::RegionNotAllowed { region: None })
+        ));
+    }
+
+    #[test]
+    fn test_error_category() {
+        let error = SdkError::<GetItemError>::timeout_error("timeout");
+        assert_eq!(
+            DynamoDbStoreInternalError::from(error).category(),
+            ErrorCategory::ServiceUnavailable
+        );
+        assert_eq!(
+            error_code_category(Some("ProvisionedThroughputExceededException")),
+            Some(ErrorCategory::Throttling)
+        );
+        assert_eq!(
+            error_code_category(Some("AccessDeniedException")),
+            Some(ErrorCategory::AccessDenied)
+        );
+        assert_eq!(
+            error_code_category(Some("ValidationException")),
+            Some(ErrorCategory::Validation)
+        );
+        assert_eq!(error_code_category(Some("ConditionalCheckFailed")), None);
+        assert_eq!(
+            DynamoDbStoreInternalError::KeyTooLong.category(),
+            ErrorCategory::Other
+        );
+    }
+
+    #[test]
+    fn test_backoff_strategies() {
+        let backoff = ExponentialJitter {
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            max_attempts: Some(10),
+        };
+        for (attempt, base) in [(0, 100), (1, 200), (3, 800), (4, 1000), (9, 1000)] {
+            let delay = backoff.next_delay(attempt).unwrap();
+            assert!(delay <= Duration::from_millis(base));
+            assert!(delay >= Duration::from_millis(base / 2));
+        }
+        assert_eq!(backoff.next_delay(10), None);
+        let unbounded = ExponentialJitter::default();
+        assert!(unbounded.next_delay(usize::MAX).unwrap() <= BATCH_WRITE_MAX_RETRY_DELAY);
+        let backoff = FixedDelay {
+            delay: Duration::from_millis(10),
+            max_attempts: Some(2),
+        };
+        assert_eq!(backoff.next_delay(1), Some(Duration::from_millis(10)));
+        assert_eq!(backoff.next_delay(2), None);
+        assert_eq!(NoRetry.next_delay(0), None);
+    }
+
+    #[test]
+    fn test_access_tracker() {
+        let tracker = AccessTracker::new(2, Duration::from_secs(3600));
+        for _ in 0..3 {
+            tracker.record(&[1, 2, 3]);
+            tracker.record(&[1, 2, 4]);
+        }
+        tracker.record(&[5]);
+        tracker.record(&[1, 3]);
+        tracker.record(&[1, 3, 1]);
+        assert_eq!(
+            tracker.stats(),
+            vec![(vec![1, 2], 6), (vec![1, 3], 2), (vec![5], 1)]
+        );
+        // Many prefixes read once each do not evict the most read prefix.
+        for i in 0..1000u32 {
+            tracker.record(&[100 + (i / 256) as u8, i as u8]);
+        }
+        let stats = tracker.stats();
+        assert!(stats.len() <= 64);
+        assert_eq!(stats[0].0, vec![1, 2]);
+        assert!(stats[0].1 >= 6);
+    }
+
+    #[test]
+    fn test_request_rate_monitor() {
+        let monitor = RequestRateMonitor::new(3, 2);
+        assert_eq!(monitor.record(3, 2), None);
+        assert_eq!(monitor.record(1, 0), Some((4, 2)));
+        // The next warning is delayed even though the rates stay too high.
+        assert_eq!(monitor.record(0, 5), None);
+    }
+
+    #[test]
+    fn test_inflight_bytes_budget() {
+        use futures::FutureExt as _;
+
+        let budget = InflightBytesBudget::new(4000);
+        let permit = budget.reserve(3000).now_or_never().unwrap();
+        assert!(budget.reserve(2000).now_or_never().is_none());
+        // The reservation that was canceled released its units.
+        assert!(budget.reserve(1000).now_or_never().is_some());
+        drop(permit);
+        // A request larger than the budget takes the whole budget.
+        let permit = budget.reserve(10000).now_or_never().unwrap();
+        assert!(budget.reserve(1).now_or_never().is_none());
+        drop(permit);
+        assert!(budget.reserve(4000).now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_check_item_size() {
+        // The item counts the names of the partition, key and value attributes.
+        let overhead = 14 + 8 + 10 + 1;
+        let key = vec![1; 1000];
+        let value = vec![2; MAX_ITEM_SIZE - overhead - key.len()];
+        let item = build_key_value(&[0], key.clone(), value);
+        assert_eq!(item_size(&item), MAX_ITEM_SIZE);
+        assert!(check_item_size(&item).is_ok());
+        // A value below the limit does not fit with a long key.
+        let value = vec![2; MAX_ITEM_SIZE - overhead - key.len() + 1];
+        let item = build_key_value(&[0], key, value);
+        assert!(matches!(
+            check_item_size(&item),
+            Err(DynamoDbStoreInternalError::ItemTooLarge { size }) if size == MAX_ITEM_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn test_item_size() {
+        // The examples of the documentation of DynamoDB: a string counts its UTF-8 bytes
+        // and a binary its bytes, each with the name of its attribute.
+        let item = [("shirt-color".to_owned(), AttributeValue::S("R".to_owned()))].into();
+        assert_eq!(item_size(&item), 12);
+        let item = [
+            ("name".to_owned(), AttributeValue::S("Žluťoučký".to_owned())),
+            ("data".to_owned(), AttributeValue::B(Blob::new(vec![0; 10]))),
+        ]
+        .into();
+        assert_eq!(item_size(&item), 4 + 13 + 4 + 10);
+        // Numbers are counted at their maximal size.
+        let item = [("count".to_owned(), AttributeValue::N("12".to_owned()))].into();
+        assert_eq!(item_size(&item), 5 + 21);
+        // The items of key-values are counted without building them.
+        let item = build_key_value(&[0, 1], vec![2; 100], vec![3; 1000]);
+        assert_eq!(key_value_item_size(2, 100, 1000), item_size(&item));
+    }
+
+    #[test]
+    fn test_backend_limits() {
+        // The limit of DynamoDB is on the items, so the largest value accepted depends on
+        // the key it was probed with.
+        let limits = BackendLimits {
+            max_value_size: MAX_ITEM_SIZE - 40,
+            max_item_size: MAX_ITEM_SIZE,
+        };
+        assert!(limits.supports_store_values());
+        let limits = BackendLimits {
+            max_value_size: MAX_ITEM_SIZE - 40,
+            max_item_size: MAX_ITEM_SIZE - 1,
+        };
+        assert!(!limits.supports_store_values());
+    }
+
+    #[test]
+    fn test_summarize_write_items() {
+        let put = Put::builder()
+            .table_name("table")
+            .set_item(Some(build_key_value(&[0, 1], vec![2; 3], vec![4; 50])))
+            .build()
+            .unwrap();
+        let delete = Delete::builder()
+            .table_name("table")
+            .set_key(Some(build_key(&[0, 1], vec![5; 7])))
+            .build()
+            .unwrap();
+        let items = [
+            TransactWriteItem::builder().put(put).build(),
+            TransactWriteItem::builder().delete(delete).build(),
+        ];
+        assert_eq!(
+            summarize_write_items(&items),
+            vec![
+                WriteItemSummary {
+                    operation: "Put",
+                    key_length: 3,
+                    value_length: Some(50),
+                },
+                WriteItemSummary {
+                    operation: "Delete",
+                    key_length: 7,
+                    value_length: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_record_part() {
+        let mut bytes = 2u32.to_le_bytes().to_vec();
+        bytes.extend([1, 2]);
+        bytes.extend(0u32.to_le_bytes());
+        let mut reader = futures::io::Cursor::new(bytes);
+        assert_eq!(
+            read_record_part(&mut reader, 2).await.unwrap(),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            read_record_part(&mut reader, 2).await.unwrap(),
+            Some(vec![])
+        );
+        assert_eq!(read_record_part(&mut reader, 2).await.unwrap(), None);
+
+        let mut reader = futures::io::Cursor::new(3u32.to_le_bytes().to_vec());
+        assert!(matches!(
+            read_record_part(&mut reader, 2).await,
+            Err(DynamoDbStoreInternalError::RecordTooLarge { length: 3 })
+        ));
+        let mut reader = futures::io::Cursor::new(vec![2, 0]);
+        assert!(matches!(
+            read_record_part(&mut reader, 2).await,
+            Err(DynamoDbStoreInternalError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_value_chunks() {
+        let items = (0..5u8)
+            .map(|i| build_key_value(&[0], vec![b'a', i], vec![i]))
+            .collect();
+        let result_queries = QueryResponses {
+            prefix_len: 1,
+            value_checksums: false,
+            responses: vec![QueryOutput::builder().set_items(Some(items)).build()],
+            skipped_items: 0,
+        };
+        let key_values = DynamoDbKeyValues { result_queries };
+        let chunks = key_values
+            .into_iterator_owned()
+            .chunks(2)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let sizes = chunks.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(chunks[2], vec![(vec![4], vec![4])]);
+    }
+
+    #[test]
+    fn test_skip_malformed_items() {
+        let missing_value = build_key(&[0], b"a2".to_vec());
+        let mut wrong_key = build_key_value(&[0], b"a3".to_vec(), b"v".to_vec());
+        wrong_key.insert(KEY_ATTRIBUTE.to_owned(), AttributeValue::S("a3".to_owned()));
+        let items = vec![
+            build_key_value(&[0], b"a1".to_vec(), b"v".to_vec()),
+            missing_value,
+            wrong_key,
+        ];
+        let mut result_queries = QueryResponses {
+            prefix_len: 1,
+            value_checksums: false,
+            responses: vec![QueryOutput::builder().set_items(Some(items)).build()],
+            skipped_items: 0,
+        };
+        result_queries.skip_malformed_items(true);
+        let key_values = DynamoDbKeyValues { result_queries };
+        assert_eq!(key_values.num_skipped_items(), 2);
+        let entries = key_values
+            .into_iterator_owned()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, vec![(b"1".to_vec(), b"v".to_vec())]);
+    }
+
+    #[test]
+    fn test_fault_injector_rules() {
+        let injector = FaultInjector::new();
+        injector.fail_nth("TransactWriteItems", 2, Fault::Crash);
+        injector.fail_first(1, Fault::Throttle);
+        // The first request matches both rules and fails with the first applicable one.
+        assert_eq!(
+            injector.next_fault("TransactWriteItems"),
+            Some(Fault::Throttle)
+        );
+        assert_eq!(injector.next_fault("GetItem"), None);
+        assert_eq!(
+            injector.next_fault("TransactWriteItems"),
+            Some(Fault::Crash)
+        );
+        assert_eq!(injector.next_fault("TransactWriteItems"), None);
+        assert_eq!(injector.num_injected(), 2);
+    }
+
+    #[test]
+    fn test_unsupported_operation() {
+        use aws_sdk_dynamodb::{
+            config::http::HttpResponse, error::ErrorMetadata,
+            operation::transact_write_items::TransactWriteItemsError,
+        };
+        use aws_smithy_types::body::SdkBody;
+
+        let service_error = |code: &str, message: &str| {
+            let error = TransactWriteItemsError::generic(
+                ErrorMetadata::builder().code(code).message(message).build(),
+            );
+            let response = HttpResponse::new(400.try_into().unwrap(), SdkBody::empty());
+            SdkError::service_error(error, response)
+        };
+        let error = service_error("UnknownOperationException", "unknown operation");
+        assert!(is_unsupported_operation(&error));
+        // Other errors mentioning an unsupported feature do not disable the transactions.
+        let error = service_error(
+            "ValidationException",
+            "Transactions on this attribute type are not supported",
+        );
+        assert!(!is_unsupported_operation(&error));
+        let error = service_error("MadeUpException", "unsupported operation");
+        assert!(!is_unsupported_operation(&error));
+        let error = SdkError::<TransactWriteItemsError>::timeout_error("timeout");
+        assert!(!is_unsupported_operation(&error));
+    }
+
+    #[test]
+    fn test_coerce_value() {
+        let value = AttributeValue::S("text".to_string());
+        assert_eq!(coerce_value(&value), Some(b"text".to_vec()));
+        // Numbers and the other types cannot be converted without guessing.
+        assert_eq!(coerce_value(&AttributeValue::N("12".to_string())), None);
+        assert_eq!(coerce_value(&AttributeValue::Bool(true)), None);
+    }
+
+    #[cfg(with_dynamodb)]
+    #[tokio::test]
+    async fn test_read_repair() {
+        use super::{DynamoDbStoreInternal, VALUE_ATTRIBUTE};
+        use crate::{
+            journaling::JournalingKeyValueStore,
+            random::generate_test_namespace,
+            store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, TestKeyValueStore as _},
+        };
+
+        let config = JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+            .await
+            .unwrap();
+        let namespace = generate_test_namespace();
+        let store = DynamoDbStoreInternal::recreate_and_connect(&config, &namespace)
+            .await
+            .unwrap();
+        // Values written by another tool as a string and as a number.
+        for (key, value) in [
+            (vec![1, 1], AttributeValue::S("text".to_string())),
+            (vec![1, 2], AttributeValue::N("12".to_string())),
+        ] {
+            let mut item = build_key_value(&store.start_key, key, Vec::new());
+            item.insert(VALUE_ATTRIBUTE.to_string(), value);
+            Box::pin(
+                store
+                    .client
+                    .put_item()
+                    .table_name(&namespace)
+                    .set_item(Some(item))
+                    .send(),
+            )
+            .await
+            .unwrap();
+        }
+        let result = store.read_value_bytes(&[1, 1]).await;
+        assert!(matches!(
+            result,
+            Err(DynamoDbStoreInternalError::WrongValueType(..))
+        ));
+        let store = store.with_read_repair(true);
+        assert_eq!(
+            store.read_value_bytes(&[1, 1]).await.unwrap(),
+            Some(b"text".to_vec())
+        );
+        // The value was re-written as a blob, which is read without the repair.
+        let store = store.with_read_repair(false);
+        assert_eq!(
+            store.read_value_bytes(&[1, 1]).await.unwrap(),
+            Some(b"text".to_vec())
+        );
+        assert_eq!(store.repair_values(&[1]).await.unwrap(), vec![vec![1, 2]]);
+    }
+
+    #[cfg(with_dynamodb)]
+    #[tokio::test]
+    async fn test_item_size_boundary() {
+        use super::{BatchViolation, DynamoDbStoreInternal, MAX_KEY_SIZE};
+        use crate::{
+            batch::Batch,
+            journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+            random::generate_test_namespace,
+            store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, TestKeyValueStore as _},
+        };
+
+        let config = JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+            .await
+            .unwrap();
+        for (value_checksums, record_modification_times) in [(false, false), (true, true)] {
+            let config = config
+                .clone()
+                .with_value_checksums(value_checksums)
+                .with_record_modification_times(record_modification_times);
+            let namespace = generate_test_namespace();
+            let store = DynamoDbStoreInternal::recreate_and_connect(&config, &namespace)
+                .await
+                .unwrap();
+            for key_len in [2, MAX_KEY_SIZE] {
+                let key = vec![1; key_len];
+                // Above the minimal size of the transformed values, the size of the item
+                // grows with the value.
+                let overhead =
+                    store.stored_item_size(store.start_key.len(), key_len, 0x1000) - 0x1000;
+                let max_len = MAX_ITEM_SIZE - overhead;
+                for len in [max_len, max_len + 1] {
+                    let mut batch = Batch::new();
+                    batch.put_key_value_bytes(key.clone(), vec![len as u8; len]);
+                    let violations = store.validate_batch(&batch).unwrap().violations;
+                    let result = store
+                        .write_batch(batch.simplify().simple_unordered_batch)
+                        .await;
+                    if len == max_len {
+                        assert!(violations.is_empty());
+                        result.unwrap();
+                        let value = store.read_value_bytes(&key).await.unwrap();
+                        assert_eq!(value, Some(vec![len as u8; len]));
+                    } else {
+                        assert!(matches!(
+                            violations[..],
+                            [BatchViolation::ItemTooLarge { index: 0, size }]
+                                if size == MAX_ITEM_SIZE + 1
+                        ));
+                        assert!(matches!(
+                            result,
+                            Err(DynamoDbStoreInternalError::ItemTooLarge { size })
+                                if size == MAX_ITEM_SIZE + 1
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(with_dynamodb)]
+    #[tokio::test]
+    async fn test_read_fallback_region() {
+        use super::{DynamoDbStoreInternal, Fault, FaultInjector};
+        use crate::{
+            batch::SimpleUnorderedBatch,
+            journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+            random::generate_test_namespace,
+            store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, TestKeyValueStore as _},
+        };
+
+        let config = JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+            .await
+            .unwrap();
+        let namespace = generate_test_namespace();
+        let store = DynamoDbStoreInternal::recreate_and_connect(&config, &namespace)
+            .await
+            .unwrap();
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, 1], vec![5])],
+        };
+        store.write_batch(batch).await.unwrap();
+        // LocalStack has no replicas, so the primary region serves as its own fallback.
+        let region = store.client.config().region().unwrap().to_string();
+        let injector = FaultInjector::new();
+        let store = store.with_fault_injector(injector.clone());
+        injector.fail_first(100, Fault::InternalError);
+        assert!(store.read_value_bytes(&[1, 1]).await.is_err());
+
+        let config = config.with_fallback_regions(vec![region]);
+        let store = DynamoDbStoreInternal::connect(&config, &namespace)
+            .await
+            .unwrap()
+            .with_fault_injector(injector.clone());
+        let num_injected = injector.num_injected();
+        assert_eq!(
+            store.read_value_bytes(&[1, 1]).await.unwrap(),
+            Some(vec![5])
+        );
+        assert!(store.contains_key(&[1, 1]).await.unwrap());
+        let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+        let keys = keys.iterator().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(keys, vec![&[1][..]]);
+        // The reads failed in the primary region before being served by the fallback.
+        assert!(injector.num_injected() > num_injected);
+    }
 }
@@ -0,0 +1,391 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Spreads the keys of a given store over the key space by prepending a short hash to them.
+//!
+//! Monotonically increasing keys, like timestamps or sequence numbers, concentrate the
+//! writes on one region of the keys, which can become a hotspot for some backends even
+//! within a single partition. With a hash of the key in front of it, consecutive keys are
+//! written to unrelated places.
+//!
+//! The keys sharing a prefix are no longer contiguous in the underlying store, so a
+//! prefix scan, and the deletion of a prefix, have to scan the whole store. This is only
+//! meant for the views that do not scan ranges of keys.
+//!
+//! The keys of the underlying store start with the tag `HASHED_KEY_TAG`, which is not
+//! lower than `MIN_VIEW_TAG`, so that they cannot clash with the internal keys of the
+//! underlying store, like the journal, and the scans only read the hashed keys.
+
+use std::collections::BTreeSet;
+
+use linera_base::ensure;
+use serde::{Deserialize, Serialize};
+use sha3::Digest as _;
+use thiserror::Error;
+
+#[cfg(with_testing)]
+use crate::store::TestKeyValueStore;
+use crate::{
+    batch::{Batch, WriteOperation},
+    store::{
+        AdminKeyValueStore, KeyIterable, KeyValueIterable, KeyValueStoreError,
+        ReadableKeyValueStore, WithError, WritableKeyValueStore,
+    },
+    views::MIN_VIEW_TAG,
+};
+
+/// The maximal length of the hash prepended to the keys.
+pub const MAX_HASH_PREFIX_LEN: usize = 8;
+
+/// The first byte of all the keys written to the underlying store.
+const HASHED_KEY_TAG: u8 = MIN_VIEW_TAG;
+
+/// The composed error type built from the inner error type.
+#[derive(Error, Debug)]
+pub enum HashPrefixError<E> {
+    /// inner store error
+    #[error(transparent)]
+    InnerStoreError(#[from] E),
+
+    /// The length of the hash prefix is larger than `MAX_HASH_PREFIX_LEN`
+    #[error("the length of the hash prefix is larger than {MAX_HASH_PREFIX_LEN}")]
+    HashPrefixTooLong,
+}
+
+impl<E: KeyValueStoreError> From<bcs::Error> for HashPrefixError<E> {
+    fn from(error: bcs::Error) -> Self {
+        let error = E::from(error);
+        HashPrefixError::InnerStoreError(error)
+    }
+}
+
+impl<E: KeyValueStoreError + 'static> KeyValueStoreError for HashPrefixError<E> {
+    const BACKEND: &'static str = "hash prefix";
+}
+
+/// The configuration type for the `HashPrefixStore`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HashPrefixConfig<C> {
+    /// The inner configuration of the `HashPrefixStore`.
+    pub inner_config: C,
+    /// The number of bytes of the hash prepended to the keys, at most
+    /// `MAX_HASH_PREFIX_LEN`. Zero stores the keys unchanged.
+    pub hash_prefix_len: usize,
+}
+
+/// A key-value store that prepends a hash of the keys to them in the underlying store.
+///
+/// Prefix scans read the whole underlying store, so this should only wrap the stores of
+/// views that do not scan ranges of keys.
+#[derive(Clone)]
+pub struct HashPrefixStore<K> {
+    /// The underlying store of the transformed store.
+    store: K,
+    /// The number of bytes of the hash prepended to the keys.
+    hash_prefix_len: usize,
+}
+
+impl<K> WithError for HashPrefixStore<K>
+where
+    K: WithError,
+    K::Error: 'static,
+{
+    type Error = HashPrefixError<K::Error>;
+}
+
+impl<K> ReadableKeyValueStore for HashPrefixStore<K>
+where
+    K: ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    const MAX_KEY_SIZE: usize = K::MAX_KEY_SIZE - 1 - MAX_HASH_PREFIX_LEN;
+    type Keys = Vec<Vec<u8>>;
+    type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn max_stream_queries(&self) -> usize {
+        self.store.max_stream_queries()
+    }
+
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.store.read_value_bytes(&self.hashed_key(key)).await?)
+    }
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        Ok(self.store.contains_key(&self.hashed_key(key)).await?)
+    }
+
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
+        let keys = keys.iter().map(|key| self.hashed_key(key)).collect();
+        Ok(self.store.contains_keys(keys).await?)
+    }
+
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let keys = keys.iter().map(|key| self.hashed_key(key)).collect();
+        Ok(self.store.read_multi_values_bytes(keys).await?)
+    }
+
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
+        if self.hash_prefix_len == 0 {
+            let keys = self
+                .store
+                .find_keys_by_prefix(&self.hashed_key(key_prefix))
+                .await?;
+            return Ok(keys
+                .iterator()
+                .map(|key| key.map(<[u8]>::to_vec))
+                .collect::<Result<_, _>>()?);
+        }
+        let mut keys = Vec::new();
+        let hashed_keys = self.store.find_keys_by_prefix(&[HASHED_KEY_TAG]).await?;
+        for hashed_key in hashed_keys.iterator() {
+            let hashed_key = hashed_key?;
+            let key = hashed_key.get(self.hash_prefix_len..);
+            if let Some(key) = key.and_then(|key| key.strip_prefix(key_prefix)) {
+                keys.push(key.to_vec());
+            }
+        }
+        keys.sort_unstable();
+        Ok(keys)
+    }
+
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Self::KeyValues, Self::Error> {
+        if self.hash_prefix_len == 0 {
+            let key_values = self
+                .store
+                .find_key_values_by_prefix(&self.hashed_key(key_prefix))
+                .await?;
+            return Ok(key_values.into_iterator_owned().collect::<Result<_, _>>()?);
+        }
+        let mut key_values = Vec::new();
+        let hashed_key_values = self
+            .store
+            .find_key_values_by_prefix(&[HASHED_KEY_TAG])
+            .await?;
+        for result in hashed_key_values.into_iterator_owned() {
+            let (hashed_key, value) = result?;
+            let key = hashed_key.get(self.hash_prefix_len..);
+            if let Some(key) = key.and_then(|key| key.strip_prefix(key_prefix)) {
+                key_values.push((key.to_vec(), value));
+            }
+        }
+        key_values.sort_unstable_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        Ok(key_values)
+    }
+}
+
+impl<K> WritableKeyValueStore for HashPrefixStore<K>
+where
+    K: WritableKeyValueStore + ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    const MAX_VALUE_SIZE: usize = K::MAX_VALUE_SIZE;
+
+    async fn write_batch(&self, batch: Batch) -> Result<(), Self::Error> {
+        let mut batch_new = Batch::new();
+        // The keys written earlier in the batch, which a scan of the store cannot find yet.
+        let mut put_keys = BTreeSet::new();
+        for operation in batch.operations {
+            match operation {
+                WriteOperation::Delete { key } => {
+                    batch_new.delete_key(self.hashed_key(&key));
+                    put_keys.remove(&key);
+                }
+                WriteOperation::Put { key, value } => {
+                    batch_new.put_key_value_bytes(self.hashed_key(&key), value);
+                    put_keys.insert(key);
+                }
+                WriteOperation::DeletePrefix { key_prefix } => {
+                    if self.hash_prefix_len == 0 {
+                        batch_new.delete_key_prefix(self.hashed_key(&key_prefix));
+                    } else if key_prefix.is_empty() {
+                        batch_new.delete_key_prefix(vec![HASHED_KEY_TAG]);
+                    } else {
+                        for suffix in self.find_keys_by_prefix(&key_prefix).await? {
+                            let mut key = key_prefix.clone();
+                            key.extend(suffix);
+                            batch_new.delete_key(self.hashed_key(&key));
+                        }
+                        for key in &put_keys {
+                            if key.starts_with(&key_prefix) {
+                                batch_new.delete_key(self.hashed_key(key));
+                            }
+                        }
+                    }
+                    put_keys.retain(|key| !key.starts_with(&key_prefix));
+                }
+            }
+        }
+        Ok(self.store.write_batch(batch_new).await?)
+    }
+
+    async fn clear_journal(&self) -> Result<(), Self::Error> {
+        Ok(self.store.clear_journal().await?)
+    }
+
+    async fn flush(&self) -> Result<(), Self::Error> {
+        Ok(self.store.flush().await?)
+    }
+}
+
+impl<K> AdminKeyValueStore for HashPrefixStore<K>
+where
+    K: AdminKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    type Config = HashPrefixConfig<K::Config>;
+
+    fn get_name() -> String {
+        format!("hash prefix {}", K::get_name())
+    }
+
+    async fn connect(config: &Self::Config, namespace: &str) -> Result<Self, Self::Error> {
+        let store = K::connect(&config.inner_config, namespace).await?;
+        Self::new(store, config.hash_prefix_len)
+    }
+
+    fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, Self::Error> {
+        let store = self.store.clone_with_root_key(root_key)?;
+        Self::new(store, self.hash_prefix_len)
+    }
+
+    async fn list_all(config: &Self::Config) -> Result<Vec<String>, Self::Error> {
+        Ok(K::list_all(&config.inner_config).await?)
+    }
+
+    async fn list_root_keys(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(K::list_root_keys(&config.inner_config, namespace).await?)
+    }
+
+    async fn delete_all(config: &Self::Config) -> Result<(), Self::Error> {
+        Ok(K::delete_all(&config.inner_config).await?)
+    }
+
+    async fn exists(config: &Self::Config, namespace: &str) -> Result<bool, Self::Error> {
+        Ok(K::exists(&config.inner_config, namespace).await?)
+    }
+
+    async fn create(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(K::create(&config.inner_config, namespace).await?)
+    }
+
+    async fn delete(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(K::delete(&config.inner_config, namespace).await?)
+    }
+}
+
+#[cfg(with_testing)]
+impl<K> TestKeyValueStore for HashPrefixStore<K>
+where
+    K: TestKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    async fn new_test_config() -> Result<HashPrefixConfig<K::Config>, Self::Error> {
+        let inner_config = K::new_test_config().await?;
+        Ok(HashPrefixConfig {
+            inner_config,
+            hash_prefix_len: 2,
+        })
+    }
+}
+
+impl<K> HashPrefixStore<K>
+where
+    K: WithError,
+{
+    /// Creates a new store that prepends `hash_prefix_len` bytes of the hash of the keys
+    /// to the keys of the given one.
+    pub fn new(store: K, hash_prefix_len: usize) -> Result<Self, HashPrefixError<K::Error>> {
+        ensure!(
+            hash_prefix_len <= MAX_HASH_PREFIX_LEN,
+            HashPrefixError::HashPrefixTooLong
+        );
+        Ok(HashPrefixStore {
+            store,
+            hash_prefix_len,
+        })
+    }
+
+    /// Returns the key of the underlying store for `key`: the tag, then the hash, then
+    /// the key itself. With a hash prefix of zero bytes, this is also the prefix of the
+    /// underlying store for the prefix `key`.
+    fn hashed_key(&self, key: &[u8]) -> Vec<u8> {
+        let mut hashed_key = Vec::with_capacity(1 + self.hash_prefix_len + key.len());
+        hashed_key.push(HASHED_KEY_TAG);
+        hashed_key.extend(&sha3::Sha3_256::digest(key)[..self.hash_prefix_len]);
+        hashed_key.extend(key);
+        hashed_key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashPrefixStore;
+    use crate::{
+        batch::Batch,
+        memory::MemoryStore,
+        store::{ReadableKeyValueStore, TestKeyValueStore as _, WritableKeyValueStore},
+        views::MIN_VIEW_TAG,
+    };
+
+    // A prefix deleted in the batch that wrote some of its keys deletes them too.
+    #[tokio::test]
+    async fn test_hash_prefix_delete_prefix() {
+        let store = HashPrefixStore::<MemoryStore>::new_test_store()
+            .await
+            .unwrap();
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1, 1], vec![1]);
+        batch.put_key_value_bytes(vec![2, 1], vec![2]);
+        store.write_batch(batch).await.unwrap();
+        let inner_keys = store.store.find_keys_by_prefix(&[]).await.unwrap();
+        assert!(inner_keys.contains(&store.hashed_key(&[1, 1])));
+
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1, 2], vec![3]);
+        batch.delete_key_prefix(vec![1]);
+        batch.put_key_value_bytes(vec![1, 3], vec![4]);
+        store.write_batch(batch).await.unwrap();
+        let key_values = store.find_key_values_by_prefix(&[]).await.unwrap();
+        assert_eq!(
+            key_values,
+            vec![(vec![1, 3], vec![4]), (vec![2, 1], vec![2])]
+        );
+    }
+
+    // The hashed keys keep a view tag in front, so the scans ignore the internal keys of
+    // the underlying store.
+    #[tokio::test]
+    async fn test_hash_prefix_internal_keys() {
+        let store = HashPrefixStore::<MemoryStore>::new_test_store()
+            .await
+            .unwrap();
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![0, 1], vec![0]);
+        store.store.write_batch(batch).await.unwrap();
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![0, 1], vec![1]);
+        store.write_batch(batch).await.unwrap();
+        assert!(store.hashed_key(&[0, 1])[0] >= MIN_VIEW_TAG);
+        assert_eq!(
+            store.find_key_values_by_prefix(&[]).await.unwrap(),
+            vec![(vec![0, 1], vec![1])]
+        );
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(Vec::new());
+        store.write_batch(batch).await.unwrap();
+        assert!(store.find_keys_by_prefix(&[]).await.unwrap().is_empty());
+        assert_eq!(
+            store.store.read_value_bytes(&[0, 1]).await.unwrap(),
+            Some(vec![0])
+        );
+    }
+}
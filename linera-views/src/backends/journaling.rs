@@ -18,52 +18,145 @@
 //! cleared. This is done by processing every block of the journal successively. Every
 //! time the data in a block are written, the journal header is updated in the same
 //! transaction to mark the block as processed.
+//!
+//! By default, deleting a key prefix is done eagerly: the keys under the prefix are listed
+//! and deleted in the same batch. With the `mark-and-sweep` feature, deleting a prefix
+//! instead writes a single "tombstone" key in the range set aside for the journal. The
+//! keys under a tombstone are treated as deleted by the reads and are reclaimed later by
+//! `sweep_tombstones`. This makes deleting large prefixes cheap, at the cost of reading
+//! the tombstones before every read and write. A write under a tombstone sweeps it first,
+//! so that the written value is not hidden.
+
+#[cfg(with_mark_and_sweep)]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(with_metrics)]
+use std::sync::LazyLock;
+use std::{
+    collections::{HashMap, HashSet},
+    pin::pin,
+    sync::{Arc, Mutex, Weak},
+};
 
 use async_trait::async_trait;
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    Stream, StreamExt as _, TryStreamExt as _,
+};
+use linera_base::{
+    ensure,
+    time::{timer, Duration},
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use static_assertions as sa;
 use thiserror::Error;
+#[cfg(with_metrics)]
+use {
+    linera_base::prometheus_util::{
+        exponential_bucket_latencies, register_histogram_vec, MeasureLatency as _,
+    },
+    prometheus::HistogramVec,
+};
 
+#[cfg(with_mark_and_sweep)]
+use crate::{batch::WriteOperation, common::INTERNAL_TAG};
 use crate::{
-    batch::{Batch, BatchValueWriter, DeletePrefixExpander, SimplifiedBatch},
+    batch::{Batch, BatchValueWriter, DeletePrefixExpander, SimplifiedBatch, UnorderedBatch},
+    common::{get_internal_key, InternalKeyKind},
     store::{
-        AdminKeyValueStore, KeyIterable, ReadableKeyValueStore, WithError, WritableKeyValueStore,
+        AdminKeyValueStore, KeyIterable, KeyValueIterable, ReadableKeyValueStore, WithError,
+        WritableKeyValueStore,
     },
-    views::MIN_VIEW_TAG,
 };
 
-/// The tag used for the journal stuff.
-const JOURNAL_TAG: u8 = 0;
-// To prevent collisions, the tag value 0 is reserved for journals.
-// The tags used by views must be greater or equal than `MIN_VIEW_TAG`.
-sa::const_assert!(JOURNAL_TAG < MIN_VIEW_TAG);
+#[cfg(with_metrics)]
+/// The latency of reading a journal block while resolving the journal.
+static JOURNAL_BLOCK_READ_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec(
+        "journal_block_read_latency",
+        "Journal block read latency",
+        &[],
+        exponential_bucket_latencies(1000.0),
+    )
+});
+
+#[cfg(with_metrics)]
+/// The latency of committing a journal block while resolving the journal.
+static JOURNAL_BLOCK_COMMIT_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec(
+        "journal_block_commit_latency",
+        "Journal block commit latency",
+        &[],
+        exponential_bucket_latencies(1000.0),
+    )
+});
 
 /// Data type indicating that the database is not consistent
 #[derive(Error, Debug)]
 #[allow(missing_docs)]
 pub enum JournalConsistencyError {
-    #[error("The journal block could not be retrieved, it could be missing or corrupted.")]
-    FailureToRetrieveJournalBlock,
+    #[error(
+        "The journal block {block} (of {block_count}) could not be retrieved: {reason}. \
+         Use `diagnose_journal` to inspect the journal."
+    )]
+    FailureToRetrieveJournalBlock {
+        block: u32,
+        block_count: u32,
+        reason: String,
+    },
 
     #[error("Refusing to use the journal without exclusive database access to the root object.")]
     JournalRequiresExclusiveAccess,
+
+    #[error("Refusing to move keys between overlapping prefixes.")]
+    OverlappingPrefixes,
+
+    #[error("Refusing to overwrite the journal already present under the new root key.")]
+    JournalAlreadyExists,
+
+    #[error(
+        "Refusing to discard the journal: its header records {found:?} blocks instead of the \
+         expected {expected:?}."
+    )]
+    UnexpectedJournal {
+        expected: Option<u32>,
+        found: Option<u32>,
+    },
+
+    #[error(
+        "The journal is being moved to the root key {new_root_key:?} by an interrupted \
+         `rebase_journal`, which must be called again."
+    )]
+    JournalBeingRebased { new_root_key: Vec<u8> },
+
+    #[error("The journal just written does not match its header: {reason}.")]
+    JournalVerificationFailed { reason: String },
 }
 
-#[repr(u8)]
-enum KeyTag {
-    /// Prefix for the storing of the header of the journal.
-    Journal = 1,
-    /// Prefix for the block entry.
-    Entry,
+fn get_journaling_key(kind: InternalKeyKind, pos: u32) -> Result<Vec<u8>, bcs::Error> {
+    Ok(get_internal_key(kind, &bcs::to_bytes(&pos)?))
 }
 
-fn get_journaling_key(tag: u8, pos: u32) -> Result<Vec<u8>, bcs::Error> {
-    let mut key = vec![JOURNAL_TAG];
-    key.extend([tag]);
-    bcs::serialize_into(&mut key, &pos)?;
-    Ok(key)
+#[cfg(with_mark_and_sweep)]
+fn get_tombstone_key(key_prefix: &[u8]) -> Vec<u8> {
+    get_internal_key(InternalKeyKind::Tombstone, key_prefix)
 }
 
+/// Tests whether the key made of `key_prefix` followed by `suffix` is under one of the
+/// tombstones.
+#[cfg(with_mark_and_sweep)]
+fn is_marked_deleted(tombstones: &[Vec<u8>], key_prefix: &[u8], suffix: &[u8]) -> bool {
+    tombstones.iter().any(|tombstone| {
+        if tombstone.len() <= key_prefix.len() {
+            key_prefix.starts_with(tombstone)
+        } else {
+            tombstone.starts_with(key_prefix) && suffix.starts_with(&tombstone[key_prefix.len()..])
+        }
+    })
+}
+
+/// The pages of keys returned by [`DirectWritableKeyValueStore::find_key_pages_by_prefix`].
+pub type KeyPages<'a, E> = BoxStream<'a, Result<Vec<Vec<u8>>, E>>;
+
 /// Low-level, asynchronous direct write key-value operations with simplified batch
 #[async_trait]
 pub trait DirectWritableKeyValueStore: WithError {
@@ -81,6 +174,39 @@ pub trait DirectWritableKeyValueStore: WithError {
 
     /// Writes the batch to the database.
     async fn write_batch(&self, batch: Self::Batch) -> Result<(), Self::Error>;
+
+    /// The maximal delay before resolving a journal left over by an interrupted write in
+    /// `clear_journal`. The actual delay is drawn at random, so that the stores recovering
+    /// at the same time, e.g. after a fleet restart, spread their load over time.
+    fn max_recovery_jitter(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Returns the size that an item with a key of `key_len` bytes and a value of
+    /// `value_len` bytes counts for in [`Self::MAX_BATCH_TOTAL_SIZE`]. The default is the
+    /// sum of the lengths.
+    fn size_of_item(&self, key_len: usize, value_len: usize) -> usize {
+        key_len + value_len
+    }
+
+    /// Checks the `batch` written to the journaling store before it is simplified into a
+    /// [`Self::Batch`], which loses the order of the operations. The default accepts all
+    /// the batches.
+    fn check_batch(&self, _batch: &Batch) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Returns the keys under `key_prefix`, without the prefix, as a stream of pages that
+    /// are read one after the other, for the expansion of the prefix deletions. The keys
+    /// of a page can then be written to the journal before the next page is read. The
+    /// default is `None`, for the stores that read all the keys at once with
+    /// `find_keys_by_prefix`.
+    fn find_key_pages_by_prefix<'a>(
+        &'a self,
+        _key_prefix: &'a [u8],
+    ) -> Option<KeyPages<'a, Self::Error>> {
+        None
+    }
 }
 
 /// Low-level, asynchronous direct read/write key-value operations with simplified batch
@@ -100,6 +226,98 @@ struct JournalHeader {
     block_count: u32,
 }
 
+/// How a batch was written by a [`JournalingKeyValueStore`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The batch was written directly, in a single transaction.
+    Fastpath,
+    /// The batch was too large and was written through the journal.
+    Journaled {
+        /// The number of journal blocks used.
+        blocks: usize,
+    },
+    /// The batch was too large and was split into independent transactions.
+    Split {
+        /// The number of transactions used.
+        transactions: usize,
+    },
+}
+
+/// How a [`JournalingKeyValueStore`] writes the batches that are too large for a single
+/// transaction of the underlying store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizedBatches {
+    /// The batch is written through the journal, so that it is applied atomically: after
+    /// a crash, either none of the batch or all of it is applied once the journal is
+    /// resolved.
+    #[default]
+    Journal,
+    /// The batch is split into transactions that are each under the limits of the
+    /// underlying store and are committed one after the other, without the journal.
+    ///
+    /// This saves the writes and reads of the journal, but the batch is no longer
+    /// atomic: if the process crashes or a transaction fails in the middle, the batch is
+    /// left partially applied and nothing can complete or undo it. This is only suitable
+    /// for batches whose parts are independent, e.g. for loading or migrating data that
+    /// can be written again.
+    AutoSplit,
+}
+
+/// Statistics about the journal of a [`JournalingKeyValueStore`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JournalStats {
+    /// Whether a journal header is present.
+    pub has_header: bool,
+    /// The number of blocks recorded in the journal header.
+    pub block_count: u32,
+    /// The number of journal blocks present in the store.
+    pub entry_count: usize,
+    /// The total size in bytes of the journal blocks present in the store.
+    pub entry_bytes: usize,
+}
+
+/// The state of the journal of a [`JournalingKeyValueStore`], for an operator to decide
+/// how to repair it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JournalDiagnosis {
+    /// The number of blocks recorded in the journal header, if a header is present.
+    pub header_block_count: Option<u32>,
+    /// The positions of the blocks expected by the header and present in the store.
+    pub present_blocks: Vec<u32>,
+    /// The positions of the blocks expected by the header but missing from the store.
+    pub missing_blocks: Vec<u32>,
+    /// The positions of the blocks present in the store but not expected by the header.
+    pub unexpected_blocks: Vec<u32>,
+    /// The positions of the blocks present in the store that cannot be deserialized.
+    pub corrupted_blocks: Vec<u32>,
+}
+
+/// The contents of a block of the journal of a [`JournalingKeyValueStore`], as returned
+/// by `inspect_journal`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JournalEntrySummary {
+    /// The position of the block.
+    pub block: u32,
+    /// The number of deletions of the block, of keys or of key prefixes.
+    pub deletions: usize,
+    /// The number of insertions of the block.
+    pub insertions: usize,
+    /// The size in bytes of the serialized block.
+    pub bytes: usize,
+}
+
+impl JournalDiagnosis {
+    /// Returns whether resolving the journal can succeed, i.e. whether every block
+    /// expected by the header is present and readable.
+    pub fn is_resolvable(&self) -> bool {
+        self.missing_blocks.is_empty()
+            && self
+                .corrupted_blocks
+                .iter()
+                .all(|block| !self.present_blocks.contains(block))
+    }
+}
+
 /// A journaling Key Value Store built from an inner [`DirectKeyValueStore`].
 #[derive(Clone)]
 pub struct JournalingKeyValueStore<K> {
@@ -107,19 +325,320 @@ pub struct JournalingKeyValueStore<K> {
     store: K,
     /// Whether we have exclusive R/W access to the keys under root key.
     has_exclusive_access: bool,
+    /// The state shared by the stores of the root key.
+    root_key_state: Arc<RootKeyState>,
+    /// The states of the root keys, shared by the stores cloned from the same one. A state
+    /// is dropped with the last store of its root key, and its entry is pruned when a new
+    /// state is created.
+    root_key_states: RootKeyStates,
+    /// How the batches too large for a single transaction are written.
+    oversized_batches: OversizedBatches,
+    /// Whether every journal written is read again and checked, in debug builds.
+    verify_journal: bool,
+}
+
+/// The state shared by the stores of a root key.
+#[derive(Default)]
+struct RootKeyState {
+    /// The lock held while writing or resolving the journal of the root key, so that a
+    /// journal is never resolved twice concurrently.
+    journal_lock: async_lock::Mutex<()>,
+    /// Tells when the tombstones of the root key need to be read again.
+    #[cfg(with_mark_and_sweep)]
+    tombstones: TombstoneTracker,
+}
+
+/// The states of the root keys still in use, by root key.
+type RootKeyStates = Arc<Mutex<HashMap<Vec<u8>, Weak<RootKeyState>>>>;
+
+/// Tracks the writes that may add tombstones under a root key, so that the tombstones
+/// are not read again by every read while there are none.
+#[cfg(with_mark_and_sweep)]
+#[derive(Default)]
+struct TombstoneTracker {
+    /// The number of writes started so far that may add tombstones.
+    writes: AtomicU64,
+    /// One more than the value of `writes` when the tombstones were last found absent,
+    /// or zero.
+    absent_after: AtomicU64,
+}
+
+#[cfg(with_mark_and_sweep)]
+impl TombstoneTracker {
+    /// Records that a write that may add tombstones is about to start.
+    fn record_write(&self) {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
 impl<K> DeletePrefixExpander for &JournalingKeyValueStore<K>
 where
     K: DirectKeyValueStore + Send + Sync,
+    K::Error: Send,
 {
     type Error = K::Error;
-    async fn expand_delete_prefix(&self, key_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
-        let mut vector_list = Vec::new();
-        for key in self.store.find_keys_by_prefix(key_prefix).await?.iterator() {
-            vector_list.push(key?.to_vec());
+    fn expand_delete_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Stream<Item = Result<Vec<u8>, Self::Error>> + 'a {
+        (*self).stream_keys_by_prefix(key_prefix)
+    }
+}
+
+impl<K> JournalingKeyValueStore<K>
+where
+    K: DirectKeyValueStore + Send + Sync,
+    K::Error: Send,
+{
+    /// Returns the stream of the keys under `key_prefix`, without the prefix, read page
+    /// by page if the inner store supports it.
+    fn stream_keys_by_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Stream<Item = Result<Vec<u8>, K::Error>> + Send + 'a {
+        if let Some(pages) = self.store.find_key_pages_by_prefix(key_prefix) {
+            return pages
+                .map_ok(|keys| stream::iter(keys.into_iter().map(Ok)))
+                .try_flatten()
+                .left_stream();
+        }
+        stream::once(self.store.find_keys_by_prefix(key_prefix))
+            .map_ok(|keys| {
+                let keys = keys
+                    .iterator()
+                    .map(|key| key.map(<[u8]>::to_vec))
+                    .collect::<Vec<_>>();
+                stream::iter(keys)
+            })
+            .try_flatten()
+            .right_stream()
+    }
+}
+
+/// Writes the operations pushed to the journal as a succession of blocks that can be
+/// interpreted later by `coherently_resolve_journal`.
+///
+/// Starting with a batch of operations that is typically too large to be executed in
+/// one go (see `is_fastpath_feasible()`), the goal of this writer is to split
+/// the batch into smaller blocks so that `coherently_resolve_journal` respects the
+/// constraints of the underlying key-value store (see its analysis).
+///
+/// For efficiency reasons, we write as many blocks as possible in each "transaction"
+/// batch, using one write-operation per block. Then we also update the journal header
+/// with the final number of blocks.
+///
+/// As a result, the constraints of the underlying database are respected if the
+/// following conditions are met while a "transaction" batch is being built:
+///
+/// (1) The number of blocks per transaction doesn't exceed `K::MAX_BATCH_SIZE`.
+/// But it is perfectly possible to have `K::MAX_BATCH_SIZE = usize::MAX`.
+///
+/// (2) The total size of the blocks together with their corresponding keys, as counted
+/// by `size_of_item`, does not exceed `K::MAX_BATCH_TOTAL_SIZE`.
+///
+/// (3) The size of each BCS-serialized block doesn't exceed `K::MAX_VALUE_SIZE`.
+///
+/// (4) When processing a journal block, we have to do two other operations.
+///   (a) removing the existing block. The cost is `key_len`.
+///   (b) updating or removing the journal. The cost is `key_len + header_value_len`
+///       or `key_len`. An upper bound is thus
+///       `journal_len_upper_bound = key_len + header_value_len`.
+///   Thus the following has to be taken as upper bound on the block size:
+///   `K::MAX_BATCH_TOTAL_SIZE - key_len - journal_len_upper_bound`.
+///
+/// NOTE:
+/// * Since a block must contain at least one operation and M bytes of the
+///   serialization overhead (typically M is 2 or 3 bytes of vector sizes), condition (3)
+///   requires that each operation in the original batch satisfies:
+///   `sizeof(key) + sizeof(value) + M <= K::MAX_VALUE_SIZE`
+///
+/// * Similarly, a transaction must contain at least one block so it is desirable that
+///   the maximum size of a block insertion `1 + sizeof(block_key) + K::MAX_VALUE_SIZE`
+///   plus M bytes of overhead doesn't exceed the threshold of condition (2).
+///
+/// The operations are pushed as they arrive, e.g. while the keys of a prefix deletion
+/// are read, and the full blocks and transactions are written right away.
+struct JournalWriter<'a, K: DirectWritableKeyValueStore> {
+    /// The inner store.
+    store: &'a K,
+    /// The length of the keys of the journal.
+    key_len: usize,
+    /// The maximal size of a serialized block.
+    max_block_size: usize,
+    /// The block being filled.
+    block_batch: K::Batch,
+    /// The size of the operations of `block_batch`, once serialized.
+    block_size: usize,
+    /// The number of blocks written or added to `transaction_batch`.
+    block_count: u32,
+    /// The blocks not written yet.
+    transaction_batch: K::Batch,
+    /// The size of `transaction_batch`, as counted by the inner store.
+    transaction_size: usize,
+}
+
+impl<'a, K> JournalWriter<'a, K>
+where
+    K: DirectWritableKeyValueStore + Send + Sync,
+    K::Error: From<JournalConsistencyError>,
+{
+    fn new(store: &'a K) -> Result<Self, K::Error> {
+        let key_len = get_journaling_key(InternalKeyKind::JournalHeader, 0)?.len();
+        let header_value_len = bcs::serialized_size(&JournalHeader::default())?;
+        let journal_len_upper_bound = key_len + header_value_len;
+        let max_block_size = std::cmp::min(
+            K::MAX_VALUE_SIZE,
+            K::MAX_BATCH_TOTAL_SIZE - key_len - journal_len_upper_bound,
+        );
+        Ok(Self {
+            store,
+            key_len,
+            max_block_size,
+            block_batch: K::Batch::default(),
+            block_size: 0,
+            block_count: 0,
+            transaction_batch: K::Batch::default(),
+            transaction_size: 0,
+        })
+    }
+
+    /// Adds the operations of `batch` to the journal, writing the blocks that are full.
+    async fn push(&mut self, batch: K::Batch) -> Result<(), K::Error> {
+        let mut iter = batch.into_iter();
+        while let Some(next_block_size) =
+            iter.next_batch_size(&self.block_batch, self.block_size)?
+        {
+            let block_full = self.block_batch.len() == K::MAX_BATCH_SIZE - 2
+                || next_block_size > self.max_block_size;
+            if block_full && !self.block_batch.is_empty() {
+                self.flush_block().await?;
+            } else {
+                iter.write_next_value(&mut self.block_batch, &mut self.block_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the deletion of `key` to the journal.
+    async fn push_delete(&mut self, key: Vec<u8>) -> Result<(), K::Error> {
+        let mut batch = K::Batch::default();
+        batch.add_delete(key);
+        self.push(batch).await
+    }
+
+    /// Adds the current block to the transaction, writing the transaction first if the
+    /// block does not fit in it.
+    async fn flush_block(&mut self) -> Result<(), K::Error> {
+        let block_batch = std::mem::take(&mut self.block_batch);
+        let value = bcs::to_bytes(&block_batch)?;
+        assert_eq!(value.len(), self.block_size + block_batch.overhead_size());
+        self.block_size = 0;
+        let key = get_journaling_key(InternalKeyKind::JournalEntry, self.block_count)?;
+        let item_size = self.store.size_of_item(self.key_len, value.len());
+        if self.transaction_batch.len() == K::MAX_BATCH_SIZE
+            || self.transaction_size + item_size > K::MAX_BATCH_TOTAL_SIZE
+        {
+            self.flush_transaction().await?;
+        }
+        self.transaction_batch.add_insert(key, value);
+        self.transaction_size += item_size;
+        self.block_count += 1;
+        Ok(())
+    }
+
+    async fn flush_transaction(&mut self) -> Result<(), K::Error> {
+        if !self.transaction_batch.is_empty() {
+            let batch = std::mem::take(&mut self.transaction_batch);
+            self.store.write_batch(batch).await?;
+            self.transaction_size = 0;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining blocks, then the header of the journal if it has any block.
+    async fn finish(mut self) -> Result<JournalHeader, K::Error> {
+        if !self.block_batch.is_empty() {
+            self.flush_block().await?;
+        }
+        self.flush_transaction().await?;
+        let header = JournalHeader {
+            block_count: self.block_count,
+        };
+        if header.block_count > 0 {
+            let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+            let mut batch = K::Batch::default();
+            batch.add_insert(header_key, bcs::to_bytes(&header)?);
+            self.store.write_batch(batch).await?;
+        }
+        Ok(header)
+    }
+}
+
+/// Writes the operations pushed in as many transactions as needed to stay under the
+/// limits of the inner store, without the journal.
+struct SplitWriter<'a, K: DirectWritableKeyValueStore> {
+    /// The inner store.
+    store: &'a K,
+    /// The operations not written yet.
+    transaction_batch: K::Batch,
+    /// The size of `transaction_batch`, as counted by the inner store.
+    transaction_size: usize,
+    /// The number of transactions written.
+    transactions: usize,
+}
+
+impl<'a, K> SplitWriter<'a, K>
+where
+    K: DirectWritableKeyValueStore + Send + Sync,
+{
+    fn new(store: &'a K) -> Self {
+        Self {
+            store,
+            transaction_batch: K::Batch::default(),
+            transaction_size: 0,
+            transactions: 0,
+        }
+    }
+
+    /// Adds the operations of `batch`, writing the transactions that are full.
+    async fn push(&mut self, batch: K::Batch) -> Result<(), K::Error> {
+        let mut iter = batch.into_iter();
+        while let Some((key_len, value_len)) = iter.next_entry_lengths() {
+            let item_size = self.store.size_of_item(key_len, value_len);
+            let transaction_full = self.transaction_batch.len() == K::MAX_BATCH_SIZE
+                || self.transaction_size + item_size > K::MAX_BATCH_TOTAL_SIZE;
+            if transaction_full && !self.transaction_batch.is_empty() {
+                self.flush_transaction().await?;
+            } else {
+                let mut serialized_size = 0;
+                iter.write_next_value(&mut self.transaction_batch, &mut serialized_size)?;
+                self.transaction_size += item_size;
+            }
         }
-        Ok(vector_list)
+        Ok(())
+    }
+
+    /// Adds the deletion of `key`.
+    async fn push_delete(&mut self, key: Vec<u8>) -> Result<(), K::Error> {
+        let mut batch = K::Batch::default();
+        batch.add_delete(key);
+        self.push(batch).await
+    }
+
+    async fn flush_transaction(&mut self) -> Result<(), K::Error> {
+        let batch = std::mem::take(&mut self.transaction_batch);
+        self.store.write_batch(batch).await?;
+        self.transaction_size = 0;
+        self.transactions += 1;
+        Ok(())
+    }
+
+    /// Writes the remaining operations and returns the number of transactions.
+    async fn finish(mut self) -> Result<usize, K::Error> {
+        if !self.transaction_batch.is_empty() {
+            self.flush_transaction().await?;
+        }
+        Ok(self.transactions)
     }
 }
 
@@ -138,26 +657,37 @@ where
     /// The size constant do not change
     const MAX_KEY_SIZE: usize = K::MAX_KEY_SIZE;
     /// The basic types do not change
+    #[cfg(not(with_mark_and_sweep))]
     type Keys = K::Keys;
+    #[cfg(not(with_mark_and_sweep))]
     type KeyValues = K::KeyValues;
+    /// The keys under a tombstone are filtered out.
+    #[cfg(with_mark_and_sweep)]
+    type Keys = Vec<Vec<u8>>;
+    #[cfg(with_mark_and_sweep)]
+    type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
 
     /// The read stuff does not change
     fn max_stream_queries(&self) -> usize {
         self.store.max_stream_queries()
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
         self.store.read_value_bytes(key).await
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
         self.store.contains_key(key).await
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
         self.store.contains_keys(keys).await
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn read_multi_values_bytes(
         &self,
         keys: Vec<Vec<u8>>,
@@ -165,16 +695,135 @@ where
         self.store.read_multi_values_bytes(keys).await
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
         self.store.find_keys_by_prefix(key_prefix).await
     }
 
+    #[cfg(not(with_mark_and_sweep))]
     async fn find_key_values_by_prefix(
         &self,
         key_prefix: &[u8],
     ) -> Result<Self::KeyValues, Self::Error> {
         self.store.find_key_values_by_prefix(key_prefix).await
     }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        if is_marked_deleted(&tombstones, key, &[]) {
+            return Ok(None);
+        }
+        self.store.read_value_bytes(key).await
+    }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        if is_marked_deleted(&tombstones, key, &[]) {
+            return Ok(false);
+        }
+        self.store.contains_key(key).await
+    }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        let deleted = keys
+            .iter()
+            .map(|key| is_marked_deleted(&tombstones, key, &[]))
+            .collect::<Vec<_>>();
+        let results = self.store.contains_keys(keys).await?;
+        Ok(results
+            .into_iter()
+            .zip(deleted)
+            .map(|(result, deleted)| result && !deleted)
+            .collect())
+    }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        let deleted = keys
+            .iter()
+            .map(|key| is_marked_deleted(&tombstones, key, &[]))
+            .collect::<Vec<_>>();
+        let values = self.store.read_multi_values_bytes(keys).await?;
+        Ok(values
+            .into_iter()
+            .zip(deleted)
+            .map(|(value, deleted)| if deleted { None } else { value })
+            .collect())
+    }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        let mut keys = Vec::new();
+        for suffix in self.store.find_keys_by_prefix(key_prefix).await?.iterator() {
+            let suffix = suffix?;
+            if !is_marked_deleted(&tombstones, key_prefix, suffix) {
+                keys.push(suffix.to_vec());
+            }
+        }
+        Ok(keys)
+    }
+
+    #[cfg(with_mark_and_sweep)]
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Self::KeyValues, Self::Error> {
+        let tombstones = self.read_tombstones().await?;
+        let mut key_values = Vec::new();
+        for entry in self
+            .store
+            .find_key_values_by_prefix(key_prefix)
+            .await?
+            .into_iterator_owned()
+        {
+            let (suffix, value) = entry?;
+            if !is_marked_deleted(&tombstones, key_prefix, &suffix) {
+                key_values.push((suffix, value));
+            }
+        }
+        Ok(key_values)
+    }
+}
+
+#[cfg(with_mark_and_sweep)]
+impl<K> JournalingKeyValueStore<K>
+where
+    K: ReadableKeyValueStore + Send + Sync,
+{
+    /// Returns the key prefixes deleted by a tombstone that was not swept yet.
+    ///
+    /// With exclusive access to the root key, the tombstones are not read while none
+    /// were found and no write that may add some was started since.
+    async fn read_tombstones(&self) -> Result<Vec<Vec<u8>>, K::Error> {
+        let tracker = &self.root_key_state.tombstones;
+        let writes = tracker.writes.load(Ordering::SeqCst);
+        if self.has_exclusive_access && tracker.absent_after.load(Ordering::SeqCst) == writes + 1 {
+            return Ok(Vec::new());
+        }
+        let tombstone_prefix = get_tombstone_key(&[]);
+        let mut tombstones = Vec::new();
+        for key in self
+            .store
+            .find_keys_by_prefix(&tombstone_prefix)
+            .await?
+            .iterator()
+        {
+            tombstones.push(key?.to_vec());
+        }
+        if self.has_exclusive_access && tombstones.is_empty() {
+            tracker.absent_after.store(writes + 1, Ordering::SeqCst);
+        }
+        Ok(tombstones)
+    }
 }
 
 impl<K> AdminKeyValueStore for JournalingKeyValueStore<K>
@@ -189,17 +838,29 @@ where
 
     async fn connect(config: &Self::Config, namespace: &str) -> Result<Self, Self::Error> {
         let store = K::connect(config, namespace).await?;
-        Ok(Self {
-            store,
-            has_exclusive_access: false,
-        })
+        Ok(Self::new(store))
     }
 
     fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, Self::Error> {
         let store = self.store.clone_with_root_key(root_key)?;
+        let mut root_key_states = self.root_key_states.lock().unwrap();
+        let root_key_state = match root_key_states.get(root_key).and_then(Weak::upgrade) {
+            Some(root_key_state) => root_key_state,
+            None => {
+                root_key_states.retain(|_, root_key_state| root_key_state.strong_count() > 0);
+                let root_key_state = Arc::<RootKeyState>::default();
+                root_key_states.insert(root_key.to_vec(), Arc::downgrade(&root_key_state));
+                root_key_state
+            }
+        };
+        drop(root_key_states);
         Ok(Self {
             store,
             has_exclusive_access: true,
+            root_key_state,
+            root_key_states: self.root_key_states.clone(),
+            oversized_batches: self.oversized_batches,
+            verify_journal: self.verify_journal,
         })
     }
 
@@ -234,28 +895,28 @@ where
 impl<K> WritableKeyValueStore for JournalingKeyValueStore<K>
 where
     K: DirectKeyValueStore + Send + Sync,
-    K::Error: From<JournalConsistencyError>,
+    K::Error: From<JournalConsistencyError> + Send,
 {
     /// The size constant do not change
     const MAX_VALUE_SIZE: usize = K::MAX_VALUE_SIZE;
 
     async fn write_batch(&self, batch: Batch) -> Result<(), Self::Error> {
-        let batch = K::Batch::from_batch(self, batch).await?;
-        if Self::is_fastpath_feasible(&batch) {
-            self.store.write_batch(batch).await
-        } else {
-            if !self.has_exclusive_access {
-                return Err(JournalConsistencyError::JournalRequiresExclusiveAccess.into());
-            }
-            let header = self.write_journal(batch).await?;
-            self.coherently_resolve_journal(header).await
-        }
+        self.write_batch_outcome(batch).await?;
+        Ok(())
     }
 
     async fn clear_journal(&self) -> Result<(), Self::Error> {
-        let key = get_journaling_key(KeyTag::Journal as u8, 0)?;
+        let key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let max_jitter = self.store.max_recovery_jitter();
+        // The delay is waited before taking the lock, which would otherwise hold back the
+        // writes to the root key.
+        if !max_jitter.is_zero() && self.read_value::<JournalHeader>(&key).await?.is_some() {
+            timer::sleep(max_jitter.mul_f64(rand::random::<f64>())).await;
+        }
+        let _journal_guard = self.root_key_state.journal_lock.lock().await;
         let value = self.read_value::<JournalHeader>(&key).await?;
         if let Some(header) = value {
+            self.check_not_rebased().await?;
             self.coherently_resolve_journal(header).await?;
         }
         Ok(())
@@ -265,7 +926,7 @@ where
 impl<K> JournalingKeyValueStore<K>
 where
     K: DirectKeyValueStore + Send + Sync,
-    K::Error: From<JournalConsistencyError>,
+    K::Error: From<JournalConsistencyError> + Send,
 {
     /// Resolves the pending operations that were previously stored in the database
     /// journal.
@@ -287,16 +948,34 @@ where
     ///
     /// (4) `block_key` and `header_key` don't exceed `K::MAX_KEY_SIZE` and `bcs_header`
     /// doesn't exceed `K::MAX_VALUE_SIZE`.
+    ///
+    /// With metrics enabled, the time spent reading each block and the time spent
+    /// committing it are recorded separately.
     async fn coherently_resolve_journal(&self, mut header: JournalHeader) -> Result<(), K::Error> {
-        let header_key = get_journaling_key(KeyTag::Journal as u8, 0)?;
+        // The blocks of the journal may add tombstones.
+        #[cfg(with_mark_and_sweep)]
+        self.root_key_state.tombstones.record_write();
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
         while header.block_count > 0 {
-            let block_key = get_journaling_key(KeyTag::Entry as u8, header.block_count - 1)?;
+            let block_key =
+                get_journaling_key(InternalKeyKind::JournalEntry, header.block_count - 1)?;
             // Read the batch of updates (aka. "block") previously saved in the journal.
-            let mut batch = self
+            #[cfg(with_metrics)]
+            let read_latency = JOURNAL_BLOCK_READ_LATENCY.measure_latency();
+            let failure = |reason: String| JournalConsistencyError::FailureToRetrieveJournalBlock {
+                block: header.block_count - 1,
+                block_count: header.block_count,
+                reason,
+            };
+            let bytes = self
                 .store
-                .read_value::<K::Batch>(&block_key)
+                .read_value_bytes(&block_key)
                 .await?
-                .ok_or(JournalConsistencyError::FailureToRetrieveJournalBlock)?;
+                .ok_or_else(|| failure("the block is missing".to_string()))?;
+            let mut batch = bcs::from_bytes::<K::Batch>(&bytes)
+                .map_err(|error| failure(format!("the block is corrupted: {error}")))?;
+            #[cfg(with_metrics)]
+            read_latency.finish();
             // Execute the block and delete it from the journal atomically.
             batch.add_delete(block_key);
             header.block_count -= 1;
@@ -306,114 +985,735 @@ where
             } else {
                 batch.add_delete(header_key.clone());
             }
+            #[cfg(with_metrics)]
+            let _commit_latency = JOURNAL_BLOCK_COMMIT_LATENCY.measure_latency();
             self.store.write_batch(batch).await?;
         }
         Ok(())
     }
 
-    /// Writes the content of `batch` to the journal as a succession of blocks that can be
-    /// interpreted later by `coherently_resolve_journal`.
+    /// Checks that the journal written by the `JournalWriter` matches its `header`: the
+    /// header is stored and all the blocks it expects are present and deserialize.
     ///
-    /// Starting with a batch of operations that is typically too large to be executed in
-    /// one go (see `is_fastpath_feasible()` below), the goal of this function is to split
-    /// the batch into smaller blocks so that `coherently_resolve_journal` respects the
-    /// constraints of the underlying key-value store (see analysis above).
+    /// The blocks beyond the header, left over by an earlier interrupted journal, are
+    /// ignored: they are never read by the resolution. This reads the whole journal
+    /// again, so it is only done in debug builds, when enabled with
+    /// `with_journal_verification`, to catch the bugs of the journal protocol before they
+    /// surface at recovery.
+    #[cfg(debug_assertions)]
+    async fn check_journal(&self, header: &JournalHeader) -> Result<(), K::Error> {
+        let mut diagnosis = self.diagnose_journal().await?;
+        diagnosis.present_blocks.sort_unstable();
+        let header_block_count = (header.block_count > 0).then_some(header.block_count);
+        let reason = if diagnosis.header_block_count != header_block_count {
+            format!(
+                "the header records {:?} blocks instead of {header_block_count:?}",
+                diagnosis.header_block_count
+            )
+        } else if !diagnosis.missing_blocks.is_empty() {
+            format!("the blocks {:?} are missing", diagnosis.missing_blocks)
+        } else if !diagnosis.corrupted_blocks.is_empty() {
+            format!("the blocks {:?} are corrupted", diagnosis.corrupted_blocks)
+        } else if diagnosis.present_blocks != (0..header.block_count).collect::<Vec<_>>() {
+            format!("the blocks {:?} are present", diagnosis.present_blocks)
+        } else {
+            return Ok(());
+        };
+        Err(JournalConsistencyError::JournalVerificationFailed { reason }.into())
+    }
+
+    /// Writes the `batch` like `write_batch` does, and reports whether the journal was
+    /// needed to do so.
+    pub async fn write_batch_outcome(&self, batch: Batch) -> Result<WriteOutcome, K::Error> {
+        self.write_batch_with_confirmation(batch, |_| ()).await
+    }
+
+    /// Writes the `batch` like `write_batch_outcome` does, and calls `on_durable` with the
+    /// outcome at the moment the batch becomes durable: once the single transaction of the
+    /// fastpath is acknowledged, once the last split transaction is acknowledged, or once
+    /// the journal is fully resolved.
+    ///
+    /// The callback is called exactly once if the write succeeds, before this function
+    /// returns and, for a journaled batch, while the journal lock is still held. It is
+    /// never called if the write fails.
+    pub async fn write_batch_with_confirmation(
+        &self,
+        batch: Batch,
+        on_durable: impl FnOnce(WriteOutcome) + Send,
+    ) -> Result<WriteOutcome, K::Error> {
+        self.store.check_batch(&batch)?;
+        #[cfg(with_mark_and_sweep)]
+        let batch = self.mark_deleted_prefixes(batch).await?;
+        self.write_expanded_batch(batch, on_durable).await
+    }
+
+    /// Converts the `batch` into the batch of the inner store that `write_batch` would
+    /// write, without writing it. The prefix deletions are expanded into the deletions of
+    /// the keys currently present under the prefixes.
     ///
-    /// For efficiency reasons, we write as many blocks as possible in each "transaction"
-    /// batch, using one write-operation per block. Then we also update the journal header
-    /// with the final number of blocks.
+    /// With the `mark-and-sweep` feature, the batch written may differ: prefix deletions
+    /// can be written as tombstones instead.
+    pub async fn simplify_batch(&self, batch: Batch) -> Result<K::Batch, K::Error> {
+        K::Batch::from_batch(self, batch).await
+    }
+
+    /// Writes the `batch`, deleting the key prefixes eagerly, and calls `on_durable` once
+    /// the batch is durable.
     ///
-    /// As a result, the constraints of the underlying database are respected if the
-    /// following conditions are met while a "transaction" batch is being built:
+    /// When the inner store deletes the key prefixes by deleting the keys under them, the
+    /// keys are read as a stream: they are buffered only while the batch fits in a single
+    /// transaction, and are then written to the journal, or to the split transactions,
+    /// as they arrive. So the size of a prefix deletion is not limited by the memory.
+    async fn write_expanded_batch(
+        &self,
+        batch: Batch,
+        on_durable: impl FnOnce(WriteOutcome) + Send,
+    ) -> Result<WriteOutcome, K::Error> {
+        let (mut batch, key_prefixes, inserted_keys) = if K::Batch::EXPANDS_KEY_PREFIXES {
+            let UnorderedBatch {
+                key_prefix_deletions,
+                simple_unordered_batch,
+            } = batch.simplify();
+            let mut batch = K::Batch::default();
+            let mut inserted_keys = HashSet::new();
+            for key in simple_unordered_batch.deletions {
+                batch.add_delete(key);
+            }
+            for (key, value) in simple_unordered_batch.insertions {
+                if !key_prefix_deletions.is_empty() {
+                    inserted_keys.insert(key.clone());
+                }
+                batch.add_insert(key, value);
+            }
+            (batch, key_prefix_deletions, inserted_keys)
+        } else {
+            let batch = K::Batch::from_batch(self, batch).await?;
+            (batch, Vec::new(), HashSet::new())
+        };
+        let mut deleted_keys = pin!(self.expand_key_prefixes(&key_prefixes, &inserted_keys));
+        let mut items_size =
+            batch.items_size(|key_len, value_len| self.store.size_of_item(key_len, value_len));
+        while Self::is_fastpath_feasible(&batch, items_size) {
+            let Some(key) = deleted_keys.try_next().await? else {
+                self.store.write_batch(batch).await?;
+                on_durable(WriteOutcome::Fastpath);
+                return Ok(WriteOutcome::Fastpath);
+            };
+            items_size += self.store.size_of_item(key.len(), 0);
+            batch.add_delete(key);
+        }
+        if self.oversized_batches == OversizedBatches::AutoSplit {
+            let mut writer = SplitWriter::new(&self.store);
+            writer.push(batch).await?;
+            while let Some(key) = deleted_keys.try_next().await? {
+                writer.push_delete(key).await?;
+            }
+            let transactions = writer.finish().await?;
+            let outcome = WriteOutcome::Split { transactions };
+            on_durable(outcome);
+            Ok(outcome)
+        } else {
+            if !self.has_exclusive_access {
+                return Err(JournalConsistencyError::JournalRequiresExclusiveAccess.into());
+            }
+            let _journal_guard = self.root_key_state.journal_lock.lock().await;
+            let mut writer = JournalWriter::new(&self.store)?;
+            writer.push(batch).await?;
+            while let Some(key) = deleted_keys.try_next().await? {
+                writer.push_delete(key).await?;
+            }
+            let header = writer.finish().await?;
+            #[cfg(debug_assertions)]
+            if self.verify_journal {
+                self.check_journal(&header).await?;
+            }
+            let blocks = header.block_count as usize;
+            self.coherently_resolve_journal(header).await?;
+            let outcome = WriteOutcome::Journaled { blocks };
+            on_durable(outcome);
+            Ok(outcome)
+        }
+    }
+
+    /// Returns the stream of the keys under the `key_prefixes`, except the
+    /// `inserted_keys`, to be deleted.
+    fn expand_key_prefixes<'a>(
+        &'a self,
+        key_prefixes: &'a [Vec<u8>],
+        inserted_keys: &'a HashSet<Vec<u8>>,
+    ) -> impl Stream<Item = Result<Vec<u8>, K::Error>> + Send + 'a {
+        stream::iter(key_prefixes)
+            .flat_map(move |key_prefix| {
+                self.stream_keys_by_prefix(key_prefix)
+                    .map_ok(move |suffix| [key_prefix.as_slice(), &suffix].concat())
+            })
+            .try_filter(move |key| future::ready(!inserted_keys.contains(key)))
+    }
+
+    /// Moves all the keys under the prefix `from` to the prefix `to`, keeping their values,
+    /// and returns the number of keys moved.
+    ///
+    /// The move is performed as a single batch, so that it goes through the journal if it
+    /// is too large for the underlying store: a crash in the middle of the move is
+    /// recovered by `clear_journal`, and running the move again afterwards is a no-op.
+    /// The prefixes must not overlap, since otherwise the moved keys would be mixed up with
+    /// the keys being deleted.
+    pub async fn move_prefix(&self, from: &[u8], to: &[u8]) -> Result<usize, K::Error> {
+        ensure!(
+            !from.starts_with(to) && !to.starts_with(from),
+            JournalConsistencyError::OverlappingPrefixes
+        );
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(from.to_vec());
+        let mut count = 0;
+        // The keys are read through the tombstones, which hide the keys already moved.
+        for entry in self.find_key_values_by_prefix(from).await?.iterator() {
+            let (suffix, value) = entry?;
+            let mut key = to.to_vec();
+            key.extend_from_slice(suffix);
+            batch.put_key_value_bytes(key, value.to_vec());
+            count += 1;
+        }
+        self.write_batch(batch).await?;
+        Ok(count)
+    }
+
+    /// Applies `transform` to the values under `key_prefix`, writes back the values that
+    /// it changes and returns the number of values changed. This is meant for migrating
+    /// the format of the values in place.
+    ///
+    /// The changed values are written in chunks of at most `K::MAX_BATCH_SIZE - 1`
+    /// values, each chunk being a single batch, written through the journal if needed.
+    /// Every chunk also records the last key that it covers. If the transformation is
+    /// interrupted, calling `transform_prefix` again on the same prefix resumes after the
+    /// last chunk written, so that no value is transformed twice. An interrupted
+    /// transformation must therefore be resumed with the same `transform`. The progress
+    /// record is deleted once all the values are transformed.
+    pub async fn transform_prefix<E>(
+        &self,
+        key_prefix: &[u8],
+        transform: impl Fn(&[u8]) -> Result<Vec<u8>, E>,
+    ) -> Result<usize, E>
+    where
+        E: From<K::Error>,
+    {
+        let progress_key = get_internal_key(InternalKeyKind::TransformProgress, key_prefix);
+        let resume_after = self.store.read_value_bytes(&progress_key).await?;
+        let chunk_size = K::MAX_BATCH_SIZE - 1;
+        let mut batch = Batch::new();
+        let mut chunk_len = 0;
+        let mut count = 0;
+        // The values are read through the tombstones, so that deleted keys are not
+        // written back.
+        let key_values = self.find_key_values_by_prefix(key_prefix).await?;
+        for entry in key_values.iterator() {
+            let (suffix, value) = entry?;
+            if resume_after.as_deref().is_some_and(|last| suffix <= last) {
+                continue;
+            }
+            let new_value = transform(value)?;
+            if new_value == value {
+                continue;
+            }
+            batch.put_key_value_bytes([key_prefix, suffix].concat(), new_value);
+            chunk_len += 1;
+            count += 1;
+            if chunk_len == chunk_size {
+                batch.put_key_value_bytes(progress_key.clone(), suffix.to_vec());
+                self.write_batch(std::mem::take(&mut batch)).await?;
+                chunk_len = 0;
+            }
+        }
+        batch.delete_key(progress_key);
+        self.write_batch(batch).await?;
+        Ok(count)
+    }
+
+    /// Resolves the journals left over by interrupted writes under all the root keys of
+    /// the `namespace`, and returns the number of journals resolved.
+    ///
+    /// This is meant for an operator recovering a whole namespace, e.g. after a faulty
+    /// deployment interrupted the writes of many views, while `clear_journal` only
+    /// resolves the journal of its own root key. The journals are resolved one after the
+    /// other, without the recovery jitter, and no other process should write to the
+    /// namespace in the meantime.
+    pub async fn recover_all_journals(
+        config: &K::Config,
+        namespace: &str,
+    ) -> Result<usize, K::Error> {
+        let store = Self::connect(config, namespace).await?;
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let mut count = 0;
+        for root_key in K::list_root_keys(config, namespace).await? {
+            let store = store.clone_with_root_key(&root_key)?;
+            let _journal_guard = store.root_key_state.journal_lock.lock().await;
+            if let Some(header) = store.store.read_value::<JournalHeader>(&header_key).await? {
+                store.check_not_rebased().await?;
+                store.coherently_resolve_journal(header).await?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Moves the journal left over by an interrupted write under `old_root_key` to
+    /// `new_root_key`, so that it is resolved there, e.g. when migrating the data of a
+    /// root key to another one. Fails if another journal is present under `new_root_key`.
+    ///
+    /// The root keys are written in separate transactions: the move is first recorded
+    /// under `old_root_key`, then the blocks and the header are copied, and the old header
+    /// is deleted last, together with the record of the move. A crash in the middle leaves
+    /// the old journal in place, possibly with a complete copy under `new_root_key`, which
+    /// would be applied twice if both were resolved. So the old journal is then not
+    /// resolved: `clear_journal` and `recover_all_journals` fail with
+    /// [`JournalConsistencyError::JournalBeingRebased`] until this method is called again
+    /// to complete the move. The journal under `new_root_key` must not be resolved before.
+    pub async fn rebase_journal(
+        &self,
+        old_root_key: &[u8],
+        new_root_key: &[u8],
+    ) -> Result<(), K::Error> {
+        if old_root_key == new_root_key {
+            return Ok(());
+        }
+        let old = self.clone_with_root_key(old_root_key)?;
+        let new = self.clone_with_root_key(new_root_key)?;
+        // The locks are taken in the order of the root keys, to avoid deadlocks.
+        let (_first_guard, _second_guard) = if old_root_key < new_root_key {
+            let first = old.root_key_state.journal_lock.lock().await;
+            (first, new.root_key_state.journal_lock.lock().await)
+        } else {
+            let first = new.root_key_state.journal_lock.lock().await;
+            (first, old.root_key_state.journal_lock.lock().await)
+        };
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let Some(header) = old.store.read_value::<JournalHeader>(&header_key).await? else {
+            return Ok(());
+        };
+        let rebase_key = get_internal_key(InternalKeyKind::JournalRebase, &[]);
+        match old.store.read_value_bytes(&rebase_key).await? {
+            Some(root_key) if root_key != new_root_key => {
+                return Err(JournalConsistencyError::JournalBeingRebased {
+                    new_root_key: root_key,
+                }
+                .into());
+            }
+            _ => (),
+        }
+        let mut blocks = Vec::new();
+        for block in 0..header.block_count {
+            let block_key = get_journaling_key(InternalKeyKind::JournalEntry, block)?;
+            let value = old.store.read_value_bytes(&block_key).await?.ok_or(
+                JournalConsistencyError::FailureToRetrieveJournalBlock {
+                    block,
+                    block_count: header.block_count,
+                    reason: "the block is missing".to_string(),
+                },
+            )?;
+            blocks.push((block_key, value));
+        }
+        if let Some(new_header) = new.store.read_value::<JournalHeader>(&header_key).await? {
+            // Only a copy left by an interrupted call can be overwritten.
+            ensure!(
+                new_header.block_count == header.block_count,
+                JournalConsistencyError::JournalAlreadyExists
+            );
+            for (block_key, value) in &blocks {
+                let new_value = new.store.read_value_bytes(block_key).await?;
+                ensure!(
+                    new_value.as_ref() == Some(value),
+                    JournalConsistencyError::JournalAlreadyExists
+                );
+            }
+        }
+        let mut batch = K::Batch::default();
+        batch.add_insert(rebase_key.clone(), new_root_key.to_vec());
+        old.store.write_batch(batch).await?;
+        // Every block is close to the maximal size of a batch, so they are copied one by
+        // one, and the header is written once all of them are present.
+        for (block_key, value) in blocks {
+            let mut batch = K::Batch::default();
+            batch.add_insert(block_key, value);
+            new.store.write_batch(batch).await?;
+        }
+        let mut batch = K::Batch::default();
+        batch.add_insert(header_key.clone(), bcs::to_bytes(&header)?);
+        new.store.write_batch(batch).await?;
+        // Once the old header is deleted, the old blocks are ignored.
+        let mut batch = K::Batch::default();
+        batch.add_delete(header_key);
+        batch.add_delete(rebase_key);
+        old.store.write_batch(batch).await?;
+        let mut batch = K::Batch::default();
+        for block in 0..header.block_count {
+            if batch.len() == K::MAX_BATCH_SIZE {
+                old.store.write_batch(std::mem::take(&mut batch)).await?;
+            }
+            batch.add_delete(get_journaling_key(InternalKeyKind::JournalEntry, block)?);
+        }
+        if !batch.is_empty() {
+            old.store.write_batch(batch).await?;
+        }
+        Ok(())
+    }
+
+    /// Fails if the journal is being moved to another root key by an interrupted
+    /// `rebase_journal`, in which case resolving it would apply it twice.
+    async fn check_not_rebased(&self) -> Result<(), K::Error> {
+        let rebase_key = get_internal_key(InternalKeyKind::JournalRebase, &[]);
+        match self.store.read_value_bytes(&rebase_key).await? {
+            Some(new_root_key) => {
+                Err(JournalConsistencyError::JournalBeingRebased { new_root_key }.into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns statistics about the journal, without resolving it.
     ///
-    /// (1) The number of blocks per transaction doesn't exceed `K::MAX_BATCH_SIZE`.
-    /// But it is perfectly possible to have `K::MAX_BATCH_SIZE = usize::MAX`.
+    /// In a healthy store, the journal is empty outside of `write_batch`. The number of
+    /// blocks present may differ from the `block_count` of the header when blocks were
+    /// left over by an interrupted write.
+    pub async fn journal_stats(&self) -> Result<JournalStats, K::Error> {
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let header = self.store.read_value::<JournalHeader>(&header_key).await?;
+        let mut stats = JournalStats {
+            has_header: header.is_some(),
+            block_count: header.map_or(0, |header| header.block_count),
+            ..JournalStats::default()
+        };
+        let entry_prefix = InternalKeyKind::JournalEntry.prefix();
+        for entry in self
+            .store
+            .find_key_values_by_prefix(&entry_prefix)
+            .await?
+            .iterator()
+        {
+            let (_, value) = entry?;
+            stats.entry_count += 1;
+            stats.entry_bytes += value.len();
+        }
+        Ok(stats)
+    }
+
+    /// Compares the journal header with the journal blocks present in the store, without
+    /// resolving the journal.
     ///
-    /// (2) The total size of BCS-serialized blocks together with their corresponding keys
-    /// does not exceed `K::MAX_BATCH_TOTAL_SIZE`.
+    /// This is meant to be used after a failure to resolve the journal, to find which
+    /// blocks are missing or corrupted.
+    pub async fn diagnose_journal(&self) -> Result<JournalDiagnosis, K::Error> {
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let header = self.store.read_value::<JournalHeader>(&header_key).await?;
+        let block_count = header.as_ref().map_or(0, |header| header.block_count);
+        let mut diagnosis = JournalDiagnosis {
+            header_block_count: header.map(|header| header.block_count),
+            ..JournalDiagnosis::default()
+        };
+        let entry_prefix = InternalKeyKind::JournalEntry.prefix();
+        for entry in self
+            .store
+            .find_key_values_by_prefix(&entry_prefix)
+            .await?
+            .iterator()
+        {
+            let (key, value) = entry?;
+            let block = bcs::from_bytes::<u32>(key)?;
+            if block < block_count {
+                diagnosis.present_blocks.push(block);
+            } else {
+                diagnosis.unexpected_blocks.push(block);
+            }
+            if bcs::from_bytes::<K::Batch>(value).is_err() {
+                diagnosis.corrupted_blocks.push(block);
+            }
+        }
+        diagnosis.missing_blocks = (0..block_count)
+            .filter(|block| !diagnosis.present_blocks.contains(block))
+            .collect();
+        Ok(diagnosis)
+    }
+
+    /// Returns a summary of every journal block present in the store, ordered by
+    /// position, without resolving the journal.
     ///
-    /// (3) The size of each BCS-serialized block doesn't exceed `K::MAX_VALUE_SIZE`.
+    /// This is meant to see what a pending journal would apply before resolving it. A
+    /// block that cannot be deserialized fails the inspection: `diagnose_journal` reports
+    /// such blocks, as well as the blocks missing from the header.
+    pub async fn inspect_journal(&self) -> Result<Vec<JournalEntrySummary>, K::Error> {
+        let entry_prefix = InternalKeyKind::JournalEntry.prefix();
+        let mut summaries = Vec::new();
+        for entry in self
+            .store
+            .find_key_values_by_prefix(&entry_prefix)
+            .await?
+            .iterator()
+        {
+            let (key, value) = entry?;
+            let batch = bcs::from_bytes::<K::Batch>(value)?;
+            let insertions = batch.num_insertions();
+            summaries.push(JournalEntrySummary {
+                block: bcs::from_bytes::<u32>(key)?,
+                deletions: batch.len() - insertions,
+                insertions,
+                bytes: value.len(),
+            });
+        }
+        // The positions are serialized in little-endian, which is not their order.
+        summaries.sort_by_key(|summary| summary.block);
+        Ok(summaries)
+    }
+
+    /// Deletes the journal header and all the journal blocks without applying them, and
+    /// returns the number of blocks deleted.
     ///
-    /// (4) When processing a journal block, we have to do two other operations.
-    ///   (a) removing the existing block. The cost is `key_len`.
-    ///   (b) updating or removing the journal. The cost is `key_len + header_value_len`
-    ///       or `key_len`. An upper bound is thus
-    ///       `journal_len_upper_bound = key_len + header_value_len`.
-    ///   Thus the following has to be taken as upper bound on the block size:
-    ///   `K::MAX_BATCH_TOTAL_SIZE - key_len - journal_len_upper_bound`.
+    /// **This is dangerous**: the batch recorded in the journal is lost, although a write
+    /// may already have reported it as committed. It is only meant for an operator who
+    /// established, e.g. with `inspect_journal`, that the pending batch must never be
+    /// applied. As a confirmation, `expected_block_count` must be the block count of the
+    /// header reported by `diagnose_journal`, `None` meaning that no header is present.
+    /// Otherwise, nothing is deleted and [`JournalConsistencyError::UnexpectedJournal`]
+    /// is returned, so that a journal written in the meantime is not discarded. A header
+    /// that cannot be deserialized makes this fail: it has to be repaired first.
     ///
-    /// NOTE:
-    /// * Since a block must contain at least one operation and M bytes of the
-    ///   serialization overhead (typically M is 2 or 3 bytes of vector sizes), condition (3)
-    ///   requires that each operation in the original batch satisfies:
-    ///   `sizeof(key) + sizeof(value) + M <= K::MAX_VALUE_SIZE`
+    /// The header is deleted first, after which the remaining blocks are ignored, so that
+    /// an interrupted call can simply be made again with `None`.
+    pub async fn discard_journal(
+        &self,
+        expected_block_count: Option<u32>,
+    ) -> Result<usize, K::Error> {
+        let _journal_guard = self.root_key_state.journal_lock.lock().await;
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let header = self.store.read_value::<JournalHeader>(&header_key).await?;
+        let found = header.as_ref().map(|header| header.block_count);
+        if found != expected_block_count {
+            return Err(JournalConsistencyError::UnexpectedJournal {
+                expected: expected_block_count,
+                found,
+            }
+            .into());
+        }
+        if header.is_some() {
+            let mut batch = K::Batch::default();
+            batch.add_delete(header_key);
+            batch.add_delete(get_internal_key(InternalKeyKind::JournalRebase, &[]));
+            self.store.write_batch(batch).await?;
+        }
+        let entry_prefix = InternalKeyKind::JournalEntry.prefix();
+        let mut count = 0;
+        let mut batch = K::Batch::default();
+        for key in self
+            .store
+            .find_keys_by_prefix(&entry_prefix)
+            .await?
+            .iterator()
+        {
+            if batch.len() == K::MAX_BATCH_SIZE {
+                self.store.write_batch(std::mem::take(&mut batch)).await?;
+            }
+            batch.add_delete(get_internal_key(InternalKeyKind::JournalEntry, key?));
+            count += 1;
+        }
+        if !batch.is_empty() {
+            self.store.write_batch(batch).await?;
+        }
+        Ok(count)
+    }
+
+    /// Rewrites the pending journal, if any, into fewer and fuller blocks, so that its
+    /// resolution needs fewer round-trips. Returns the number of blocks removed.
     ///
-    /// * Similarly, a transaction must contain at least one block so it is desirable that
-    ///   the maximum size of a block insertion `1 + sizeof(block_key) + K::MAX_VALUE_SIZE`
-    ///   plus M bytes of overhead doesn't exceed the threshold of condition (2).
-    async fn write_journal(&self, batch: K::Batch) -> Result<JournalHeader, K::Error> {
-        let header_key = get_journaling_key(KeyTag::Journal as u8, 0)?;
+    /// The operations of a journal come from a single simplified batch and are on
+    /// disjoint keys, so they can be applied in any order and the blocks can be merged
+    /// freely. The last block is repeatedly merged into the lowest block with enough room
+    /// left for it. Each merge is a single transaction writing the merged block, deleting
+    /// the last block and decrementing the block count of the header, so an interrupted
+    /// compaction leaves a valid journal, with the same pending operations, which can be
+    /// resolved or compacted again.
+    pub async fn compact_journal(&self) -> Result<usize, K::Error> {
+        if !self.has_exclusive_access {
+            return Err(JournalConsistencyError::JournalRequiresExclusiveAccess.into());
+        }
+        let _journal_guard = self.root_key_state.journal_lock.lock().await;
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let Some(mut header) = self.store.read_value::<JournalHeader>(&header_key).await? else {
+            return Ok(0);
+        };
+        let mut blocks = Vec::new();
+        for block in 0..header.block_count {
+            let block_key = get_journaling_key(InternalKeyKind::JournalEntry, block)?;
+            let value = self.store.read_value_bytes(&block_key).await?.ok_or(
+                JournalConsistencyError::FailureToRetrieveJournalBlock {
+                    block,
+                    block_count: header.block_count,
+                    reason: "the block is missing".to_string(),
+                },
+            )?;
+            blocks.push(value);
+        }
+        // A merge writes the merged block and the header and deletes the last block, all
+        // counted by `size_of_item` as in `JournalWriter`, and the resolution of the
+        // merged block must remain possible, see `coherently_resolve_journal`.
         let key_len = header_key.len();
         let header_value_len = bcs::serialized_size(&JournalHeader::default())?;
-        let journal_len_upper_bound = key_len + header_value_len;
-        // Each block in a transaction comes with a key.
-        let max_transaction_size = K::MAX_BATCH_TOTAL_SIZE;
         let max_block_size = std::cmp::min(
             K::MAX_VALUE_SIZE,
-            K::MAX_BATCH_TOTAL_SIZE - key_len - journal_len_upper_bound,
+            K::MAX_BATCH_TOTAL_SIZE - 2 * key_len - header_value_len,
         );
-
-        let mut iter = batch.into_iter();
-        let mut block_batch = K::Batch::default();
-        let mut block_size = 0;
-        let mut block_count = 0;
-        let mut transaction_batch = K::Batch::default();
-        let mut transaction_size = 0;
-        while iter.write_next_value(&mut block_batch, &mut block_size)? {
-            let (block_flush, transaction_flush) = {
-                if iter.is_empty() || transaction_batch.len() == K::MAX_BATCH_SIZE - 1 {
-                    (true, true)
-                } else {
-                    let next_block_size = iter
-                        .next_batch_size(&block_batch, block_size)?
-                        .expect("iter is not empty");
-                    let next_transaction_size = transaction_size + next_block_size + key_len;
-                    let transaction_flush = next_transaction_size > max_transaction_size;
-                    let block_flush = transaction_flush
-                        || block_batch.len() == K::MAX_BATCH_SIZE - 2
-                        || next_block_size > max_block_size;
-                    (block_flush, transaction_flush)
-                }
-            };
-            if block_flush {
-                block_size += block_batch.overhead_size();
-                let value = bcs::to_bytes(&block_batch)?;
-                block_batch = K::Batch::default();
-                assert_eq!(value.len(), block_size);
-                let key = get_journaling_key(KeyTag::Entry as u8, block_count)?;
-                transaction_batch.add_insert(key, value);
-                block_count += 1;
-                transaction_size += block_size + key_len;
-                block_size = 0;
-            }
-            if transaction_flush {
-                let batch = std::mem::take(&mut transaction_batch);
-                self.store.write_batch(batch).await?;
-                transaction_size = 0;
+        let merge_overhead = self.store.size_of_item(key_len, header_value_len)
+            + self.store.size_of_item(key_len, 0);
+        let mut removed = 0;
+        let mut target = 0;
+        while target + 1 < blocks.len() {
+            let last = blocks.len() - 1;
+            let mut merged = bcs::from_bytes::<K::Batch>(&blocks[target])?;
+            let mut iter = bcs::from_bytes::<K::Batch>(&blocks[last])?.into_iter();
+            let mut merged_size = 0;
+            while iter.write_next_value(&mut merged, &mut merged_size)? {}
+            let value = bcs::to_bytes(&merged)?;
+            let merge_size = merge_overhead + self.store.size_of_item(key_len, value.len());
+            if merged.len() > K::MAX_BATCH_SIZE - 2
+                || value.len() > max_block_size
+                || merge_size > K::MAX_BATCH_TOTAL_SIZE
+            {
+                // The last block does not fit, the next block is tried.
+                target += 1;
+                continue;
             }
+            header.block_count -= 1;
+            let mut batch = K::Batch::default();
+            batch.add_insert(
+                get_journaling_key(InternalKeyKind::JournalEntry, target as u32)?,
+                value.clone(),
+            );
+            batch.add_delete(get_journaling_key(
+                InternalKeyKind::JournalEntry,
+                last as u32,
+            )?);
+            batch.add_insert(header_key.clone(), bcs::to_bytes(&header)?);
+            self.store.write_batch(batch).await?;
+            blocks[target] = value;
+            blocks.pop();
+            removed += 1;
+            // The new last block is smaller, so it may fit in a block skipped before.
+            target = 0;
+        }
+        Ok(removed)
+    }
+
+    /// Rebuilds the journal header when it cannot be deserialized, as after a torn
+    /// write, then resolves the journal. Returns the block count inferred for the header,
+    /// or `None` if the header is absent or readable, in which case nothing is done.
+    ///
+    /// Since the blocks are resolved from the last one down, the blocks of a journal are
+    /// always numbered from `0`: the block count is inferred as the number of blocks
+    /// present before the first missing one. Resolving the journal otherwise fails on a
+    /// corrupted header, so that this repair is only made on request.
+    pub async fn repair_journal_header(&self) -> Result<Option<u32>, K::Error> {
+        let _journal_guard = self.root_key_state.journal_lock.lock().await;
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        let Some(bytes) = self.store.read_value_bytes(&header_key).await? else {
+            return Ok(None);
+        };
+        if bcs::from_bytes::<JournalHeader>(&bytes).is_ok() {
+            return Ok(None);
         }
-        let header = JournalHeader { block_count };
-        if block_count > 0 {
-            let value = bcs::to_bytes(&header)?;
+        let entry_prefix = InternalKeyKind::JournalEntry.prefix();
+        let mut blocks = Vec::new();
+        for key in self
+            .store
+            .find_keys_by_prefix(&entry_prefix)
+            .await?
+            .iterator()
+        {
+            blocks.push(bcs::from_bytes::<u32>(key?)?);
+        }
+        blocks.sort_unstable();
+        let block_count = blocks
+            .iter()
+            .zip(0..)
+            .take_while(|(block, index)| *block == index)
+            .count() as u32;
+        if block_count == 0 {
             let mut batch = K::Batch::default();
-            batch.add_insert(header_key, value);
+            batch.add_delete(header_key);
             self.store.write_batch(batch).await?;
+        } else {
+            self.coherently_resolve_journal(JournalHeader { block_count })
+                .await?;
         }
-        Ok(header)
+        Ok(Some(block_count))
+    }
+
+    /// Replaces the prefix deletions of the `batch` by tombstones, where possible.
+    ///
+    /// The tombstones covering a key written by the batch are swept beforehand. A prefix
+    /// deletion followed in the batch by a write under the prefix, or covering the range
+    /// of keys set aside for the journal, is kept as is and executed eagerly.
+    #[cfg(with_mark_and_sweep)]
+    async fn mark_deleted_prefixes(&self, batch: Batch) -> Result<Batch, K::Error> {
+        let tombstones = self.read_tombstones().await?;
+        for tombstone in tombstones {
+            let is_written = batch.operations.iter().any(|operation| {
+                matches!(operation, WriteOperation::Put { key, .. } if key.starts_with(&tombstone))
+            });
+            if is_written {
+                self.sweep_tombstone(&tombstone).await?;
+            }
+        }
+        let mut operations = batch.operations;
+        let mut marked_batch = Batch::new();
+        for index in 0..operations.len() {
+            let operation = std::mem::replace(
+                &mut operations[index],
+                WriteOperation::Delete { key: Vec::new() },
+            );
+            match operation {
+                WriteOperation::DeletePrefix { key_prefix }
+                    if key_prefix.first().is_some_and(|tag| *tag != INTERNAL_TAG)
+                        && key_prefix.len() + 2 <= K::MAX_KEY_SIZE
+                        && !operations[index + 1..].iter().any(|operation| {
+                            matches!(operation, WriteOperation::Put { key, .. } if key.starts_with(&key_prefix))
+                        }) =>
+                {
+                    self.root_key_state.tombstones.record_write();
+                    marked_batch.put_key_value_bytes(get_tombstone_key(&key_prefix), Vec::new());
+                }
+                operation => marked_batch.operations.push(operation),
+            }
+        }
+        Ok(marked_batch)
     }
 
-    fn is_fastpath_feasible(batch: &K::Batch) -> bool {
-        batch.len() <= K::MAX_BATCH_SIZE && batch.num_bytes() <= K::MAX_BATCH_TOTAL_SIZE
+    /// Deletes the keys under the tombstone of `key_prefix`, together with the tombstone.
+    #[cfg(with_mark_and_sweep)]
+    async fn sweep_tombstone(&self, key_prefix: &[u8]) -> Result<(), K::Error> {
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(key_prefix.to_vec());
+        batch.delete_key(get_tombstone_key(key_prefix));
+        self.write_expanded_batch(batch, |_| ()).await?;
+        Ok(())
+    }
+
+    /// Reclaims the keys deleted by tombstones and removes the tombstones. Returns the
+    /// number of tombstones swept.
+    ///
+    /// Every tombstone is swept in its own batch, so the sweep can be interrupted and
+    /// resumed at any time.
+    #[cfg(with_mark_and_sweep)]
+    pub async fn sweep_tombstones(&self) -> Result<usize, K::Error> {
+        let tombstones = self.read_tombstones().await?;
+        for tombstone in &tombstones {
+            self.sweep_tombstone(tombstone).await?;
+        }
+        Ok(tombstones.len())
+    }
+
+    /// Returns whether the `batch`, whose items sum up to `items_size` as counted by
+    /// `size_of_item`, can be written in a single transaction, that is if both its number
+    /// of entries and its size are within the limits of the inner store. Otherwise, the
+    /// batch has to go through the journal.
+    fn is_fastpath_feasible(batch: &K::Batch, items_size: usize) -> bool {
+        batch.len() <= K::MAX_BATCH_SIZE && items_size <= K::MAX_BATCH_TOTAL_SIZE
     }
 }
 
@@ -423,6 +1723,97 @@ impl<K> JournalingKeyValueStore<K> {
         Self {
             store,
             has_exclusive_access: false,
+            root_key_state: Arc::default(),
+            root_key_states: Arc::default(),
+            oversized_batches: OversizedBatches::Journal,
+            verify_journal: false,
         }
     }
+
+    /// Sets how the batches too large for a single transaction of the underlying store
+    /// are written. See [`OversizedBatches`] for the guarantees of each mode.
+    pub fn with_oversized_batches(mut self, oversized_batches: OversizedBatches) -> Self {
+        self.oversized_batches = oversized_batches;
+        self
+    }
+
+    /// Sets whether every journal is read again after it is written, to check that its
+    /// header and its blocks match, failing the write with
+    /// [`JournalConsistencyError::JournalVerificationFailed`] otherwise. This is meant to
+    /// catch the bugs of the journal protocol in tests, and does nothing in release
+    /// builds.
+    pub fn with_journal_verification(mut self, verify_journal: bool) -> Self {
+        self.verify_journal = verify_journal;
+        self
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<K> JournalingKeyValueStore<K>
+where
+    K: AdminKeyValueStore + DirectKeyValueStore + Clone + Send + Sync + 'static,
+    K::Error: From<JournalConsistencyError> + Send,
+{
+    /// Spawns a task resolving, every `interval`, the journals left over under the given
+    /// root keys, e.g. after an error that was not retried.
+    ///
+    /// The journal of a root key is locked while it is written or resolved, so that the
+    /// task never resolves a journal concurrently with `write_batch` or `clear_journal`
+    /// on a store cloned from this one. Stores created by another `connect` do not share
+    /// these locks.
+    pub fn spawn_journal_resolver(
+        &self,
+        root_keys: Vec<Vec<u8>>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(self.clone().run_journal_resolver(root_keys, interval))
+    }
+
+    async fn run_journal_resolver(self, root_keys: Vec<Vec<u8>>, interval: Duration) {
+        loop {
+            timer::sleep(interval).await;
+            for root_key in &root_keys {
+                if let Err(error) = self.resolve_lingering_journal(root_key).await {
+                    tracing::warn!(
+                        "Failed to resolve the journal of root key {root_key:?}: {error}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Resolves the journal of the given root key, if any.
+    async fn resolve_lingering_journal(&self, root_key: &[u8]) -> Result<(), K::Error> {
+        let store = self.clone_with_root_key(root_key)?;
+        let _journal_guard = store.root_key_state.journal_lock.lock().await;
+        let header_key = get_journaling_key(InternalKeyKind::JournalHeader, 0)?;
+        if let Some(header) = store.store.read_value::<JournalHeader>(&header_key).await? {
+            store.check_not_rebased().await?;
+            tracing::info!(
+                "Resolving a lingering journal of {} blocks for root key {root_key:?}",
+                header.block_count
+            );
+            store.coherently_resolve_journal(header).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memory::MemoryStore, store::TestKeyValueStore as _};
+
+    #[tokio::test]
+    async fn test_root_key_states_pruned() {
+        let store = JournalingKeyValueStore::new(MemoryStore::new_test_store().await.unwrap());
+        let store1 = store.clone_with_root_key(&[1]).unwrap();
+        let store2 = store1.clone_with_root_key(&[1]).unwrap();
+        assert!(Arc::ptr_eq(&store1.root_key_state, &store2.root_key_state));
+        drop((store1, store2));
+        // The state of `[1]` is no longer used, so its entry goes away.
+        let _store3 = store.clone_with_root_key(&[2]).unwrap();
+        let root_key_states = store.root_key_states.lock().unwrap();
+        assert_eq!(root_key_states.keys().collect::<Vec<_>>(), vec![&vec![2]]);
+    }
 }
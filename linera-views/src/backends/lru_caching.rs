@@ -6,7 +6,7 @@
 #[cfg(with_metrics)]
 use std::sync::LazyLock;
 use std::{
-    collections::{btree_map, hash_map::RandomState, BTreeMap},
+    collections::{btree_map, hash_map::RandomState, BTreeMap, BTreeSet, HashSet},
     sync::{Arc, Mutex},
 };
 
@@ -17,8 +17,11 @@ use {linera_base::prometheus_util::register_int_counter_vec, prometheus::IntCoun
 
 use crate::{
     batch::{Batch, WriteOperation},
-    common::get_interval,
-    store::{AdminKeyValueStore, ReadableKeyValueStore, WithError, WritableKeyValueStore},
+    common::{contains_prefix_of, get_interval, insert_key_prefix},
+    store::{
+        AdminKeyValueStore, KeyIterable as _, KeyValueIterable as _, ReadableKeyValueStore,
+        WithError, WritableKeyValueStore,
+    },
 };
 #[cfg(with_testing)]
 use crate::{memory::MemoryStore, store::TestKeyValueStore};
@@ -63,6 +66,36 @@ static CONTAINS_KEY_CACHE_HIT_COUNT: LazyLock<IntCounterVec> = LazyLock::new(||
     )
 });
 
+#[cfg(with_metrics)]
+/// The total number of find keys by prefix cache misses
+static FIND_KEYS_CACHE_MISS_COUNT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec(
+        "num_find_keys_cache_miss",
+        "Number of find keys by prefix cache misses",
+        &[],
+    )
+});
+
+#[cfg(with_metrics)]
+/// The total number of find keys by prefix cache hits
+static FIND_KEYS_CACHE_HIT_COUNT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec(
+        "num_find_keys_cache_hits",
+        "Number of find keys by prefix cache hits",
+        &[],
+    )
+});
+
+#[cfg(with_metrics)]
+/// The total number of puts skipped because they did not change the stored value
+static UNCHANGED_PUTS_SKIPPED_COUNT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec(
+        "num_unchanged_puts_skipped",
+        "Number of puts skipped because they did not change the stored value",
+        &[],
+    )
+});
+
 /// The parametrization of the cache
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StorageCacheConfig {
@@ -169,6 +202,13 @@ impl LruPrefixCache {
         self.trim_cache();
     }
 
+    /// Returns whether an entry of the given size can be added without evicting any
+    /// other entry.
+    fn has_room_for(&self, key_value_size: usize) -> bool {
+        self.queue.len() < self.storage_cache_config.max_cache_entries
+            && self.total_size + key_value_size <= self.storage_cache_config.max_cache_size
+    }
+
     /// Inserts a read_value entry into the cache.
     pub fn insert_read_value(&mut self, key: Vec<u8>, value: &Option<Vec<u8>>) {
         let cache_entry = match value {
@@ -226,6 +266,16 @@ impl LruPrefixCache {
         }
     }
 
+    /// Returns the length of the cached value, or `Some(None)` if the entry does not exist
+    /// in the database. If `None` is returned, the length is not known from the cache.
+    pub fn query_value_len(&self, key: &[u8]) -> Option<Option<usize>> {
+        match self.map.get(key)? {
+            CacheEntry::DoesNotExist => Some(None),
+            CacheEntry::Exists => None,
+            CacheEntry::Value(vec) => Some(Some(vec.len())),
+        }
+    }
+
     /// Returns `Some(true)` or `Some(false)` if we know that the entry does or does not
     /// exist in the database. Returns `None` if that information is not in the cache.
     pub fn query_contains_key(&self, key: &[u8]) -> Option<bool> {
@@ -235,6 +285,144 @@ impl LruPrefixCache {
     }
 }
 
+/// Stores the results of `find_keys_by_prefix` queries, by key prefix.
+///
+/// A result is dropped as soon as a batch writes a key under its prefix.
+struct LruKeysCache {
+    map: BTreeMap<Vec<u8>, Vec<Vec<u8>>>,
+    queue: LinkedHashMap<Vec<u8>, usize, RandomState>,
+    storage_cache_config: StorageCacheConfig,
+    total_size: usize,
+    /// The number of batches written, so that a result read concurrently with a write
+    /// is not cached.
+    generation: u64,
+}
+
+/// The keys and the key prefixes written by a batch, whose cached keys are dropped.
+struct KeysCacheInvalidation {
+    keys: Vec<Vec<u8>>,
+    key_prefixes: Vec<Vec<u8>>,
+}
+
+impl KeysCacheInvalidation {
+    fn new(batch: &Batch) -> Self {
+        let mut keys = Vec::new();
+        let mut key_prefixes = Vec::new();
+        for operation in &batch.operations {
+            match operation {
+                WriteOperation::Put { key, .. } | WriteOperation::Delete { key } => {
+                    keys.push(key.clone());
+                }
+                WriteOperation::DeletePrefix { key_prefix } => {
+                    key_prefixes.push(key_prefix.clone());
+                }
+            }
+        }
+        Self { keys, key_prefixes }
+    }
+
+    /// Drops the cached keys and starts a new generation, so that the keys being read
+    /// concurrently are not cached.
+    fn apply(&self, keys_cache: &mut LruKeysCache) {
+        keys_cache.generation += 1;
+        for key in &self.keys {
+            keys_cache.invalidate_key(key);
+        }
+        for key_prefix in &self.key_prefixes {
+            keys_cache.invalidate_prefix(key_prefix);
+        }
+    }
+}
+
+impl LruKeysCache {
+    /// Creates an `LruKeysCache`.
+    fn new(storage_cache_config: StorageCacheConfig) -> Self {
+        Self {
+            map: BTreeMap::new(),
+            queue: LinkedHashMap::new(),
+            storage_cache_config,
+            total_size: 0,
+            generation: 0,
+        }
+    }
+
+    /// Returns the cached keys under `key_prefix`, if any, and marks them as the most
+    /// recently used.
+    fn query(&mut self, key_prefix: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let keys = self.map.get(key_prefix)?.clone();
+        self.queue.get_refresh(key_prefix);
+        Some(keys)
+    }
+
+    /// Inserts the keys under `key_prefix`, read while the generation was `generation`.
+    fn insert(&mut self, key_prefix: Vec<u8>, keys: Vec<Vec<u8>>, generation: u64) {
+        let size = key_prefix.len() + keys.iter().map(Vec::len).sum::<usize>();
+        if generation != self.generation || size > self.storage_cache_config.max_entry_size {
+            return;
+        }
+        self.remove(&key_prefix);
+        self.map.insert(key_prefix.clone(), keys);
+        self.queue.insert(key_prefix, size);
+        self.total_size += size;
+        while self.total_size > self.storage_cache_config.max_cache_size
+            || self.queue.len() > self.storage_cache_config.max_cache_entries
+        {
+            let Some((key_prefix, size)) = self.queue.pop_front() else {
+                break;
+            };
+            self.map.remove(&key_prefix);
+            self.total_size -= size;
+        }
+    }
+
+    fn remove(&mut self, key_prefix: &[u8]) {
+        if let Some(size) = self.queue.remove(key_prefix) {
+            self.map.remove(key_prefix);
+            self.total_size -= size;
+        }
+    }
+
+    /// Drops the results under the prefixes of `key`, which is written.
+    fn invalidate_key(&mut self, key: &[u8]) {
+        for len in 0..=key.len() {
+            self.remove(&key[..len]);
+        }
+    }
+
+    /// Drops the results under the prefixes of `key_prefix` and under the prefixes that
+    /// it is a prefix of, since all their keys under `key_prefix` are deleted.
+    fn invalidate_prefix(&mut self, key_prefix: &[u8]) {
+        self.invalidate_key(key_prefix);
+        let extensions = self
+            .map
+            .range(get_interval(key_prefix.to_vec()))
+            .map(|(cached_prefix, _)| cached_prefix.clone())
+            .collect::<Vec<_>>();
+        for cached_prefix in extensions {
+            self.remove(&cached_prefix);
+        }
+    }
+}
+
+/// Whether `write_batch` skips the puts of a value equal to the stored one.
+///
+/// Skipping a put saves the write at the cost of a comparison, and possibly of a read,
+/// which is only worth it when the writes are the scarce resource, e.g. for views that
+/// are often recomputed and written back unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnchangedPuts {
+    /// Every put is written.
+    #[default]
+    Write,
+    /// The puts of a value equal to the one in the cache are skipped.
+    SkipCached,
+    /// The puts of a value equal to the stored one are skipped. The values that are not
+    /// in the cache are read from the store, with a single multi-key read per batch.
+    /// Since the stored value may be changed concurrently between the read and the
+    /// write, this is only correct for a store with a single writer.
+    SkipStored,
+}
+
 /// We take a store, a maximum size and build a LRU-based system.
 #[derive(Clone)]
 pub struct LruCachingStore<K> {
@@ -242,6 +430,10 @@ pub struct LruCachingStore<K> {
     store: K,
     /// The LRU cache of values.
     cache: Option<Arc<Mutex<LruPrefixCache>>>,
+    /// The LRU cache of the results of `find_keys_by_prefix`, if enabled.
+    keys_cache: Option<Arc<Mutex<LruKeysCache>>>,
+    /// Whether the puts of unchanged values are skipped.
+    unchanged_puts: UnchangedPuts,
 }
 
 impl<K> WithError for LruCachingStore<K>
@@ -257,7 +449,7 @@ where
 {
     // The LRU cache does not change the underlying store's size limits.
     const MAX_KEY_SIZE: usize = K::MAX_KEY_SIZE;
-    type Keys = K::Keys;
+    type Keys = Vec<Vec<u8>>;
     type KeyValues = K::KeyValues;
 
     fn max_stream_queries(&self) -> usize {
@@ -384,7 +576,31 @@ where
     }
 
     async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
-        self.store.find_keys_by_prefix(key_prefix).await
+        let Some(keys_cache) = &self.keys_cache else {
+            let keys = self.store.find_keys_by_prefix(key_prefix).await?;
+            return keys.iterator().map(|key| key.map(<[u8]>::to_vec)).collect();
+        };
+        let generation = {
+            let mut keys_cache = keys_cache.lock().unwrap();
+            if let Some(keys) = keys_cache.query(key_prefix) {
+                #[cfg(with_metrics)]
+                FIND_KEYS_CACHE_HIT_COUNT.with_label_values(&[]).inc();
+                return Ok(keys);
+            }
+            keys_cache.generation
+        };
+        #[cfg(with_metrics)]
+        FIND_KEYS_CACHE_MISS_COUNT.with_label_values(&[]).inc();
+        let keys = self
+            .store
+            .find_keys_by_prefix(key_prefix)
+            .await?
+            .iterator()
+            .map(|key| key.map(<[u8]>::to_vec))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut keys_cache = keys_cache.lock().unwrap();
+        keys_cache.insert(key_prefix.to_vec(), keys.clone(), generation);
+        Ok(keys)
     }
 
     async fn find_key_values_by_prefix(
@@ -397,17 +613,27 @@ where
 
 impl<K> WritableKeyValueStore for LruCachingStore<K>
 where
-    K: WritableKeyValueStore + Send + Sync,
+    K: ReadableKeyValueStore + WritableKeyValueStore + Send + Sync,
 {
     // The LRU cache does not change the underlying store's size limits.
     const MAX_VALUE_SIZE: usize = K::MAX_VALUE_SIZE;
 
     async fn write_batch(&self, batch: Batch) -> Result<(), Self::Error> {
-        let Some(cache) = &self.cache else {
-            return self.store.write_batch(batch).await;
+        let batch = match self.unchanged_puts {
+            UnchangedPuts::Write => batch,
+            UnchangedPuts::SkipCached | UnchangedPuts::SkipStored => {
+                self.remove_unchanged_puts(batch).await?
+            }
         };
-
-        {
+        // The cached keys are dropped before the write, and again after it, since a
+        // `find_keys_by_prefix` running concurrently may have cached the keys read before
+        // the write landed.
+        let keys_cache_invalidation = self.keys_cache.as_ref().map(|keys_cache| {
+            let invalidation = KeysCacheInvalidation::new(&batch);
+            invalidation.apply(&mut keys_cache.lock().unwrap());
+            invalidation
+        });
+        if let Some(cache) = &self.cache {
             let mut cache = cache.lock().unwrap();
             for operation in &batch.operations {
                 match operation {
@@ -425,12 +651,21 @@ where
                 }
             }
         }
-        self.store.write_batch(batch).await
+        self.store.write_batch(batch).await?;
+        if let (Some(keys_cache), Some(invalidation)) = (&self.keys_cache, keys_cache_invalidation)
+        {
+            invalidation.apply(&mut keys_cache.lock().unwrap());
+        }
+        Ok(())
     }
 
     async fn clear_journal(&self) -> Result<(), Self::Error> {
         self.store.clear_journal().await
     }
+
+    async fn flush(&self) -> Result<(), Self::Error> {
+        self.store.flush().await
+    }
 }
 
 /// The configuration type for the `LruCachingStore`.
@@ -462,7 +697,12 @@ where
 
     fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, Self::Error> {
         let store = self.store.clone_with_root_key(root_key)?;
-        let store = LruCachingStore::new(store, self.storage_cache_config());
+        let mut store = LruCachingStore::new(store, self.storage_cache_config())
+            .with_unchanged_puts(self.unchanged_puts);
+        if let Some(keys_cache) = &self.keys_cache {
+            let storage_cache_config = keys_cache.lock().unwrap().storage_cache_config.clone();
+            store = store.with_keys_cache(storage_cache_config);
+        }
         store.enable_exclusive_access();
         Ok(store)
     }
@@ -522,7 +762,107 @@ impl<K> LruCachingStore<K> {
                 ))))
             }
         };
-        Self { store, cache }
+        Self {
+            store,
+            cache,
+            keys_cache: None,
+            unchanged_puts: UnchangedPuts::Write,
+        }
+    }
+
+    /// Enables the caching of the results of `find_keys_by_prefix`, bounded by
+    /// `storage_cache_config` independently of the cache of values: an entry is the list
+    /// of the keys under a prefix.
+    ///
+    /// A result is dropped when a batch written through this store writes or deletes a
+    /// key under its prefix. Since the writes of other stores are not seen, this is only
+    /// correct for a store with a single writer, and a stale list of keys can be worse
+    /// than a stale value, hence the opt-in.
+    pub fn with_keys_cache(mut self, storage_cache_config: StorageCacheConfig) -> Self {
+        self.keys_cache = (storage_cache_config.max_cache_entries > 0)
+            .then(|| Arc::new(Mutex::new(LruKeysCache::new(storage_cache_config))));
+        self
+    }
+
+    /// Sets whether `write_batch` skips the puts of a value equal to the stored one.
+    pub fn with_unchanged_puts(mut self, unchanged_puts: UnchangedPuts) -> Self {
+        self.unchanged_puts = unchanged_puts;
+        self
+    }
+
+    /// Removes from the `batch` the puts that would not change the stored value,
+    /// according to `unchanged_puts`.
+    ///
+    /// Only the puts of keys not written nor deleted by an earlier operation of the batch
+    /// are considered, since the stored value is the one before the batch.
+    async fn remove_unchanged_puts(&self, batch: Batch) -> Result<Batch, K::Error>
+    where
+        K: ReadableKeyValueStore,
+    {
+        let mut written_keys = HashSet::new();
+        let mut deleted_prefixes = BTreeSet::new();
+        let mut candidates = Vec::new();
+        for (index, operation) in batch.operations.iter().enumerate() {
+            match operation {
+                WriteOperation::Put { key, .. } => {
+                    if !written_keys.contains(key) && !contains_prefix_of(&deleted_prefixes, key) {
+                        candidates.push(index);
+                    }
+                    written_keys.insert(key.clone());
+                }
+                WriteOperation::Delete { key } => {
+                    written_keys.insert(key.clone());
+                }
+                WriteOperation::DeletePrefix { key_prefix } => {
+                    insert_key_prefix(&mut deleted_prefixes, key_prefix.clone());
+                }
+            }
+        }
+        let put_of = |index: usize| match &batch.operations[index] {
+            WriteOperation::Put { key, value } => (key, value),
+            _ => unreachable!("the candidates are puts"),
+        };
+        let mut unchanged = vec![false; batch.operations.len()];
+        let mut uncached = Vec::new();
+        match &self.cache {
+            None => uncached = candidates,
+            Some(cache) => {
+                let cache = cache.lock().unwrap();
+                for index in candidates {
+                    let (key, value) = put_of(index);
+                    match cache.map.get(key) {
+                        Some(CacheEntry::Value(cached_value)) => {
+                            unchanged[index] = cached_value == value;
+                        }
+                        Some(CacheEntry::DoesNotExist) => {}
+                        Some(CacheEntry::Exists) | None => uncached.push(index),
+                    }
+                }
+            }
+        }
+        if self.unchanged_puts == UnchangedPuts::SkipStored && !uncached.is_empty() {
+            let keys = uncached
+                .iter()
+                .map(|index| put_of(*index).0.clone())
+                .collect();
+            let stored_values = self.store.read_multi_values_bytes(keys).await?;
+            for (index, stored_value) in uncached.into_iter().zip(stored_values) {
+                unchanged[index] = stored_value.as_ref() == Some(put_of(index).1);
+            }
+        }
+        #[cfg(with_metrics)]
+        let num_operations = batch.operations.len();
+        let operations = batch
+            .operations
+            .into_iter()
+            .zip(unchanged)
+            .filter_map(|(operation, unchanged)| (!unchanged).then_some(operation))
+            .collect::<Vec<_>>();
+        #[cfg(with_metrics)]
+        UNCHANGED_PUTS_SKIPPED_COUNT
+            .with_label_values(&[])
+            .inc_by((num_operations - operations.len()) as u64);
+        Ok(Batch { operations })
     }
 
     /// Gets the `cache_size`.
@@ -540,6 +880,68 @@ impl<K> LruCachingStore<K> {
         }
     }
 
+    /// Returns the length of the value stored at `key`, if any.
+    ///
+    /// If the value is in the cache, no request to the underlying store is made.
+    /// Otherwise, the value is read and dropped immediately without being cached.
+    pub async fn value_len(&self, key: &[u8]) -> Result<Option<usize>, K::Error>
+    where
+        K: ReadableKeyValueStore,
+    {
+        if let Some(cache) = &self.cache {
+            let cache = cache.lock().unwrap();
+            if let Some(value_len) = cache.query_value_len(key) {
+                return Ok(value_len);
+            }
+        }
+        let value = self.store.read_value_bytes(key).await?;
+        Ok(value.map(|value| value.len()))
+    }
+
+    /// Reads the key-values under `key_prefix` and loads them into the cache. Returns
+    /// the number of entries that were loaded.
+    ///
+    /// Warming stops as soon as the cache is full, so that it never evicts the entries
+    /// already in the cache. Values larger than `max_entry_size` are skipped.
+    pub async fn warm_prefix(&self, key_prefix: &[u8]) -> Result<usize, K::Error>
+    where
+        K: ReadableKeyValueStore,
+    {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+        let key_values = self.store.find_key_values_by_prefix(key_prefix).await?;
+        let mut cache = cache.lock().unwrap();
+        let mut count = 0;
+        for key_value in key_values.iterator() {
+            let (suffix, value) = key_value?;
+            let key_value_size = key_prefix.len() + suffix.len() + value.len();
+            if key_value_size > cache.storage_cache_config.max_entry_size {
+                continue;
+            }
+            if !cache.has_room_for(key_value_size) {
+                break;
+            }
+            let key = [key_prefix, suffix].concat();
+            cache.insert(key, CacheEntry::Value(value.to_vec()));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns whether the cache holds an entry for `key`, be it the value, or only
+    /// whether the key exists. This does not count as an access of the entry: its
+    /// position in the LRU order is unchanged.
+    pub fn is_cached(&self, key: &[u8]) -> bool {
+        match &self.cache {
+            None => false,
+            Some(cache) => {
+                let cache = cache.lock().unwrap();
+                cache.map.contains_key(key)
+            }
+        }
+    }
+
     /// Sets the value `has_exclusive_access` to `true`, if applicable.
     pub fn enable_exclusive_access(&self) {
         if let Some(cache) = &self.cache {
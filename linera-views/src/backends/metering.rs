@@ -434,6 +434,10 @@ where
         let _metric = self.counter.clear_journal_latency.measure_latency();
         self.store.clear_journal().await
     }
+
+    async fn flush(&self) -> Result<(), Self::Error> {
+        self.store.flush().await
+    }
 }
 
 impl<K> AdminKeyValueStore for MeteredStore<K>
@@ -8,6 +8,11 @@ pub mod metering;
 
 pub mod value_splitting;
 
+pub mod hash_prefix;
+
+#[cfg(with_packing)]
+pub mod packing;
+
 pub mod memory;
 
 pub mod lru_caching;
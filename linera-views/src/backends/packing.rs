@@ -0,0 +1,661 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Packs small values whose keys share a prefix into a single value of a given store.
+//!
+//! Stores like DynamoDB have a minimal cost per item for reads, writes and storage, so
+//! that a view made of many tiny values is dominated by the per-item overhead. The keys
+//! longer than `prefix_len` are grouped by their first `prefix_len` bytes into buckets,
+//! and each bucket is stored as one value of the underlying store. The values that do
+//! not fit in the bucket are stored under their own key and marked as unpacked in the
+//! bucket.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use linera_base::ensure;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(with_testing)]
+use crate::store::TestKeyValueStore;
+use crate::{
+    batch::{Batch, WriteOperation},
+    store::{
+        AdminKeyValueStore, KeyValueIterable, KeyValueStoreError, ReadableKeyValueStore, WithError,
+        WritableKeyValueStore,
+    },
+};
+
+/// The tag following the prefix of a bucket in the key of the bucket.
+const BUCKET_TAG: u8 = 0;
+
+/// The tag following the prefix of a bucket in the keys of its unpacked values.
+const UNPACKED_TAG: u8 = 1;
+
+/// The composed error type built from the inner error type.
+#[derive(Error, Debug)]
+pub enum PackingError<E> {
+    /// inner store error
+    #[error(transparent)]
+    InnerStoreError(#[from] E),
+
+    /// A value marked as unpacked in its bucket is missing from the database
+    #[error("unpacked value is missing from the database")]
+    MissingUnpackedValue,
+}
+
+impl<E: KeyValueStoreError> From<bcs::Error> for PackingError<E> {
+    fn from(error: bcs::Error) -> Self {
+        let error = E::from(error);
+        PackingError::InnerStoreError(error)
+    }
+}
+
+impl<E: KeyValueStoreError + 'static> KeyValueStoreError for PackingError<E> {
+    const BACKEND: &'static str = "packing";
+}
+
+/// The parametrization of the packing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackingParameters {
+    /// The length of the prefix shared by the keys of a bucket. Shorter keys are not packed.
+    pub prefix_len: usize,
+    /// The maximal size of a serialized bucket, in bytes. It must not exceed the maximal
+    /// value size of the underlying store.
+    pub max_bucket_size: usize,
+}
+
+/// The configuration type for the `PackingStore`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackingConfig<C> {
+    /// The inner configuration of the `PackingStore`.
+    pub inner_config: C,
+    /// The parameters of the packing.
+    pub packing_parameters: PackingParameters,
+}
+
+/// A value of a bucket.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum PackedValue {
+    /// The value is stored in the bucket.
+    Packed(Vec<u8>),
+    /// The value is stored under its own key.
+    Unpacked,
+}
+
+/// The values of a bucket, indexed by the keys without the prefix of the bucket.
+type Bucket = BTreeMap<Vec<u8>, PackedValue>;
+
+/// A bucket being modified by a batch.
+struct BucketState {
+    /// The values of the bucket.
+    entries: Bucket,
+    /// The keys of the values that are stored unpacked in the database.
+    stored_unpacked: BTreeSet<Vec<u8>>,
+    /// Whether the bucket has to be written.
+    modified: bool,
+}
+
+/// A key-value store that packs small values sharing a key prefix into one value.
+///
+/// It wraps a key-value store and groups the keys longer than `prefix_len` by their
+/// prefix. Each group is stored as a single value of the wrapped store, as long as its
+/// serialized size stays below `max_bucket_size`. Beyond that, the largest values of the
+/// group are stored under their own keys.
+#[derive(Clone)]
+pub struct PackingStore<K> {
+    /// The underlying store of the transformed store.
+    store: K,
+    /// The parameters of the packing.
+    packing_parameters: PackingParameters,
+}
+
+impl<K> WithError for PackingStore<K>
+where
+    K: WithError,
+    K::Error: 'static,
+{
+    type Error = PackingError<K::Error>;
+}
+
+impl<K> ReadableKeyValueStore for PackingStore<K>
+where
+    K: ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    const MAX_KEY_SIZE: usize = K::MAX_KEY_SIZE - 1;
+    type Keys = Vec<Vec<u8>>;
+    type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn max_stream_queries(&self) -> usize {
+        self.store.max_stream_queries()
+    }
+
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        let Some((prefix, suffix)) = self.split_key(key) else {
+            return Ok(self.store.read_value_bytes(key).await?);
+        };
+        let mut entries = self.read_bucket(prefix).await?;
+        match entries.remove(suffix) {
+            None => Ok(None),
+            Some(PackedValue::Packed(value)) => Ok(Some(value)),
+            Some(PackedValue::Unpacked) => {
+                let unpacked_key = Self::get_unpacked_key(prefix, suffix);
+                let value = self.store.read_value_bytes(&unpacked_key).await?;
+                Ok(Some(value.ok_or(PackingError::MissingUnpackedValue)?))
+            }
+        }
+    }
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        let Some((prefix, suffix)) = self.split_key(key) else {
+            return Ok(self.store.contains_key(key).await?);
+        };
+        Ok(self.read_bucket(prefix).await?.contains_key(suffix))
+    }
+
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
+        let buckets = self.read_buckets(&keys).await?;
+        let short_keys = keys
+            .iter()
+            .filter(|key| self.split_key(key).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut short_results = self.store.contains_keys(short_keys).await?.into_iter();
+        let mut results = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let result = match self.split_key(key) {
+                None => short_results.next().unwrap(),
+                Some((prefix, suffix)) => buckets[prefix].contains_key(suffix),
+            };
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        let buckets = self.read_buckets(&keys).await?;
+        let mut values = Vec::with_capacity(keys.len());
+        // The keys read from the underlying store, with their index and whether they
+        // are the keys of unpacked values.
+        let mut inner_keys = Vec::new();
+        let mut positions = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            match self.split_key(key) {
+                None => {
+                    inner_keys.push(key.clone());
+                    positions.push((index, false));
+                    values.push(None);
+                }
+                Some((prefix, suffix)) => match buckets[prefix].get(suffix) {
+                    None => values.push(None),
+                    Some(PackedValue::Packed(value)) => values.push(Some(value.clone())),
+                    Some(PackedValue::Unpacked) => {
+                        inner_keys.push(Self::get_unpacked_key(prefix, suffix));
+                        positions.push((index, true));
+                        values.push(None);
+                    }
+                },
+            }
+        }
+        let inner_values = self.store.read_multi_values_bytes(inner_keys).await?;
+        for ((index, unpacked), value) in positions.into_iter().zip(inner_values) {
+            ensure!(
+                !unpacked || value.is_some(),
+                PackingError::MissingUnpackedValue
+            );
+            values[index] = value;
+        }
+        Ok(values)
+    }
+
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
+        let Some((prefix, sub_prefix)) = self.split_key(key_prefix) else {
+            // The buckets have to be read anyway, so the keys come from a scan of the values.
+            let key_values = self.find_key_values_by_prefix(key_prefix).await?;
+            return Ok(key_values.into_iter().map(|(key, _)| key).collect());
+        };
+        let entries = self.read_bucket(prefix).await?;
+        Ok(entries
+            .into_keys()
+            .filter_map(|suffix| suffix.strip_prefix(sub_prefix).map(<[u8]>::to_vec))
+            .collect())
+    }
+
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Self::KeyValues, Self::Error> {
+        let Some((prefix, sub_prefix)) = self.split_key(key_prefix) else {
+            return self.find_key_values_by_short_prefix(key_prefix).await;
+        };
+        let entries = self
+            .read_bucket(prefix)
+            .await?
+            .into_iter()
+            .filter(|(suffix, _)| suffix.starts_with(sub_prefix))
+            .collect::<Vec<_>>();
+        let unpacked_keys = entries
+            .iter()
+            .filter(|(_, value)| matches!(value, PackedValue::Unpacked))
+            .map(|(suffix, _)| Self::get_unpacked_key(prefix, suffix))
+            .collect();
+        let mut unpacked_values = self
+            .store
+            .read_multi_values_bytes(unpacked_keys)
+            .await?
+            .into_iter();
+        let mut key_values = Vec::with_capacity(entries.len());
+        for (suffix, value) in entries {
+            let value = match value {
+                PackedValue::Packed(value) => value,
+                PackedValue::Unpacked => unpacked_values
+                    .next()
+                    .flatten()
+                    .ok_or(PackingError::MissingUnpackedValue)?,
+            };
+            key_values.push((suffix[sub_prefix.len()..].to_vec(), value));
+        }
+        Ok(key_values)
+    }
+}
+
+impl<K> WritableKeyValueStore for PackingStore<K>
+where
+    K: WritableKeyValueStore + ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    const MAX_VALUE_SIZE: usize = K::MAX_VALUE_SIZE;
+
+    async fn write_batch(&self, batch: Batch) -> Result<(), Self::Error> {
+        // The buckets touched by the batch are loaded first, so that the operations can
+        // be applied to them in order.
+        let prefixes = batch
+            .operations
+            .iter()
+            .filter_map(|operation| match operation {
+                WriteOperation::Delete { key } | WriteOperation::Put { key, .. } => {
+                    self.split_key(key)
+                }
+                WriteOperation::DeletePrefix { key_prefix } => self.split_key(key_prefix),
+            })
+            .map(|(prefix, _)| prefix.to_vec())
+            .collect::<BTreeSet<_>>();
+        let bucket_keys = prefixes.iter().map(|prefix| Self::get_bucket_key(prefix));
+        let values = self
+            .store
+            .read_multi_values_bytes(bucket_keys.collect())
+            .await?;
+        let mut buckets = BTreeMap::new();
+        for (prefix, value) in prefixes.into_iter().zip(values) {
+            let entries = Self::decode_bucket(value)?;
+            let stored_unpacked = entries
+                .iter()
+                .filter(|(_, value)| matches!(value, PackedValue::Unpacked))
+                .map(|(suffix, _)| suffix.clone())
+                .collect();
+            let state = BucketState {
+                entries,
+                stored_unpacked,
+                modified: false,
+            };
+            buckets.insert(prefix, state);
+        }
+        let mut batch_new = Batch::new();
+        for operation in batch.operations {
+            match operation {
+                WriteOperation::Delete { key } => match self.split_key(&key) {
+                    None => batch_new.delete_key(key),
+                    Some((prefix, suffix)) => {
+                        let state = buckets.get_mut(prefix).unwrap();
+                        state.entries.remove(suffix);
+                        state.modified = true;
+                    }
+                },
+                WriteOperation::Put { key, value } => match self.split_key(&key) {
+                    None => batch_new.put_key_value_bytes(key, value),
+                    Some((prefix, suffix)) => {
+                        let state = buckets.get_mut(prefix).unwrap();
+                        state
+                            .entries
+                            .insert(suffix.to_vec(), PackedValue::Packed(value));
+                        state.modified = true;
+                    }
+                },
+                WriteOperation::DeletePrefix { key_prefix } => {
+                    match self.split_key(&key_prefix) {
+                        None => {
+                            // The deletion covers whole buckets with their unpacked values.
+                            for (prefix, state) in &mut buckets {
+                                if prefix.starts_with(&key_prefix) {
+                                    state.entries.clear();
+                                    state.stored_unpacked.clear();
+                                    state.modified = false;
+                                }
+                            }
+                            batch_new.delete_key_prefix(key_prefix);
+                        }
+                        Some((prefix, sub_prefix)) => {
+                            let state = buckets.get_mut(prefix).unwrap();
+                            state
+                                .entries
+                                .retain(|suffix, _| !suffix.starts_with(sub_prefix));
+                            state.modified = true;
+                        }
+                    }
+                }
+            }
+        }
+        for (prefix, state) in buckets {
+            if state.modified {
+                self.write_bucket(&mut batch_new, &prefix, state)?;
+            }
+        }
+        Ok(self.store.write_batch(batch_new).await?)
+    }
+
+    async fn clear_journal(&self) -> Result<(), Self::Error> {
+        Ok(self.store.clear_journal().await?)
+    }
+
+    async fn flush(&self) -> Result<(), Self::Error> {
+        Ok(self.store.flush().await?)
+    }
+}
+
+impl<K> AdminKeyValueStore for PackingStore<K>
+where
+    K: AdminKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    type Config = PackingConfig<K::Config>;
+
+    fn get_name() -> String {
+        format!("packing {}", K::get_name())
+    }
+
+    async fn connect(config: &Self::Config, namespace: &str) -> Result<Self, Self::Error> {
+        let store = K::connect(&config.inner_config, namespace).await?;
+        Ok(Self::new(store, config.packing_parameters.clone()))
+    }
+
+    fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, Self::Error> {
+        let store = self.store.clone_with_root_key(root_key)?;
+        Ok(Self::new(store, self.packing_parameters.clone()))
+    }
+
+    async fn list_all(config: &Self::Config) -> Result<Vec<String>, Self::Error> {
+        Ok(K::list_all(&config.inner_config).await?)
+    }
+
+    async fn list_root_keys(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(K::list_root_keys(&config.inner_config, namespace).await?)
+    }
+
+    async fn delete_all(config: &Self::Config) -> Result<(), Self::Error> {
+        Ok(K::delete_all(&config.inner_config).await?)
+    }
+
+    async fn exists(config: &Self::Config, namespace: &str) -> Result<bool, Self::Error> {
+        Ok(K::exists(&config.inner_config, namespace).await?)
+    }
+
+    async fn create(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(K::create(&config.inner_config, namespace).await?)
+    }
+
+    async fn delete(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(K::delete(&config.inner_config, namespace).await?)
+    }
+}
+
+#[cfg(with_testing)]
+impl<K> TestKeyValueStore for PackingStore<K>
+where
+    K: TestKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    async fn new_test_config() -> Result<PackingConfig<K::Config>, Self::Error> {
+        let inner_config = K::new_test_config().await?;
+        // Short prefixes and small buckets, so that the tests exercise the unpacked values.
+        let packing_parameters = PackingParameters {
+            prefix_len: 2,
+            max_bucket_size: 1000,
+        };
+        Ok(PackingConfig {
+            inner_config,
+            packing_parameters,
+        })
+    }
+}
+
+impl<K> PackingStore<K>
+where
+    K: WithError,
+{
+    /// Creates a new store that packs the small values of the given one.
+    pub fn new(store: K, packing_parameters: PackingParameters) -> Self {
+        PackingStore {
+            store,
+            packing_parameters,
+        }
+    }
+
+    /// Splits a key into the prefix of its bucket and the rest, if it is long enough to
+    /// be packed.
+    fn split_key<'a>(&self, key: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        (key.len() > self.packing_parameters.prefix_len)
+            .then(|| key.split_at(self.packing_parameters.prefix_len))
+    }
+
+    fn get_bucket_key(prefix: &[u8]) -> Vec<u8> {
+        let mut bucket_key = prefix.to_vec();
+        bucket_key.push(BUCKET_TAG);
+        bucket_key
+    }
+
+    fn get_unpacked_key(prefix: &[u8], suffix: &[u8]) -> Vec<u8> {
+        let mut unpacked_key = prefix.to_vec();
+        unpacked_key.push(UNPACKED_TAG);
+        unpacked_key.extend(suffix);
+        unpacked_key
+    }
+
+    fn decode_bucket(value: Option<Vec<u8>>) -> Result<Bucket, PackingError<K::Error>> {
+        match value {
+            None => Ok(Bucket::new()),
+            Some(value) => Ok(bcs::from_bytes(&value)?),
+        }
+    }
+
+    /// Adds to `batch` the operations writing the bucket of the given `prefix`.
+    ///
+    /// If the serialized bucket is larger than `max_bucket_size`, its largest values are
+    /// stored under their own keys until it fits.
+    fn write_bucket(
+        &self,
+        batch: &mut Batch,
+        prefix: &[u8],
+        mut state: BucketState,
+    ) -> Result<(), PackingError<K::Error>> {
+        let mut size = bcs::serialized_size(&state.entries)?;
+        if size > self.packing_parameters.max_bucket_size {
+            let mut packed = state
+                .entries
+                .iter()
+                .filter_map(|(suffix, value)| match value {
+                    PackedValue::Packed(value) => Some((value.len(), suffix.clone())),
+                    PackedValue::Unpacked => None,
+                })
+                .collect::<Vec<_>>();
+            packed.sort_unstable_by(|(len1, _), (len2, _)| len2.cmp(len1));
+            for (_, suffix) in packed {
+                if size <= self.packing_parameters.max_bucket_size {
+                    break;
+                }
+                let Some(PackedValue::Packed(value)) =
+                    state.entries.insert(suffix.clone(), PackedValue::Unpacked)
+                else {
+                    continue;
+                };
+                // Both variants have a tag of the same size, so the bucket shrinks by
+                // the size of the value.
+                size -= bcs::serialized_size(&value)?;
+                batch.put_key_value_bytes(Self::get_unpacked_key(prefix, &suffix), value);
+            }
+        }
+        for suffix in state.stored_unpacked {
+            if !matches!(state.entries.get(&suffix), Some(PackedValue::Unpacked)) {
+                batch.delete_key(Self::get_unpacked_key(prefix, &suffix));
+            }
+        }
+        let bucket_key = Self::get_bucket_key(prefix);
+        if state.entries.is_empty() {
+            batch.delete_key(bucket_key);
+        } else {
+            batch.put_key_value_bytes(bucket_key, bcs::to_bytes(&state.entries)?);
+        }
+        Ok(())
+    }
+}
+
+impl<K> PackingStore<K>
+where
+    K: ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    async fn read_bucket(&self, prefix: &[u8]) -> Result<Bucket, PackingError<K::Error>> {
+        let value = self
+            .store
+            .read_value_bytes(&Self::get_bucket_key(prefix))
+            .await?;
+        Self::decode_bucket(value)
+    }
+
+    /// Reads the buckets of the keys that are long enough to be packed.
+    async fn read_buckets(
+        &self,
+        keys: &[Vec<u8>],
+    ) -> Result<BTreeMap<Vec<u8>, Bucket>, PackingError<K::Error>> {
+        let prefixes = keys
+            .iter()
+            .filter_map(|key| self.split_key(key))
+            .map(|(prefix, _)| prefix.to_vec())
+            .collect::<BTreeSet<_>>();
+        let bucket_keys = prefixes.iter().map(|prefix| Self::get_bucket_key(prefix));
+        let values = self
+            .store
+            .read_multi_values_bytes(bucket_keys.collect())
+            .await?;
+        let mut buckets = BTreeMap::new();
+        for (prefix, value) in prefixes.into_iter().zip(values) {
+            buckets.insert(prefix, Self::decode_bucket(value)?);
+        }
+        Ok(buckets)
+    }
+
+    /// Finds the key-value pairs matching a prefix that is too short to select a single
+    /// bucket. The buckets and the unpacked values are all obtained from one scan of the
+    /// underlying store.
+    async fn find_key_values_by_short_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, PackingError<K::Error>> {
+        // The position of the tag in the keys of the scan, which do not include `key_prefix`.
+        let tag_index = self.packing_parameters.prefix_len - key_prefix.len();
+        let mut key_values = Vec::new();
+        let mut buckets = Vec::new();
+        let mut unpacked_values = BTreeMap::new();
+        let inner_key_values = self.store.find_key_values_by_prefix(key_prefix).await?;
+        for result in inner_key_values.into_iterator_owned() {
+            let (key, value) = result?;
+            if key.len() <= tag_index {
+                key_values.push((key, value));
+            } else if key[tag_index] == BUCKET_TAG {
+                buckets.push((key, value));
+            } else {
+                unpacked_values.insert(key, value);
+            }
+        }
+        for (mut bucket_key, value) in buckets {
+            bucket_key.truncate(tag_index);
+            let entries = Self::decode_bucket(Some(value))?;
+            for (suffix, value) in entries {
+                let mut key = bucket_key.clone();
+                key.extend(&suffix);
+                let value = match value {
+                    PackedValue::Packed(value) => value,
+                    PackedValue::Unpacked => {
+                        let mut unpacked_key = bucket_key.clone();
+                        unpacked_key.push(UNPACKED_TAG);
+                        unpacked_key.extend(&suffix);
+                        unpacked_values
+                            .remove(&unpacked_key)
+                            .ok_or(PackingError::MissingUnpackedValue)?
+                    }
+                };
+                key_values.push((key, value));
+            }
+        }
+        key_values.sort_unstable_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        Ok(key_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PackingStore;
+    use crate::{
+        batch::Batch,
+        memory::MemoryStore,
+        store::{ReadableKeyValueStore, TestKeyValueStore as _, WritableKeyValueStore},
+    };
+
+    // Values overflowing a bucket are stored under their own keys, and deleted with it.
+    #[tokio::test]
+    async fn test_packing_unpacked_values() {
+        let store = PackingStore::<MemoryStore>::new_test_store().await.unwrap();
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1], vec![0; 5]);
+        batch.put_key_value_bytes(vec![1, 2, 3], vec![1; 10]);
+        batch.put_key_value_bytes(vec![1, 2, 4], vec![2; 2000]);
+        batch.put_key_value_bytes(vec![1, 2, 5], vec![3; 990]);
+        store.write_batch(batch).await.unwrap();
+        let inner_keys = store.store.find_keys_by_prefix(&[]).await.unwrap();
+        assert_eq!(
+            inner_keys,
+            vec![vec![1], vec![1, 2, 0], vec![1, 2, 1, 4], vec![1, 2, 1, 5]]
+        );
+        let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+        assert_eq!(keys, vec![vec![], vec![2, 3], vec![2, 4], vec![2, 5]]);
+        let value = store.read_value_bytes(&[1, 2, 4]).await.unwrap();
+        assert_eq!(value, Some(vec![2; 2000]));
+
+        // The values fit in the bucket again once the largest one is removed.
+        let mut batch = Batch::new();
+        batch.delete_key(vec![1, 2, 4]);
+        batch.put_key_value_bytes(vec![1, 2, 6], vec![4; 10]);
+        store.write_batch(batch).await.unwrap();
+        let inner_keys = store.store.find_keys_by_prefix(&[]).await.unwrap();
+        assert_eq!(inner_keys, vec![vec![1], vec![1, 2, 0], vec![1, 2, 1, 5]]);
+
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1, 2, 5], vec![5; 10]);
+        store.write_batch(batch).await.unwrap();
+        let inner_keys = store.store.find_keys_by_prefix(&[]).await.unwrap();
+        assert_eq!(inner_keys, vec![vec![1], vec![1, 2, 0]]);
+
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(vec![1]);
+        batch.put_key_value_bytes(vec![1, 3, 7], vec![6; 10]);
+        store.write_batch(batch).await.unwrap();
+        let key_values = store.find_key_values_by_prefix(&[]).await.unwrap();
+        assert_eq!(key_values, vec![(vec![1, 3, 7], vec![6; 10])]);
+    }
+}
@@ -3,6 +3,8 @@
 
 //! Adds support for large values to a given store by splitting them between several keys.
 
+use std::collections::BTreeMap;
+
 use linera_base::ensure;
 use thiserror::Error;
 
@@ -191,6 +193,9 @@ where
         for big_key in self.store.find_keys_by_prefix(key_prefix).await?.iterator() {
             let big_key = big_key?;
             let len = big_key.len();
+            if len < 4 {
+                continue; // Segment of a key shorter than the prefix.
+            }
             if Self::read_index_from_key(big_key)? == 0 {
                 let key = big_key[0..len - 4].to_vec();
                 keys.push(key);
@@ -204,28 +209,33 @@ where
         key_prefix: &[u8],
     ) -> Result<Self::KeyValues, Self::Error> {
         let small_key_values = self.store.find_key_values_by_prefix(key_prefix).await?;
-        let mut small_kv_iterator = small_key_values.into_iterator_owned();
-        let mut key_values = Vec::new();
-        while let Some(result) = small_kv_iterator.next() {
-            let (mut big_key, value) = result?;
-            if Self::read_index_from_key(&big_key)? != 0 {
-                continue; // Leftover segment from an earlier value.
+        // The segments of a value are not always contiguous: the first segment of the key
+        // `[key, 0]` comes between the first two segments of `key`.
+        let mut first_segments = Vec::new();
+        let mut segments = BTreeMap::new();
+        for result in small_key_values.into_iterator_owned() {
+            let (big_key, value) = result?;
+            if big_key.len() < 4 {
+                continue; // Segment of a key shorter than the prefix.
             }
+            if Self::read_index_from_key(&big_key)? == 0 {
+                first_segments.push((big_key, value));
+            } else {
+                segments.insert(big_key, value);
+            }
+        }
+        let mut key_values = Vec::new();
+        for (mut big_key, value) in first_segments {
             big_key.truncate(big_key.len() - 4);
             let key = big_key;
             let count = Self::read_count_from_value(&value)?;
             let mut big_value = value[4..].to_vec();
             for idx in 1..count {
-                let (big_key, value) = small_kv_iterator
-                    .next()
-                    .ok_or(ValueSplittingError::MissingSegment)??;
-                ensure!(
-                    Self::read_index_from_key(&big_key)? == idx
-                        && big_key.starts_with(&key)
-                        && big_key.len() == key.len() + 4,
-                    ValueSplittingError::MissingSegment
-                );
-                big_value.extend(value);
+                let segment_key = Self::get_segment_key(&key, idx)?;
+                let segment = segments
+                    .remove(&segment_key)
+                    .ok_or(ValueSplittingError::MissingSegment)?;
+                big_value.extend(segment);
             }
             key_values.push((key, big_value));
         }
@@ -276,6 +286,10 @@ where
     async fn clear_journal(&self) -> Result<(), Self::Error> {
         Ok(self.store.clear_journal().await?)
     }
+
+    async fn flush(&self) -> Result<(), Self::Error> {
+        Ok(self.store.flush().await?)
+    }
 }
 
 impl<K> AdminKeyValueStore for ValueSplittingStore<K>
@@ -387,6 +401,63 @@ where
     }
 }
 
+impl<K> ValueSplittingStore<K>
+where
+    K: ReadableKeyValueStore + Send + Sync,
+    K::Error: 'static,
+{
+    /// Scans the segments of the underlying store whose keys start with `key_prefix`
+    /// and returns the keys of the segments that cannot be reached from a value.
+    ///
+    /// A segment is reported when the first segment of its value is absent, when its
+    /// index is beyond the count of the value (a leftover from an earlier, larger value),
+    /// or when the value has missing segments. The returned keys are keys of the
+    /// underlying store. The store is not modified, so that the segments can be
+    /// reviewed before being deleted.
+    pub async fn verify_chunks(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Vec<Vec<u8>>, ValueSplittingError<K::Error>> {
+        let mut values = BTreeMap::<Vec<u8>, (Option<u32>, Vec<(u32, Vec<u8>)>)>::new();
+        let small_key_values = self.store.find_key_values_by_prefix(key_prefix).await?;
+        for result in small_key_values.iterator() {
+            let (suffix, value) = result?;
+            if suffix.len() < 4 {
+                continue; // Segment of a key shorter than the prefix.
+            }
+            let mut big_key = key_prefix.to_vec();
+            big_key.extend(suffix);
+            let index = Self::read_index_from_key(&big_key)?;
+            let key = big_key[..big_key.len() - 4].to_vec();
+            let (count, segments) = values.entry(key).or_default();
+            if index == 0 {
+                *count = Some(Self::read_count_from_value(value)?);
+            }
+            segments.push((index, big_key));
+        }
+        let mut orphaned_keys = Vec::new();
+        for (count, segments) in values.into_values() {
+            match count {
+                None => orphaned_keys.extend(segments.into_iter().map(|(_, big_key)| big_key)),
+                Some(count) => {
+                    let num_present = segments.iter().filter(|(index, _)| *index < count).count();
+                    if num_present == count as usize {
+                        orphaned_keys.extend(
+                            segments
+                                .into_iter()
+                                .filter(|(index, _)| *index >= count)
+                                .map(|(_, big_key)| big_key),
+                        );
+                    } else {
+                        orphaned_keys.extend(segments.into_iter().map(|(_, big_key)| big_key));
+                    }
+                }
+            }
+        }
+        Ok(orphaned_keys)
+    }
+}
+
 /// A memory store for which the values are limited to 100 bytes and can be used for tests.
 #[derive(Clone)]
 #[cfg(with_testing)]
@@ -584,4 +655,67 @@ mod tests {
         let keys = store.find_keys_by_prefix(&[0]).await.unwrap();
         assert_eq!(keys, vec![vec![0, 0, 0, 0, 1], vec![0, 0, 0, 0, 2]]);
     }
+
+    #[tokio::test]
+    async fn test_value_splitting4_verify_chunks() {
+        let store = LimitedTestMemoryStore::new();
+        const MAX_LEN: usize = LimitedTestMemoryStore::MAX_VALUE_SIZE;
+        let big_store = ValueSplittingStore::new(store.clone());
+        // Writing two values of three segments
+        let mut batch = Batch::new();
+        let value = vec![0; 3 * MAX_LEN - 4];
+        batch.put_key_value_bytes(vec![0, 0], value.clone());
+        batch.put_key_value_bytes(vec![0, 1], value);
+        big_store.write_batch(batch).await.unwrap();
+        assert!(big_store.verify_chunks(&[0]).await.unwrap().is_empty());
+        // Shrinking the first value and deleting the second one
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![0, 0], vec![0; MAX_LEN]);
+        batch.delete_key(vec![0, 1]);
+        big_store.write_batch(batch).await.unwrap();
+        let keys = big_store.verify_chunks(&[0]).await.unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                vec![0, 0, 0, 0, 0, 2],
+                vec![0, 1, 0, 0, 0, 1],
+                vec![0, 1, 0, 0, 0, 2],
+            ]
+        );
+        // The values of the store are not modified
+        let keys = store.find_keys_by_prefix(&[0]).await.unwrap();
+        assert_eq!(keys.len(), 5);
+    }
+
+    // The first segment of the key `[1, 0]` comes between the two segments of the key
+    // `[1]`, and the segments of `[1]` also match the prefix `[1, 0, 0]`.
+    #[tokio::test]
+    async fn test_value_splitting5_keys_sharing_prefixes() {
+        let store = LimitedTestMemoryStore::new();
+        const MAX_LEN: usize = LimitedTestMemoryStore::MAX_VALUE_SIZE;
+        let big_store = ValueSplittingStore::new(store);
+        let big_value = vec![3; MAX_LEN + 1];
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1], big_value.clone());
+        batch.put_key_value_bytes(vec![1, 0], vec![4]);
+        batch.put_key_value_bytes(vec![1, 0, 0, 0], vec![5]);
+        big_store.write_batch(batch).await.unwrap();
+
+        let key_values = big_store.find_key_values_by_prefix(&[1]).await.unwrap();
+        assert_eq!(
+            key_values,
+            vec![
+                (vec![], big_value),
+                (vec![0], vec![4]),
+                (vec![0, 0, 0], vec![5]),
+            ]
+        );
+        let keys = big_store.find_keys_by_prefix(&[1, 0, 0]).await.unwrap();
+        assert_eq!(keys, vec![vec![0]]);
+        let key_values = big_store
+            .find_key_values_by_prefix(&[1, 0, 0])
+            .await
+            .unwrap();
+        assert_eq!(key_values, vec![(vec![0], vec![5])]);
+    }
 }
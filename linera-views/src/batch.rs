@@ -20,11 +20,13 @@ use std::{
     fmt::Debug,
     iter::Peekable,
     ops::Bound,
+    pin::pin,
     vec::IntoIter,
 };
 
 use async_trait::async_trait;
 use bcs::serialized_size;
+use futures::{Stream, TryStreamExt as _};
 use linera_witty::{WitLoad, WitStore, WitType};
 use serde::{Deserialize, Serialize};
 
@@ -100,7 +102,8 @@ impl UnorderedBatch {
         let insertions = self.simple_unordered_batch.insertions;
         let mut deletions = self.simple_unordered_batch.deletions;
         for key_prefix in self.key_prefix_deletions {
-            for short_key in db.expand_delete_prefix(&key_prefix).await?.iter() {
+            let mut short_keys = pin!(db.expand_delete_prefix(&key_prefix));
+            while let Some(short_key) = short_keys.try_next().await? {
                 let mut key = key_prefix.clone();
                 key.extend(short_key);
                 if !insert_set.contains(&key) {
@@ -137,7 +140,8 @@ impl UnorderedBatch {
                 .next()
                 .is_some()
             {
-                for short_key in db.expand_delete_prefix(key_prefix).await?.iter() {
+                let mut short_keys = pin!(db.expand_delete_prefix(key_prefix));
+                while let Some(short_key) = short_keys.try_next().await? {
                     let mut key = key_prefix.clone();
                     key.extend(short_key);
                     if !inserted_keys.contains(&key) {
@@ -311,6 +315,28 @@ impl Batch {
         true
     }
 
+    /// Checks that the batch does not write any key more than once.
+    ///
+    /// When a key is written several times, e.g. deleted and then inserted again, the
+    /// simplification of the batch only keeps the last operation: the final state is the
+    /// same, but the operations are not applied in order. Callers relying on the order of
+    /// the operations can use this function to reject such batches before writing them.
+    /// Writing a key under a deleted prefix is accepted, since the deletions of prefixes
+    /// are always applied first.
+    pub fn check_distinct_keys(&self) -> Result<(), ViewError> {
+        let mut keys = HashSet::new();
+        for operation in &self.operations {
+            let key = match operation {
+                WriteOperation::Put { key, .. } | WriteOperation::Delete { key } => key,
+                WriteOperation::DeletePrefix { .. } => continue,
+            };
+            if !keys.insert(key) {
+                return Err(ViewError::RepeatedKeyInBatch(key.clone()));
+            }
+        }
+        Ok(())
+    }
+
     /// Adds the insertion of a key-value pair into the batch with a serializable value.
     /// ```rust
     /// # use linera_views::batch::Batch;
@@ -367,14 +393,18 @@ impl Batch {
 ///
 /// Certain databases (e.g. DynamoDB) do not support the deletion by prefix.
 /// Thus we need to access the databases in order to replace a `DeletePrefix`
-/// by a vector of the keys to be removed.
+/// by the keys to be removed.
 #[trait_variant::make(DeletePrefixExpander: Send)]
 pub trait LocalDeletePrefixExpander {
     /// The error type that can happen when expanding the key prefix.
     type Error: Debug;
 
-    /// Returns the list of keys to be appended to the list.
-    async fn expand_delete_prefix(&self, key_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error>;
+    /// Returns the stream of the keys to be deleted under `key_prefix`, without the
+    /// prefix. The keys can be consumed as they arrive, without collecting them first.
+    fn expand_delete_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Stream<Item = Result<Vec<u8>, Self::Error>> + 'a;
 }
 
 /// A notion of batch useful for certain computations (notably journaling).
@@ -383,6 +413,10 @@ pub trait SimplifiedBatch: Sized + Send + Sync {
     /// The iterator type used to process values from the batch.
     type Iter: BatchValueWriter<Self>;
 
+    /// Whether the key-prefix deletions are replaced by the deletions of the keys under
+    /// them, which requires reading these keys.
+    const EXPANDS_KEY_PREFIXES: bool;
+
     /// Creates a simplified batch from a standard one.
     async fn from_batch<S: DeletePrefixExpander + Send + Sync>(
         store: S,
@@ -395,12 +429,19 @@ pub trait SimplifiedBatch: Sized + Send + Sync {
     /// Returns the total number of entries in the batch.
     fn len(&self) -> usize;
 
+    /// Returns the number of insertions in the batch, the other entries being deletions.
+    fn num_insertions(&self) -> usize;
+
     /// Returns the total number of bytes in the batch.
     fn num_bytes(&self) -> usize;
 
     /// Returns the overhead size of the batch.
     fn overhead_size(&self) -> usize;
 
+    /// Returns the sum of `size_of_item(key_len, value_len)` over the entries of the
+    /// batch, deletions having an empty value.
+    fn items_size(&self, size_of_item: impl Fn(usize, usize) -> usize) -> usize;
+
     /// Adds the deletion of key to the batch.
     fn add_delete(&mut self, key: Vec<u8>);
 
@@ -435,6 +476,10 @@ pub trait BatchValueWriter<Batch>: Send + Sync {
         batch: &Batch,
         batch_size: usize,
     ) -> Result<Option<usize>, bcs::Error>;
+
+    /// Returns the lengths of the key and of the value of the next entry (if any) without
+    /// consuming it. Deletions have an empty value.
+    fn next_entry_lengths(&mut self) -> Option<(usize, usize)>;
 }
 
 /// The iterator that corresponds to a `SimpleUnorderedBatch`
@@ -446,6 +491,7 @@ pub struct SimpleUnorderedBatchIter {
 #[async_trait]
 impl SimplifiedBatch for SimpleUnorderedBatch {
     type Iter = SimpleUnorderedBatchIter;
+    const EXPANDS_KEY_PREFIXES: bool = true;
 
     fn into_iter(self) -> Self::Iter {
         let delete_iter = self.deletions.into_iter().peekable();
@@ -460,6 +506,10 @@ impl SimplifiedBatch for SimpleUnorderedBatch {
         self.deletions.len() + self.insertions.len()
     }
 
+    fn num_insertions(&self) -> usize {
+        self.insertions.len()
+    }
+
     fn num_bytes(&self) -> usize {
         let mut total_size = 0;
         for (key, value) in &self.insertions {
@@ -475,6 +525,17 @@ impl SimplifiedBatch for SimpleUnorderedBatch {
         get_uleb128_size(self.deletions.len()) + get_uleb128_size(self.insertions.len())
     }
 
+    fn items_size(&self, size_of_item: impl Fn(usize, usize) -> usize) -> usize {
+        let mut total_size = 0;
+        for (key, value) in &self.insertions {
+            total_size += size_of_item(key.len(), value.len());
+        }
+        for deletion in &self.deletions {
+            total_size += size_of_item(deletion.len(), 0);
+        }
+        total_size
+    }
+
     fn add_delete(&mut self, key: Vec<u8>) {
         self.deletions.push(key)
     }
@@ -540,6 +601,14 @@ impl BatchValueWriter<SimpleUnorderedBatch> for SimpleUnorderedBatchIter {
             Ok(None)
         }
     }
+    fn next_entry_lengths(&mut self) -> Option<(usize, usize)> {
+        if let Some(delete) = self.delete_iter.peek() {
+            Some((delete.len(), 0))
+        } else {
+            let (key, value) = self.insert_iter.peek()?;
+            Some((key.len(), value.len()))
+        }
+    }
 }
 
 /// The iterator that corresponds to a `SimpleUnorderedBatch`
@@ -551,6 +620,7 @@ pub struct UnorderedBatchIter {
 #[async_trait]
 impl SimplifiedBatch for UnorderedBatch {
     type Iter = UnorderedBatchIter;
+    const EXPANDS_KEY_PREFIXES: bool = false;
 
     fn into_iter(self) -> Self::Iter {
         let delete_prefix_iter = self.key_prefix_deletions.into_iter().peekable();
@@ -565,6 +635,10 @@ impl SimplifiedBatch for UnorderedBatch {
         self.key_prefix_deletions.len() + self.simple_unordered_batch.len()
     }
 
+    fn num_insertions(&self) -> usize {
+        self.simple_unordered_batch.num_insertions()
+    }
+
     fn num_bytes(&self) -> usize {
         let mut total_size = self.simple_unordered_batch.num_bytes();
         for prefix_deletion in &self.key_prefix_deletions {
@@ -578,6 +652,14 @@ impl SimplifiedBatch for UnorderedBatch {
             + self.simple_unordered_batch.overhead_size()
     }
 
+    fn items_size(&self, size_of_item: impl Fn(usize, usize) -> usize) -> usize {
+        let mut total_size = self.simple_unordered_batch.items_size(&size_of_item);
+        for prefix_deletion in &self.key_prefix_deletions {
+            total_size += size_of_item(prefix_deletion.len(), 0);
+        }
+        total_size
+    }
+
     fn add_delete(&mut self, key: Vec<u8>) {
         self.simple_unordered_batch.add_delete(key)
     }
@@ -637,6 +719,13 @@ impl BatchValueWriter<UnorderedBatch> for UnorderedBatchIter {
                 .next_batch_size(&batch.simple_unordered_batch, batch_size)
         }
     }
+    fn next_entry_lengths(&mut self) -> Option<(usize, usize)> {
+        if let Some(delete_prefix) = self.delete_prefix_iter.peek() {
+            Some((delete_prefix.len(), 0))
+        } else {
+            self.insert_deletion_iter.next_entry_lengths()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -644,6 +733,7 @@ mod tests {
     use linera_views::{
         batch::{Batch, SimpleUnorderedBatch, UnorderedBatch},
         context::{Context, MemoryContext},
+        views::ViewError,
     };
 
     #[test]
@@ -662,6 +752,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_check_distinct_keys() {
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(vec![1]);
+        batch.put_key_value_bytes(vec![1, 2], vec![]);
+        batch.delete_key(vec![1, 3]);
+        batch.check_distinct_keys().unwrap();
+        batch.delete_key(vec![1, 2]);
+        batch.put_key_value_bytes(vec![1, 2], vec![5]);
+        assert!(matches!(
+            batch.check_distinct_keys(),
+            Err(ViewError::RepeatedKeyInBatch(key)) if key == vec![1, 2]
+        ));
+    }
+
     #[test]
     fn test_simplify_batch2() {
         let mut batch = Batch::new();
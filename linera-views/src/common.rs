@@ -12,8 +12,9 @@ use std::{
 };
 
 use serde::de::DeserializeOwned;
+use static_assertions as sa;
 
-use crate::views::ViewError;
+use crate::views::{ViewError, MIN_VIEW_TAG};
 
 #[doc(hidden)]
 pub type HasherOutputSize = <sha3::Sha3_256 as sha3::digest::OutputSizeUser>::OutputSize;
@@ -317,6 +318,52 @@ pub(crate) const fn get_uleb128_size(len: usize) -> usize {
     expo
 }
 
+/// The tag starting every key reserved for the internal use of the storage layers.
+/// Views only use tags from `MIN_VIEW_TAG` onwards, so internal keys cannot clash
+/// with user keys.
+pub(crate) const INTERNAL_TAG: u8 = 0;
+sa::const_assert!(INTERNAL_TAG < MIN_VIEW_TAG);
+
+/// The allocation of the internal namespace. Every internal key starts with
+/// `[INTERNAL_TAG, kind]`. New internal features must add a variant here with a fresh
+/// discriminant rather than building keys by hand. Discriminants are persisted, so
+/// existing values must never be reused or changed.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InternalKeyKind {
+    /// The header of the journal.
+    JournalHeader = 1,
+    /// A block entry of the journal.
+    JournalEntry = 2,
+    /// A tombstone of a deleted key prefix.
+    #[cfg(with_mark_and_sweep)]
+    Tombstone = 3,
+    /// The advisory lease of the writer of a root key.
+    #[cfg(with_dynamodb)]
+    Lease = 4,
+    /// The progress of an interrupted transformation of the values under a key prefix.
+    TransformProgress = 5,
+    /// The throwaway item written when probing the limits of the backend.
+    #[cfg(with_dynamodb)]
+    LimitProbe = 6,
+    /// The root key to which the journal is being moved by `rebase_journal`.
+    JournalRebase = 7,
+}
+
+impl InternalKeyKind {
+    /// The prefix shared by all the internal keys of this kind.
+    pub(crate) const fn prefix(self) -> [u8; 2] {
+        [INTERNAL_TAG, self as u8]
+    }
+}
+
+/// Builds the internal key of the given kind with the given suffix.
+pub(crate) fn get_internal_key(kind: InternalKeyKind, suffix: &[u8]) -> Vec<u8> {
+    let mut key = kind.prefix().to_vec();
+    key.extend_from_slice(suffix);
+    key
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeSet;
@@ -350,6 +397,35 @@ mod tests {
             assert_eq!(val2, val_ret2);
         }
     }
+
+    #[test]
+    fn test_internal_keys_do_not_clash() {
+        use crate::{
+            common::{get_internal_key, InternalKeyKind},
+            views::MIN_VIEW_TAG,
+        };
+
+        let kinds = [
+            InternalKeyKind::JournalHeader,
+            InternalKeyKind::JournalEntry,
+            #[cfg(with_mark_and_sweep)]
+            InternalKeyKind::Tombstone,
+            #[cfg(with_dynamodb)]
+            InternalKeyKind::Lease,
+            InternalKeyKind::TransformProgress,
+            #[cfg(with_dynamodb)]
+            InternalKeyKind::LimitProbe,
+            InternalKeyKind::JournalRebase,
+        ];
+        for (i, kind1) in kinds.iter().enumerate() {
+            let key = get_internal_key(*kind1, &[MIN_VIEW_TAG, 255]);
+            // Internal keys are disjoint from the keys written by views.
+            assert!(key[0] < MIN_VIEW_TAG);
+            for (j, kind2) in kinds.iter().enumerate() {
+                assert_eq!(key.starts_with(&kind2.prefix()), i == j);
+            }
+        }
+    }
 }
 
 #[test]
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use async_trait::async_trait;
+use futures::{stream, Stream, TryStreamExt as _};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
@@ -9,7 +10,7 @@ use crate::{
     common::from_bytes_option,
     memory::MemoryStore,
     store::{KeyIterable, KeyValueIterable, KeyValueStoreError, RestrictedKeyValueStore},
-    views::MIN_VIEW_TAG,
+    views::{ViewError, MIN_VIEW_TAG},
 };
 
 /// The context in which a view is operated. Typically, this includes the client to
@@ -181,6 +182,17 @@ where
 }
 
 impl<E, S> ViewContext<E, S> {
+    /// Creates a context for the given base key, store, and an extra argument, after
+    /// checking that the base key is non-empty and does not start with one of the tags
+    /// reserved below `MIN_VIEW_TAG` (e.g. for the journal). As for
+    /// [`ViewContext::new_unsafe`], the journal of the store is not checked.
+    pub fn new(store: S, base_key: Vec<u8>, extra: E) -> Result<Self, ViewError> {
+        match base_key.first() {
+            Some(tag) if *tag >= MIN_VIEW_TAG => Ok(Self::new_unsafe(store, base_key, extra)),
+            _ => Err(ViewError::InvalidBaseKey(base_key)),
+        }
+    }
+
     /// Creates a context for the given base key, store, and an extra argument. NOTE: this
     /// constructor doesn't check the journal of the store. In doubt, use
     /// [`ViewContext::create_root_context`] instead.
@@ -292,13 +304,32 @@ impl<E> MemoryContext<E> {
 impl DeletePrefixExpander for MemoryContext<()> {
     type Error = crate::memory::MemoryStoreError;
 
-    async fn expand_delete_prefix(&self, key_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
-        let mut vector_list = Vec::new();
-        for key in <Vec<Vec<u8>> as KeyIterable<Self::Error>>::iterator(
-            &self.find_keys_by_prefix(key_prefix).await?,
-        ) {
-            vector_list.push(key?.to_vec());
-        }
-        Ok(vector_list)
+    fn expand_delete_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> impl Stream<Item = Result<Vec<u8>, Self::Error>> + 'a {
+        stream::once(self.find_keys_by_prefix(key_prefix))
+            .map_ok(|keys| stream::iter(keys.into_iter().map(Ok)))
+            .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_context_base_key_validation() {
+        assert!(matches!(
+            ViewContext::new((), Vec::new(), ()),
+            Err(ViewError::InvalidBaseKey(base_key)) if base_key.is_empty()
+        ));
+        let reserved_key = vec![MIN_VIEW_TAG - 1, 5];
+        assert!(matches!(
+            ViewContext::new((), reserved_key.clone(), ()),
+            Err(ViewError::InvalidBaseKey(base_key)) if base_key == reserved_key
+        ));
+        let context = ViewContext::new((), vec![MIN_VIEW_TAG, 5], ()).unwrap();
+        assert_eq!(context.base_key, vec![MIN_VIEW_TAG, 5]);
     }
 }
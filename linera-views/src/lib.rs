@@ -98,11 +98,13 @@ pub use backends::dynamo_db;
 pub use backends::indexed_db;
 #[cfg(with_metrics)]
 pub use backends::metering;
+#[cfg(with_packing)]
+pub use backends::packing;
 #[cfg(with_rocksdb)]
 pub use backends::rocks_db;
 #[cfg(with_scylladb)]
 pub use backends::scylla_db;
-pub use backends::{journaling, lru_caching, memory, value_splitting};
+pub use backends::{hash_prefix, journaling, lru_caching, memory, value_splitting};
 pub use views::{
     bucket_queue_view, collection_view, hashable_wrapper, key_value_store_view, log_view, map_view,
     queue_view, reentrant_collection_view, register_view, set_view,
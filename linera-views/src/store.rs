@@ -161,6 +161,18 @@ pub trait LocalWritableKeyValueStore: WithError {
     /// Clears any journal entry that may remain.
     /// The journal is located at the `root_key`.
     async fn clear_journal(&self) -> Result<(), Self::Error>;
+
+    /// Waits until all the writes made so far are durably stored.
+    ///
+    /// Writes are not buffered by the stores of this crate, so by default this only
+    /// resolves the journal that an interrupted write may have left. A store that buffers
+    /// writes must override this method to write them out.
+    fn flush(&self) -> impl Future<Output = Result<(), Self::Error>>
+    where
+        Self: Sync,
+    {
+        async { self.clear_journal().await }
+    }
 }
 
 /// Low-level trait for the administration of stores and their namespaces.
@@ -139,6 +139,14 @@ pub enum ViewError {
     #[error("The key must not be too long")]
     KeyTooLong,
 
+    /// A batch required to apply its operations in order writes the same key twice.
+    #[error("The batch writes the key {0:?} more than once")]
+    RepeatedKeyInBatch(Vec<u8>),
+
+    /// The base key of a context must be non-empty and must not start with a reserved tag.
+    #[error("Invalid base key: {0:?}")]
+    InvalidBaseKey(Vec<u8>),
+
     /// The entry does not exist in memory
     // FIXME(#148): This belongs to a future `linera_storage::StoreError`.
     #[error("Entry does not exist in memory: {0}")]
@@ -14,6 +14,11 @@ use linera_views::{
     },
     value_splitting::create_value_splitting_memory_store,
 };
+use proptest::{
+    collection::{btree_map, vec},
+    prelude::{any, prop_oneof, Just, Strategy},
+};
+use test_strategy::proptest;
 #[cfg(web)]
 use wasm_bindgen_test::wasm_bindgen_test;
 
@@ -64,6 +69,26 @@ async fn test_reads_memory() {
     }
 }
 
+/// Generates distinct keys with their values. The keys are drawn from a small alphabet,
+/// including the journal tag `0` and the highest byte, so that they share prefixes.
+fn arbitrary_key_values() -> impl Strategy<Value = Vec<(Vec<u8>, Vec<u8>)>> {
+    let byte = prop_oneof![Just(0u8), Just(1), Just(u8::MAX), any::<u8>()];
+    let key = vec(byte, 1..8);
+    let value = vec(any::<u8>(), 0..1000);
+    btree_map(key, value, 0..20).prop_map(|key_values| key_values.into_iter().collect())
+}
+
+#[proptest(async = "tokio", cases = 64)]
+async fn test_reads_arbitrary_bytes_memory(
+    #[strategy(arbitrary_key_values())] key_values: Vec<(Vec<u8>, Vec<u8>)>,
+) {
+    let store = MemoryStore::new_test_store().await.unwrap();
+    run_reads(store, key_values.clone()).await;
+    // The values of more than 100 bytes are split by this store.
+    let store = create_value_splitting_memory_store();
+    run_reads(store, key_values).await;
+}
+
 #[cfg(with_rocksdb)]
 #[tokio::test]
 async fn test_reads_rocks_db() {
@@ -75,20 +100,28 @@ async fn test_reads_rocks_db() {
     }
 }
 
+/// Runs the read scenarios, each on a new store of type `S` with the empty root key.
 #[cfg(with_dynamodb)]
-#[tokio::test]
-async fn test_reads_dynamo_db() {
-    use linera_views::store::AdminKeyValueStore as _;
-
+async fn run_reads_with_empty_root_key<S: linera_views::store::TestKeyValueStore>() {
     for scenario in get_random_test_scenarios() {
-        let store = linera_views::dynamo_db::DynamoDbStore::new_test_store()
-            .await
-            .unwrap();
+        let store = S::new_test_store().await.unwrap();
         let store = store.clone_with_root_key(&[]).unwrap();
         run_reads(store, scenario).await;
     }
 }
 
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_reads_dynamo_db() {
+    run_reads_with_empty_root_key::<linera_views::dynamo_db::DynamoDbStore>().await;
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_reads_uncached_dynamo_db() {
+    run_reads_with_empty_root_key::<linera_views::dynamo_db::UncachedDynamoDbStore>().await;
+}
+
 #[cfg(with_scylladb)]
 #[tokio::test]
 async fn test_reads_scylla_db() {
@@ -345,3 +378,3112 @@ async fn test_scylladb_access() {
 async fn test_dynamodb_access() {
     access_admin_test::<linera_views::dynamo_db::DynamoDbStore>().await
 }
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_uncached_dynamodb_access() {
+    access_admin_test::<linera_views::dynamo_db::UncachedDynamoDbStore>().await
+}
+
+/// Returns the configuration of the DynamoDB tests.
+#[cfg(with_dynamodb)]
+async fn dynamo_db_test_config() -> linera_views::dynamo_db::DynamoDbStoreInternalConfig {
+    use linera_views::{dynamo_db::DynamoDbStoreInternal, journaling::JournalingKeyValueStore};
+
+    JournalingKeyValueStore::<DynamoDbStoreInternal>::new_test_config()
+        .await
+        .unwrap()
+}
+
+/// Creates a [`DynamoDbStoreInternal`] on a new table and returns it with the name of
+/// the table.
+#[cfg(with_dynamodb)]
+async fn new_dynamo_db_test_store(
+    config: &linera_views::dynamo_db::DynamoDbStoreInternalConfig,
+) -> (linera_views::dynamo_db::DynamoDbStoreInternal, String) {
+    use linera_views::{
+        dynamo_db::DynamoDbStoreInternal, random::generate_test_namespace,
+        store::AdminKeyValueStore as _,
+    };
+
+    let namespace = generate_test_namespace();
+    let store = DynamoDbStoreInternal::recreate_and_connect(config, &namespace)
+        .await
+        .unwrap();
+    (store, namespace)
+}
+
+/// Creates a journaling store over a new table, with exclusive access, and returns it
+/// with the name of the table.
+#[cfg(with_dynamodb)]
+async fn new_dynamo_db_journaling_test_store(
+    config: &linera_views::dynamo_db::DynamoDbStoreInternalConfig,
+) -> (
+    linera_views::journaling::JournalingKeyValueStore<
+        linera_views::dynamo_db::DynamoDbStoreInternal,
+    >,
+    String,
+) {
+    use linera_views::store::AdminKeyValueStore as _;
+
+    let (store, namespace) = new_dynamo_db_test_store(config).await;
+    let store = linera_views::journaling::JournalingKeyValueStore::new(store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    (store, namespace)
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_read_multi_values_fails_fast() {
+    use linera_views::{
+        dynamo_db::{DynamoDbStoreInternalError, Fault, FaultInjector},
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let injector = FaultInjector::new();
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_fault_injector(injector.clone());
+    // Every request fails with an error that is not retried. The test configuration
+    // sends at most 10 requests at a time, so the other requests are abandoned.
+    injector.fail_first(200, Fault::Crash);
+    let keys = (0..100u8).map(|i| vec![1, i]).collect::<Vec<_>>();
+    let result = store.read_multi_values_bytes(keys.clone()).await;
+    assert!(matches!(result, Err(DynamoDbStoreInternalError::Get(_))));
+    let num_sent = injector.num_injected();
+    assert!((1..=10).contains(&num_sent));
+    let result = store.contains_keys(keys).await;
+    assert!(matches!(result, Err(DynamoDbStoreInternalError::Get(_))));
+    assert!((num_sent + 1..=num_sent + 10).contains(&injector.num_injected()));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_writes_without_transactions() {
+    use linera_views::{
+        dynamo_db::{Fault, FaultInjector},
+        journaling::JournalingKeyValueStore,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_transactions_supported(false);
+    let injector = FaultInjector::new();
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_fault_injector(injector.clone());
+    let store = JournalingKeyValueStore::new(store);
+    // A transaction would fail, so the writes must all go through `BatchWriteItem`.
+    injector.fail_nth("TransactWriteItems", 1, Fault::Crash);
+    let mut batch = Batch::new();
+    for i in 0..200u8 {
+        batch.put_key_value_bytes(vec![1, i], vec![i]);
+    }
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(injector.num_injected(), 0);
+    for i in 0..200u8 {
+        assert_eq!(
+            store.read_value_bytes(&[1, i]).await.unwrap(),
+            Some(vec![i])
+        );
+    }
+    let mut batch = Batch::new();
+    batch.delete_key(vec![1, 0]);
+    store.write_batch(batch).await.unwrap();
+    assert!(!store.contains_key(&[1, 0]).await.unwrap());
+    assert_eq!(injector.num_injected(), 0);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_recreate_without_create_if_missing() {
+    use linera_views::{
+        dynamo_db::{DynamoDbStore, DynamoDbStoreError, DynamoDbStoreInternalError},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = DynamoDbStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let store = DynamoDbStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1], vec![2]);
+    store.write_batch(batch).await.unwrap();
+    // The table could not be created again, so it is not deleted.
+    let config = config.with_create_if_missing(false);
+    let result = DynamoDbStore::recreate_and_connect(&config, &namespace).await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreError::InnerStoreError(
+            DynamoDbStoreInternalError::TableDeletionDisabled(_)
+        ))
+    ));
+    let store = DynamoDbStore::maybe_create_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    assert_eq!(store.read_value_bytes(&[1]).await.unwrap(), Some(vec![2]));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_write_batch_with_version() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, dynamo_db::DynamoDbStoreInternalError,
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let version_key = vec![1, 0];
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![2, 0], vec![42])],
+    };
+    let version = store
+        .write_batch_with_version(batch, &version_key, None)
+        .await
+        .unwrap();
+    assert_eq!(version, 0);
+    assert_eq!(
+        store.read_value::<u64>(&version_key).await.unwrap(),
+        Some(0)
+    );
+
+    let batch = SimpleUnorderedBatch {
+        deletions: vec![vec![2, 0]],
+        insertions: Vec::new(),
+    };
+    let result = store
+        .write_batch_with_version(batch, &version_key, None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::VersionConflict)
+    ));
+    assert_eq!(
+        store.read_value_bytes(&[2, 0]).await.unwrap(),
+        Some(vec![42])
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_key_prefix_validation() {
+    use linera_views::{
+        dynamo_db::{DynamoDbStoreInternalError, KeyValidator},
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_key_validator(KeyValidator::new(|key, _| match key.first() {
+        Some(9) => Err("reserved tag".to_string()),
+        _ => Ok(()),
+    }));
+    assert!(store.find_keys_by_prefix(&[1]).await.is_ok());
+    let result = store.find_keys_by_prefix(&[9]).await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::InvalidKey(_))
+    ));
+    let result = store.find_key_values_by_prefix(&[9, 1]).await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::InvalidKey(_))
+    ));
+    let result = store.find_keys_by_prefix(&[1; 2000]).await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::KeyPrefixTooLong)
+    ));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_fixed_length_key_validator() {
+    use linera_views::{
+        batch::Batch,
+        dynamo_db::{DynamoDbStoreInternalError, KeyKind, KeyValidator},
+        journaling::{JournalingKeyValueStore, WriteOutcome},
+        store::{
+            AdminKeyValueStore as _, KeyIterable as _, ReadableKeyValueStore as _,
+            WritableKeyValueStore as _,
+        },
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // Only the keys of the user have a fixed length, not the prefixes of the scans nor
+    // the keys of the journal.
+    let store = store.with_key_validator(KeyValidator::new(|key, kind| match kind {
+        KeyKind::Key if key.len() != 3 => Err(format!("key of {} bytes", key.len())),
+        _ => Ok(()),
+    }));
+    let store = JournalingKeyValueStore::new(store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    let mut batch = Batch::new();
+    for index in 0..200u8 {
+        batch.put_key_value_bytes(vec![1, index, 0], vec![index]);
+    }
+    let outcome = store.write_batch_outcome(batch).await.unwrap();
+    assert!(matches!(outcome, WriteOutcome::Journaled { .. }));
+    let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+    let keys = keys
+        .iterator()
+        .collect::<Result<Vec<_>, DynamoDbStoreInternalError>>()
+        .unwrap();
+    let expected_keys = (0..200u8).map(|index| vec![index, 0]).collect::<Vec<_>>();
+    assert_eq!(keys, expected_keys);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 0], vec![0]);
+    assert!(matches!(
+        store.write_batch(batch).await,
+        Err(DynamoDbStoreInternalError::InvalidKey(_))
+    ));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_bulk_loader_repeated_keys() {
+    use linera_views::{dynamo_db::BulkLoader, store::ReadableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // The keys are repeated within a single request.
+    let key_values = (0..30u8).map(|index| (vec![1, index % 4], vec![index]));
+    let progress = BulkLoader::new(&store)
+        .load(futures::stream::iter(key_values), |_| {})
+        .await
+        .unwrap();
+    assert_eq!(progress.items_written, 30);
+    for (key, last_value) in [(0, 28), (1, 29), (2, 26), (3, 27)] {
+        assert_eq!(
+            store.read_value_bytes(&[1, key]).await.unwrap(),
+            Some(vec![last_value])
+        );
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_item_collection_metrics() {
+    use linera_views::{
+        journaling::JournalingKeyValueStore,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_item_collection_metrics(true)
+        .with_record_modification_times(true);
+    // The table is created with the index that DynamoDB requires to return the metrics.
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = JournalingKeyValueStore::new(store);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1], vec![2]);
+    batch.put_key_value_bytes(vec![2], vec![3]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(store.read_value_bytes(&[1]).await.unwrap(), Some(vec![2]));
+    assert_eq!(store.read_value_bytes(&[2]).await.unwrap(), Some(vec![3]));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_store_identity() {
+    use linera_views::store::AdminKeyValueStore as _;
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    assert_eq!(store.table_name(), namespace);
+    assert_eq!(store.root_key(), &[] as &[u8]);
+    let store = store.clone_with_root_key(&[1, 2]).unwrap();
+    assert_eq!(store.table_name(), namespace);
+    assert_eq!(store.root_key(), &[1, 2]);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_append_bounded() {
+    use linera_views::dynamo_db::DynamoDbStoreInternalError;
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A log of at most 3 entries: appending the entry `i` evicts the entry `i - 3`.
+    let prefix = vec![1];
+    for index in 0..10u64 {
+        store
+            .append_bounded(&prefix, index + 3, vec![index as u8], index)
+            .await
+            .unwrap();
+    }
+    let entries = store.read_index_range(&prefix, 0..20).await.unwrap();
+    assert_eq!(entries, vec![(10, vec![7]), (11, vec![8]), (12, vec![9])]);
+    let entries = store.read_index_range(&prefix, 11..12).await.unwrap();
+    assert_eq!(entries, vec![(11, vec![8])]);
+    // Evicting the entry being appended is rejected, and nothing is written.
+    let error = store
+        .append_bounded(&prefix, 20, vec![20], 20)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        DynamoDbStoreInternalError::AppendEvictsItself { index: 20 }
+    ));
+    let entries = store.read_index_range(&prefix, 0..30).await.unwrap();
+    assert_eq!(entries.len(), 3);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_detect_prefix_overlaps() {
+    use linera_views::{batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![1, 1], vec![]),
+            (vec![1, 2, 3], vec![]),
+            (vec![2, 1], vec![]),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let overlaps = store
+        .detect_prefix_overlaps(vec![vec![1], vec![1, 2]])
+        .await
+        .unwrap();
+    assert_eq!(
+        overlaps,
+        vec![
+            (vec![1, 2, 3], vec![vec![1], vec![1, 2]]),
+            (vec![2, 1], vec![]),
+        ]
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_journals_batches_exceeding_total_size() {
+    use linera_views::{batch::Batch, journaling::WriteOutcome, store::ReadableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    // 50 entries are within the limit on the number of items of a transaction. With
+    // values of 90 kB, their keys and values exceed its limit on the total size. With
+    // values of 79998 bytes, their keys and values take exactly 4 MB, the limit, and only
+    // the rest of the items, such as the names of their attributes, pushes them over it.
+    for value_len in [90_000, 79_998] {
+        let (store, _) = new_dynamo_db_journaling_test_store(&config).await;
+        let mut batch = Batch::new();
+        for index in 0..50u8 {
+            batch.put_key_value_bytes(vec![1, index], vec![index; value_len]);
+        }
+        let outcome = store.write_batch_outcome(batch).await.unwrap();
+        assert!(matches!(outcome, WriteOutcome::Journaled { .. }));
+        for index in 0..50u8 {
+            assert_eq!(
+                store.read_value_bytes(&[1, index]).await.unwrap(),
+                Some(vec![index; value_len])
+            );
+        }
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_diagnose_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalDiagnosis, JournalingKeyValueStore},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A journal header expecting two blocks, of which only the first one was written,
+    // and a leftover block beyond the header.
+    let block = bcs::to_bytes(&SimpleUnorderedBatch::default()).unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&2u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], block.clone()),
+            (vec![0, 2, 5, 0, 0, 0], block),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    let diagnosis = store.diagnose_journal().await.unwrap();
+    assert_eq!(
+        diagnosis,
+        JournalDiagnosis {
+            header_block_count: Some(2),
+            present_blocks: vec![0],
+            missing_blocks: vec![1],
+            unexpected_blocks: vec![5],
+            corrupted_blocks: Vec::new(),
+        }
+    );
+    assert!(!diagnosis.is_resolvable());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_repair_journal_header() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::DynamoDbStoreInternalError,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A torn journal header, with the two blocks of the journal.
+    let block = |key: u8| {
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key])],
+        };
+        bcs::to_bytes(&batch).unwrap()
+    };
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], vec![2]),
+            (vec![0, 2, 0, 0, 0, 0], block(0)),
+            (vec![0, 2, 1, 0, 0, 0], block(1)),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    assert!(matches!(
+        store.clear_journal().await,
+        Err(DynamoDbStoreInternalError::BcsError(_))
+    ));
+    assert_eq!(store.repair_journal_header().await.unwrap(), Some(2));
+    assert_eq!(
+        store.read_value_bytes(&[1, 0]).await.unwrap(),
+        Some(vec![0])
+    );
+    assert_eq!(
+        store.read_value_bytes(&[1, 1]).await.unwrap(),
+        Some(vec![1])
+    );
+    assert!(!store.journal_stats().await.unwrap().has_header);
+    assert_eq!(store.repair_journal_header().await.unwrap(), None);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_write_conditional() {
+    use linera_views::{
+        dynamo_db::{ConditionExpr, DynamoDbStoreInternalError},
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let key = [1, 0];
+    store
+        .write_conditional(&key, vec![1], &ConditionExpr::NotExists)
+        .await
+        .unwrap();
+    let result = store
+        .write_conditional(&key, vec![2], &ConditionExpr::NotExists)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::ConditionFailed)
+    ));
+    store
+        .write_conditional(&key, vec![2], &ConditionExpr::ValueEquals(vec![1]))
+        .await
+        .unwrap();
+    assert_eq!(store.read_value_bytes(&key).await.unwrap(), Some(vec![2]));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_map_values() {
+    use std::collections::HashMap;
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    assert_eq!(store.read_map(&[1]).await.unwrap(), None);
+    let map = HashMap::from([
+        ("balance".to_string(), vec![1, 2]),
+        ("nonce".to_string(), vec![3]),
+    ]);
+    store.write_map(&[1], map.clone()).await.unwrap();
+    assert_eq!(store.read_map(&[1]).await.unwrap(), Some(map));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_diff() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, dynamo_db::StoreDiff,
+        journaling::DirectWritableKeyValueStore as _, store::AdminKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let other = store.clone_with_root_key(&[1]).unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], vec![1]),
+            (vec![1, 1], vec![1]),
+            (vec![1, 2], vec![2]),
+            (vec![1, 3], vec![3]),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![1, 2], vec![2]),
+            (vec![1, 3], vec![4]),
+            (vec![1, 4], vec![4]),
+        ],
+    };
+    other.write_batch(batch).await.unwrap();
+    let diff = store.diff(&other, 10, 1).await.unwrap();
+    assert_eq!(
+        diff,
+        StoreDiff {
+            only_in_self: vec![vec![1, 1]],
+            only_in_other: vec![vec![1, 4]],
+            different_values: vec![vec![1, 3]],
+            truncated: false,
+        }
+    );
+    assert_eq!(store.diff(&other, 10, 8).await.unwrap(), diff);
+    let diff = store.diff(&other, 2, 1).await.unwrap();
+    assert_eq!(diff.len(), 2);
+    assert!(diff.truncated);
+    assert_eq!(store.diff(&other, 2, 8).await.unwrap(), diff);
+    assert!(store.diff(&store, 10, 1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_lru_caching_is_cached() {
+    use linera_views::{
+        lru_caching::{LruCachingStore, StorageCacheConfig},
+        store::WritableKeyValueStore as _,
+    };
+
+    let store = MemoryStore::new_test_store().await.unwrap();
+    let storage_cache_config = StorageCacheConfig {
+        max_cache_size: 1000,
+        max_entry_size: 100,
+        max_cache_entries: 2,
+    };
+    let store = LruCachingStore::new(store, storage_cache_config);
+    for key in [[1], [2]] {
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(key.to_vec(), vec![0]);
+        store.write_batch(batch).await.unwrap();
+    }
+    assert!(store.is_cached(&[1]));
+    assert!(store.is_cached(&[2]));
+    assert!(!store.is_cached(&[3]));
+    // Checking the key did not make it the most recently used entry.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![3], vec![0]);
+    store.write_batch(batch).await.unwrap();
+    assert!(!store.is_cached(&[1]));
+    assert!(store.is_cached(&[2]));
+    assert!(store.is_cached(&[3]));
+}
+
+/// A store whose writes wait to be released, to interleave reads with them.
+#[derive(Clone)]
+struct SlowWriteStore {
+    store: MemoryStore,
+    write_started: std::sync::Arc<tokio::sync::Notify>,
+    write_released: std::sync::Arc<tokio::sync::Notify>,
+}
+
+impl linera_views::store::WithError for SlowWriteStore {
+    type Error = linera_views::memory::MemoryStoreError;
+}
+
+impl linera_views::store::ReadableKeyValueStore for SlowWriteStore {
+    const MAX_KEY_SIZE: usize = usize::MAX;
+    type Keys = Vec<Vec<u8>>;
+    type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn max_stream_queries(&self) -> usize {
+        self.store.max_stream_queries()
+    }
+
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.store.read_value_bytes(key).await
+    }
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        self.store.contains_key(key).await
+    }
+
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
+        self.store.contains_keys(keys).await
+    }
+
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        self.store.read_multi_values_bytes(keys).await
+    }
+
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
+        self.store.find_keys_by_prefix(key_prefix).await
+    }
+
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Self::KeyValues, Self::Error> {
+        self.store.find_key_values_by_prefix(key_prefix).await
+    }
+}
+
+impl linera_views::store::WritableKeyValueStore for SlowWriteStore {
+    const MAX_VALUE_SIZE: usize = usize::MAX;
+
+    async fn write_batch(&self, batch: Batch) -> Result<(), Self::Error> {
+        self.write_started.notify_one();
+        self.write_released.notified().await;
+        self.store.write_batch(batch).await
+    }
+
+    async fn clear_journal(&self) -> Result<(), Self::Error> {
+        self.store.clear_journal().await
+    }
+}
+
+#[tokio::test]
+async fn test_lru_caching_keys_cache_concurrent_write() {
+    use linera_views::{
+        lru_caching::{LruCachingStore, StorageCacheConfig},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let slow_store = SlowWriteStore {
+        store: MemoryStore::new_test_store().await.unwrap(),
+        write_started: Default::default(),
+        write_released: Default::default(),
+    };
+    let storage_cache_config = StorageCacheConfig {
+        max_cache_size: 1000,
+        max_entry_size: 100,
+        max_cache_entries: 10,
+    };
+    let store = LruCachingStore::new(slow_store.clone(), storage_cache_config.clone())
+        .with_keys_cache(storage_cache_config);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1], vec![0]);
+    let write = store.write_batch(batch);
+    // The keys are read after the write started and before it landed.
+    let read = async {
+        slow_store.write_started.notified().await;
+        let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+        slow_store.write_released.notify_one();
+        keys
+    };
+    let (result, keys) = futures::join!(write, read);
+    result.unwrap();
+    assert!(keys.is_empty());
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1]]
+    );
+}
+
+/// The errors of a [`SmallBatchStore`].
+#[derive(Debug, thiserror::Error)]
+enum SmallBatchStoreError {
+    #[error(transparent)]
+    Memory(#[from] linera_views::memory::MemoryStoreError),
+    #[error(transparent)]
+    Journal(#[from] linera_views::journaling::JournalConsistencyError),
+    #[error(transparent)]
+    Bcs(#[from] bcs::Error),
+}
+
+impl linera_views::store::KeyValueStoreError for SmallBatchStoreError {
+    const BACKEND: &'static str = "small_batch";
+}
+
+/// A direct store over a `MemoryStore` with small transactions and small pages of keys,
+/// to exercise the journal without a database. Every item counts for `item_overhead`,
+/// by default [`SMALL_BATCH_ITEM_OVERHEAD`], more than its key and value, and the limits
+/// of every batch written are checked.
+#[derive(Clone)]
+struct SmallBatchStore {
+    store: MemoryStore,
+    /// The keys and pages of keys read and the batches written, in order.
+    events: std::sync::Arc<std::sync::Mutex<Vec<SmallBatchEvent>>>,
+    /// Notified after every batch written.
+    batch_written: std::sync::Arc<tokio::sync::Notify>,
+    /// The maximal delay before resolving a journal left over by an interrupted write.
+    recovery_jitter: std::time::Duration,
+    /// The size of an item besides its key and value.
+    item_overhead: usize,
+}
+
+/// The default size of an item of a [`SmallBatchStore`] besides its key and value.
+const SMALL_BATCH_ITEM_OVERHEAD: usize = 50;
+
+/// An operation of a [`SmallBatchStore`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SmallBatchEvent {
+    KeysRead,
+    PageRead,
+    BatchWritten,
+}
+
+impl linera_views::store::WithError for SmallBatchStore {
+    type Error = SmallBatchStoreError;
+}
+
+impl linera_views::store::ReadableKeyValueStore for SmallBatchStore {
+    const MAX_KEY_SIZE: usize = usize::MAX;
+    type Keys = Vec<Vec<u8>>;
+    type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn max_stream_queries(&self) -> usize {
+        self.store.max_stream_queries()
+    }
+
+    async fn read_value_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.store.read_value_bytes(key).await?)
+    }
+
+    async fn contains_key(&self, key: &[u8]) -> Result<bool, Self::Error> {
+        Ok(self.store.contains_key(key).await?)
+    }
+
+    async fn contains_keys(&self, keys: Vec<Vec<u8>>) -> Result<Vec<bool>, Self::Error> {
+        Ok(self.store.contains_keys(keys).await?)
+    }
+
+    async fn read_multi_values_bytes(
+        &self,
+        keys: Vec<Vec<u8>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Self::Error> {
+        Ok(self.store.read_multi_values_bytes(keys).await?)
+    }
+
+    async fn find_keys_by_prefix(&self, key_prefix: &[u8]) -> Result<Self::Keys, Self::Error> {
+        self.events.lock().unwrap().push(SmallBatchEvent::KeysRead);
+        Ok(self.store.find_keys_by_prefix(key_prefix).await?)
+    }
+
+    async fn find_key_values_by_prefix(
+        &self,
+        key_prefix: &[u8],
+    ) -> Result<Self::KeyValues, Self::Error> {
+        Ok(self.store.find_key_values_by_prefix(key_prefix).await?)
+    }
+}
+
+#[async_trait::async_trait]
+impl linera_views::journaling::DirectWritableKeyValueStore for SmallBatchStore {
+    const MAX_BATCH_SIZE: usize = 10;
+    const MAX_BATCH_TOTAL_SIZE: usize = 1000;
+    const MAX_VALUE_SIZE: usize = 500;
+    type Batch = linera_views::batch::SimpleUnorderedBatch;
+
+    async fn write_batch(&self, batch: Self::Batch) -> Result<(), Self::Error> {
+        use linera_views::{batch::SimplifiedBatch as _, store::WritableKeyValueStore as _};
+
+        assert!(batch.len() <= Self::MAX_BATCH_SIZE);
+        assert!(
+            batch.items_size(|key_len, value_len| self.size_of_item(key_len, value_len))
+                <= Self::MAX_BATCH_TOTAL_SIZE
+        );
+        let mut memory_batch = Batch::new();
+        for key in batch.deletions {
+            memory_batch.delete_key(key);
+        }
+        for (key, value) in batch.insertions {
+            assert!(value.len() <= Self::MAX_VALUE_SIZE);
+            memory_batch.put_key_value_bytes(key, value);
+        }
+        self.store.write_batch(memory_batch).await?;
+        self.events
+            .lock()
+            .unwrap()
+            .push(SmallBatchEvent::BatchWritten);
+        self.batch_written.notify_one();
+        Ok(())
+    }
+
+    fn size_of_item(&self, key_len: usize, value_len: usize) -> usize {
+        key_len + value_len + self.item_overhead
+    }
+
+    fn max_recovery_jitter(&self) -> std::time::Duration {
+        self.recovery_jitter
+    }
+
+    fn find_key_pages_by_prefix<'a>(
+        &'a self,
+        key_prefix: &'a [u8],
+    ) -> Option<linera_views::journaling::KeyPages<'a, Self::Error>> {
+        use futures::{stream, StreamExt as _, TryStreamExt as _};
+        use linera_views::store::ReadableKeyValueStore as _;
+
+        let events = self.events.clone();
+        let pages = stream::once(self.store.find_keys_by_prefix(key_prefix))
+            .map_ok(move |keys| {
+                let pages = keys.chunks(7).map(<[_]>::to_vec).collect::<Vec<_>>();
+                let events = events.clone();
+                stream::iter(pages).map(move |page| {
+                    events.lock().unwrap().push(SmallBatchEvent::PageRead);
+                    Ok::<_, linera_views::memory::MemoryStoreError>(page)
+                })
+            })
+            .try_flatten()
+            .map_err(SmallBatchStoreError::from);
+        Some(pages.boxed())
+    }
+}
+
+impl linera_views::store::AdminKeyValueStore for SmallBatchStore {
+    type Config = <MemoryStore as linera_views::store::AdminKeyValueStore>::Config;
+
+    fn get_name() -> String {
+        "small batch".to_string()
+    }
+
+    async fn connect(config: &Self::Config, namespace: &str) -> Result<Self, Self::Error> {
+        let store = MemoryStore::connect(config, namespace).await?;
+        let events = Default::default();
+        let batch_written = Default::default();
+        Ok(Self {
+            store,
+            events,
+            batch_written,
+            recovery_jitter: std::time::Duration::ZERO,
+            item_overhead: SMALL_BATCH_ITEM_OVERHEAD,
+        })
+    }
+
+    fn clone_with_root_key(&self, root_key: &[u8]) -> Result<Self, Self::Error> {
+        let store = self.store.clone_with_root_key(root_key)?;
+        let events = self.events.clone();
+        let batch_written = self.batch_written.clone();
+        Ok(Self {
+            store,
+            events,
+            batch_written,
+            recovery_jitter: self.recovery_jitter,
+            item_overhead: self.item_overhead,
+        })
+    }
+
+    async fn list_all(config: &Self::Config) -> Result<Vec<String>, Self::Error> {
+        Ok(MemoryStore::list_all(config).await?)
+    }
+
+    async fn list_root_keys(
+        config: &Self::Config,
+        namespace: &str,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        Ok(MemoryStore::list_root_keys(config, namespace).await?)
+    }
+
+    async fn exists(config: &Self::Config, namespace: &str) -> Result<bool, Self::Error> {
+        Ok(MemoryStore::exists(config, namespace).await?)
+    }
+
+    async fn create(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(MemoryStore::create(config, namespace).await?)
+    }
+
+    async fn delete(config: &Self::Config, namespace: &str) -> Result<(), Self::Error> {
+        Ok(MemoryStore::delete(config, namespace).await?)
+    }
+}
+
+/// Creates a journaling store over a new [`SmallBatchStore`], with exclusive access, and
+/// returns it with the events of the inner store.
+async fn new_small_batch_journaling_store() -> (
+    linera_views::journaling::JournalingKeyValueStore<SmallBatchStore>,
+    std::sync::Arc<std::sync::Mutex<Vec<SmallBatchEvent>>>,
+) {
+    use linera_views::{
+        journaling::JournalingKeyValueStore, random::generate_test_namespace,
+        store::AdminKeyValueStore as _,
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    let events = store.events.clone();
+    let store = JournalingKeyValueStore::new(store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    (store, events)
+}
+
+#[tokio::test]
+async fn test_journaling_prefix_deletion_streamed() {
+    use linera_views::{
+        journaling::{OversizedBatches, WriteOutcome},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    for oversized_batches in [OversizedBatches::Journal, OversizedBatches::AutoSplit] {
+        let (store, _) = new_small_batch_journaling_store().await;
+        let store = store.with_oversized_batches(oversized_batches);
+        for chunk in 0..30u8 {
+            let mut batch = Batch::new();
+            for index in 0..10u8 {
+                batch.put_key_value_bytes(vec![1, chunk, index], vec![index]);
+            }
+            store.write_batch(batch).await.unwrap();
+        }
+        // The deletion of the 300 keys is written in many transactions, keeping the key
+        // inserted again under the prefix.
+        let mut batch = Batch::new();
+        batch.delete_key_prefix(vec![1]);
+        batch.put_key_value_bytes(vec![1, 0, 0], vec![7]);
+        batch.put_key_value_bytes(vec![2], vec![8]);
+        let outcome = store.write_batch_outcome(batch).await.unwrap();
+        match oversized_batches {
+            OversizedBatches::Journal => {
+                assert!(matches!(outcome, WriteOutcome::Journaled { blocks } if blocks >= 38))
+            }
+            OversizedBatches::AutoSplit => {
+                assert!(
+                    matches!(outcome, WriteOutcome::Split { transactions } if transactions >= 31)
+                )
+            }
+        }
+        assert_eq!(
+            store.find_keys_by_prefix(&[1]).await.unwrap(),
+            vec![vec![0, 0]]
+        );
+        assert_eq!(
+            store.read_value_bytes(&[1, 0, 0]).await.unwrap(),
+            Some(vec![7])
+        );
+        assert_eq!(store.read_value_bytes(&[2]).await.unwrap(), Some(vec![8]));
+        assert!(!store.journal_stats().await.unwrap().has_header);
+    }
+}
+
+#[tokio::test]
+async fn test_journaling_item_overhead() {
+    use linera_views::{
+        journaling::{OversizedBatches, WriteOutcome},
+        store::ReadableKeyValueStore as _,
+    };
+
+    for oversized_batches in [OversizedBatches::Journal, OversizedBatches::AutoSplit] {
+        let (store, _) = new_small_batch_journaling_store().await;
+        let store = store.with_oversized_batches(oversized_batches);
+        // The keys and values only take 657 bytes, but the items count for 1107 bytes
+        // in the inner store, so the batch does not fit in a single transaction.
+        let mut batch = Batch::new();
+        for index in 0..9u8 {
+            batch.put_key_value_bytes(vec![1, 0, index], vec![index; 70]);
+        }
+        let outcome = store.write_batch_outcome(batch).await.unwrap();
+        match oversized_batches {
+            OversizedBatches::Journal => {
+                assert!(matches!(outcome, WriteOutcome::Journaled { .. }))
+            }
+            OversizedBatches::AutoSplit => {
+                assert_eq!(outcome, WriteOutcome::Split { transactions: 2 })
+            }
+        }
+        assert_eq!(store.find_keys_by_prefix(&[1]).await.unwrap().len(), 9);
+    }
+}
+
+#[cfg(with_mark_and_sweep)]
+#[tokio::test]
+async fn test_journaling_tombstones_read_when_recorded() {
+    use linera_views::store::{
+        AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _,
+    };
+
+    let (store, events) = new_small_batch_journaling_store().await;
+    let keys_read = || {
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| **event == SmallBatchEvent::KeysRead)
+            .count()
+    };
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1], vec![1]);
+    store.write_batch(batch).await.unwrap();
+    // No tombstone was found when writing, so the reads don't look for them.
+    let count = keys_read();
+    assert_eq!(
+        store.read_value_bytes(&[1, 1]).await.unwrap(),
+        Some(vec![1])
+    );
+    assert!(store.contains_key(&[1, 1]).await.unwrap());
+    assert_eq!(keys_read(), count);
+    // A tombstone written by another store of the root key is seen.
+    let other = store.clone_with_root_key(&[]).unwrap();
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![1]);
+    other.write_batch(batch).await.unwrap();
+    assert_eq!(store.read_value_bytes(&[1, 1]).await.unwrap(), None);
+    assert!(!store.contains_key(&[1, 1]).await.unwrap());
+    // Once the tombstone is swept, it is looked for only once more.
+    assert_eq!(store.sweep_tombstones().await.unwrap(), 1);
+    let count = keys_read();
+    assert_eq!(store.read_value_bytes(&[1, 1]).await.unwrap(), None);
+    assert_eq!(store.read_value_bytes(&[1, 1]).await.unwrap(), None);
+    assert_eq!(keys_read(), count + 1);
+}
+
+#[cfg(with_mark_and_sweep)]
+#[tokio::test]
+async fn test_journaling_transform_prefix_after_deletion() {
+    use linera_views::store::{ReadableKeyValueStore as _, WritableKeyValueStore as _};
+
+    let (store, _) = new_small_batch_journaling_store().await;
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1, 1], vec![1]);
+    batch.put_key_value_bytes(vec![1, 1, 2], vec![2]);
+    batch.put_key_value_bytes(vec![1, 2], vec![3]);
+    store.write_batch(batch).await.unwrap();
+    // The keys under `[1, 1]` remain in the inner store, behind a tombstone.
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![1, 1]);
+    store.write_batch(batch).await.unwrap();
+    let count = store
+        .transform_prefix(&[1], |value| {
+            Ok::<_, SmallBatchStoreError>(vec![value[0] + 10])
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(store.read_value_bytes(&[1, 1, 1]).await.unwrap(), None);
+    assert_eq!(store.read_value_bytes(&[1, 1, 2]).await.unwrap(), None);
+    assert_eq!(
+        store.read_value_bytes(&[1, 2]).await.unwrap(),
+        Some(vec![13])
+    );
+}
+
+// With mark-and-sweep, the prefix deletion is replaced by a tombstone and no key is read.
+#[cfg(not(with_mark_and_sweep))]
+#[tokio::test]
+async fn test_journaling_prefix_deletion_paged() {
+    use linera_views::store::{ReadableKeyValueStore as _, WritableKeyValueStore as _};
+
+    let (store, events) = new_small_batch_journaling_store().await;
+    for chunk in 0..10u8 {
+        let mut batch = Batch::new();
+        for index in 0..10u8 {
+            batch.put_key_value_bytes(vec![1, chunk, index], vec![index]);
+        }
+        store.write_batch(batch).await.unwrap();
+    }
+    events.lock().unwrap().clear();
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![1]);
+    store.write_batch(batch).await.unwrap();
+    assert!(store.find_keys_by_prefix(&[1]).await.unwrap().is_empty());
+    // The 100 keys are read in 15 pages, and the journal blocks are written as the pages
+    // arrive, not once all the keys are read.
+    let events = events.lock().unwrap();
+    let pages_read = |events: &[SmallBatchEvent]| {
+        events
+            .iter()
+            .filter(|event| **event == SmallBatchEvent::PageRead)
+            .count()
+    };
+    assert_eq!(pages_read(&events), 15);
+    let first_write = events
+        .iter()
+        .position(|event| *event == SmallBatchEvent::BatchWritten)
+        .unwrap();
+    assert!(pages_read(&events[..first_write]) < 15);
+}
+
+#[tokio::test]
+async fn test_journaling_compact_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    // A journal of four blocks of 96, 46, 66 and 407 bytes, the blocks being of at most
+    // 500 bytes. The last block only fits in the second one, after which the new last
+    // block fits in the first one.
+    let value_lens = [90, 40, 60, 400];
+    let mut batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&4u32).unwrap())],
+    };
+    for (key, value_len) in (0..).zip(value_lens) {
+        let block = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key; value_len])],
+        };
+        batch
+            .insertions
+            .push((vec![0, 2, key, 0, 0, 0], bcs::to_bytes(&block).unwrap()));
+    }
+    inner_store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(inner_store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    assert_eq!(store.compact_journal().await.unwrap(), 2);
+    assert_eq!(store.journal_stats().await.unwrap().entry_count, 2);
+    store.clear_journal().await.unwrap();
+    for (key, value_len) in (0..).zip(value_lens) {
+        assert_eq!(
+            store.read_value_bytes(&[1, key]).await.unwrap(),
+            Some(vec![key; value_len])
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_journaling_compact_journal_with_item_overhead() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let mut inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    // With 250 bytes per item, a merge of blocks writing the header, the merged block and
+    // the deletion of the last block only fits if the merged block has at most 228 bytes,
+    // well below `MAX_VALUE_SIZE`. The two blocks of 126 bytes are not merged.
+    inner_store.item_overhead = 250;
+    let value_lens = [120, 120];
+    let mut batch = SimpleUnorderedBatch::default();
+    batch
+        .insertions
+        .push((vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&2u32).unwrap()));
+    inner_store.write_batch(batch).await.unwrap();
+    for (key, value_len) in (0..).zip(value_lens) {
+        let block = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key; value_len])],
+        };
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![0, 2, key, 0, 0, 0], bcs::to_bytes(&block).unwrap())],
+        };
+        inner_store.write_batch(batch).await.unwrap();
+    }
+    let store = JournalingKeyValueStore::new(inner_store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    assert_eq!(store.compact_journal().await.unwrap(), 0);
+    assert_eq!(store.journal_stats().await.unwrap().entry_count, 2);
+    store.clear_journal().await.unwrap();
+    for (key, value_len) in (0..).zip(value_lens) {
+        assert_eq!(
+            store.read_value_bytes(&[1, key]).await.unwrap(),
+            Some(vec![key; value_len])
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_journaling_journal_stats() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalStats, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::AdminKeyValueStore as _,
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    let store = JournalingKeyValueStore::new(inner_store.clone())
+        .clone_with_root_key(&[])
+        .unwrap();
+    assert_eq!(
+        store.journal_stats().await.unwrap(),
+        JournalStats::default()
+    );
+    // A journal of two blocks, with a third block left over by an earlier write.
+    let mut batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&2u32).unwrap())],
+    };
+    let mut entry_bytes = 0;
+    for key in 0..3u8 {
+        let block = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key; 10])],
+        };
+        let block = bcs::to_bytes(&block).unwrap();
+        entry_bytes += block.len();
+        batch.insertions.push((vec![0, 2, key, 0, 0, 0], block));
+    }
+    inner_store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.journal_stats().await.unwrap(),
+        JournalStats {
+            has_header: true,
+            block_count: 2,
+            entry_count: 3,
+            entry_bytes,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_journaling_write_outcome() {
+    use linera_views::{journaling::WriteOutcome, store::ReadableKeyValueStore as _};
+
+    let (store, events) = new_small_batch_journaling_store().await;
+    let batches_written = || {
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| **event == SmallBatchEvent::BatchWritten)
+            .count()
+    };
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 0], vec![0]);
+    let outcome = store.write_batch_outcome(batch).await.unwrap();
+    assert_eq!(outcome, WriteOutcome::Fastpath);
+    assert_eq!(batches_written(), 1);
+    // The blocks of the journal hold at most 8 operations, two less than a batch, and
+    // each of them is resolved in its own batch.
+    let mut batch = Batch::new();
+    for index in 0..30u8 {
+        batch.put_key_value_bytes(vec![2, index], vec![index]);
+    }
+    let outcome = store.write_batch_outcome(batch).await.unwrap();
+    assert_eq!(outcome, WriteOutcome::Journaled { blocks: 4 });
+    assert!(batches_written() > 1 + 4);
+    assert_eq!(
+        store.read_value_bytes(&[2, 29]).await.unwrap(),
+        Some(vec![29])
+    );
+}
+
+#[tokio::test]
+async fn test_journaling_move_prefix() {
+    use linera_views::{
+        journaling::JournalConsistencyError,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let (store, _) = new_small_batch_journaling_store().await;
+    let mut batch = Batch::new();
+    for index in 0..20u8 {
+        batch.put_key_value_bytes(vec![1, index], vec![index]);
+    }
+    batch.put_key_value_bytes(vec![3, 0], vec![0]);
+    store.write_batch(batch).await.unwrap();
+    // The move is too large for a single batch, so it goes through the journal.
+    assert_eq!(store.move_prefix(&[1], &[2]).await.unwrap(), 20);
+    assert!(store.find_keys_by_prefix(&[1]).await.unwrap().is_empty());
+    for index in 0..20u8 {
+        assert_eq!(
+            store.read_value_bytes(&[2, index]).await.unwrap(),
+            Some(vec![index])
+        );
+    }
+    assert_eq!(
+        store.read_value_bytes(&[3, 0]).await.unwrap(),
+        Some(vec![0])
+    );
+    // Moving again is a no-op.
+    assert_eq!(store.move_prefix(&[1], &[2]).await.unwrap(), 0);
+    assert_eq!(store.find_keys_by_prefix(&[2]).await.unwrap().len(), 20);
+    let result = store.move_prefix(&[2], &[2, 1]).await;
+    assert!(matches!(
+        result,
+        Err(SmallBatchStoreError::Journal(
+            JournalConsistencyError::OverlappingPrefixes
+        ))
+    ));
+}
+
+#[tokio::test]
+async fn test_journaling_recovery_jitter_without_lock() {
+    use std::time::Duration;
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let mut inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    inner_store.recovery_jitter = Duration::from_secs(3600);
+    // A journal of one block, left over by an interrupted write.
+    let block = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1, 2], vec![3])],
+    };
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&1u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], bcs::to_bytes(&block).unwrap()),
+        ],
+    };
+    inner_store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(inner_store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    let clearing = tokio::spawn({
+        let store = store.clone();
+        async move { store.clear_journal().await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    // The recovery waits for its jitter without the journal lock, which the compaction
+    // takes meanwhile.
+    tokio::time::timeout(Duration::from_secs(10), store.compact_journal())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!clearing.is_finished());
+    clearing.abort();
+}
+
+#[tokio::test]
+async fn test_journal_resolver() {
+    use std::time::Duration;
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _},
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    let batch_written = inner_store.batch_written.clone();
+    // A journal of one block, left over under the root key `[1]`.
+    let block = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1, 2], vec![3])],
+    };
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&1u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], bcs::to_bytes(&block).unwrap()),
+        ],
+    };
+    inner_store
+        .clone_with_root_key(&[1])
+        .unwrap()
+        .write_batch(batch)
+        .await
+        .unwrap();
+    batch_written.notified().await;
+    let store = JournalingKeyValueStore::new(inner_store);
+    let resolver = store.spawn_journal_resolver(vec![vec![1]], Duration::from_millis(10));
+    // The journal is resolved in a single batch.
+    batch_written.notified().await;
+    resolver.abort();
+    let store = store.clone_with_root_key(&[1]).unwrap();
+    assert!(!store.journal_stats().await.unwrap().has_header);
+    assert_eq!(
+        store.read_value_bytes(&[1, 2]).await.unwrap(),
+        Some(vec![3])
+    );
+}
+
+#[tokio::test]
+async fn test_journal_resolver_skips_rebased_journal() {
+    use std::time::Duration;
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _},
+    };
+
+    let config = MemoryStore::new_test_config().await.unwrap();
+    let namespace = generate_test_namespace();
+    let inner_store = SmallBatchStore::recreate_and_connect(&config, &namespace)
+        .await
+        .unwrap();
+    let events = inner_store.events.clone();
+    // A journal of one block under the root key `[1]`, which an interrupted
+    // `rebase_journal` was moving to the root key `[2]`.
+    let block = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1, 2], vec![3])],
+    };
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&1u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], bcs::to_bytes(&block).unwrap()),
+            (vec![0, 7], vec![2]),
+        ],
+    };
+    inner_store
+        .clone_with_root_key(&[1])
+        .unwrap()
+        .write_batch(batch)
+        .await
+        .unwrap();
+    let store = JournalingKeyValueStore::new(inner_store);
+    let resolver = store.spawn_journal_resolver(vec![vec![1]], Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    resolver.abort();
+    // The journal is left for the rebase to complete, and never applied.
+    assert_eq!(
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| **event == SmallBatchEvent::BatchWritten)
+            .count(),
+        1
+    );
+    let store = store.clone_with_root_key(&[1]).unwrap();
+    assert!(store.journal_stats().await.unwrap().has_header);
+    assert_eq!(store.read_value_bytes(&[1, 2]).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_lru_caching_value_len() {
+    use linera_views::{
+        lru_caching::{LruCachingStore, StorageCacheConfig},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let memory_store = MemoryStore::new_test_store().await.unwrap();
+    let storage_cache_config = StorageCacheConfig {
+        max_cache_size: 1000,
+        max_entry_size: 100,
+        max_cache_entries: 10,
+    };
+    let store = LruCachingStore::new(memory_store.clone(), storage_cache_config);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1], vec![0; 5]);
+    batch.put_key_value_bytes(vec![2], vec![0; 4]);
+    memory_store.write_batch(batch).await.unwrap();
+    assert_eq!(store.value_len(&[1]).await.unwrap(), Some(5));
+    assert_eq!(store.value_len(&[3]).await.unwrap(), None);
+    // The value read for its length is not cached.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![2], vec![0; 6]);
+    memory_store.write_batch(batch).await.unwrap();
+    assert_eq!(store.value_len(&[2]).await.unwrap(), Some(6));
+    // The length of a cached value is read from the cache, so the writes made without
+    // the caching store are not seen.
+    assert_eq!(
+        store.read_value_bytes(&[1]).await.unwrap(),
+        Some(vec![0; 5])
+    );
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1], vec![0; 7]);
+    memory_store.write_batch(batch).await.unwrap();
+    assert_eq!(store.value_len(&[1]).await.unwrap(), Some(5));
+}
+
+#[tokio::test]
+async fn test_lru_caching_keys_cache() {
+    use linera_views::{
+        lru_caching::{LruCachingStore, StorageCacheConfig},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let memory_store = MemoryStore::new_test_store().await.unwrap();
+    let storage_cache_config = StorageCacheConfig {
+        max_cache_size: 1000,
+        max_entry_size: 100,
+        max_cache_entries: 10,
+    };
+    let store = LruCachingStore::new(memory_store.clone(), storage_cache_config.clone())
+        .with_keys_cache(storage_cache_config);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1], vec![0]);
+    batch.put_key_value_bytes(vec![2, 1], vec![0]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1]]
+    );
+    assert_eq!(
+        store.find_keys_by_prefix(&[2]).await.unwrap(),
+        vec![vec![1]]
+    );
+    // The writes made without the caching store are not seen.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 2], vec![0]);
+    memory_store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1]]
+    );
+    // A write under the prefix drops the cached keys, and only those.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 3], vec![0]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1], vec![2], vec![3]]
+    );
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![2, 2], vec![0]);
+    memory_store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[2]).await.unwrap(),
+        vec![vec![1]]
+    );
+    // So does the deletion of a prefix, be it shorter or longer.
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![1, 3]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1], vec![2]]
+    );
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![]);
+    store.write_batch(batch).await.unwrap();
+    assert!(store.find_keys_by_prefix(&[2]).await.unwrap().is_empty());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_write_hook() {
+    use std::sync::{Arc, Mutex};
+
+    use linera_views::{
+        dynamo_db::WriteHook, journaling::JournalingKeyValueStore,
+        store::WritableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let writes = writes.clone();
+        WriteHook::new(move |deletions, insertions| {
+            writes
+                .lock()
+                .unwrap()
+                .push((deletions.to_vec(), insertions.to_vec()));
+        })
+    };
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_write_hook(hook);
+    let store = JournalingKeyValueStore::new(store);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1], vec![1]);
+    batch.delete_key(vec![1, 2]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        *writes.lock().unwrap(),
+        vec![(vec![vec![1, 2]], vec![(vec![1, 1], vec![1])])]
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_lease() {
+    use std::time::Duration;
+
+    use linera_views::{dynamo_db::DynamoDbStoreInternalError, store::AdminKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let ttl = Duration::from_secs(60);
+    let mut lease = store.acquire_lease(ttl).await.unwrap();
+    assert!(matches!(
+        store.acquire_lease(ttl).await,
+        Err(DynamoDbStoreInternalError::LeaseHeld)
+    ));
+    // The leases of different root keys are independent.
+    let other_store = store.clone_with_root_key(&[1]).unwrap();
+    other_store
+        .acquire_lease(ttl)
+        .await
+        .unwrap()
+        .release()
+        .await
+        .unwrap();
+    lease.renew(ttl).await.unwrap();
+    lease.release().await.unwrap();
+    // An expired lease can be taken over, after which the previous holder lost it.
+    let mut lease = store.acquire_lease(Duration::ZERO).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let other_lease = store.acquire_lease(ttl).await.unwrap();
+    assert!(matches!(
+        lease.renew(ttl).await,
+        Err(DynamoDbStoreInternalError::LeaseLost)
+    ));
+    other_lease.release().await.unwrap();
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_transform_prefix() {
+    use linera_views::{
+        dynamo_db::DynamoDbStoreInternalError,
+        journaling::JournalingKeyValueStore,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = JournalingKeyValueStore::new(store);
+    let mut batch = Batch::new();
+    for index in 1..=4 {
+        batch.put_key_value_bytes(vec![1, index], vec![index]);
+    }
+    store.write_batch(batch).await.unwrap();
+    let transform = |value: &[u8]| -> Result<Vec<u8>, DynamoDbStoreInternalError> {
+        Ok(value.iter().map(|byte| byte.max(&2) * 10).collect())
+    };
+    // Interrupted after the keys up to `[1, 2]` were transformed.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![0, 5, 1], vec![2]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(store.transform_prefix(&[1], transform).await.unwrap(), 2);
+    assert_eq!(
+        store.read_value_bytes(&[1, 2]).await.unwrap(),
+        Some(vec![2])
+    );
+    assert_eq!(
+        store.read_value_bytes(&[1, 4]).await.unwrap(),
+        Some(vec![40])
+    );
+    assert_eq!(store.read_value_bytes(&[0, 5, 1]).await.unwrap(), None);
+    // Without progress record, the whole prefix is transformed.
+    let transform = |value: &[u8]| -> Result<Vec<u8>, DynamoDbStoreInternalError> {
+        Ok(value.iter().map(|byte| byte % 30).collect())
+    };
+    assert_eq!(store.transform_prefix(&[1], transform).await.unwrap(), 2);
+    assert_eq!(
+        store.read_value_bytes(&[1, 4]).await.unwrap(),
+        Some(vec![10])
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_read_with_next() {
+    use linera_views::{batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![1, 1], vec![1]),
+            (vec![1, 2], vec![2]),
+            (vec![2, 1], vec![3]),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let mut entries = Vec::new();
+    let mut after = None;
+    while let Some(((key, value), has_next)) =
+        store.read_with_next(&[1], after.as_deref()).await.unwrap()
+    {
+        entries.push((key.clone(), value, has_next));
+        after = Some(key);
+    }
+    assert_eq!(
+        entries,
+        vec![(vec![1, 1], vec![1], true), (vec![1, 2], vec![2], false)]
+    );
+    assert_eq!(store.read_with_next(&[3], None).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_lru_caching_skips_unchanged_puts() {
+    use linera_views::{
+        lru_caching::{LruCachingStore, UnchangedPuts, DEFAULT_STORAGE_CACHE_CONFIG},
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let memory_store = MemoryStore::new_test_store().await.unwrap();
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1], vec![1]);
+    batch.put_key_value_bytes(vec![2], vec![2]);
+    memory_store.write_batch(batch).await.unwrap();
+    for unchanged_puts in [UnchangedPuts::SkipCached, UnchangedPuts::SkipStored] {
+        let store = LruCachingStore::new(memory_store.clone(), DEFAULT_STORAGE_CACHE_CONFIG)
+            .with_unchanged_puts(unchanged_puts);
+        assert_eq!(store.read_value_bytes(&[1]).await.unwrap(), Some(vec![1]));
+        // Changes made behind the cache reveal which puts were skipped.
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1], vec![3]);
+        batch.put_key_value_bytes(vec![2], vec![3]);
+        memory_store.write_batch(batch).await.unwrap();
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1], vec![1]);
+        batch.put_key_value_bytes(vec![2], vec![3]);
+        store.write_batch(batch).await.unwrap();
+        assert_eq!(
+            memory_store.read_value_bytes(&[1]).await.unwrap(),
+            Some(vec![3])
+        );
+        // A put following a deletion in the same batch is always written.
+        let mut batch = Batch::new();
+        batch.delete_key(vec![2]);
+        batch.put_key_value_bytes(vec![2], vec![3]);
+        store.write_batch(batch).await.unwrap();
+        assert_eq!(
+            memory_store.read_value_bytes(&[2]).await.unwrap(),
+            Some(vec![3])
+        );
+        // Restore the initial state.
+        let mut batch = Batch::new();
+        batch.put_key_value_bytes(vec![1], vec![1]);
+        batch.put_key_value_bytes(vec![2], vec![2]);
+        memory_store.write_batch(batch).await.unwrap();
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_auto_split_oversized_batches() {
+    use linera_views::{
+        journaling::{JournalingKeyValueStore, OversizedBatches, WriteOutcome},
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // The journal requires exclusive access, which splitting the batch does not.
+    let store =
+        JournalingKeyValueStore::new(store).with_oversized_batches(OversizedBatches::AutoSplit);
+    let mut batch = Batch::new();
+    for index in 0..250u8 {
+        batch.put_key_value_bytes(vec![1, index], vec![index]);
+    }
+    let outcome = store.write_batch_outcome(batch).await.unwrap();
+    assert_eq!(outcome, WriteOutcome::Split { transactions: 3 });
+    let stats = store.journal_stats().await.unwrap();
+    assert!(!stats.has_header);
+    for index in 0..250u8 {
+        assert_eq!(
+            store.read_value_bytes(&[1, index]).await.unwrap(),
+            Some(vec![index])
+        );
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_probe_limits() {
+    use linera_views::store::{KeyIterable as _, ReadableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let limits = store.probe_limits().await.unwrap();
+    assert!(limits.supports_store_values());
+    // The item of the largest value also holds the key of the probe.
+    assert!(limits.max_item_size > limits.max_value_size);
+    // The sentinel item of the probe is deleted.
+    let keys = store.find_keys_by_prefix(&[0]).await.unwrap();
+    assert_eq!(keys.iterator().count(), 0);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_find_key_values_capped() {
+    use linera_views::{batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![1, 1], vec![1]),
+            (vec![1, 2], vec![2]),
+            (vec![1, 3], vec![3]),
+            (vec![2, 1], vec![4]),
+            (vec![3, 1], vec![5]),
+            (vec![3, 2], vec![6]),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let prefixes = vec![vec![1], vec![2], vec![3]];
+    let key_values = store
+        .find_key_values_capped(prefixes.clone(), 5)
+        .await
+        .unwrap();
+    let keys = key_values
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        vec![vec![1, 1], vec![2, 1], vec![3, 1], vec![1, 2], vec![3, 2]]
+    );
+    let key_values = store.find_key_values_capped(prefixes, 100).await.unwrap();
+    assert_eq!(key_values.len(), 6);
+    assert_eq!(key_values[5], (vec![1, 3], vec![3]));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_set_values() {
+    use std::collections::BTreeSet;
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::DirectWritableKeyValueStore as _,
+        store::{KeyIterable as _, KeyValueIterable as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    assert!(store.set_read(&[1]).await.unwrap().is_empty());
+    store
+        .set_add(&[1], vec![vec![1], vec![2], vec![1]])
+        .await
+        .unwrap();
+    store.set_add(&[1], vec![vec![3]]).await.unwrap();
+    store
+        .set_remove(&[1], vec![vec![2], vec![4]])
+        .await
+        .unwrap();
+    assert_eq!(
+        store.set_read(&[1]).await.unwrap(),
+        BTreeSet::from([vec![1], vec![3]])
+    );
+    store
+        .set_remove(&[1], vec![vec![1], vec![3]])
+        .await
+        .unwrap();
+    assert!(store.set_read(&[1]).await.unwrap().is_empty());
+    // Neither the emptied set nor the removal from a missing set leave an item behind, so
+    // the scans still work.
+    store.set_remove(&[1, 2], vec![vec![1]]).await.unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1, 3], vec![4])],
+    };
+    store.write_batch(batch).await.unwrap();
+    let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+    assert_eq!(
+        keys.iterator().collect::<Result<Vec<_>, _>>().unwrap(),
+        vec![&[3][..]]
+    );
+    let key_values = store.find_key_values_by_prefix(&[1]).await.unwrap();
+    assert_eq!(
+        key_values
+            .iterator()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        vec![(&[3][..], &[4][..])]
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_next_sequence() {
+    use futures::future::try_join_all;
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    assert_eq!(store.next_sequence(&[1]).await.unwrap(), 1);
+    let mut values = try_join_all((0..20).map(|_| store.next_sequence(&[1])))
+        .await
+        .unwrap();
+    values.sort_unstable();
+    assert_eq!(values, (2..22).collect::<Vec<_>>());
+    assert_eq!(store.next_sequence(&[2]).await.unwrap(), 1);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_reject_conflicting_operations() {
+    use linera_views::{
+        dynamo_db::DynamoDbStoreInternalError,
+        journaling::JournalingKeyValueStore,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_reject_conflicting_operations(true);
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = JournalingKeyValueStore::new(store);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 1], vec![1]);
+    batch.delete_key(vec![1, 1]);
+    batch.delete_key(vec![1, 2]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(store.read_value_bytes(&[1, 1]).await.unwrap(), None);
+    let mut batch = Batch::new();
+    batch.delete_key(vec![1, 2]);
+    batch.put_key_value_bytes(vec![1, 2], vec![2]);
+    assert!(matches!(
+        store.write_batch(batch).await,
+        Err(DynamoDbStoreInternalError::ConflictingSameKeyOps { key }) if key == [1, 2]
+    ));
+    assert_eq!(store.read_value_bytes(&[1, 2]).await.unwrap(), None);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_read_key_with_metadata() {
+    use linera_base::time::SystemTime;
+    use linera_views::{batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _};
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_record_modification_times(true);
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let before = SystemTime::now();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![2])],
+    };
+    store.write_batch(batch).await.unwrap();
+    let (value, modified) = store.read_key_with_metadata(&[1]).await.unwrap().unwrap();
+    assert_eq!(value, vec![2]);
+    let modified = modified.unwrap();
+    assert!(modified >= before - std::time::Duration::from_millis(1));
+    assert!(modified <= SystemTime::now());
+    assert_eq!(store.read_key_with_metadata(&[2]).await.unwrap(), None);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_rebase_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::{DynamoDbStoreInternalError, Fault, FaultInjector},
+        journaling::{
+            DirectWritableKeyValueStore as _, JournalConsistencyError, JournalingKeyValueStore,
+        },
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let direct_store = store.clone();
+    // Writes a journal of a single block inserting `key_value` under `root_key`.
+    let write_journal = |root_key: Vec<u8>, key_value: (Vec<u8>, Vec<u8>)| {
+        let store = store.clone_with_root_key(&root_key).unwrap();
+        async move {
+            let block = SimpleUnorderedBatch {
+                deletions: Vec::new(),
+                insertions: vec![key_value],
+            };
+            let batch = SimpleUnorderedBatch {
+                deletions: Vec::new(),
+                insertions: vec![
+                    (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&1u32).unwrap()),
+                    (vec![0, 2, 0, 0, 0, 0], bcs::to_bytes(&block).unwrap()),
+                ],
+            };
+            store.write_batch(batch).await.unwrap();
+        }
+    };
+    write_journal(vec![1], (vec![5], vec![6])).await;
+    let store = JournalingKeyValueStore::new(store.clone());
+    store.rebase_journal(&[1], &[2]).await.unwrap();
+    let old = store.clone_with_root_key(&[1]).unwrap();
+    let stats = old.journal_stats().await.unwrap();
+    assert!(!stats.has_header);
+    assert_eq!(stats.entry_count, 0);
+    let new = store.clone_with_root_key(&[2]).unwrap();
+    new.clear_journal().await.unwrap();
+    assert_eq!(new.read_value_bytes(&[5]).await.unwrap(), Some(vec![6]));
+    assert_eq!(old.read_value_bytes(&[5]).await.unwrap(), None);
+
+    // A different journal under the new root key is not overwritten.
+    write_journal(vec![3], (vec![5], vec![7])).await;
+    write_journal(vec![4], (vec![5], vec![8])).await;
+    assert!(matches!(
+        store.rebase_journal(&[3], &[4]).await,
+        Err(DynamoDbStoreInternalError::JournalConsistencyError(
+            JournalConsistencyError::JournalAlreadyExists
+        ))
+    ));
+
+    // A crash at any point of the move never lets the journal be applied twice.
+    for crash_at in 1u8.. {
+        let old_root_key = vec![10, crash_at];
+        let new_root_key = vec![11, crash_at];
+        write_journal(old_root_key.clone(), (vec![5], vec![crash_at])).await;
+        let injector = FaultInjector::new();
+        let store = JournalingKeyValueStore::new(
+            direct_store.clone().with_fault_injector(injector.clone()),
+        );
+        injector.fail_nth("TransactWriteItems", crash_at.into(), Fault::Crash);
+        let result = store.rebase_journal(&old_root_key, &new_root_key).await;
+        if injector.num_injected() == 0 {
+            result.unwrap();
+            break;
+        }
+        assert!(result.is_err());
+        let old = store.clone_with_root_key(&old_root_key).unwrap();
+        let new = store.clone_with_root_key(&new_root_key).unwrap();
+        match old.clear_journal().await {
+            Ok(()) => {
+                // Either the move was not recorded yet and the old journal is resolved in
+                // place, or the old header was already deleted.
+                new.clear_journal().await.unwrap();
+                let old_value = old.read_value_bytes(&[5]).await.unwrap();
+                let new_value = new.read_value_bytes(&[5]).await.unwrap();
+                assert_ne!(old_value.is_some(), new_value.is_some());
+                assert_eq!(old_value.or(new_value), Some(vec![crash_at]));
+            }
+            Err(error) => {
+                assert!(matches!(
+                    error,
+                    DynamoDbStoreInternalError::JournalConsistencyError(
+                        JournalConsistencyError::JournalBeingRebased { .. }
+                    )
+                ));
+                store
+                    .rebase_journal(&old_root_key, &new_root_key)
+                    .await
+                    .unwrap();
+                old.clear_journal().await.unwrap();
+                new.clear_journal().await.unwrap();
+                assert_eq!(old.read_value_bytes(&[5]).await.unwrap(), None);
+                assert_eq!(
+                    new.read_value_bytes(&[5]).await.unwrap(),
+                    Some(vec![crash_at])
+                );
+            }
+        }
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_read_key_with() {
+    use linera_views::{batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _};
+
+    let config = dynamo_db_test_config().await.with_value_checksums(true);
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![2, 3, 4])],
+    };
+    store.write_batch(batch).await.unwrap();
+    let sum = store
+        .read_key_with(&[1], |value| value.map(|value| value.iter().sum::<u8>()))
+        .await
+        .unwrap();
+    assert_eq!(sum, Some(9));
+    let missing = store.read_key_with(&[2], |value| value.is_none()).await;
+    assert!(missing.unwrap());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_recover_all_journals() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::DynamoDbStoreInternal,
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    // Journals of a single block inserting a key under two root keys.
+    for root_key in [[1], [2]] {
+        let store = store.clone_with_root_key(&root_key).unwrap();
+        let block = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![5], root_key.to_vec())],
+        };
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![
+                (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&1u32).unwrap()),
+                (vec![0, 2, 0, 0, 0, 0], bcs::to_bytes(&block).unwrap()),
+            ],
+        };
+        store.write_batch(batch).await.unwrap();
+    }
+    let count =
+        JournalingKeyValueStore::<DynamoDbStoreInternal>::recover_all_journals(&config, &namespace)
+            .await
+            .unwrap();
+    assert_eq!(count, 2);
+    let store = JournalingKeyValueStore::new(store);
+    for root_key in [[1], [2]] {
+        let store = store.clone_with_root_key(&root_key).unwrap();
+        assert_eq!(
+            store.read_value_bytes(&[5]).await.unwrap(),
+            Some(root_key.to_vec())
+        );
+        assert!(!store.journal_stats().await.unwrap().has_header);
+    }
+}
+
+#[cfg(with_packing)]
+#[tokio::test]
+async fn test_reads_packing_memory() {
+    use linera_views::packing::PackingStore;
+    for scenario in get_random_test_scenarios() {
+        let store = PackingStore::<MemoryStore>::new_test_store().await.unwrap();
+        run_reads(store, scenario).await;
+    }
+}
+
+#[cfg(with_packing)]
+#[tokio::test]
+async fn test_packing_memory_writes_from_blank() {
+    use linera_views::packing::PackingStore;
+    let store = PackingStore::<MemoryStore>::new_test_store().await.unwrap();
+    run_writes_from_blank(&store).await;
+}
+
+#[cfg(with_packing)]
+#[tokio::test]
+async fn test_packing_memory_writes_from_state() {
+    use linera_views::packing::PackingStore;
+    let store = PackingStore::<MemoryStore>::new_test_store().await.unwrap();
+    run_writes_from_state(&store).await;
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_update() {
+    let config = dynamo_db_test_config().await.with_max_update_attempts(100);
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let increment = |value: Option<Vec<u8>>| {
+        let count = value.map_or(0, |value| u64::from_le_bytes(value.try_into().unwrap()));
+        Some((count + 1).to_le_bytes().to_vec())
+    };
+    // Concurrent writers incrementing the same counter do not lose any increment.
+    let writers = (0..4).map(|_| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            for _ in 0..10 {
+                store.update(&[1], increment).await.unwrap();
+            }
+        })
+    });
+    for writer in writers.collect::<Vec<_>>() {
+        writer.await.unwrap();
+    }
+    let value = store.read_key_with(&[1], |value| value.map(<[u8]>::to_vec));
+    assert_eq!(value.await.unwrap(), Some(40u64.to_le_bytes().to_vec()));
+    store.update(&[1], |_| None).await.unwrap();
+    let value = store.read_key_with(&[1], |value| value.is_some());
+    assert!(!value.await.unwrap());
+}
+
+#[tokio::test]
+async fn test_reads_hash_prefix_memory() {
+    use linera_views::hash_prefix::HashPrefixStore;
+    for scenario in get_random_test_scenarios() {
+        let store = HashPrefixStore::<MemoryStore>::new_test_store()
+            .await
+            .unwrap();
+        run_reads(store, scenario).await;
+    }
+}
+
+#[tokio::test]
+async fn test_hash_prefix_memory_writes_from_blank() {
+    use linera_views::hash_prefix::HashPrefixStore;
+    let store = HashPrefixStore::<MemoryStore>::new_test_store()
+        .await
+        .unwrap();
+    run_writes_from_blank(&store).await;
+}
+
+#[tokio::test]
+async fn test_hash_prefix_memory_writes_from_state() {
+    use linera_views::hash_prefix::HashPrefixStore;
+    let store = HashPrefixStore::<MemoryStore>::new_test_store()
+        .await
+        .unwrap();
+    run_writes_from_state(&store).await;
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_find_typed_by_prefix() {
+    use futures::TryStreamExt as _;
+    use linera_views::{
+        batch::SimpleUnorderedBatch, dynamo_db::DynamoDbStoreInternalError,
+        journaling::DirectWritableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let mut insertions = (0..5u64)
+        .map(|index| {
+            let value = bcs::to_bytes(&(index, format!("value {index}"))).unwrap();
+            (vec![1, index as u8], value)
+        })
+        .collect::<Vec<_>>();
+    insertions.push((vec![2, 0], vec![1]));
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions,
+    };
+    store.write_batch(batch).await.unwrap();
+    let entries = store
+        .find_typed_by_prefix::<(u64, String)>(&[1])
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let expected = (0..5u64)
+        .map(|index| (vec![index as u8], (index, format!("value {index}"))))
+        .collect::<Vec<_>>();
+    assert_eq!(entries, expected);
+    let result = store
+        .find_typed_by_prefix::<u64>(&[2])
+        .try_collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        result,
+        Err(DynamoDbStoreInternalError::BcsErrorAtKey { key, .. }) if key == [0]
+    ));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_consistent_reads() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _,
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![10]), (vec![2], vec![20])],
+    };
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.read_key_bytes_consistent(&[1]).await.unwrap(),
+        Some(vec![10])
+    );
+    assert_eq!(store.read_key_bytes_consistent(&[3]).await.unwrap(), None);
+    let keys = vec![vec![1], vec![2], vec![3]];
+    let values = store
+        .read_multi_key_bytes_consistent(keys.clone())
+        .await
+        .unwrap();
+    assert_eq!(values, vec![Some(vec![10]), Some(vec![20]), None]);
+    assert_eq!(store.read_multi_values_bytes(keys).await.unwrap(), values);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_export_import_prefix() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::DirectWritableKeyValueStore as _,
+        store::{KeyValueIterable as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let insertions = (0..250u32)
+        .map(|index| {
+            let mut key = vec![1];
+            key.extend(index.to_be_bytes());
+            (key, vec![index as u8; 100])
+        })
+        .collect::<Vec<_>>();
+    for chunk in insertions.chunks(100) {
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: chunk.to_vec(),
+        };
+        store.write_batch(batch).await.unwrap();
+    }
+    let mut export = futures::io::Cursor::new(Vec::new());
+    assert_eq!(store.export_prefix(&[1], &mut export).await.unwrap(), 250);
+    let export = export.into_inner();
+
+    // A partial import is resumed by importing again.
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![2, 0, 0, 0, 0], vec![0; 100])],
+    };
+    store.write_batch(batch).await.unwrap();
+    let mut reader = futures::io::Cursor::new(export);
+    assert_eq!(store.import_prefix(&[2], &mut reader).await.unwrap(), 249);
+    let expected = store.find_key_values_by_prefix(&[1]).await.unwrap();
+    let imported = store.find_key_values_by_prefix(&[2]).await.unwrap();
+    assert_eq!(
+        imported
+            .into_iterator_owned()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        expected
+            .into_iterator_owned()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_separate_internal_partition() {
+    use std::time::Duration;
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::DynamoDbStoreInternal,
+        journaling::DirectWritableKeyValueStore as _,
+        store::{AdminKeyValueStore as _, KeyIterable as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    let separate_config = config.with_separate_internal_partition(true);
+    let separate_store = DynamoDbStoreInternal::connect(&separate_config, &namespace)
+        .await
+        .unwrap()
+        .clone_with_root_key(&[1])
+        .unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![10])],
+    };
+    separate_store.write_batch(batch).await.unwrap();
+    let lease = separate_store
+        .acquire_lease(Duration::from_secs(60))
+        .await
+        .unwrap();
+    // The lease is stored apart from the data of the root key.
+    async fn count_internal_keys(store: &DynamoDbStoreInternal) -> usize {
+        let keys = store.find_keys_by_prefix(&[0]).await.unwrap();
+        keys.iterator().count()
+    }
+    assert_eq!(count_internal_keys(&separate_store).await, 1);
+    let store = store.clone_with_root_key(&[1]).unwrap();
+    assert_eq!(count_internal_keys(&store).await, 0);
+    assert_eq!(store.read_value_bytes(&[1]).await.unwrap(), Some(vec![10]));
+    lease.release().await.unwrap();
+    assert_eq!(count_internal_keys(&separate_store).await, 0);
+    let root_keys = DynamoDbStoreInternal::list_root_keys(&separate_config, &namespace)
+        .await
+        .unwrap();
+    assert_eq!(root_keys, vec![vec![1]]);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_exact_item_count() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _,
+        store::AdminKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    for (root_key, num_keys) in [(1, 3u8), (2, 2)] {
+        let batch = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: (0..num_keys).map(|key| (vec![1, key], vec![key])).collect(),
+        };
+        let store = store.clone_with_root_key(&[root_key]).unwrap();
+        store.write_batch(batch).await.unwrap();
+    }
+    assert_eq!(store.exact_item_count(true, 1).await.unwrap(), 5);
+    assert_eq!(store.exact_item_count(true, 4).await.unwrap(), 5);
+    // The two root keys are registered in the table.
+    let count = store.exact_item_count(false, 3).await.unwrap();
+    assert!(count >= 7);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_empty_key_prefix() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::{DynamoDbStoreInternal, DynamoDbStoreInternalError},
+        journaling::DirectWritableKeyValueStore as _,
+        store::{
+            AdminKeyValueStore as _, KeyIterable as _, KeyValueIterable as _,
+            ReadableKeyValueStore as _,
+        },
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: (0..3u8).map(|key| (vec![1, key], vec![key])).collect(),
+    };
+    store.write_batch(batch).await.unwrap();
+    assert!(matches!(
+        store.find_keys_by_prefix(&[]).await,
+        Err(DynamoDbStoreInternalError::ZeroLengthKeyPrefix)
+    ));
+
+    let config = config.with_allow_empty_key_prefix(true);
+    let store = DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap();
+    let keys = store.find_keys_by_prefix(&[]).await.unwrap();
+    let keys = keys
+        .iterator()
+        .map(|key| key.unwrap().to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec![vec![1, 0], vec![1, 1], vec![1, 2]]);
+    let key_values = store.find_key_values_by_prefix(&[]).await.unwrap();
+    assert_eq!(key_values.iterator().count(), 3);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_write_batch_per_partition() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, dynamo_db::DynamoDbStoreInternalError,
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_separate_internal_partition(true);
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // The internal key and the key of the data are in two partitions.
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![0, 5], vec![1]), (vec![1, 1], vec![2])],
+    };
+    assert_eq!(store.write_batch_per_partition(batch).await.unwrap(), 2);
+    assert_eq!(
+        store.read_value_bytes(&[0, 5]).await.unwrap(),
+        Some(vec![1])
+    );
+    assert_eq!(
+        store.read_value_bytes(&[1, 1]).await.unwrap(),
+        Some(vec![2])
+    );
+
+    // A partition with too many operations fails the whole batch.
+    let batch = SimpleUnorderedBatch {
+        deletions: vec![vec![0, 5]],
+        insertions: (0..=100u8)
+            .map(|key| (vec![1, 2, key], vec![key]))
+            .collect(),
+    };
+    assert!(matches!(
+        store.write_batch_per_partition(batch).await,
+        Err(DynamoDbStoreInternalError::TransactUpperLimitSize)
+    ));
+    assert_eq!(
+        store.read_value_bytes(&[0, 5]).await.unwrap(),
+        Some(vec![1])
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_inspect_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        journaling::{
+            DirectWritableKeyValueStore as _, JournalEntrySummary, JournalingKeyValueStore,
+        },
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A pending journal of two blocks, the second one numbered 256 so that its key
+    // sorts before the first one.
+    let first_block = SimpleUnorderedBatch {
+        deletions: vec![vec![1, 1]],
+        insertions: vec![(vec![1, 2], vec![2]), (vec![1, 3], vec![3])],
+    };
+    let second_block = SimpleUnorderedBatch {
+        deletions: vec![vec![1, 4], vec![1, 5]],
+        insertions: Vec::new(),
+    };
+    let first_block = bcs::to_bytes(&first_block).unwrap();
+    let second_block = bcs::to_bytes(&second_block).unwrap();
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&2u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], first_block.clone()),
+            (vec![0, 2, 0, 1, 0, 0], second_block.clone()),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    let summaries = store.inspect_journal().await.unwrap();
+    assert_eq!(
+        summaries,
+        vec![
+            JournalEntrySummary {
+                block: 0,
+                deletions: 1,
+                insertions: 2,
+                bytes: first_block.len(),
+            },
+            JournalEntrySummary {
+                block: 256,
+                deletions: 2,
+                insertions: 0,
+                bytes: second_block.len(),
+            },
+        ]
+    );
+    // Nothing was applied.
+    assert!(store.contains_key(&[0, 1, 0, 0, 0, 0]).await.unwrap());
+    assert!(!store.contains_key(&[1, 2]).await.unwrap());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_discard_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::DynamoDbStoreInternalError,
+        journaling::{
+            DirectWritableKeyValueStore as _, JournalConsistencyError, JournalingKeyValueStore,
+        },
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A pending journal of two blocks inserting a key each.
+    let block = |key: u8| {
+        bcs::to_bytes(&SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key])],
+        })
+        .unwrap()
+    };
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![
+            (vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&2u32).unwrap()),
+            (vec![0, 2, 0, 0, 0, 0], block(1)),
+            (vec![0, 2, 1, 0, 0, 0], block(2)),
+        ],
+    };
+    store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    assert!(matches!(
+        store.discard_journal(Some(3)).await,
+        Err(DynamoDbStoreInternalError::JournalConsistencyError(
+            JournalConsistencyError::UnexpectedJournal {
+                expected: Some(3),
+                found: Some(2),
+            }
+        ))
+    ));
+    assert_eq!(store.discard_journal(Some(2)).await.unwrap(), 2);
+    assert!(!store.journal_stats().await.unwrap().has_header);
+    assert_eq!(store.journal_stats().await.unwrap().entry_count, 0);
+    // The blocks were not applied.
+    assert!(!store.contains_key(&[1, 1]).await.unwrap());
+    assert!(!store.contains_key(&[1, 2]).await.unwrap());
+    assert_eq!(store.discard_journal(None).await.unwrap(), 0);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_copy_to() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _,
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // Key-values under several view tags, more than a `BatchWriteItem` request holds,
+    // and an internal key, which is not copied.
+    let mut insertions = (0..60u8)
+        .map(|index| (vec![1 + index % 3, index], vec![index]))
+        .collect::<Vec<_>>();
+    insertions.push((vec![0, 9], vec![9]));
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions,
+    };
+    store.write_batch(batch).await.unwrap();
+    for concurrency in [1, 8] {
+        let (target, _) = new_dynamo_db_test_store(&config).await;
+        assert_eq!(store.copy_to(&target, concurrency).await.unwrap(), 60);
+        let diff = store.diff(&target, 10, concurrency).await.unwrap();
+        assert!(diff.is_empty());
+        assert!(!target.contains_key(&[0, 9]).await.unwrap());
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_capabilities() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::{Capabilities, DynamoDbStoreInternal},
+        journaling::DirectWritableKeyValueStore as _,
+        store::{AdminKeyValueStore as _, KeyIterable as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    // The probe only reads, so the store is still empty.
+    let capabilities = store.capabilities().await.unwrap();
+    assert!(capabilities.transactions);
+    let keys = store.find_keys_by_prefix(&[0]).await.unwrap();
+    assert_eq!(keys.iterator().count(), 0);
+    let store = store.clone_with_root_key(&[1]).unwrap();
+    assert_eq!(store.capabilities().await.unwrap(), capabilities);
+
+    // The configured capabilities are used as they are.
+    let capabilities = Capabilities {
+        transactions: false,
+        time_to_live: false,
+        partiql: true,
+    };
+    let config = config.with_capabilities(Some(capabilities));
+    let store = DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap();
+    assert_eq!(store.capabilities().await.unwrap(), capabilities);
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![2])],
+    };
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(store.read_value_bytes(&[1]).await.unwrap(), Some(vec![2]));
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_touch() {
+    use std::time::{Duration, SystemTime};
+
+    use linera_views::{
+        batch::SimpleUnorderedBatch, journaling::DirectWritableKeyValueStore as _,
+        store::ReadableKeyValueStore as _,
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let expiry = SystemTime::now() + Duration::from_secs(3600);
+    // A missing item is not created.
+    assert!(!store.touch(&[1], expiry).await.unwrap());
+    assert!(!store.contains_key(&[1]).await.unwrap());
+
+    let batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1], vec![2, 3])],
+    };
+    store.write_batch(batch).await.unwrap();
+    assert!(store.touch(&[1], expiry).await.unwrap());
+    assert!(store
+        .touch(&[1], expiry + Duration::from_secs(60))
+        .await
+        .unwrap());
+    assert_eq!(
+        store.read_value_bytes(&[1]).await.unwrap(),
+        Some(vec![2, 3])
+    );
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_fault_injection() {
+    use linera_views::{
+        batch::Batch,
+        dynamo_db::{DynamoDbStoreInternal, Fault, FaultInjector},
+        journaling::JournalingKeyValueStore,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let injector = FaultInjector::new();
+    let (store, namespace) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_fault_injector(injector.clone());
+    let store = JournalingKeyValueStore::new(store);
+    // A throttled request is retried by the SDK.
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 0], vec![0]);
+    injector.fail_first(1, Fault::Throttle);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(injector.num_injected(), 1);
+    assert_eq!(
+        store.read_value_bytes(&[1, 0]).await.unwrap(),
+        Some(vec![0])
+    );
+    // A batch too large for a single transaction goes through the journal. The crash
+    // happens after the journal is written, while it is being resolved.
+    let mut batch = Batch::new();
+    for i in 0..200u8 {
+        batch.put_key_value_bytes(vec![2, i], vec![i]);
+    }
+    injector.fail_nth("TransactWriteItems", 2, Fault::Crash);
+    assert!(store.write_batch(batch).await.is_err());
+    assert_eq!(injector.num_injected(), 2);
+    assert!(!store.contains_key(&[2, 199]).await.unwrap());
+    // A new store resolves the pending journal.
+    let store = DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    assert!(store.journal_stats().await.unwrap().has_header);
+    store.clear_journal().await.unwrap();
+    for i in 0..200u8 {
+        assert_eq!(
+            store.read_value_bytes(&[2, i]).await.unwrap(),
+            Some(vec![i])
+        );
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_validate_batch() {
+    use linera_views::{batch::Batch, dynamo_db::BatchViolation};
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 0], vec![0]);
+    batch.delete_key(vec![1, 1]);
+    let validation = store.validate_batch(&batch).unwrap();
+    assert!(validation.is_valid());
+    assert!(validation.fits_fastpath);
+    assert_eq!(validation.prefix_deletions, 0);
+
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(Vec::new(), vec![0]);
+    batch.delete_key(vec![1; 2000]);
+    batch.put_key_value_bytes(vec![0, 1], vec![0]);
+    batch.put_key_value_bytes(vec![1, 2], vec![0; 500000]);
+    batch.delete_key_prefix(Vec::new());
+    let violations = store.validate_batch(&batch).unwrap().violations;
+    assert_eq!(violations.len(), 6);
+    assert_eq!(violations[0], BatchViolation::EmptyKey { index: 0 });
+    assert_eq!(
+        violations[1],
+        BatchViolation::KeyTooLong {
+            index: 1,
+            length: 2000
+        }
+    );
+    assert_eq!(violations[2], BatchViolation::ReservedKey { index: 2 });
+    assert_eq!(
+        violations[3],
+        BatchViolation::ValueTooLarge {
+            index: 3,
+            length: 500000
+        }
+    );
+    assert!(matches!(
+        violations[4],
+        BatchViolation::ItemTooLarge { index: 3, size } if size > 500000
+    ));
+    assert_eq!(violations[5], BatchViolation::ReservedKey { index: 4 });
+    assert_eq!(store.validate_batch(&batch).unwrap().prefix_deletions, 1);
+
+    let mut batch = Batch::new();
+    for i in 0..200u8 {
+        batch.put_key_value_bytes(vec![1, i], vec![i]);
+    }
+    let validation = store.validate_batch(&batch).unwrap();
+    assert!(validation.is_valid());
+    assert!(!validation.fits_fastpath);
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_write_batch_with_confirmation() {
+    use std::sync::Mutex;
+
+    use linera_views::{
+        batch::Batch,
+        dynamo_db::{DynamoDbStoreInternal, Fault, FaultInjector},
+        journaling::{JournalingKeyValueStore, WriteOutcome},
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, namespace) = new_dynamo_db_journaling_test_store(&config).await;
+    let confirmations = Mutex::new(Vec::new());
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![1, 0], vec![0]);
+    let outcome = store
+        .write_batch_with_confirmation(batch, |outcome| confirmations.lock().unwrap().push(outcome))
+        .await
+        .unwrap();
+    assert_eq!(outcome, WriteOutcome::Fastpath);
+    let mut batch = Batch::new();
+    for index in 0..200u8 {
+        batch.put_key_value_bytes(vec![2, index], vec![index]);
+    }
+    let outcome = store
+        .write_batch_with_confirmation(batch, |outcome| confirmations.lock().unwrap().push(outcome))
+        .await
+        .unwrap();
+    assert!(matches!(outcome, WriteOutcome::Journaled { .. }));
+    assert_eq!(
+        *confirmations.lock().unwrap(),
+        vec![WriteOutcome::Fastpath, outcome]
+    );
+    assert!(store.contains_key(&[2, 199]).await.unwrap());
+
+    // A failed write is not confirmed.
+    let injector = FaultInjector::new();
+    let store = DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap()
+        .with_fault_injector(injector.clone());
+    let store = JournalingKeyValueStore::new(store);
+    injector.fail_first(1, Fault::Crash);
+    let mut batch = Batch::new();
+    batch.put_key_value_bytes(vec![3, 0], vec![0]);
+    assert!(store
+        .write_batch_with_confirmation(batch, |_| panic!("the write failed"))
+        .await
+        .is_err());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_compact_journal() {
+    use linera_views::{
+        batch::SimpleUnorderedBatch,
+        dynamo_db::{DynamoDbStoreInternalError, Fault, FaultInjector},
+        journaling::{
+            DirectWritableKeyValueStore as _, JournalConsistencyError, JournalingKeyValueStore,
+        },
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let injector = FaultInjector::new();
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = store.with_fault_injector(injector.clone());
+    // A fragmented journal of six blocks inserting a key each.
+    let mut batch = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![0, 1, 0, 0, 0, 0], bcs::to_bytes(&6u32).unwrap())],
+    };
+    for key in 0..6u8 {
+        let block = SimpleUnorderedBatch {
+            deletions: Vec::new(),
+            insertions: vec![(vec![1, key], vec![key])],
+        };
+        batch
+            .insertions
+            .push((vec![0, 2, key, 0, 0, 0], bcs::to_bytes(&block).unwrap()));
+    }
+    store.write_batch(batch).await.unwrap();
+    let store = JournalingKeyValueStore::new(store);
+    assert!(matches!(
+        store.compact_journal().await,
+        Err(DynamoDbStoreInternalError::JournalConsistencyError(
+            JournalConsistencyError::JournalRequiresExclusiveAccess
+        ))
+    ));
+    let store = store.clone_with_root_key(&[]).unwrap();
+    // An interrupted compaction leaves a valid journal. The first transaction registers
+    // the root key of the cloned store.
+    injector.fail_nth("TransactWriteItems", 4, Fault::Crash);
+    assert!(store.compact_journal().await.is_err());
+    assert_eq!(store.journal_stats().await.unwrap().entry_count, 4);
+    assert_eq!(store.compact_journal().await.unwrap(), 3);
+    assert_eq!(store.journal_stats().await.unwrap().entry_count, 1);
+    assert_eq!(store.inspect_journal().await.unwrap()[0].insertions, 6);
+    assert_eq!(store.compact_journal().await.unwrap(), 0);
+    store.clear_journal().await.unwrap();
+    assert!(!store.journal_stats().await.unwrap().has_header);
+    for key in 0..6u8 {
+        assert_eq!(
+            store.read_value_bytes(&[1, key]).await.unwrap(),
+            Some(vec![key])
+        );
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_create_writes_schema_version() {
+    use linera_views::{
+        dynamo_db::DynamoDbStoreInternal,
+        random::generate_test_namespace,
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config()
+        .await
+        .with_check_schema_version(true);
+    let namespace = generate_test_namespace();
+    // The table is written to right after its creation, once it is active.
+    DynamoDbStoreInternal::create(&config, &namespace)
+        .await
+        .unwrap();
+    let store = DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap();
+    assert!(!store.contains_key(&[1]).await.unwrap());
+    // Creating the table again fails without touching its schema version.
+    assert!(DynamoDbStoreInternal::create(&config, &namespace)
+        .await
+        .is_err());
+    DynamoDbStoreInternal::connect(&config, &namespace)
+        .await
+        .unwrap();
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_hash_prefix() {
+    use linera_views::{
+        batch::Batch,
+        dynamo_db::DynamoDbStore,
+        hash_prefix::HashPrefixStore,
+        store::{ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let store = HashPrefixStore::new(DynamoDbStore::new_test_store().await.unwrap(), 2).unwrap();
+    let mut batch = Batch::new();
+    for index in 0..4u8 {
+        batch.put_key_value_bytes(vec![index % 2, index], vec![index]);
+    }
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(
+        store.find_keys_by_prefix(&[1]).await.unwrap(),
+        vec![vec![1], vec![3]]
+    );
+    assert_eq!(
+        store.find_key_values_by_prefix(&[0]).await.unwrap(),
+        vec![(vec![0], vec![0]), (vec![2], vec![2])]
+    );
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![0]);
+    store.write_batch(batch).await.unwrap();
+    assert_eq!(store.find_keys_by_prefix(&[]).await.unwrap().len(), 2);
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(Vec::new());
+    store.write_batch(batch).await.unwrap();
+    assert!(store.find_keys_by_prefix(&[]).await.unwrap().is_empty());
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_journal_verification_with_leftover_block() {
+    use linera_views::{
+        batch::{Batch, SimpleUnorderedBatch},
+        journaling::{DirectWritableKeyValueStore as _, JournalingKeyValueStore},
+        store::{AdminKeyValueStore as _, ReadableKeyValueStore as _, WritableKeyValueStore as _},
+    };
+
+    let config = dynamo_db_test_config().await;
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    // A stale block left over by an earlier interrupted journal, beyond any header.
+    let block = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![1, 0], vec![0])],
+    };
+    let leftover = SimpleUnorderedBatch {
+        deletions: Vec::new(),
+        insertions: vec![(vec![0, 2, 9, 0, 0, 0], bcs::to_bytes(&block).unwrap())],
+    };
+    store
+        .clone_with_root_key(&[])
+        .unwrap()
+        .write_batch(leftover)
+        .await
+        .unwrap();
+    let store = JournalingKeyValueStore::new(store)
+        .with_journal_verification(true)
+        .clone_with_root_key(&[])
+        .unwrap();
+    let mut batch = Batch::new();
+    for key in 0..200u8 {
+        batch.put_key_value_bytes(vec![2, key], vec![key]);
+    }
+    store.write_batch(batch).await.unwrap();
+    for key in 0..200u8 {
+        assert_eq!(
+            store.read_value_bytes(&[2, key]).await.unwrap(),
+            Some(vec![key])
+        );
+    }
+}
+
+#[cfg(with_dynamodb)]
+#[tokio::test]
+async fn test_dynamo_db_prefix_deletion_paged() {
+    use linera_views::{
+        dynamo_db::DynamoDbStoreInternalError,
+        journaling::{JournalingKeyValueStore, WriteOutcome},
+        store::{
+            AdminKeyValueStore as _, KeyIterable, ReadableKeyValueStore as _,
+            WritableKeyValueStore as _,
+        },
+    };
+
+    let config = dynamo_db_test_config().await.with_query_page_size(Some(7));
+    let (store, _) = new_dynamo_db_test_store(&config).await;
+    let store = JournalingKeyValueStore::new(store)
+        .clone_with_root_key(&[])
+        .unwrap();
+    for chunk in 0..3u8 {
+        let mut batch = Batch::new();
+        for index in 0..100u8 {
+            batch.put_key_value_bytes(vec![1, chunk, index], vec![index]);
+        }
+        store.write_batch(batch).await.unwrap();
+    }
+    // The 300 keys are read in pages of 7 keys and written to the journal as they come.
+    let mut batch = Batch::new();
+    batch.delete_key_prefix(vec![1]);
+    let outcome = store.write_batch_outcome(batch).await.unwrap();
+    assert!(matches!(outcome, WriteOutcome::Journaled { .. }));
+    let keys = store.find_keys_by_prefix(&[1]).await.unwrap();
+    assert_eq!(
+        KeyIterable::<DynamoDbStoreInternalError>::iterator(&keys).count(),
+        0
+    );
+}